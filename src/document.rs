@@ -0,0 +1,959 @@
+//! W3C DID documents built from the registry's records, as returned by
+//! `resolve`. Follows the conventions of ethr-did-resolver for ERC-1056:
+//!
+//! - the owner's ed25519 key, when known, is `#owner`, usable for both
+//!   authentication and assertions;
+//! - delegates enter the relationships of their type in `DELEGATE_TYPES`:
+//!   `sigAuth` authentication and assertion, `veriKey` assertion, `enc` key
+//!   agreement; delegates of other types are only in `verificationMethod`;
+//! - `did/pub/...` attributes are keys and `did/svc/...` attributes services
+//!   whose endpoint is the value, named as parsed by `attribute_name`; names
+//!   in those namespaces that do not parse are left out and reported in the
+//!   metadata;
+//! - `DIDCommMessaging` service values are `DidCommEndpoint` JSON objects or
+//!   plain URIs, emitted as endpoint objects; other values are left out with
+//!   a warning, their service number still counted;
+//! - `LinkedDomains` service values are web origins, emitted as
+//!   `{ "origins": [origin] }`; values failing `check_origin` are left out
+//!   the same way;
+//! - `did/cfg/context` values are JSON arrays of context URIs appended to
+//!   `@context` after `DID_CONTEXT`, up to `MAX_CONTEXTS` in all; values
+//!   that are not, or would pass the cap, are left out with a warning.
+//!
+//! Key ids are stable: each delegate and key attribute is
+//! `#delegate-<fragment>`, the fragment the registry allocated it when it
+//! was first added, kept when it is revoked or others are, so gaps are
+//! left. Records without one are numbered after the highest fragment so far.
+//!
+//! Documents are canonical, so that the same records, with the same
+//! fragments, give byte-identical JSON whatever order they were written in:
+//!
+//! - delegates are taken in `(delegate_type, delegate)` order, then
+//!   attributes in `(name, value)` order, the order `document_hash` hashes
+//!   them in; services are numbered `#service-1`, ... in that order;
+//! - `verificationMethod` lists `#owner` first, then the keys in that
+//!   order, and the relationship lists and `service` follow the same order;
+//! - the fields of the JSON types are declared in ascending key order, so
+//!   objects serialize with sorted keys as in RFC 8785.
+//!
+//! Nothing here reads the chain: `resolve` builds a document from plain
+//! `IdentityRecords`, so off-chain resolvers get the contract's documents
+//! from the `resolver` feature alone, without the contract.
+
+use near_sdk::{near, serde_json};
+
+use crate::RegistryError;
+
+pub mod attribute_name;
+pub mod key_format;
+
+use attribute_name::{KeyAlgorithm, KeyEncoding, ParsedAttributeName};
+use key_format::{Jwk, KeyFormat};
+
+pub const DID_PREFIX: &str = "did:near:";
+pub const DID_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+/// Upper bound on the number of `@context` entries, `DID_CONTEXT` included.
+pub const MAX_CONTEXTS: usize = 8;
+/// Upper bound on the length of a `did/cfg/context` value, in bytes.
+pub const MAX_CONTEXT_BYTES: usize = 1024;
+/// Service type whose endpoints are DIDComm endpoint objects.
+pub const DIDCOMM_SERVICE_TYPE: &str = "DIDCommMessaging";
+/// Service type whose endpoints list the web origins linked to the DID.
+pub const LINKED_DOMAINS_SERVICE_TYPE: &str = "LinkedDomains";
+/// Upper bound on the length of a linked origin, in bytes.
+pub const MAX_ORIGIN_BYTES: usize = 255;
+/// Owner of an identity whose ownership was renounced: the implicit account
+/// of the all-zero key, which no one can sign for.
+pub const RENOUNCED_OWNER: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// The account id in `did:near:<account>` or `did:near:<network>:<account>`;
+/// ids that are not DIDs are taken as account ids already. A DID naming a
+/// network other than `network`, the one the registry is deployed on, is a
+/// `BadDid`: its account is not the one of the same name here.
+pub fn parse_did<'a>(id: &'a str, network: &str) -> Result<&'a str, RegistryError> {
+    let Some(rest) = id.strip_prefix("did:") else {
+        return Ok(id);
+    };
+    let rest = rest.strip_prefix("near:").ok_or(RegistryError::UnsupportedDidMethod)?;
+    let account = match rest.split_once(':') {
+        None => rest,
+        Some((named, account)) if named == network && !account.contains(':') => account,
+        Some(_) => return Err(RegistryError::BadDid),
+    };
+    if account.is_empty() {
+        return Err(RegistryError::BadDid);
+    }
+    Ok(account)
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocument {
+    #[serde(rename = "@context")]
+    pub context: Vec<String>,
+    pub assertion_method: Vec<RelationshipEntry>,
+    pub authentication: Vec<RelationshipEntry>,
+    /// DID of the owner, when the identity does not own itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controller: Option<String>,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_agreement: Vec<RelationshipEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub service: Vec<Service>,
+    pub verification_method: Vec<VerificationMethod>,
+}
+
+/// An entry of a verification relationship: the id of a method of
+/// `verificationMethod`, or, in compact documents, the method itself.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum RelationshipEntry {
+    Reference(String),
+    Embedded(Box<VerificationMethod>),
+}
+
+impl RelationshipEntry {
+    /// Id of the method referenced or embedded.
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Reference(id) => id,
+            Self::Embedded(method) => &method.id,
+        }
+    }
+}
+
+impl PartialEq<&str> for RelationshipEntry {
+    fn eq(&self, id: &&str) -> bool {
+        matches!(self, Self::Reference(reference) if reference == id)
+    }
+}
+
+impl PartialEq<String> for RelationshipEntry {
+    fn eq(&self, id: &String) -> bool {
+        self == &id.as_str()
+    }
+}
+
+/// Options of `resolve`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResolveOptions {
+    /// Format keys are shown in, see `key_format`.
+    #[serde(default)]
+    pub key_format: KeyFormat,
+    /// Only `authentication` and `assertionMethod`, with their methods
+    /// embedded, see `compact`.
+    #[serde(default)]
+    pub compact: bool,
+    /// Only the services of this type.
+    #[serde(default)]
+    pub service_type: Option<String>,
+}
+
+/// A public key, carrying exactly one of the `public_key_*` encodings.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationMethod {
+    pub controller: String,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_base58: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_base64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_hex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_jwk: Option<Jwk>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_multibase: Option<String>,
+    #[serde(rename = "type")]
+    pub method_type: String,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Service {
+    pub id: String,
+    pub service_endpoint: ServiceEndpoint,
+    #[serde(rename = "type")]
+    pub service_type: String,
+}
+
+/// Where a service is reached: the attribute value as a URI, or for
+/// `DIDCommMessaging` services a DIDComm v2 endpoint object.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum ServiceEndpoint {
+    Uri(String),
+    DidComm(DidCommEndpoint),
+    LinkedDomains(LinkedDomainsEndpoint),
+}
+
+impl ServiceEndpoint {
+    /// The URI the service is reached at.
+    pub fn uri(&self) -> &str {
+        match self {
+            Self::Uri(uri) => uri,
+            Self::DidComm(endpoint) => &endpoint.uri,
+            Self::LinkedDomains(endpoint) => endpoint.origins.first().map_or("", String::as_str),
+        }
+    }
+}
+
+/// DID Configuration endpoint of a `LinkedDomains` service: the origin of
+/// a `did/svc/LinkedDomains` value, checked by `check_origin`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LinkedDomainsEndpoint {
+    pub origins: Vec<String>,
+}
+
+/// Checks that `origin` is an `https` web origin, host and optional port
+/// with no path, query or fragment, of at most `MAX_ORIGIN_BYTES`.
+pub fn check_origin(origin: &str) -> Result<(), &'static str> {
+    if origin.len() > MAX_ORIGIN_BYTES {
+        return Err("origin too long");
+    }
+    let authority = origin.strip_prefix("https://").ok_or("origin not https")?;
+    if authority.contains(['/', '?', '#']) {
+        return Err("origin has a path");
+    }
+    let (host, port) = authority.rsplit_once(':').unwrap_or((authority, ""));
+    let host_ok = !host.is_empty() && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'));
+    let port_ok = authority.len() == host.len() || (!port.is_empty() && port.parse::<u16>().is_ok());
+    if !host_ok || !port_ok {
+        return Err("origin has a bad host");
+    }
+    Ok(())
+}
+
+/// DIDComm v2 service endpoint: the value of a `did/svc/DIDCommMessaging`
+/// attribute, as JSON, or a plain URI wrapped as `{ "uri": ... }`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DidCommEndpoint {
+    /// Media types of the messages accepted, e.g. `didcomm/v2`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accept: Vec<String>,
+    /// DID URLs of the mediator keys messages are wrapped for, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routing_keys: Vec<String>,
+    pub uri: String,
+}
+
+impl DidCommEndpoint {
+    /// Parses a `did/svc/DIDCommMessaging` value, checking that `uri` and
+    /// the routing keys are URIs.
+    fn parse(value: &[u8]) -> Result<Self, String> {
+        let endpoint = if value.trim_ascii_start().starts_with(b"{") {
+            serde_json::from_slice(value).map_err(|_| "not a DIDComm endpoint object".to_string())?
+        } else {
+            let uri = std::str::from_utf8(value).map_err(|_| "not UTF-8".to_string())?;
+            Self { uri: uri.to_string(), ..Self::default() }
+        };
+        if let Some(uri) = std::iter::once(&endpoint.uri).chain(&endpoint.routing_keys).find(|uri| !is_uri(uri)) {
+            return Err(format!("{:?} is not a URI", uri));
+        }
+        Ok(endpoint)
+    }
+}
+
+/// Verification relationships a key is referenced from, besides
+/// `verificationMethod`.
+#[near(serializers = [json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Relationship {
+    Authentication,
+    AssertionMethod,
+    KeyAgreement,
+}
+
+/// Delegate type of a delegate, or purpose of a `did/pub` attribute key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DelegateType {
+    VeriKey,
+    SigAuth,
+    Enc,
+    Unknown,
+}
+
+/// The known delegate types with their stored names and relationships; the
+/// one place the mapping is defined, see `get_delegate_type_relationships`.
+pub const DELEGATE_TYPES: [(DelegateType, &str, &[Relationship]); 3] = [
+    (DelegateType::VeriKey, "veriKey", &[Relationship::AssertionMethod]),
+    (DelegateType::SigAuth, "sigAuth", &[Relationship::Authentication, Relationship::AssertionMethod]),
+    (DelegateType::Enc, "enc", &[Relationship::KeyAgreement]),
+];
+
+impl DelegateType {
+    pub fn parse(delegate_type: &str) -> Self {
+        DELEGATE_TYPES.iter().find(|(_, name, _)| *name == delegate_type).map_or(Self::Unknown, |(parsed, _, _)| *parsed)
+    }
+
+    /// Relationships a key of this type is placed in, none when unknown.
+    pub fn relationships(self) -> &'static [Relationship] {
+        DELEGATE_TYPES.iter().find(|(known, _, _)| *known == self).map_or(&[], |(_, _, relationships)| relationships)
+    }
+}
+
+/// A list of the DID document, see `resolve_paginated`.
+#[near(serializers = [json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DocSection {
+    VerificationMethod,
+    Service,
+    Authentication,
+    AssertionMethod,
+    KeyAgreement,
+}
+
+/// A page of one `DocSection`, serialized as the bare array.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum SectionEntries {
+    Methods(Vec<VerificationMethod>),
+    Services(Vec<Service>),
+    References(Vec<String>),
+}
+
+/// Number of entries in each list of the DID document, see
+/// `resolve_summary`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSummary {
+    pub assertion_method: u32,
+    pub authentication: u32,
+    pub key_agreement: u32,
+    pub service: u32,
+    pub verification_method: u32,
+    /// Block height of the identity's last change, as in the metadata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+}
+
+/// DID document metadata, see `resolve_with_metadata`. Timestamps are
+/// RFC 3339 in UTC; all but `deactivated` are omitted while nothing was
+/// written for the identity.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetadata {
+    /// Account that manages the identity's delegates and attributes besides
+    /// its owner, see `set_controller`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controller: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    pub deactivated: bool,
+    /// Whether the identity is locked against writes, see `lock_identity`;
+    /// omitted when false.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// Names of valid `did/pub/` and `did/svc/` attributes left out of the
+    /// document for not following the attribute grammar.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub malformed_attributes: Vec<String>,
+    /// Whether the identity's ownership was renounced, leaving its document
+    /// frozen; omitted when false.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub renounced: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<String>,
+    /// Block height of the identity's last change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    /// Keys left out of the document for not fitting the requested key
+    /// format, each as `<method id>: <reason>`, and `did/cfg/context` values
+    /// left out, as `did/cfg/context: <reason>`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl DocumentMetadata {
+    /// Metadata of the identity of `records`, from its owner, controller
+    /// and the times of its writes: all of it but what building the document
+    /// finds, `malformed_attributes` and `warnings`.
+    pub fn of_records(records: &IdentityRecords) -> Self {
+        Self {
+            controller: records.controller.clone(),
+            created: records.created.map(rfc3339),
+            deactivated: records.deactivated,
+            locked: records.locked,
+            renounced: records.owner == RENOUNCED_OWNER,
+            updated: records.updated.map(rfc3339),
+            version_id: records.version_id.map(|version_id| version_id.to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Why a DID did not resolve, see `ResolutionMetadata`.
+#[near(serializers = [json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ResolutionError {
+    /// Not an account id, `did:near:<account>` or
+    /// `did:near:<network>:<account>`.
+    InvalidDid,
+    /// A DID of another method than `near`.
+    MethodNotSupported,
+    /// The identity was deactivated.
+    Deactivated,
+    /// The version asked for is not a height the identity changed at.
+    VersionNotFound,
+    /// The version asked for is older than the history kept.
+    VersionPruned,
+}
+
+/// DID resolution metadata: the error of a DID that did not resolve.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResolutionMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ResolutionError>,
+}
+
+/// A DID document with its metadata, or, when the DID did not resolve, no
+/// document and the error.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolutionResult {
+    pub did_document: Option<DidDocument>,
+    pub did_document_metadata: DocumentMetadata,
+    #[serde(default)]
+    pub did_resolution_metadata: ResolutionMetadata,
+}
+
+impl ResolutionResult {
+    pub fn failed(error: ResolutionError) -> Self {
+        Self { did_resolution_metadata: ResolutionMetadata { error: Some(error) }, did_document: None, did_document_metadata: DocumentMetadata::default() }
+    }
+}
+
+/// A verification method or service of a document.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum DocumentEntry {
+    Method(VerificationMethod),
+    Service(Service),
+}
+
+/// One entry that differs between two documents, by the fragment of its id:
+/// `from` is absent for added entries, `to` for removed ones.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryDiff {
+    pub fragment: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<DocumentEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<DocumentEntry>,
+}
+
+/// Verification methods and services that differ between two documents, see
+/// `diff`, or, when either version could not be rebuilt, nothing and the
+/// error.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DocumentDiff {
+    pub added: Vec<EntryDiff>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ResolutionError>,
+    pub modified: Vec<EntryDiff>,
+    pub removed: Vec<EntryDiff>,
+}
+
+impl DocumentDiff {
+    pub fn failed(error: ResolutionError) -> Self {
+        Self { error: Some(error), ..Self::default() }
+    }
+}
+
+/// What changed from `from` to `to`, matching verification methods, then
+/// services, by the fragment of their ids: entries only in `to` are added,
+/// in `to`'s order, entries only in `from` removed, in `from`'s, and
+/// entries in both that differ modified.
+pub fn diff(from: &DidDocument, to: &DidDocument) -> DocumentDiff {
+    let entries = |document: &DidDocument| -> Vec<(String, DocumentEntry)> {
+        let fragment = |id: &str| id.split_once('#').map_or(id, |(_, fragment)| fragment).to_string();
+        let methods = document.verification_method.iter().map(|method| (fragment(&method.id), DocumentEntry::Method(method.clone())));
+        let services = document.service.iter().map(|service| (fragment(&service.id), DocumentEntry::Service(service.clone())));
+        methods.chain(services).collect()
+    };
+    let (from, to) = (entries(from), entries(to));
+    let find = |entries: &[(String, DocumentEntry)], fragment: &str| entries.iter().find(|(other, _)| other == fragment).map(|(_, entry)| entry.clone());
+    let mut diff = DocumentDiff::default();
+    for (fragment, entry) in &to {
+        match find(&from, fragment) {
+            None => diff.added.push(EntryDiff { fragment: fragment.clone(), from: None, to: Some(entry.clone()) }),
+            Some(previous) if &previous != entry => diff.modified.push(EntryDiff { fragment: fragment.clone(), from: Some(previous), to: Some(entry.clone()) }),
+            Some(_) => {}
+        }
+    }
+    for (fragment, entry) in &from {
+        if find(&to, fragment).is_none() {
+            diff.removed.push(EntryDiff { fragment: fragment.clone(), from: Some(entry.clone()), to: None });
+        }
+    }
+    diff
+}
+
+/// Whether `uri` starts with a scheme, as `https:`, and has no whitespace.
+fn is_uri(uri: &str) -> bool {
+    let Some((scheme, rest)) = uri.split_once(':') else {
+        return false;
+    };
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        && !rest.is_empty()
+        && !uri.contains(char::is_whitespace)
+}
+
+/// Formats unix seconds as an RFC 3339 UTC timestamp,
+/// `1970-01-01T00:00:00Z` style.
+pub fn rfc3339(timestamp_secs: u64) -> String {
+    let (days, secs) = (timestamp_secs / 86_400, timestamp_secs % 86_400);
+    // Civil date from days since the epoch, after Howard Hinnant's
+    // `civil_from_days`, shifted to 400-year eras starting on 0000-03-01.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, secs / 3_600, secs / 60 % 60, secs % 60)
+}
+
+/// What the document of an identity is built from: its owner and the
+/// records valid at the time of resolution, in any order, with the times of
+/// its writes for the metadata.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IdentityRecords {
+    pub identity: String,
+    pub owner: String,
+    /// The owner's ed25519 key, when known.
+    pub owner_key: Option<[u8; 32]>,
+    /// `(delegate_type, delegate, key, fragment)` of the delegates with an
+    /// ed25519 key, `fragment` being the number of their `#delegate-<n>`.
+    pub delegates: Vec<(String, String, [u8; 32], Option<u32>)>,
+    /// `(name, value, fragment)` of the attributes, `fragment` as for
+    /// delegates when the attribute is a key.
+    pub attributes: Vec<(String, Vec<u8>, Option<u32>)>,
+    /// Unix seconds of the identity's first write.
+    pub created: Option<u64>,
+    /// Unix seconds of the identity's last write.
+    pub updated: Option<u64>,
+    /// Block height of the identity's last write.
+    pub version_id: Option<u64>,
+    /// Whether the identity was deactivated, in which case its document is
+    /// empty whatever the records.
+    #[serde(default)]
+    pub deactivated: bool,
+    /// The identity's controller, see `DocumentMetadata`.
+    #[serde(default)]
+    pub controller: Option<String>,
+    /// Whether the identity is locked, see `DocumentMetadata`.
+    #[serde(default)]
+    pub locked: bool,
+}
+
+/// The document of `records` with its metadata.
+pub fn resolve(records: &IdentityRecords, options: &ResolveOptions) -> ResolutionResult {
+    let (did_document, did_document_metadata) = build(records, options);
+    ResolutionResult { did_resolution_metadata: ResolutionMetadata::default(), did_document: Some(did_document), did_document_metadata }
+}
+
+/// The document of `records` and its metadata, as `resolve` returns them.
+pub fn build(records: &IdentityRecords, options: &ResolveOptions) -> (DidDocument, DocumentMetadata) {
+    if records.deactivated {
+        let did_document = DocumentBuilder::new(&records.identity, &records.identity).build();
+        return (did_document, DocumentMetadata::of_records(records));
+    }
+    let mut document = DocumentBuilder::new(&records.identity, &records.owner).with_key_format(options.key_format);
+    if let Some(key) = &records.owner_key {
+        document.owner(key);
+    }
+    let mut delegates: Vec<_> = records.delegates.iter().collect();
+    delegates.sort_by(|(type_a, delegate_a, ..), (type_b, delegate_b, ..)| (type_a, delegate_a).cmp(&(type_b, delegate_b)));
+    for (delegate_type, _, key, fragment) in delegates {
+        document.delegate(delegate_type, key, *fragment);
+    }
+    let mut attributes: Vec<_> = records.attributes.iter().collect();
+    attributes.sort();
+    for (name, value, fragment) in attributes {
+        document.attribute(name, value, *fragment);
+    }
+    let (mut did_document, malformed_attributes, warnings) = document.finish();
+    if let Some(service_type) = &options.service_type {
+        did_document.service.retain(|service| &service.service_type == service_type);
+    }
+    if options.compact {
+        did_document = compact(did_document);
+    }
+    let did_document_metadata = DocumentMetadata { malformed_attributes, warnings, ..DocumentMetadata::of_records(records) };
+    (did_document, did_document_metadata)
+}
+
+/// The compact form of `document`, for clients that only authenticate and
+/// verify assertions: `verificationMethod` and `keyAgreement` are emptied,
+/// and each method of `authentication` and `assertionMethod` is embedded in
+/// the first of the two it is in, the other referencing it, so that method
+/// ids stay unique. Keys in neither are dropped.
+pub fn compact(mut document: DidDocument) -> DidDocument {
+    let mut methods = std::mem::take(&mut document.verification_method);
+    document.key_agreement.clear();
+    for entry in document.authentication.iter_mut().chain(document.assertion_method.iter_mut()) {
+        if let Some(position) = methods.iter().position(|method| method.id == entry.id()) {
+            *entry = RelationshipEntry::Embedded(Box::new(methods.remove(position)));
+        }
+    }
+    document
+}
+
+/// Collects the valid records of one identity into its document, in the
+/// order they are added: the canonical order of the module docs.
+pub struct DocumentBuilder {
+    document: DidDocument,
+    keys: u32,
+    services: u32,
+    key_format: KeyFormat,
+    malformed: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl DocumentBuilder {
+    pub fn new(identity: &str, owner: &str) -> Self {
+        let did = format!("{DID_PREFIX}{identity}");
+        Self {
+            document: DidDocument {
+                context: vec![DID_CONTEXT.to_string()],
+                controller: (owner != identity).then(|| format!("{DID_PREFIX}{owner}")),
+                id: did,
+                verification_method: Vec::new(),
+                authentication: Vec::new(),
+                assertion_method: Vec::new(),
+                key_agreement: Vec::new(),
+                service: Vec::new(),
+            },
+            keys: 0,
+            services: 0,
+            key_format: KeyFormat::Default,
+            malformed: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn with_key_format(mut self, key_format: KeyFormat) -> Self {
+        self.key_format = key_format;
+        self
+    }
+
+    /// Adds the owner's ed25519 key as `#owner`.
+    pub fn owner(&mut self, key: &[u8; 32]) {
+        let id = format!("{}#owner", self.document.id);
+        self.add_method(id, KeyAlgorithm::Ed25519, KeyEncoding::Base58, key, &[Relationship::Authentication, Relationship::AssertionMethod]);
+    }
+
+    /// Adds a delegate's ed25519 key, referenced from the relationships of
+    /// its type, as `#delegate-<fragment>`; see `next_key_id`.
+    pub fn delegate(&mut self, delegate_type: &str, key: &[u8; 32], fragment: Option<u32>) {
+        let id = self.next_key_id(fragment);
+        self.add_method(id, KeyAlgorithm::Ed25519, KeyEncoding::Base58, key, DelegateType::parse(delegate_type).relationships());
+    }
+
+    /// Adds a `did/pub/...` or `did/svc/...` attribute, recording its name as
+    /// malformed if it does not parse. Other attributes are left out. Keys
+    /// are numbered as delegates are.
+    pub fn attribute(&mut self, name: &str, value: &[u8], fragment: Option<u32>) {
+        match attribute_name::parse(name) {
+            Some(ParsedAttributeName::PublicKey { algorithm, purpose, encoding }) => {
+                let id = self.next_key_id(fragment);
+                self.add_method(id, algorithm, encoding, value, purpose.relationships());
+            }
+            Some(ParsedAttributeName::Service { service_type }) => {
+                let id = self.next_service_id();
+                let service_endpoint = match service_type.as_str() {
+                    DIDCOMM_SERVICE_TYPE => DidCommEndpoint::parse(value).map(ServiceEndpoint::DidComm),
+                    LINKED_DOMAINS_SERVICE_TYPE => std::str::from_utf8(value)
+                        .map_err(|_| "origin not UTF-8")
+                        .and_then(|origin| check_origin(origin).map(|()| origin))
+                        .map(|origin| ServiceEndpoint::LinkedDomains(LinkedDomainsEndpoint { origins: vec![origin.to_string()] }))
+                        .map_err(str::to_string),
+                    _ => Ok(ServiceEndpoint::Uri(String::from_utf8_lossy(value).into_owned())),
+                };
+                match service_endpoint {
+                    Ok(service_endpoint) => self.document.service.push(Service { id, service_endpoint, service_type }),
+                    Err(reason) => self.warnings.push(format!("{}: {}", id, reason)),
+                }
+            }
+            Some(ParsedAttributeName::Context) => {
+                if let Err(reason) = self.add_contexts(value) {
+                    self.warnings.push(format!("{}: {}", name, reason));
+                }
+            }
+            Some(ParsedAttributeName::Other) => {}
+            None => self.malformed.push(name.to_string()),
+        }
+    }
+
+    pub fn build(self) -> DidDocument {
+        self.document
+    }
+
+    /// Number of entries added to `section` so far.
+    pub fn section_len(&self, section: DocSection) -> usize {
+        match section {
+            DocSection::VerificationMethod => self.document.verification_method.len(),
+            DocSection::Service => self.document.service.len(),
+            DocSection::Authentication => self.document.authentication.len(),
+            DocSection::AssertionMethod => self.document.assertion_method.len(),
+            DocSection::KeyAgreement => self.document.key_agreement.len(),
+        }
+    }
+
+    /// Up to `limit` entries of `section` from `from_index`.
+    pub fn section(self, section: DocSection, from_index: usize, limit: usize) -> SectionEntries {
+        fn page<T>(entries: Vec<T>, from_index: usize, limit: usize) -> Vec<T> {
+            entries.into_iter().skip(from_index).take(limit).collect()
+        }
+        fn references(entries: Vec<RelationshipEntry>) -> Vec<String> {
+            entries.iter().map(|entry| entry.id().to_string()).collect()
+        }
+        let document = self.document;
+        match section {
+            DocSection::VerificationMethod => SectionEntries::Methods(page(document.verification_method, from_index, limit)),
+            DocSection::Service => SectionEntries::Services(page(document.service, from_index, limit)),
+            DocSection::Authentication => SectionEntries::References(page(references(document.authentication), from_index, limit)),
+            DocSection::AssertionMethod => SectionEntries::References(page(references(document.assertion_method), from_index, limit)),
+            DocSection::KeyAgreement => SectionEntries::References(page(references(document.key_agreement), from_index, limit)),
+        }
+    }
+
+    /// The document with the names of the attributes left out as malformed
+    /// and the warnings about keys left out.
+    pub fn finish(self) -> (DidDocument, Vec<String>, Vec<String>) {
+        (self.document, self.malformed, self.warnings)
+    }
+
+    /// Appends the contexts of a `did/cfg/context` value, all or none;
+    /// contexts already listed are not repeated.
+    fn add_contexts(&mut self, value: &[u8]) -> Result<(), String> {
+        if value.len() > MAX_CONTEXT_BYTES {
+            return Err(format!("value longer than {} bytes", MAX_CONTEXT_BYTES));
+        }
+        let contexts: Vec<String> = serde_json::from_slice(value).map_err(|_| "not a JSON array of strings".to_string())?;
+        if let Some(context) = contexts.iter().find(|context| !is_uri(context)) {
+            return Err(format!("{:?} is not a URI", context));
+        }
+        let mut added = Vec::new();
+        for context in contexts {
+            if !self.document.context.contains(&context) && !added.contains(&context) {
+                added.push(context);
+            }
+        }
+        if self.document.context.len() + added.len() > MAX_CONTEXTS {
+            return Err(format!("more than {} contexts", MAX_CONTEXTS));
+        }
+        self.document.context.extend(added);
+        Ok(())
+    }
+
+    /// Id of a key numbered `fragment`, or when it has none the number after
+    /// the highest one so far.
+    fn next_key_id(&mut self, fragment: Option<u32>) -> String {
+        self.keys = fragment.unwrap_or(self.keys + 1).max(self.keys);
+        format!("{}#delegate-{}", self.document.id, fragment.unwrap_or(self.keys))
+    }
+
+    fn next_service_id(&mut self) -> String {
+        self.services += 1;
+        format!("{}#service-{}", self.document.id, self.services)
+    }
+
+    fn add_method(&mut self, id: String, algorithm: KeyAlgorithm, encoding: KeyEncoding, key: &[u8], relationships: &[Relationship]) {
+        let mut method = VerificationMethod {
+            id: id.clone(),
+            method_type: algorithm.method_type().to_string(),
+            controller: self.document.id.clone(),
+            public_key_hex: None,
+            public_key_base64: None,
+            public_key_base58: None,
+            public_key_multibase: None,
+            public_key_jwk: None,
+        };
+        let formatted = match self.key_format {
+            KeyFormat::Default => {
+                let encoded = Some(encoding.encode(key));
+                match encoding {
+                    KeyEncoding::Hex => method.public_key_hex = encoded,
+                    KeyEncoding::Base64 => method.public_key_base64 = encoded,
+                    KeyEncoding::Base58 => method.public_key_base58 = encoded,
+                }
+                Ok(())
+            }
+            KeyFormat::Multibase => key_format::multibase(algorithm, key).map(|multibase| {
+                method.method_type = "Multikey".to_string();
+                method.public_key_multibase = Some(multibase);
+            }),
+            KeyFormat::Jwk => key_format::jwk(algorithm, key).map(|jwk| {
+                method.method_type = "JsonWebKey2020".to_string();
+                method.public_key_jwk = Some(jwk);
+            }),
+        };
+        if let Err(reason) = formatted {
+            self.warnings.push(format!("{}: {}", id, reason));
+            return;
+        }
+        for relationship in relationships {
+            match relationship {
+                Relationship::Authentication => &mut self.document.authentication,
+                Relationship::AssertionMethod => &mut self.document.assertion_method,
+                Relationship::KeyAgreement => &mut self.document.key_agreement,
+            }
+            .push(RelationshipEntry::Reference(id.clone()));
+        }
+        self.document.verification_method.push(method);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::serde_json::{json, to_value};
+
+    use super::*;
+
+    fn records() -> IdentityRecords {
+        IdentityRecords {
+            identity: "alice.near".to_string(),
+            owner: "alice.near".to_string(),
+            owner_key: Some([1; 32]),
+            delegates: vec![("sigAuth".to_string(), "bob.near".to_string(), [2; 32], None), ("custom".to_string(), "carol.near".to_string(), [3; 32], None)],
+            attributes: vec![
+                ("did/pub/X25519/enc/base64".to_string(), vec![4; 32], None),
+                ("did/pub/Ed25519/veriKey/pem".to_string(), vec![5], None),
+                ("did/svc/Hub".to_string(), b"https://hub.example".to_vec(), None),
+                ("email".to_string(), b"alice@example.com".to_vec(), None),
+            ],
+            created: Some(0),
+            updated: Some(951_782_400),
+            version_id: Some(42),
+            deactivated: false,
+            controller: None,
+            locked: false,
+        }
+    }
+
+    #[test]
+    fn resolve_builds_document_from_records() {
+        let result = resolve(&records(), &ResolveOptions::default());
+        let did = "did:near:alice.near";
+        assert_eq!(
+            to_value(result.did_document.as_ref()).unwrap(),
+            json!({
+                "@context": [DID_CONTEXT],
+                "id": did,
+                "verificationMethod": [
+                    { "id": "did:near:alice.near#owner", "type": "Ed25519VerificationKey2018", "controller": did, "publicKeyBase58": KeyEncoding::Base58.encode(&[1; 32]) },
+                    { "id": "did:near:alice.near#delegate-1", "type": "Ed25519VerificationKey2018", "controller": did, "publicKeyBase58": KeyEncoding::Base58.encode(&[3; 32]) },
+                    { "id": "did:near:alice.near#delegate-2", "type": "Ed25519VerificationKey2018", "controller": did, "publicKeyBase58": KeyEncoding::Base58.encode(&[2; 32]) },
+                    { "id": "did:near:alice.near#delegate-3", "type": "X25519KeyAgreementKey2019", "controller": did, "publicKeyBase64": KeyEncoding::Base64.encode(&[4; 32]) },
+                ],
+                "authentication": ["did:near:alice.near#owner", "did:near:alice.near#delegate-2"],
+                "assertionMethod": ["did:near:alice.near#owner", "did:near:alice.near#delegate-2"],
+                "keyAgreement": ["did:near:alice.near#delegate-3"],
+                "service": [{ "id": "did:near:alice.near#service-1", "type": "Hub", "serviceEndpoint": "https://hub.example" }],
+            })
+        );
+        assert_eq!(
+            result.did_document_metadata,
+            DocumentMetadata {
+                controller: None,
+                created: Some("1970-01-01T00:00:00Z".to_string()),
+                updated: Some("2000-02-29T00:00:00Z".to_string()),
+                version_id: Some("42".to_string()),
+                deactivated: false,
+                malformed_attributes: vec!["did/pub/Ed25519/veriKey/pem".to_string()],
+                locked: false,
+                renounced: false,
+                warnings: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_applies_key_format_and_controller() {
+        let records = IdentityRecords { owner: "bob.near".to_string(), owner_key: None, ..records() };
+        let (document, _) = build(&records, &ResolveOptions { key_format: KeyFormat::Multibase, ..Default::default() });
+        assert_eq!(document.controller, Some("did:near:bob.near".to_string()));
+        assert!(document.verification_method.iter().all(|method| method.method_type == "Multikey"));
+        assert_eq!(document.verification_method[2].public_key_multibase, key_format::multibase(KeyAlgorithm::X25519, &[4; 32]).ok());
+
+        let empty = resolve(&IdentityRecords { identity: "carol.near".to_string(), owner: "carol.near".to_string(), ..Default::default() }, &ResolveOptions::default());
+        assert_eq!(
+            to_value(empty).unwrap(),
+            json!({
+                "did_resolution_metadata": {},
+                "did_document": { "@context": [DID_CONTEXT], "id": "did:near:carol.near", "verificationMethod": [], "authentication": [], "assertionMethod": [] },
+                "did_document_metadata": { "deactivated": false },
+            })
+        );
+    }
+
+    #[test]
+    fn jwk_needs_well_formed_keys() {
+        assert_eq!(key_format::jwk(KeyAlgorithm::X25519, &[4; 31]), Err("invalid X25519 key length 31".to_string()));
+        assert_eq!(key_format::jwk(KeyAlgorithm::Secp256k1, &[2; 33]), Err("compressed Secp256k1 key has no JWK".to_string()));
+        assert_eq!(key_format::multibase(KeyAlgorithm::Secp256k1, &[5; 65]), Err("invalid Secp256k1 key length 65".to_string()));
+    }
+
+    #[test]
+    fn keys_keep_their_fragments() {
+        let mut records = records();
+        records.delegates[0].3 = Some(7);
+        records.delegates[1].3 = Some(2);
+        let ids: Vec<String> = build(&records, &ResolveOptions::default()).0.verification_method.into_iter().map(|method| method.id).collect();
+        let fragments: Vec<&str> = ids.iter().map(|id| id.split_once('#').unwrap().1).collect();
+        // `custom` before `sigAuth`; the X25519 attribute has no fragment.
+        assert_eq!(fragments, ["owner", "delegate-2", "delegate-7", "delegate-8"]);
+    }
+
+    #[test]
+    fn check_origin_accepts_only_https_origins() {
+        for origin in ["https://example.com", "https://id.example.co.uk:8443", "https://localhost"] {
+            assert_eq!(check_origin(origin), Ok(()), "{}", origin);
+        }
+        let long = format!("https://{}.com", "a".repeat(MAX_ORIGIN_BYTES));
+        for (origin, reason) in [
+            ("http://example.com", "origin not https"),
+            ("example.com", "origin not https"),
+            ("https://example.com/", "origin has a path"),
+            ("https://example.com/.well-known/did-configuration.json", "origin has a path"),
+            ("https://example.com?q=1", "origin has a path"),
+            ("https://example.com#top", "origin has a path"),
+            ("https://", "origin has a bad host"),
+            ("https://user@example.com", "origin has a bad host"),
+            ("https://example.com:https", "origin has a bad host"),
+            ("https://example.com:", "origin has a bad host"),
+            (long.as_str(), "origin too long"),
+        ] {
+            assert_eq!(check_origin(origin), Err(reason), "{}", origin);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Grammar of the attribute names that enter DID documents:
+//!
+//! ```text
+//! did/pub/<algorithm>/<purpose>[/<encoding>]
+//! did/svc/<type>
+//! did/cfg/context
+//! ```
+//!
+//! `algorithm` is `Ed25519`, `Secp256k1` or `X25519`, `purpose` one of the
+//! delegate types of `DELEGATE_TYPES` and `encoding` `hex` (the default),
+//! `base64` or `base58`, the encoding the value's bytes are shown in. A
+//! service `type` is any non-empty rest of the name, slashes included.
+//! `did/cfg/context` holds extra JSON-LD contexts, the only name of the
+//! `did/cfg/` namespace so far. Names outside the `did/pub/`, `did/svc/` and
+//! `did/cfg/` namespaces are not part of documents.
+
+use near_sdk::{
+    base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+    bs58,
+};
+
+use super::DelegateType;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAlgorithm {
+    Ed25519,
+    Secp256k1,
+    X25519,
+}
+
+impl KeyAlgorithm {
+    /// Verification method type of keys of this algorithm.
+    pub fn method_type(self) -> &'static str {
+        match self {
+            Self::Ed25519 => "Ed25519VerificationKey2018",
+            Self::Secp256k1 => "EcdsaSecp256k1VerificationKey2019",
+            Self::X25519 => "X25519KeyAgreementKey2019",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEncoding {
+    Hex,
+    Base64,
+    Base58,
+}
+
+impl KeyEncoding {
+    pub fn encode(self, value: &[u8]) -> String {
+        match self {
+            Self::Hex => value.iter().map(|b| format!("{:02x}", b)).collect(),
+            Self::Base64 => BASE64.encode(value),
+            Self::Base58 => bs58::encode(value).into_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParsedAttributeName {
+    PublicKey { algorithm: KeyAlgorithm, purpose: DelegateType, encoding: KeyEncoding },
+    Service { service_type: String },
+    /// `did/cfg/context`.
+    Context,
+    /// Outside the `did/pub/`, `did/svc/` and `did/cfg/` namespaces.
+    Other,
+}
+
+/// Parses an attribute name, `None` for names in the `did/pub/`, `did/svc/`
+/// or `did/cfg/` namespace that do not follow their grammar.
+pub fn parse(name: &str) -> Option<ParsedAttributeName> {
+    if let Some(key) = name.strip_prefix("did/pub/") {
+        let parts: Vec<&str> = key.split('/').collect();
+        let (algorithm, purpose, encoding) = match parts.as_slice() {
+            [algorithm, purpose] => (*algorithm, *purpose, "hex"),
+            [algorithm, purpose, encoding] => (*algorithm, *purpose, *encoding),
+            _ => return None,
+        };
+        let algorithm = match algorithm {
+            "Ed25519" => KeyAlgorithm::Ed25519,
+            "Secp256k1" => KeyAlgorithm::Secp256k1,
+            "X25519" => KeyAlgorithm::X25519,
+            _ => return None,
+        };
+        let encoding = match encoding {
+            "hex" => KeyEncoding::Hex,
+            "base64" => KeyEncoding::Base64,
+            "base58" => KeyEncoding::Base58,
+            _ => return None,
+        };
+        let purpose = DelegateType::parse(purpose);
+        (purpose != DelegateType::Unknown).then_some(ParsedAttributeName::PublicKey { algorithm, purpose, encoding })
+    } else if let Some(service_type) = name.strip_prefix("did/svc/") {
+        (!service_type.is_empty()).then(|| ParsedAttributeName::Service { service_type: service_type.to_string() })
+    } else if let Some(setting) = name.strip_prefix("did/cfg/") {
+        (setting == "context").then_some(ParsedAttributeName::Context)
+    } else {
+        Some(ParsedAttributeName::Other)
+    }
+}
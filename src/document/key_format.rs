@@ -0,0 +1,85 @@
+//! Formats keys are shown in by `resolve`:
+//!
+//! - `default`: `Ed25519VerificationKey2018` style methods with the key in
+//!   the encoding of its attribute name (`base58` for owners and delegates),
+//!   whatever its length;
+//! - `multibase`: `Multikey` methods with `publicKeyMultibase`, `z` and the
+//!   base58btc of the key behind its multicodec prefix;
+//! - `jwk`: `JsonWebKey2020` methods with `publicKeyJwk` (RFC 8037 for
+//!   Ed25519 and X25519, `EC` for secp256k1).
+//!
+//! The last two need well formed keys: 32 bytes for Ed25519 and X25519, 33
+//! (compressed) or 65 (uncompressed) for secp256k1. JWKs of compressed
+//! secp256k1 keys are not derived, as that takes curve arithmetic.
+
+use near_sdk::{
+    base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine},
+    bs58, near,
+};
+
+use super::attribute_name::KeyAlgorithm;
+
+#[near(serializers = [json])]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyFormat {
+    #[default]
+    Default,
+    Multibase,
+    Jwk,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Jwk {
+    pub crv: String,
+    pub kty: String,
+    pub x: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+}
+
+impl KeyAlgorithm {
+    /// Multicodec of public keys of this algorithm, as its unsigned varint.
+    fn multicodec(self) -> [u8; 2] {
+        match self {
+            Self::Ed25519 => [0xed, 0x01],
+            Self::Secp256k1 => [0xe7, 0x01],
+            Self::X25519 => [0xec, 0x01],
+        }
+    }
+}
+
+/// `publicKeyMultibase` of `key`, or why it cannot be given.
+pub fn multibase(algorithm: KeyAlgorithm, key: &[u8]) -> Result<String, String> {
+    let key = match (algorithm, key.len()) {
+        (KeyAlgorithm::Ed25519 | KeyAlgorithm::X25519, 32) | (KeyAlgorithm::Secp256k1, 33) => key.to_vec(),
+        (KeyAlgorithm::Secp256k1, 65) if key[0] == 0x04 => compress(key),
+        (_, len) => return Err(invalid_length(algorithm, len)),
+    };
+    Ok(format!("z{}", bs58::encode([&algorithm.multicodec()[..], &key].concat()).into_string()))
+}
+
+/// `publicKeyJwk` of `key`, or why it cannot be given.
+pub fn jwk(algorithm: KeyAlgorithm, key: &[u8]) -> Result<Jwk, String> {
+    let okp = |crv: &str| Jwk { kty: "OKP".to_string(), crv: crv.to_string(), x: BASE64_URL.encode(key), y: None };
+    match (algorithm, key.len()) {
+        (KeyAlgorithm::Ed25519, 32) => Ok(okp("Ed25519")),
+        (KeyAlgorithm::X25519, 32) => Ok(okp("X25519")),
+        (KeyAlgorithm::Secp256k1, 65) if key[0] == 0x04 => {
+            Ok(Jwk { kty: "EC".to_string(), crv: "secp256k1".to_string(), x: BASE64_URL.encode(&key[1..33]), y: Some(BASE64_URL.encode(&key[33..])) })
+        }
+        (KeyAlgorithm::Secp256k1, 33) => Err("compressed Secp256k1 key has no JWK".to_string()),
+        (_, len) => Err(invalid_length(algorithm, len)),
+    }
+}
+
+/// Compressed form of an uncompressed secp256k1 key: the parity of `y`, then
+/// `x`.
+fn compress(key: &[u8]) -> Vec<u8> {
+    [&[0x02 | (key[64] & 1)], &key[1..33]].concat()
+}
+
+fn invalid_length(algorithm: KeyAlgorithm, len: usize) -> String {
+    format!("invalid {:?} key length {}", algorithm, len)
+}
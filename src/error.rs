@@ -0,0 +1,141 @@
+//! Errors the registry aborts with. Each variant panics with a stable
+//! snake_case code so clients can match on the failure message.
+
+use std::fmt;
+
+use near_sdk::env;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegistryError {
+    BadActor,
+    BadSignature,
+    BadKey,
+    NoOwnerKey,
+    EmptyBatch,
+    BatchTooLarge,
+    SignatureExpired,
+    BadThreshold,
+    MultisigRequired,
+    NoThreshold,
+    ThresholdNotMet,
+    TooManyKeys,
+    UnknownKey,
+    TooManyObservers,
+    UnknownObserver,
+    MemoTooLong,
+    TooManyDelegations,
+    TooManyValues,
+    BadHistoryLimit,
+    TooManyIdentities,
+    BadCursor,
+    UnsupportedDidMethod,
+    BadDid,
+    BadOrigin,
+    IdentityDeactivated,
+    NoOwnerProposal,
+    NotProposedOwner,
+    NoPendingOwnerChange,
+    OwnerChangeLocked,
+    OwnershipRenounced,
+    NotGuardian,
+    NoPendingRecovery,
+    RecoveryPending,
+    AlreadyApproved,
+    NotSuccessor,
+    OwnerActive,
+    UnregisteredIdentity,
+    ImplicitIdentity,
+    IdentityRegistered,
+    InsufficientDeposit,
+    DelegatesNotCleared,
+    IdentityLocked,
+    NoScheduledTransfer,
+    IdentityMigrated,
+    UntrustedRegistry,
+    InvalidOwner,
+    AlreadyOwner,
+    NoAuthorizingContract,
+    OwnerChangeCooldown,
+    NotRecoveryAddress,
+    TooManyOperators,
+    UnknownOperator,
+    NoLegacyState,
+}
+
+impl RegistryError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BadActor => "bad_actor",
+            Self::BadSignature => "bad_signature",
+            Self::BadKey => "bad_key",
+            Self::NoOwnerKey => "no_owner_key",
+            Self::EmptyBatch => "empty_batch",
+            Self::BatchTooLarge => "batch_too_large",
+            Self::SignatureExpired => "signature_expired",
+            Self::BadThreshold => "bad_threshold",
+            Self::MultisigRequired => "multisig_required",
+            Self::NoThreshold => "no_threshold",
+            Self::ThresholdNotMet => "threshold_not_met",
+            Self::TooManyKeys => "too_many_keys",
+            Self::UnknownKey => "unknown_key",
+            Self::TooManyObservers => "too_many_observers",
+            Self::UnknownObserver => "unknown_observer",
+            Self::MemoTooLong => "memo_too_long",
+            Self::TooManyDelegations => "too_many_delegations",
+            Self::TooManyValues => "too_many_values",
+            Self::BadHistoryLimit => "bad_history_limit",
+            Self::TooManyIdentities => "too_many_identities",
+            Self::BadCursor => "bad_cursor",
+            Self::UnsupportedDidMethod => "unsupported_did_method",
+            Self::BadDid => "bad_did",
+            Self::BadOrigin => "bad_origin",
+            Self::IdentityDeactivated => "identity_deactivated",
+            Self::NoOwnerProposal => "no_owner_proposal",
+            Self::NotProposedOwner => "not_proposed_owner",
+            Self::NoPendingOwnerChange => "no_pending_owner_change",
+            Self::OwnerChangeLocked => "owner_change_locked",
+            Self::OwnershipRenounced => "ownership_renounced",
+            Self::NotGuardian => "not_guardian",
+            Self::NoPendingRecovery => "no_pending_recovery",
+            Self::RecoveryPending => "recovery_pending",
+            Self::AlreadyApproved => "already_approved",
+            Self::NotSuccessor => "not_successor",
+            Self::OwnerActive => "owner_active",
+            Self::UnregisteredIdentity => "unregistered_identity",
+            Self::ImplicitIdentity => "implicit_identity",
+            Self::IdentityRegistered => "identity_registered",
+            Self::InsufficientDeposit => "insufficient_deposit",
+            Self::DelegatesNotCleared => "delegates_not_cleared",
+            Self::IdentityLocked => "identity_locked",
+            Self::NoScheduledTransfer => "no_scheduled_transfer",
+            Self::IdentityMigrated => "identity_migrated",
+            Self::UntrustedRegistry => "untrusted_registry",
+            Self::InvalidOwner => "invalid_owner",
+            Self::AlreadyOwner => "already_owner",
+            Self::NoAuthorizingContract => "no_authorizing_contract",
+            Self::OwnerChangeCooldown => "owner_change_cooldown",
+            Self::NotRecoveryAddress => "not_recovery_address",
+            Self::TooManyOperators => "too_many_operators",
+            Self::UnknownOperator => "unknown_operator",
+            Self::NoLegacyState => "no_legacy_state",
+        }
+    }
+
+    pub fn panic(self) -> ! {
+        env::panic_str(self.as_str())
+    }
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Aborts with `error` unless `condition` holds.
+#[cfg_attr(not(feature = "contract"), allow(dead_code))]
+pub(crate) fn ensure(condition: bool, error: RegistryError) {
+    if !condition {
+        error.panic()
+    }
+}
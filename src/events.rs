@@ -0,0 +1,876 @@
+//! NEP-297 events logged by the registry, one `EVENT_JSON:` line per change:
+//!
+//! ```text
+//! EVENT_JSON:{"standard":"did-near","version":"1.0.0","event":"did_owner_changed","data":{..}}
+//! ```
+//!
+//! Indexers can depend on `did-near` with `default-features = false` to get
+//! these types without the contract and parse logs with `EventLog::from_log`.
+
+use near_sdk::{
+    base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+    env, near,
+    serde::Serialize,
+    serde_json, AccountId, PublicKey,
+};
+
+use crate::Permissions;
+
+pub mod erc1056;
+
+pub const STANDARD: &str = "did-near";
+pub const VERSION: &str = "1.0.0";
+/// Version of the `get_event_schema` description. Bump whenever an event's
+/// shape changes.
+pub const SCHEMA_VERSION: u32 = 8;
+/// Attribute values longer than this are truncated in events, so that even a
+/// full signed batch stays below the protocol's 16 KiB per-receipt log limit.
+pub const MAX_EVENT_VALUE_BYTES: usize = 512;
+const EVENT_PREFIX: &str = "EVENT_JSON:";
+
+/// First write for the identity, logged once before that write's own event.
+/// `actor` is the account that made it.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdentityCreated {
+    pub identity: String,
+    pub actor: String,
+}
+
+/// The identity's owner changed. `changed` is the block height written to
+/// `get_changed`; `previous_change` is the height it held before, 0 for the
+/// identity's first change, so resolvers can walk an identity's events
+/// backwards block by block. The other events carry the same pair.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnerChanged {
+    pub identity: String,
+    pub previous_owner: String,
+    pub new_owner: String,
+    pub changed: u64,
+    pub previous_change: u64,
+    /// Reason given by the owner, if any; omitted from the log when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// The identity was deactivated by its owner. Nothing can be written to it
+/// afterwards.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdentityDeactivated {
+    pub identity: String,
+    pub changed: u64,
+    pub previous_change: u64,
+}
+
+/// The identity was imported by `target_registry`, see `export_identity`.
+/// Nothing can be written to it here afterwards.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdentityMigrated {
+    pub identity: String,
+    pub target_registry: AccountId,
+}
+
+/// The owner removed `delegates` delegates and `attributes` attributes of
+/// the identity from storage in one `clear_identity` call, revoked ones
+/// included. Logged once per call instead of an event per entry.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdentityCleared {
+    pub identity: String,
+    pub delegates: u32,
+    pub attributes: u32,
+    pub changed: u64,
+    pub previous_change: u64,
+}
+
+/// The owner set the identity's recovery guardians, see
+/// `set_recovery_config`. No `guardians` means recovery was turned off.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryConfigured {
+    pub identity: String,
+    pub guardians: Vec<AccountId>,
+    pub threshold: u8,
+    pub delay_secs: u64,
+}
+
+/// A guardian started recovering the identity to `new_owner`, which can be
+/// finalized from unix seconds `effective_at` on.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryInitiated {
+    pub identity: String,
+    pub guardian: AccountId,
+    pub new_owner: String,
+    pub effective_at: u64,
+}
+
+/// A guardian approved the pending recovery; `approvals` counts the
+/// guardians that did so far, the initiator included.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryApproved {
+    pub identity: String,
+    pub guardian: AccountId,
+    pub approvals: u8,
+}
+
+/// The pending recovery was dropped by `actor`, the owner, either vetoing
+/// it or replacing the recovery config.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryCancelled {
+    pub identity: String,
+    pub actor: String,
+}
+
+/// The pending recovery was applied, logged before the owner change's own
+/// `did_owner_changed`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryFinalized {
+    pub identity: String,
+    pub new_owner: String,
+}
+
+/// The identity's recovery address handed it to `new_owner`, see
+/// `recover_ownership`, logged before the owner change's own
+/// `did_owner_changed`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnershipRecovered {
+    pub identity: String,
+    pub recovery_address: AccountId,
+    pub new_owner: String,
+}
+
+/// A delegate was added or revoked. `valid_to` is the delegate's expiry in
+/// unix seconds, 0 for a revocation.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelegateChanged {
+    pub identity: String,
+    pub delegate_type: String,
+    pub delegate: String,
+    pub valid_to: u64,
+    pub changed: u64,
+    pub previous_change: u64,
+    /// Reason given by the owner, if any; omitted from the log when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An attribute was set or revoked. `value` is base64 encoded and cut to
+/// `MAX_EVENT_VALUE_BYTES` bytes, in which case `truncated` is set.
+/// `valid_to` is the attribute's expiry in unix seconds, 0 for a revocation.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeChanged {
+    pub identity: String,
+    pub name: String,
+    pub value: String,
+    pub truncated: bool,
+    pub valid_to: u64,
+    pub changed: u64,
+    pub previous_change: u64,
+    /// Reason given by the owner, if any; omitted from the log when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+impl AttributeChanged {
+    pub fn new(identity: String, name: String, value: &[u8], valid_to: u64, changed: u64, previous_change: u64, memo: Option<String>) -> Self {
+        let (value, truncated) = encode_value(value);
+        Self { identity, name, value, truncated, valid_to, changed, previous_change, memo }
+    }
+}
+
+/// An expired delegate or attribute was removed from storage. `valid_to` is
+/// the expiry that had passed. Purges don't touch `changed`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryPurged {
+    pub identity: String,
+    #[serde(flatten)]
+    pub entry: PurgedEntry,
+    pub valid_to: u64,
+}
+
+/// Key of a purged entry, tagged with its `kind`. Attribute values are
+/// encoded as in `AttributeChanged`.
+#[near(serializers = [json])]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum PurgedEntry {
+    Delegate { delegate_type: String, delegate: String },
+    Attribute { name: String, value: String, truncated: bool },
+}
+
+impl PurgedEntry {
+    pub fn attribute(name: String, value: &[u8]) -> Self {
+        let (value, truncated) = encode_value(value);
+        Self::Attribute { name, value, truncated }
+    }
+}
+
+fn encode_value(value: &[u8]) -> (String, bool) {
+    let truncated = value.len() > MAX_EVENT_VALUE_BYTES;
+    (BASE64.encode(&value[..value.len().min(MAX_EVENT_VALUE_BYTES)]), truncated)
+}
+
+/// A relayed operation ran. `signer` is the account or key whose nonce was
+/// consumed, `public_keys` the keys whose signatures were accepted (several
+/// for a multisig owner change) and `relayer` the account that submitted it.
+/// Logged in addition to the operation's own event.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedOperationExecuted {
+    pub identity: String,
+    pub operation: String,
+    pub signer: String,
+    pub public_keys: Vec<PublicKey>,
+    pub nonce: u64,
+    pub relayer: AccountId,
+}
+
+/// The owner named `controller` to manage the identity's delegates and
+/// attributes alongside it, see `set_controller`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControllerSet {
+    pub identity: String,
+    pub controller: AccountId,
+}
+
+/// The owner removed the identity's `controller`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControllerCleared {
+    pub identity: String,
+    pub controller: AccountId,
+}
+
+/// The owner approved `operator` for the writes `permissions` grant, see
+/// `approve_operator`, replacing any earlier approval.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OperatorApproved {
+    pub identity: String,
+    pub operator: AccountId,
+    pub permissions: Permissions,
+}
+
+/// The owner withdrew the approval of `operator`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OperatorRevoked {
+    pub identity: String,
+    pub operator: AccountId,
+}
+
+/// The owner locked the identity, see `lock_identity`. `changed` is left
+/// alone.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdentityLocked {
+    pub identity: String,
+}
+
+/// The owner lifted the identity's lock.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdentityUnlocked {
+    pub identity: String,
+}
+
+/// The owner set the minimum interval between the identity's owner
+/// changes, see `set_owner_change_cooldown`; 0 removed it.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnerChangeCooldownSet {
+    pub identity: String,
+    pub cooldown_secs: u64,
+}
+
+/// The owning `contract` enabled or disabled authorizing writes requested
+/// with `request_authorized`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContractAuthorizationSet {
+    pub identity: String,
+    pub contract: AccountId,
+    pub enabled: bool,
+}
+
+/// The owner lent the identity to `temp_owner` until unix seconds
+/// `expires_at`, see `grant_temporary_ownership`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemporaryOwnerGranted {
+    pub identity: String,
+    pub temp_owner: AccountId,
+    pub expires_at: u64,
+}
+
+/// The owner ended the grant to `temp_owner`, due to expire at unix
+/// seconds `expires_at`, early.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemporaryOwnerRevoked {
+    pub identity: String,
+    pub temp_owner: AccountId,
+    pub expires_at: u64,
+}
+
+/// The owner set the identity's policy, see `set_policy`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicySet {
+    pub identity: String,
+    pub revoke_delegates_on_owner_change: bool,
+}
+
+/// The owner named `recovery_address` as the account that may hand the
+/// identity to a new owner, see `set_recovery_address`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryAddressSet {
+    pub identity: String,
+    pub recovery_address: AccountId,
+}
+
+/// The owner proposed `proposed_owner` as the next owner, who takes over
+/// by calling `accept_ownership`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnerProposed {
+    pub identity: String,
+    pub proposed_owner: AccountId,
+}
+
+/// The owner withdrew the proposal of `proposed_owner`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnerProposalCancelled {
+    pub identity: String,
+    pub proposed_owner: AccountId,
+}
+
+/// The owner scheduled a change to `new_owner` that `finalize_owner_change`
+/// applies from unix seconds `effective_at` on.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnerChangeScheduled {
+    pub identity: String,
+    pub new_owner: String,
+    pub effective_at: u64,
+}
+
+/// The owner dropped the change to `new_owner` scheduled for unix seconds
+/// `effective_at`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnerChangeCancelled {
+    pub identity: String,
+    pub new_owner: String,
+    pub effective_at: u64,
+}
+
+/// The owner scheduled the identity to pass to `new_owner` by itself at
+/// unix seconds `effective_at`, see `schedule_transfer_at`. Its owner
+/// change is logged once recorded.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferScheduled {
+    pub identity: String,
+    pub new_owner: String,
+    pub effective_at: u64,
+}
+
+/// The owner dropped the transfer to `new_owner` scheduled for unix
+/// seconds `effective_at` before its time.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferCancelled {
+    pub identity: String,
+    pub new_owner: String,
+    pub effective_at: u64,
+}
+
+/// The owner advanced `signer`'s nonce past `nonce`, so relayers can drop
+/// payloads signed at it, see `cancel_nonce`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct NonceCancelled {
+    pub identity: String,
+    pub signer: String,
+    pub nonce: u64,
+}
+
+/// Every event the registry emits, tagged with its NEP-297 `event` name.
+#[near(serializers = [json])]
+#[serde(tag = "event", content = "data")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum DidEvent {
+    #[serde(rename = "did_identity_created")]
+    IdentityCreated(IdentityCreated),
+    #[serde(rename = "did_owner_changed")]
+    OwnerChanged(OwnerChanged),
+    #[serde(rename = "did_delegate_changed")]
+    DelegateChanged(DelegateChanged),
+    #[serde(rename = "did_attribute_changed")]
+    AttributeChanged(AttributeChanged),
+    #[serde(rename = "did_signed_operation_executed")]
+    SignedOperationExecuted(SignedOperationExecuted),
+    #[serde(rename = "did_entry_purged")]
+    EntryPurged(EntryPurged),
+    #[serde(rename = "did_deactivated")]
+    IdentityDeactivated(IdentityDeactivated),
+    #[serde(rename = "did_identity_cleared")]
+    IdentityCleared(IdentityCleared),
+    #[serde(rename = "did_identity_migrated")]
+    IdentityMigrated(IdentityMigrated),
+    #[serde(rename = "did_recovery_configured")]
+    RecoveryConfigured(RecoveryConfigured),
+    #[serde(rename = "did_recovery_initiated")]
+    RecoveryInitiated(RecoveryInitiated),
+    #[serde(rename = "did_recovery_approved")]
+    RecoveryApproved(RecoveryApproved),
+    #[serde(rename = "did_recovery_cancelled")]
+    RecoveryCancelled(RecoveryCancelled),
+    #[serde(rename = "did_recovery_finalized")]
+    RecoveryFinalized(RecoveryFinalized),
+    #[serde(rename = "did_ownership_recovered")]
+    OwnershipRecovered(OwnershipRecovered),
+    #[serde(rename = "did_controller_set")]
+    ControllerSet(ControllerSet),
+    #[serde(rename = "did_controller_cleared")]
+    ControllerCleared(ControllerCleared),
+    #[serde(rename = "did_operator_approved")]
+    OperatorApproved(OperatorApproved),
+    #[serde(rename = "did_operator_revoked")]
+    OperatorRevoked(OperatorRevoked),
+    #[serde(rename = "did_locked")]
+    IdentityLocked(IdentityLocked),
+    #[serde(rename = "did_unlocked")]
+    IdentityUnlocked(IdentityUnlocked),
+    #[serde(rename = "did_owner_change_cooldown_set")]
+    OwnerChangeCooldownSet(OwnerChangeCooldownSet),
+    #[serde(rename = "did_contract_authorization_set")]
+    ContractAuthorizationSet(ContractAuthorizationSet),
+    #[serde(rename = "did_temporary_owner_granted")]
+    TemporaryOwnerGranted(TemporaryOwnerGranted),
+    #[serde(rename = "did_temporary_owner_revoked")]
+    TemporaryOwnerRevoked(TemporaryOwnerRevoked),
+    #[serde(rename = "did_policy_set")]
+    PolicySet(PolicySet),
+    #[serde(rename = "did_recovery_address_set")]
+    RecoveryAddressSet(RecoveryAddressSet),
+    #[serde(rename = "did_owner_proposed")]
+    OwnerProposed(OwnerProposed),
+    #[serde(rename = "did_owner_proposal_cancelled")]
+    OwnerProposalCancelled(OwnerProposalCancelled),
+    #[serde(rename = "did_owner_change_scheduled")]
+    OwnerChangeScheduled(OwnerChangeScheduled),
+    #[serde(rename = "did_owner_change_cancelled")]
+    OwnerChangeCancelled(OwnerChangeCancelled),
+    #[serde(rename = "did_transfer_scheduled")]
+    TransferScheduled(TransferScheduled),
+    #[serde(rename = "did_transfer_cancelled")]
+    TransferCancelled(TransferCancelled),
+    #[serde(rename = "did_nonce_cancelled")]
+    NonceCancelled(NonceCancelled),
+}
+
+impl DidEvent {
+    /// The `EVENT_JSON:` log line for this event.
+    pub fn to_event_string(&self) -> String {
+        #[derive(Serialize)]
+        #[serde(crate = "near_sdk::serde")]
+        struct Envelope<'a> {
+            standard: &'static str,
+            version: &'static str,
+            #[serde(flatten)]
+            event: &'a DidEvent,
+        }
+
+        let envelope = Envelope { standard: STANDARD, version: VERSION, event: self };
+        format!("{EVENT_PREFIX}{}", serde_json::to_string(&envelope).unwrap())
+    }
+
+    pub fn emit(&self) {
+        env::log_str(&self.to_event_string());
+    }
+}
+
+impl From<IdentityCreated> for DidEvent {
+    fn from(event: IdentityCreated) -> Self {
+        Self::IdentityCreated(event)
+    }
+}
+
+impl From<OwnerChanged> for DidEvent {
+    fn from(event: OwnerChanged) -> Self {
+        Self::OwnerChanged(event)
+    }
+}
+
+impl From<DelegateChanged> for DidEvent {
+    fn from(event: DelegateChanged) -> Self {
+        Self::DelegateChanged(event)
+    }
+}
+
+impl From<AttributeChanged> for DidEvent {
+    fn from(event: AttributeChanged) -> Self {
+        Self::AttributeChanged(event)
+    }
+}
+
+impl From<SignedOperationExecuted> for DidEvent {
+    fn from(event: SignedOperationExecuted) -> Self {
+        Self::SignedOperationExecuted(event)
+    }
+}
+
+impl From<EntryPurged> for DidEvent {
+    fn from(event: EntryPurged) -> Self {
+        Self::EntryPurged(event)
+    }
+}
+
+impl From<IdentityDeactivated> for DidEvent {
+    fn from(event: IdentityDeactivated) -> Self {
+        Self::IdentityDeactivated(event)
+    }
+}
+
+impl From<IdentityCleared> for DidEvent {
+    fn from(event: IdentityCleared) -> Self {
+        Self::IdentityCleared(event)
+    }
+}
+
+impl From<IdentityMigrated> for DidEvent {
+    fn from(event: IdentityMigrated) -> Self {
+        Self::IdentityMigrated(event)
+    }
+}
+
+impl From<RecoveryConfigured> for DidEvent {
+    fn from(event: RecoveryConfigured) -> Self {
+        Self::RecoveryConfigured(event)
+    }
+}
+
+impl From<RecoveryInitiated> for DidEvent {
+    fn from(event: RecoveryInitiated) -> Self {
+        Self::RecoveryInitiated(event)
+    }
+}
+
+impl From<RecoveryApproved> for DidEvent {
+    fn from(event: RecoveryApproved) -> Self {
+        Self::RecoveryApproved(event)
+    }
+}
+
+impl From<RecoveryCancelled> for DidEvent {
+    fn from(event: RecoveryCancelled) -> Self {
+        Self::RecoveryCancelled(event)
+    }
+}
+
+impl From<RecoveryFinalized> for DidEvent {
+    fn from(event: RecoveryFinalized) -> Self {
+        Self::RecoveryFinalized(event)
+    }
+}
+
+impl From<OwnershipRecovered> for DidEvent {
+    fn from(event: OwnershipRecovered) -> Self {
+        Self::OwnershipRecovered(event)
+    }
+}
+
+impl From<ControllerSet> for DidEvent {
+    fn from(event: ControllerSet) -> Self {
+        Self::ControllerSet(event)
+    }
+}
+
+impl From<ControllerCleared> for DidEvent {
+    fn from(event: ControllerCleared) -> Self {
+        Self::ControllerCleared(event)
+    }
+}
+
+impl From<OperatorApproved> for DidEvent {
+    fn from(event: OperatorApproved) -> Self {
+        Self::OperatorApproved(event)
+    }
+}
+
+impl From<OperatorRevoked> for DidEvent {
+    fn from(event: OperatorRevoked) -> Self {
+        Self::OperatorRevoked(event)
+    }
+}
+
+impl From<IdentityLocked> for DidEvent {
+    fn from(event: IdentityLocked) -> Self {
+        Self::IdentityLocked(event)
+    }
+}
+
+impl From<IdentityUnlocked> for DidEvent {
+    fn from(event: IdentityUnlocked) -> Self {
+        Self::IdentityUnlocked(event)
+    }
+}
+
+impl From<OwnerChangeCooldownSet> for DidEvent {
+    fn from(event: OwnerChangeCooldownSet) -> Self {
+        Self::OwnerChangeCooldownSet(event)
+    }
+}
+
+impl From<ContractAuthorizationSet> for DidEvent {
+    fn from(event: ContractAuthorizationSet) -> Self {
+        Self::ContractAuthorizationSet(event)
+    }
+}
+
+impl From<TemporaryOwnerGranted> for DidEvent {
+    fn from(event: TemporaryOwnerGranted) -> Self {
+        Self::TemporaryOwnerGranted(event)
+    }
+}
+
+impl From<TemporaryOwnerRevoked> for DidEvent {
+    fn from(event: TemporaryOwnerRevoked) -> Self {
+        Self::TemporaryOwnerRevoked(event)
+    }
+}
+
+impl From<PolicySet> for DidEvent {
+    fn from(event: PolicySet) -> Self {
+        Self::PolicySet(event)
+    }
+}
+
+impl From<RecoveryAddressSet> for DidEvent {
+    fn from(event: RecoveryAddressSet) -> Self {
+        Self::RecoveryAddressSet(event)
+    }
+}
+
+impl From<OwnerProposed> for DidEvent {
+    fn from(event: OwnerProposed) -> Self {
+        Self::OwnerProposed(event)
+    }
+}
+
+impl From<OwnerProposalCancelled> for DidEvent {
+    fn from(event: OwnerProposalCancelled) -> Self {
+        Self::OwnerProposalCancelled(event)
+    }
+}
+
+impl From<OwnerChangeScheduled> for DidEvent {
+    fn from(event: OwnerChangeScheduled) -> Self {
+        Self::OwnerChangeScheduled(event)
+    }
+}
+
+impl From<OwnerChangeCancelled> for DidEvent {
+    fn from(event: OwnerChangeCancelled) -> Self {
+        Self::OwnerChangeCancelled(event)
+    }
+}
+
+impl From<TransferScheduled> for DidEvent {
+    fn from(event: TransferScheduled) -> Self {
+        Self::TransferScheduled(event)
+    }
+}
+
+impl From<TransferCancelled> for DidEvent {
+    fn from(event: TransferCancelled) -> Self {
+        Self::TransferCancelled(event)
+    }
+}
+
+impl From<NonceCancelled> for DidEvent {
+    fn from(event: NonceCancelled) -> Self {
+        Self::NonceCancelled(event)
+    }
+}
+
+impl DidEvent {
+    /// Instances of every variant, with and without each optional part.
+    /// `event_schema` is derived from their serialization.
+    fn samples() -> Vec<Self> {
+        let identity = || "alice.near".to_string();
+        let samples = vec![
+            Self::from(IdentityCreated { identity: identity(), actor: identity() }),
+            Self::from(OwnerChanged { identity: identity(), previous_owner: identity(), new_owner: identity(), changed: 0, previous_change: 0, memo: None }),
+            Self::from(OwnerChanged { identity: identity(), previous_owner: identity(), new_owner: identity(), changed: 0, previous_change: 0, memo: Some(String::new()) }),
+            Self::from(DelegateChanged { identity: identity(), delegate_type: String::new(), delegate: String::new(), valid_to: 0, changed: 0, previous_change: 0, memo: None }),
+            Self::from(DelegateChanged {
+                identity: identity(),
+                delegate_type: String::new(),
+                delegate: String::new(),
+                valid_to: 0,
+                changed: 0,
+                previous_change: 0,
+                memo: Some(String::new()),
+            }),
+            Self::from(AttributeChanged::new(identity(), String::new(), &[], 0, 0, 0, None)),
+            Self::from(AttributeChanged::new(identity(), String::new(), &[], 0, 0, 0, Some(String::new()))),
+            Self::from(SignedOperationExecuted {
+                identity: identity(),
+                operation: String::new(),
+                signer: String::new(),
+                public_keys: vec!["ed25519:11111111111111111111111111111111".parse().unwrap()],
+                nonce: 0,
+                relayer: "relayer.near".parse().unwrap(),
+            }),
+            Self::from(EntryPurged { identity: identity(), entry: PurgedEntry::Delegate { delegate_type: String::new(), delegate: String::new() }, valid_to: 0 }),
+            Self::from(EntryPurged { identity: identity(), entry: PurgedEntry::attribute(String::new(), &[]), valid_to: 0 }),
+            Self::from(IdentityDeactivated { identity: identity(), changed: 0, previous_change: 0 }),
+            Self::from(IdentityCleared { identity: identity(), delegates: 0, attributes: 0, changed: 0, previous_change: 0 }),
+            Self::from(IdentityMigrated { identity: identity(), target_registry: "registry.near".parse().unwrap() }),
+            Self::from(RecoveryConfigured { identity: identity(), guardians: vec!["guardian.near".parse().unwrap()], threshold: 0, delay_secs: 0 }),
+            Self::from(RecoveryInitiated { identity: identity(), guardian: "guardian.near".parse().unwrap(), new_owner: identity(), effective_at: 0 }),
+            Self::from(RecoveryApproved { identity: identity(), guardian: "guardian.near".parse().unwrap(), approvals: 0 }),
+            Self::from(RecoveryCancelled { identity: identity(), actor: identity() }),
+            Self::from(RecoveryFinalized { identity: identity(), new_owner: identity() }),
+            Self::from(OwnershipRecovered { identity: identity(), recovery_address: "recovery.near".parse().unwrap(), new_owner: identity() }),
+            Self::from(ControllerSet { identity: identity(), controller: "controller.near".parse().unwrap() }),
+            Self::from(ControllerCleared { identity: identity(), controller: "controller.near".parse().unwrap() }),
+            Self::from(OperatorApproved { identity: identity(), operator: "operator.near".parse().unwrap(), permissions: Permissions::ALL }),
+            Self::from(OperatorRevoked { identity: identity(), operator: "operator.near".parse().unwrap() }),
+            Self::from(IdentityLocked { identity: identity() }),
+            Self::from(IdentityUnlocked { identity: identity() }),
+            Self::from(OwnerChangeCooldownSet { identity: identity(), cooldown_secs: 0 }),
+            Self::from(ContractAuthorizationSet { identity: identity(), contract: "dao.near".parse().unwrap(), enabled: false }),
+            Self::from(TemporaryOwnerGranted { identity: identity(), temp_owner: "temp.near".parse().unwrap(), expires_at: 0 }),
+            Self::from(TemporaryOwnerRevoked { identity: identity(), temp_owner: "temp.near".parse().unwrap(), expires_at: 0 }),
+            Self::from(PolicySet { identity: identity(), revoke_delegates_on_owner_change: false }),
+            Self::from(RecoveryAddressSet { identity: identity(), recovery_address: "recovery.near".parse().unwrap() }),
+            Self::from(OwnerProposed { identity: identity(), proposed_owner: "proposed.near".parse().unwrap() }),
+            Self::from(OwnerProposalCancelled { identity: identity(), proposed_owner: "proposed.near".parse().unwrap() }),
+            Self::from(OwnerChangeScheduled { identity: identity(), new_owner: identity(), effective_at: 0 }),
+            Self::from(OwnerChangeCancelled { identity: identity(), new_owner: identity(), effective_at: 0 }),
+            Self::from(TransferScheduled { identity: identity(), new_owner: identity(), effective_at: 0 }),
+            Self::from(TransferCancelled { identity: identity(), new_owner: identity(), effective_at: 0 }),
+            Self::from(NonceCancelled { identity: identity(), signer: identity(), nonce: 0 }),
+        ];
+        // Adding a variant fails to compile here until it has a sample above.
+        for sample in &samples {
+            match sample {
+                Self::IdentityCreated(_)
+                | Self::OwnerChanged(_)
+                | Self::DelegateChanged(_)
+                | Self::AttributeChanged(_)
+                | Self::SignedOperationExecuted(_)
+                | Self::EntryPurged(_)
+                | Self::IdentityDeactivated(_)
+                | Self::IdentityCleared(_)
+                | Self::IdentityMigrated(_)
+                | Self::RecoveryConfigured(_)
+                | Self::RecoveryInitiated(_)
+                | Self::RecoveryApproved(_)
+                | Self::RecoveryCancelled(_)
+                | Self::RecoveryFinalized(_)
+                | Self::OwnershipRecovered(_)
+                | Self::ControllerSet(_)
+                | Self::ControllerCleared(_)
+                | Self::OperatorApproved(_)
+                | Self::OperatorRevoked(_)
+                | Self::IdentityLocked(_)
+                | Self::IdentityUnlocked(_)
+                | Self::OwnerChangeCooldownSet(_)
+                | Self::ContractAuthorizationSet(_)
+                | Self::TemporaryOwnerGranted(_)
+                | Self::TemporaryOwnerRevoked(_)
+                | Self::PolicySet(_)
+                | Self::RecoveryAddressSet(_)
+                | Self::OwnerProposed(_)
+                | Self::OwnerProposalCancelled(_)
+                | Self::OwnerChangeScheduled(_)
+                | Self::OwnerChangeCancelled(_)
+                | Self::TransferScheduled(_)
+                | Self::TransferCancelled(_)
+                | Self::NonceCancelled(_) => {}
+            }
+        }
+        samples
+    }
+}
+
+/// Machine-readable description of every event: its name and the JSON type
+/// of each `data` field. Fields present in only some shapes of an event,
+/// like the key fields of `did_entry_purged`, are marked `optional`.
+pub fn event_schema() -> serde_json::Value {
+    fn json_type(value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::Null => "null".to_string(),
+            serde_json::Value::Bool(_) => "boolean".to_string(),
+            serde_json::Value::Number(_) => "integer".to_string(),
+            serde_json::Value::String(_) => "string".to_string(),
+            serde_json::Value::Array(items) => format!("array<{}>", items.first().map_or("null".to_string(), json_type)),
+            serde_json::Value::Object(_) => "object".to_string(),
+        }
+    }
+
+    let mut events: Vec<(String, Vec<serde_json::Map<String, serde_json::Value>>)> = Vec::new();
+    for sample in DidEvent::samples() {
+        let serde_json::Value::Object(mut event) = serde_json::to_value(&sample).unwrap() else { unreachable!() };
+        let name = event["event"].as_str().unwrap().to_string();
+        let serde_json::Value::Object(data) = event.remove("data").unwrap() else { unreachable!() };
+        match events.iter_mut().find(|(event, _)| *event == name) {
+            Some((_, shapes)) => shapes.push(data),
+            None => events.push((name, vec![data])),
+        }
+    }
+
+    let events: Vec<_> = events
+        .into_iter()
+        .map(|(name, shapes)| {
+            let mut fields = serde_json::Map::new();
+            for shape in &shapes {
+                for (field, value) in shape {
+                    let optional = !shapes.iter().all(|shape| shape.contains_key(field));
+                    fields.entry(field.clone()).or_insert_with(|| serde_json::json!({ "type": json_type(value), "optional": optional }));
+                }
+            }
+            serde_json::json!({ "event": name, "fields": fields })
+        })
+        .collect();
+
+    serde_json::json!({ "standard": STANDARD, "version": VERSION, "schema_version": SCHEMA_VERSION, "events": events })
+}
+
+/// A parsed registry log line.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventLog {
+    pub standard: String,
+    pub version: String,
+    #[serde(flatten)]
+    pub event: DidEvent,
+}
+
+impl EventLog {
+    /// Parses an `EVENT_JSON:` log line. Returns `None` for other logs and for
+    /// events of other standards.
+    pub fn from_log(log: &str) -> Option<Self> {
+        let event: Self = serde_json::from_str(log.strip_prefix(EVENT_PREFIX)?).ok()?;
+        (event.standard == STANDARD).then_some(event)
+    }
+}
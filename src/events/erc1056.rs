@@ -0,0 +1,114 @@
+//! ERC-1056 (`EthereumDIDRegistry`) shaped events for ethr-did-resolver
+//! forks, logged alongside the NEP-297 events when the crate is built with
+//! the `erc1056-events` feature:
+//!
+//! ```text
+//! EVENT_JSON:{"standard":"erc1056","version":"1.0.0","event":"DIDDelegateChanged","data":{..}}
+//! ```
+//!
+//! Fields use the Solidity event argument names. Values are mapped as:
+//!
+//! * `delegateType` and attribute `name` are `bytes32`: the UTF-8 bytes
+//!   right-padded with zeros, or `keccak256` of them when longer than 32
+//!   bytes, as `0x` + 64 lowercase hex digits.
+//! * attribute `value` is `bytes`: `0x` + lowercase hex, cut to
+//!   `MAX_EVENT_VALUE_BYTES` with `truncated` set, as for `AttributeChanged`.
+//! * `validTo` and `previousChange` are JSON numbers; `identity`, `owner` and
+//!   `delegate` are account ids.
+
+use near_sdk::{env, near, serde_json};
+
+use super::{EVENT_PREFIX, MAX_EVENT_VALUE_BYTES};
+
+pub const STANDARD: &str = "erc1056";
+pub const VERSION: &str = "1.0.0";
+/// `owner` of the `DIDOwnerChanged` logged on deactivation: ERC-1056
+/// resolvers read an identity owned by the zero address as deactivated.
+pub const NULL_OWNER: &str = "0x0000000000000000000000000000000000000000";
+
+#[near(serializers = [json])]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DidOwnerChanged {
+    pub identity: String,
+    pub owner: String,
+    pub previous_change: u64,
+}
+
+#[near(serializers = [json])]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DidDelegateChanged {
+    pub identity: String,
+    pub delegate_type: String,
+    pub delegate: String,
+    pub valid_to: u64,
+    pub previous_change: u64,
+}
+
+#[near(serializers = [json])]
+#[serde(rename_all = "camelCase")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DidAttributeChanged {
+    pub identity: String,
+    pub name: String,
+    pub value: String,
+    pub truncated: bool,
+    pub valid_to: u64,
+    pub previous_change: u64,
+}
+
+impl DidDelegateChanged {
+    pub fn new(identity: String, delegate_type: &str, delegate: String, valid_to: u64, previous_change: u64) -> Self {
+        Self { identity, delegate_type: bytes32(delegate_type), delegate, valid_to, previous_change }
+    }
+}
+
+impl DidAttributeChanged {
+    pub fn new(identity: String, name: &str, value: &[u8], valid_to: u64, previous_change: u64) -> Self {
+        let truncated = value.len() > MAX_EVENT_VALUE_BYTES;
+        let value = hex(&value[..value.len().min(MAX_EVENT_VALUE_BYTES)]);
+        Self { identity, name: bytes32(name), value, truncated, valid_to, previous_change }
+    }
+}
+
+#[near(serializers = [json])]
+#[serde(tag = "event", content = "data")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Erc1056Event {
+    #[serde(rename = "DIDOwnerChanged")]
+    OwnerChanged(DidOwnerChanged),
+    #[serde(rename = "DIDDelegateChanged")]
+    DelegateChanged(DidDelegateChanged),
+    #[serde(rename = "DIDAttributeChanged")]
+    AttributeChanged(DidAttributeChanged),
+}
+
+impl Erc1056Event {
+    pub fn to_event_string(&self) -> String {
+        let mut event = serde_json::to_value(self).unwrap();
+        event["standard"] = STANDARD.into();
+        event["version"] = VERSION.into();
+        format!("{EVENT_PREFIX}{event}")
+    }
+
+    pub fn emit(&self) {
+        env::log_str(&self.to_event_string());
+    }
+}
+
+/// `bytes32` encoding of a delegate type or attribute name.
+pub fn bytes32(name: &str) -> String {
+    let mut word = [0u8; 32];
+    if name.len() <= 32 {
+        word[..name.len()].copy_from_slice(name.as_bytes());
+    } else {
+        word = env::keccak256_array(name.as_bytes());
+    }
+    hex(&word)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", hex)
+}
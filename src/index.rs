@@ -0,0 +1,101 @@
+//! Enumeration indices behind the list views, and their page cursors.
+
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    bs58, near,
+    store::{LookupMap, TreeMap},
+};
+
+use crate::RegistryError;
+
+/// Version of the cursor encoding, the first byte of every cursor.
+pub const CURSOR_VERSION: u8 = 1;
+
+/// A storage set iterated in insertion order. Each value is keyed by an
+/// ever-increasing sequence number, so removing one never moves another and
+/// a cursor holding the last sequence number seen stays valid across writes.
+#[near]
+pub struct OrderedSet<T>
+where
+    T: BorshSerialize + BorshDeserialize + Ord + Clone,
+{
+    entries: TreeMap<u64, T>,
+    sequences: LookupMap<T, u64>,
+    next: u64,
+}
+
+impl<T> OrderedSet<T>
+where
+    T: BorshSerialize + BorshDeserialize + Ord + Clone,
+{
+    pub fn new(prefix: Vec<u8>) -> Self {
+        Self { entries: TreeMap::new([prefix.as_slice(), b"e"].concat()), sequences: LookupMap::new([prefix.as_slice(), b"s"].concat()), next: 0 }
+    }
+
+    /// Appends `value` unless it is already in the set.
+    pub fn insert(&mut self, value: T) -> bool {
+        if self.sequences.contains_key(&value) {
+            return false;
+        }
+        self.entries.insert(self.next, value.clone());
+        self.sequences.insert(value, self.next);
+        self.next += 1;
+        true
+    }
+
+    pub fn remove(&mut self, value: &T) -> bool {
+        match self.sequences.remove(value) {
+            Some(sequence) => self.entries.remove(&sequence).is_some(),
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.sequences.contains_key(value)
+    }
+
+    pub fn len(&self) -> u32 {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries.values()
+    }
+
+    /// Up to `limit` items made by `item` from the values numbered `start` or
+    /// later, skipping values it returns `None` for, and the sequence number
+    /// to resume from if any values are left unexamined.
+    pub fn page<V>(&self, start: u64, limit: usize, mut item: impl FnMut(&T) -> Option<V>) -> (Vec<V>, Option<u64>) {
+        let mut items = Vec::new();
+        for (sequence, value) in self.entries.range(start..) {
+            if items.len() == limit {
+                return (items, Some(*sequence));
+            }
+            items.extend(item(value));
+        }
+        (items, None)
+    }
+}
+
+/// Encodes the position a list view resumes at: `part` tells apart the
+/// lists of views that walk several, `sequence` is the first sequence number
+/// left to examine.
+pub fn encode_cursor(part: u8, sequence: u64) -> String {
+    let mut bytes = vec![CURSOR_VERSION, part];
+    bytes.extend(sequence.to_le_bytes());
+    bs58::encode(bytes).into_string()
+}
+
+/// Decodes a cursor made by `encode_cursor` into `(part, sequence)`,
+/// rejecting cursors of another version or with a `part` of `parts` or more.
+pub fn decode_cursor(cursor: &str, parts: u8) -> Result<(u8, u64), RegistryError> {
+    let bytes = bs58::decode(cursor).into_vec().map_err(|_| RegistryError::BadCursor)?;
+    match bytes.as_slice() {
+        [CURSOR_VERSION, part, sequence @ ..] if *part < parts => Ok((*part, u64::from_le_bytes(sequence.try_into().map_err(|_| RegistryError::BadCursor)?))),
+        _ => Err(RegistryError::BadCursor),
+    }
+}
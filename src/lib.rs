@@ -1,8 +1,675 @@
 
-use near_sdk::{env, near, store::LookupMap, AccountId};
+use near_sdk::{json_types::Base64VecU8, near, AccountId, PublicKey};
+#[cfg(feature = "contract")]
+use near_sdk::{
+    bs58, env, serde_json,
+    store::LookupMap,
+    CurveType, Gas, NearToken, Promise, PromiseError,
+};
 
+#[cfg(feature = "resolver")]
+pub mod document;
+pub mod error;
+pub mod events;
+#[cfg(feature = "contract")]
+mod index;
+pub mod payload;
+pub mod signature;
+
+#[cfg(feature = "resolver")]
+pub use document::{DelegateType, DidCommEndpoint, DidDocument, DocSection, DocumentDiff, DocumentEntry, DocumentMetadata, DocumentSummary, EntryDiff, LinkedDomainsEndpoint, Relationship, RelationshipEntry, ResolutionError, ResolutionMetadata, ResolutionResult, ResolveOptions, SectionEntries, ServiceEndpoint};
+pub use error::RegistryError;
+#[cfg(feature = "contract")]
+use error::ensure;
+#[cfg(feature = "contract")]
+use document::{attribute_name::{self, ParsedAttributeName}, DocumentBuilder, IdentityRecords, RENOUNCED_OWNER};
+#[cfg(feature = "contract")]
+use index::{decode_cursor, encode_cursor, OrderedSet};
+
+pub use events::{
+    AttributeChanged, ContractAuthorizationSet, ControllerCleared, ControllerSet, DelegateChanged, DidEvent, EntryPurged, EventLog, IdentityCleared, IdentityCreated, IdentityDeactivated, IdentityLocked, IdentityMigrated,
+    IdentityUnlocked, NonceCancelled, OperatorApproved, OperatorRevoked, OwnerChangeCancelled, OwnerChangeCooldownSet, OwnerChangeScheduled, OwnerChanged, OwnerProposalCancelled, OwnerProposed, OwnershipRecovered, PolicySet,
+    PurgedEntry, RecoveryAddressSet, RecoveryApproved, RecoveryCancelled, RecoveryConfigured, RecoveryFinalized, RecoveryInitiated, SignedOperationExecuted, TemporaryOwnerGranted, TemporaryOwnerRevoked, TransferCancelled,
+    TransferScheduled,
+};
+pub use payload::SignedOp;
+#[cfg(feature = "contract")]
+use signature::Signature;
+
+/// Upper bound on the number of operations in one `execute_signed_batch`.
+pub const MAX_BATCH_OPS: usize = 16;
+/// Upper bound on the number of keys in an owner multisig.
+pub const MAX_THRESHOLD_KEYS: usize = 10;
+/// Upper bound on the number of recovery guardians per identity.
+pub const MAX_GUARDIANS: usize = 10;
+/// Upper bound on the number of signing keys per identity.
+pub const MAX_SIGNING_KEYS: usize = 10;
+/// Upper bound on the number of entries one `purge_expired` call inspects.
+pub const MAX_PURGE_ENTRIES: usize = 32;
+/// Upper bound on the number of identities one `transfer_all_owned` call
+/// examines, so that its gas and events stay bounded.
+pub const MAX_TRANSFER_ALL: u64 = 16;
+/// Upper bound on the number of entries one `clear_identity` call removes.
+pub const MAX_CLEAR_ENTRIES: u64 = 50;
+/// Upper bound on the number of entries one `migrate_entries` call indexes.
+pub const MAX_MIGRATE_ENTRIES: usize = 64;
+/// Upper bound on the number of delegates an identity may hold for an owner
+/// change to revoke them under its policy, see `set_policy`. As for
+/// `MAX_BATCH_OPS`, their events stay below the per-receipt log limit.
+pub const MAX_OWNER_CHANGE_REVOCATIONS: u64 = 16;
+/// Upper bound on the length of the memo of a direct change, in bytes.
+pub const MAX_MEMO_BYTES: usize = 256;
+/// Upper bound on the `limit` of paginated views.
+pub const MAX_PAGE_SIZE: u64 = 100;
+/// Upper bound on the number of identities one `resolve_many` call resolves.
+pub const MAX_RESOLVE_MANY: usize = 10;
+/// Upper bound on the number of writes kept per change height for
+/// `resolve_at_version`, purges included.
+pub const MAX_SNAPSHOT_WRITES: usize = 64;
+/// Upper bound on the number of unrevoked delegations to one account.
+pub const MAX_DELEGATIONS: usize = 256;
+/// Upper bound on the number of unrevoked values of one attribute name.
+pub const MAX_ATTRIBUTE_VALUES: usize = 16;
+/// Default number of change heights kept per identity, see
+/// `get_change_history`.
+pub const DEFAULT_HISTORY_LIMIT: u64 = 1000;
+/// Upper bound on the length of an identifier passed to `register_identity`.
+pub const MAX_IDENTIFIER_BYTES: usize = 128;
+/// Upper bound on the length of a new owner, that of the longest account id.
+pub const MAX_OWNER_BYTES: usize = 64;
+/// Deposit `register_identity` requires, covering the storage of the
+/// identity's owner, indexes and history.
+#[cfg(feature = "contract")]
+pub const REGISTRATION_DEPOSIT: NearToken = NearToken::from_millinear(20);
+/// Upper bound on the number of observer contracts per identity.
+pub const MAX_OBSERVERS: usize = 4;
+/// Upper bound on the number of approved operators per identity.
+pub const MAX_OPERATORS: usize = 8;
+/// Gas attached to each `on_did_changed` notification.
+#[cfg(feature = "contract")]
+pub const OBSERVER_GAS: Gas = Gas::from_tgas(5);
+/// Upper bound on the number of valid entries `export_identity` carries
+/// over. As for `MAX_BATCH_OPS`, their events on import stay below the
+/// per-receipt log limit.
+pub const MAX_EXPORT_ENTRIES: usize = 16;
+/// Gas attached to the `import_identity` call of `export_identity`.
+#[cfg(feature = "contract")]
+pub const IMPORT_GAS: Gas = Gas::from_tgas(50);
+/// Gas attached to the `on_identity_exported` callback.
+#[cfg(feature = "contract")]
+pub const EXPORT_CALLBACK_GAS: Gas = Gas::from_tgas(10);
+/// Gas attached to the owner contract's `is_authorized` call, see
+/// `request_authorized`.
+#[cfg(feature = "contract")]
+pub const AUTHORIZATION_GAS: Gas = Gas::from_tgas(10);
+/// Gas attached to the `on_authorized` callback, which makes the write and
+/// notifies its observers.
+#[cfg(feature = "contract")]
+pub const AUTHORIZED_CALLBACK_GAS: Gas = Gas::from_tgas(40);
+
+/// An identity's `(delegate_type, delegate)` and `(name, value)` entries.
+#[cfg(feature = "contract")]
+type DocumentEntries = (Vec<(String, String)>, Vec<(String, Vec<u8>)>);
+
+/// Receipt of the last signed operation executed for an identity. The relayer
+/// is recorded for accountability only; it is never trusted.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RelayedOp {
+    pub operation: String,
+    pub signer: String,
+    pub relayer: AccountId,
+    pub nonce: u64,
+    pub block_height: u64,
+}
+
+/// What a mutation notified to observers changed.
+#[near(serializers = [json])]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeKind {
+    Owner,
+    Delegate,
+    Attribute,
+    /// A signed batch, which may mix the other kinds.
+    Batch,
+    Deactivated,
+    /// Delegates and attributes removed by `clear_identity`.
+    Cleared,
+}
+
+/// Class of an identifier, see `get_identifier_kind`.
+#[near(serializers = [json])]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdentifierKind {
+    /// A NEAR account id.
+    Account,
+    /// A base58 ed25519 key or an Ethereum address, acting by signature.
+    Key,
+    /// Anything else, e.g. a UUID or a hash, owned once registered.
+    Other,
+}
+
+/// A delegate to check with `valid_delegate_many`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelegateQuery {
+    pub identity: String,
+    pub delegate_type: String,
+    pub delegate: String,
+}
+
+/// A delegate of an identity as listed by `list_delegates`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelegateEntry {
+    pub delegate_type: String,
+    pub delegate: String,
+    /// Expiry in unix seconds, `0` once revoked.
+    pub valid_until: u64,
+}
+
+/// A delegation to an account as listed by `identities_for_delegate`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelegatedIdentity {
+    pub identity: String,
+    pub delegate_type: String,
+    /// Expiry in unix seconds.
+    pub valid_until: u64,
+}
+
+/// An attribute of an identity as listed by `list_attributes`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeEntry {
+    pub name: String,
+    /// The full value, base64 encoded.
+    pub value: Base64VecU8,
+    /// Expiry in unix seconds, `0` once revoked.
+    pub valid_until: u64,
+}
+
+/// A delegate or attribute as listed by `list_expiring`.
+#[near(serializers = [json])]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExpiringEntry {
+    Delegate { delegate_type: String, delegate: String, valid_until: u64 },
+    Attribute { name: String, value: Base64VecU8, valid_until: u64 },
+}
+
+/// When an identity last changed, see `get_changed_info`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangedInfo {
+    pub block_height: u64,
+    /// Block timestamp in unix seconds, `0` for changes made before it was
+    /// recorded.
+    pub timestamp: u64,
+}
+
+/// When an identity was first written, see `get_created`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreatedInfo {
+    pub block_height: u64,
+    /// Block timestamp in unix seconds.
+    pub timestamp: u64,
+}
+
+/// A change of an identity's owner, see `get_owner_history`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnerChange {
+    pub previous_owner: String,
+    pub new_owner: String,
+    pub block_height: u64,
+    /// Block timestamp in unix seconds.
+    pub timestamp: u64,
+}
+
+/// An owner change waiting out its delay, see `schedule_owner_change` and
+/// `schedule_transfer_at`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingOwnerChange {
+    pub new_owner: String,
+    /// Unix seconds from which the change applies.
+    pub effective_at: u64,
+}
+
+/// How to undo one write to an identity's records: the state of the entry
+/// before it, `None` if there was no entry. See `resolve_at_version`.
+#[cfg(feature = "contract")]
+#[near(serializers = [borsh])]
+#[derive(Clone, Debug, PartialEq)]
+enum RecordChange {
+    Owner { previous: Option<String> },
+    Delegate { delegate_type: String, delegate: String, previous: Option<u64> },
+    Attribute { name: String, value: Vec<u8>, previous: Option<u64> },
+    Deactivated,
+}
+
+/// The writes to an identity's records from one change height until the
+/// next, each with the block height it was made at: the writes of the
+/// change itself and the purges after it.
+#[cfg(feature = "contract")]
+#[near(serializers = [borsh])]
+#[derive(Clone, Debug, PartialEq)]
+struct Snapshot {
+    block_height: u64,
+    /// Block timestamp in unix seconds.
+    timestamp: u64,
+    writes: Vec<(u64, RecordChange)>,
+    /// False once more than `MAX_SNAPSHOT_WRITES` writes were made, which
+    /// are then not kept: versions up to `block_height` no longer resolve.
+    complete: bool,
+}
+
+/// Records as they were before the writes undone by `resolve_at_version`,
+/// `None` for entries that did not exist.
+#[cfg(feature = "contract")]
+#[derive(Default)]
+struct UndoneWrites {
+    owner: Option<String>,
+    delegates: std::collections::BTreeMap<(String, String), Option<u64>>,
+    attributes: std::collections::BTreeMap<(String, Vec<u8>), Option<u64>>,
+    deactivated: bool,
+}
+
+/// Everything the registry holds for one identity, as returned by
+/// `get_identity_state`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdentityState {
+    pub identity: String,
+    pub owner: String,
+    /// One page of the identity's delegates, see `list_delegates`.
+    pub delegates: Vec<DelegateEntry>,
+    /// One page of the identity's attributes, see `list_attributes`.
+    pub attributes: Vec<AttributeEntry>,
+    pub changed: u64,
+    pub created: Option<CreatedInfo>,
+    /// The owner's next nonce.
+    pub nonce: u64,
+    /// `from_index` of the next page, `None` once both lists are exhausted.
+    pub next_index: Option<u64>,
+}
+
+/// Registry-wide counters, see `get_registry_stats`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegistryStats {
+    /// Identities written at least once.
+    pub identities_touched: u64,
+    /// Unrevoked delegates, counted like `count_delegates`.
+    pub total_delegates: u64,
+    /// Unrevoked attributes, counted like `count_attributes`.
+    pub total_attributes: u64,
+    pub total_owner_changes: u64,
+}
+
+/// Direct writes an account may make to an identity, see `can_manage`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManagePermissions {
+    /// `add_delegate` and `revoke_delegate`.
+    pub delegates: bool,
+    /// `set_attribute` and `revoke_attribute`.
+    pub attributes: bool,
+    /// `change_owner`.
+    pub owner: bool,
+}
+
+/// Delegate and attribute writes an approved operator may make to an
+/// identity, see `approve_operator` and `get_permissions`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Permissions {
+    pub add_delegate: bool,
+    pub revoke_delegate: bool,
+    pub set_attribute: bool,
+    pub revoke_attribute: bool,
+}
+
+impl Permissions {
+    /// Every write, as the owner and the controller may make them.
+    pub const ALL: Self = Self { add_delegate: true, revoke_delegate: true, set_attribute: true, revoke_attribute: true };
+}
+
+/// One page of a cursor-paginated view. Pass `next_cursor` back to fetch
+/// the next page; it is `None` once the list is exhausted. Cursors resume
+/// after the last entry examined, so entries added or removed in between
+/// neither shift nor repeat the rest of the list.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// Key set whose approvals an owner change requires.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnerThreshold {
+    pub keys: Vec<PublicKey>,
+    pub threshold: u8,
+}
+
+/// Guardians that may recover an identity, see `set_recovery_config`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryConfig {
+    pub guardians: Vec<AccountId>,
+    /// Number of distinct guardians a recovery needs.
+    pub threshold: u8,
+    /// Time between a recovery's initiation and its earliest finalization.
+    pub delay_secs: u64,
+}
+
+/// A recovery waiting for approvals and its delay, see `initiate_recovery`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingRecovery {
+    pub new_owner: String,
+    /// Guardians that approved it, the initiator first.
+    pub approvals: Vec<AccountId>,
+    /// Unix seconds from which `finalize_recovery` may apply it.
+    pub effective_at: u64,
+}
+
+/// An identity's state as `export_identity` hands it to another registry's
+/// `import_identity`: its owner and valid entries, with their expiries in
+/// unix seconds.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdentityExport {
+    pub identity: String,
+    pub owner: String,
+    /// `(delegate_type, delegate, valid_to)`.
+    pub delegates: Vec<(String, String, u64)>,
+    /// `(name, value, valid_to)`.
+    pub attributes: Vec<(String, Base64VecU8, u64)>,
+}
+
+/// Per-identity rules the owner opted into, see `set_policy`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IdentityPolicy {
+    /// Revoke every valid delegate whenever the owner changes.
+    pub revoke_delegates_on_owner_change: bool,
+}
+
+/// Account acting as an identity's owner until `expires_at` (unix
+/// seconds), see `grant_temporary_ownership`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemporaryOwner {
+    pub temp_owner: AccountId,
+    pub expires_at: u64,
+}
+
+/// Account that may take an inactive identity over, see
+/// `set_inactivity_successor`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct InactivitySuccessor {
+    pub successor: AccountId,
+    /// Time without owner activity after which `successor` may claim.
+    pub inactivity_secs: u64,
+}
+
+/// Block time in unix seconds, the clock every expiry and signature deadline
+/// is compared against.
+#[cfg(feature = "contract")]
+fn now_secs() -> u64 {
+    env::block_timestamp_ms() / 1000
+}
+
+/// Storage prefix of the per-key set tagged `tag` in an index map.
+#[cfg(feature = "contract")]
+fn index_prefix(tag: &[u8], key: &str) -> Vec<u8> {
+    [tag, &env::sha256_array(key.as_bytes())].concat()
+}
+
+/// Updates the identity's count of unrevoked entries, and the registry-wide
+/// `total`, for an entry going from `was_counted` to `is_counted`.
+#[cfg(feature = "contract")]
+fn update_count(counts: &mut LookupMap<String, u64>, total: &mut u64, identity: &str, was_counted: bool, is_counted: bool) {
+    *total = *total + is_counted as u64 - was_counted as u64;
+    let count = counts.get(identity).copied().unwrap_or(0) + is_counted as u64 - was_counted as u64;
+    if count == 0 {
+        counts.remove(identity);
+    } else {
+        counts.insert(identity.to_string(), count);
+    }
+}
+
+/// Appends `entry` to the identity's history in `entries`, whose kept
+/// sequence numbers are tracked in `bounds`, dropping up to two of the oldest
+/// entries beyond `limit` so that lowering the limit is caught up with over
+/// the following appends.
+#[cfg(feature = "contract")]
+fn push_history<V>(entries: &mut LookupMap<(String, u64), V>, bounds: &mut LookupMap<String, (u64, u64)>, limit: u64, identity: &str, entry: V)
+where
+    V: near_sdk::borsh::BorshSerialize + near_sdk::borsh::BorshDeserialize,
+{
+    let (mut start, end) = bounds.get(identity).copied().unwrap_or((0, 0));
+    entries.insert((identity.to_string(), end), entry);
+    for _ in 0..2 {
+        if end + 1 - start <= limit {
+            break;
+        }
+        entries.remove(&(identity.to_string(), start));
+        start += 1;
+    }
+    bounds.insert(identity.to_string(), (start, end + 1));
+}
+
+/// Up to `limit` (at most `MAX_PAGE_SIZE`) of the last `history_limit`
+/// entries of the identity's history, oldest first, from `from_index`.
+#[cfg(feature = "contract")]
+fn history_page<V>(entries: &LookupMap<(String, u64), V>, bounds: &LookupMap<String, (u64, u64)>, history_limit: u64, identity: String, from_index: u64, limit: u64) -> Vec<V>
+where
+    V: near_sdk::borsh::BorshSerialize + near_sdk::borsh::BorshDeserialize + Clone,
+{
+    let Some(&(start, end)) = bounds.get(&identity) else {
+        return Vec::new();
+    };
+    let first = start.max(end.saturating_sub(history_limit)).saturating_add(from_index);
+    (first..end.min(first.saturating_add(limit.min(MAX_PAGE_SIZE)))).map(|sequence| entries[&(identity.clone(), sequence)].clone()).collect()
+}
+
+/// `(part, sequence)` a cursor-paginated view over `parts` lists resumes
+/// at, the start of the first list without a cursor.
+#[cfg(feature = "contract")]
+fn cursor_position(cursor: Option<String>, parts: u8) -> (u8, u64) {
+    cursor.map_or((0, 0), |cursor| decode_cursor(&cursor, parts).unwrap_or_else(|error| error.panic()))
+}
+
+/// Page of `items` with the cursor resuming a single list at `next`.
+#[cfg(feature = "contract")]
+fn page<V>((items, next): (Vec<V>, Option<u64>)) -> Page<V> {
+    Page { items, next_cursor: next.map(|sequence| encode_cursor(0, sequence)) }
+}
+
+/// Like `history_page`, from the cursor holding the absolute sequence number
+/// to resume at; entries dropped since skip ahead to the oldest one kept.
+#[cfg(feature = "contract")]
+fn history_cursor_page<V>(entries: &LookupMap<(String, u64), V>, bounds: &LookupMap<String, (u64, u64)>, history_limit: u64, identity: String, cursor: Option<String>, limit: u64) -> Page<V>
+where
+    V: near_sdk::borsh::BorshSerialize + near_sdk::borsh::BorshDeserialize + Clone,
+{
+    let (_, sequence) = cursor_position(cursor, 1);
+    let Some(&(start, end)) = bounds.get(&identity) else {
+        return page((Vec::new(), None));
+    };
+    let first = start.max(end.saturating_sub(history_limit)).max(sequence);
+    let last = end.min(first.saturating_add(limit.min(MAX_PAGE_SIZE)));
+    page(((first..last).map(|sequence| entries[&(identity.clone(), sequence)].clone()).collect(), (last < end).then_some(last)))
+}
+
+/// The bare account id `id` names, which may be given as a
+/// `did:near:[<network>:]<account>` DID. Storage is keyed by the bare form.
+#[cfg(feature = "contract")]
+fn normalize_did(id: String) -> String {
+    document::parse_did(&id, current_network()).unwrap_or_else(|error| error.panic()).to_string()
+}
+
+/// The network the registry is deployed on, the only one a
+/// `did:near:<network>:<account>` DID may name: `testnet` for a `.testnet`
+/// account, `mainnet` otherwise.
+#[cfg(feature = "contract")]
+fn current_network() -> &'static str {
+    if env::current_account_id().as_str().ends_with(".testnet") { "testnet" } else { "mainnet" }
+}
+
+/// The bare form of the new owner `id`, see `normalize_did`, which must be
+/// an account id or a key identifier of at most `MAX_OWNER_BYTES`.
+#[cfg(feature = "contract")]
+fn normalize_owner(id: String) -> String {
+    let owner = normalize_did(id);
+    ensure(owner.len() <= MAX_OWNER_BYTES && identifier_kind(&owner) != IdentifierKind::Other, RegistryError::InvalidOwner);
+    owner
+}
+
+/// The `IdentifierKind` of a normalized identity.
+#[cfg(feature = "contract")]
+fn identifier_kind(identity: &str) -> IdentifierKind {
+    let eth_address = identity.len() == 42 && identity.starts_with("0x") && identity[2..].bytes().all(|c| c.is_ascii_hexdigit());
+    if identity.parse::<AccountId>().is_ok() {
+        IdentifierKind::Account
+    } else if eth_address || bs58::decode(identity).into_vec().is_ok_and(|key| key.len() == 32) {
+        IdentifierKind::Key
+    } else {
+        IdentifierKind::Other
+    }
+}
+
+/// Rejects memos over `MAX_MEMO_BYTES` before anything is written.
+#[cfg(feature = "contract")]
+fn assert_memo(memo: &Option<String>) {
+    ensure(memo.as_ref().is_none_or(|memo| memo.len() <= MAX_MEMO_BYTES), RegistryError::MemoTooLong);
+}
+
+/// Attribute name the origins of `set_linked_domain` are stored under.
+#[cfg(feature = "contract")]
+fn linked_domains_attribute() -> String {
+    format!("did/svc/{}", document::LINKED_DOMAINS_SERVICE_TYPE)
+}
+
+#[cfg(feature = "contract")]
 #[near(contract_state)]
 pub struct NearDIDRegistry {
+    owners: LookupMap<String, String>,
+    delegates: LookupMap<(String, String, String), u64>,
+    attributes: LookupMap<(String, String, Vec<u8>), u64>,
+    changed: LookupMap<String, u64>,
+    nonce: LookupMap<(String, String), u64>,
+    legacy_nonce: LookupMap<String, u64>,
+    owner_keys: LookupMap<String, PublicKey>,
+    relayed: LookupMap<String, RelayedOp>,
+    thresholds: LookupMap<String, OwnerThreshold>,
+    signing_keys: LookupMap<String, Vec<PublicKey>>,
+    registry_salt: [u8; 32],
+    observers: LookupMap<String, Vec<AccountId>>,
+    /// `(delegate_type, delegate)` pairs in `delegates`, per identity.
+    delegate_index: LookupMap<String, OrderedSet<(String, String)>>,
+    /// `(name, value)` pairs in `attributes`, per identity.
+    attribute_index: LookupMap<String, OrderedSet<(String, Vec<u8>)>>,
+    /// Unrevoked `(identity, delegate_type)` delegations, per delegate.
+    delegations: LookupMap<String, OrderedSet<(String, String)>>,
+    /// Identities with an entry in `owners`, per owner.
+    owned: LookupMap<String, OrderedSet<String>>,
+    /// Unrevoked values per `(identity, name)`, oldest first.
+    attribute_values: LookupMap<(String, String), Vec<Vec<u8>>>,
+    /// Names with unrevoked values in `attribute_values`, per identity.
+    attribute_names: LookupMap<String, OrderedSet<String>>,
+    /// Number of unrevoked entries in `delegates`, per identity.
+    delegate_counts: LookupMap<String, u64>,
+    /// Number of unrevoked delegates of each type in use, per identity.
+    delegate_types: LookupMap<String, Vec<(String, u32)>>,
+    /// Number of unrevoked entries in `attributes`, per identity.
+    attribute_counts: LookupMap<String, u64>,
+    /// Block heights the identity changed at, by sequence number.
+    history: LookupMap<(String, u64), u64>,
+    /// Sequence numbers of the oldest kept and the next change height.
+    history_bounds: LookupMap<String, (u64, u64)>,
+    history_limit: u64,
+    /// Block timestamp in unix seconds of the change recorded in `changed`.
+    changed_timestamps: LookupMap<String, u64>,
+    created: LookupMap<String, CreatedInfo>,
+    /// Owner changes of the identity, by sequence number.
+    owner_history: LookupMap<(String, u64), OwnerChange>,
+    owner_history_bounds: LookupMap<String, (u64, u64)>,
+    /// Writes of each change height of the identity, by sequence number.
+    snapshots: LookupMap<(String, u64), Snapshot>,
+    snapshot_bounds: LookupMap<String, (u64, u64)>,
+    /// Fragment number of each delegate, allocated from `fragment_counts`
+    /// when first added and kept after revocation.
+    delegate_fragments: LookupMap<(String, String, String), u32>,
+    /// Fragment number of each key attribute, as for delegates.
+    attribute_fragments: LookupMap<(String, String, Vec<u8>), u32>,
+    /// Last fragment number allocated, per identity.
+    fragment_counts: LookupMap<String, u32>,
+    /// Block height each deactivated identity was deactivated at.
+    deactivated: LookupMap<String, u64>,
+    /// Account managing each identity's delegates and attributes besides
+    /// its owner, see `set_controller`.
+    controllers: LookupMap<String, AccountId>,
+    /// Block height each locked identity was locked at, see
+    /// `lock_identity`.
+    locked: LookupMap<String, u64>,
+    /// Owner change each identity's owner scheduled for a set time, see
+    /// `schedule_transfer_at`.
+    scheduled_transfers: LookupMap<String, PendingOwnerChange>,
+    /// Registry each migrated identity was imported by, see
+    /// `export_identity`.
+    migrated: LookupMap<String, AccountId>,
+    /// Registries `import_identity` accepts identities from.
+    trusted_registries: LookupMap<AccountId, ()>,
+    /// Owner contract each identity's requests are authorized by, see
+    /// `set_contract_authorization`.
+    authorizing_contracts: LookupMap<String, AccountId>,
+    /// Minimum interval between owner changes, in seconds, of each identity
+    /// that set one, see `set_owner_change_cooldown`.
+    owner_change_cooldowns: LookupMap<String, u64>,
+    /// Account each identity may be recovered by once, see
+    /// `set_recovery_address`.
+    recovery_addresses: LookupMap<String, AccountId>,
+    /// Accounts approved to make some of each identity's delegate and
+    /// attribute writes, with their permissions, see `approve_operator`.
+    operators: LookupMap<String, Vec<(AccountId, Permissions)>>,
+    /// Account each identity is lent to, see `grant_temporary_ownership`.
+    temporary_owners: LookupMap<String, TemporaryOwner>,
+    /// Policy of each identity that set one, see `set_policy`.
+    policies: LookupMap<String, IdentityPolicy>,
+    /// Account each identity's owner proposed to hand it over to, see
+    /// `propose_owner`.
+    proposed_owners: LookupMap<String, AccountId>,
+    /// Owner change each identity's owner scheduled, see
+    /// `schedule_owner_change`.
+    pending_owner_changes: LookupMap<String, PendingOwnerChange>,
+    recovery_configs: LookupMap<String, RecoveryConfig>,
+    /// Recovery underway for each identity, see `initiate_recovery`.
+    recoveries: LookupMap<String, PendingRecovery>,
+    successors: LookupMap<String, InactivitySuccessor>,
+    /// Unix seconds of the last write the owner authorized, directly or by
+    /// signature, per identity.
+    last_activity: LookupMap<String, u64>,
+    stats: RegistryStats,
+    /// Every identity written at least once.
+    identities: OrderedSet<String>,
+}
+
+/// State of a registry deployed before nonces were kept per signer, read
+/// by `migrate`. Its maps keep their storage prefixes in `NearDIDRegistry`,
+/// `nonce` as `legacy_nonce`.
+#[cfg(feature = "contract")]
+#[near(serializers = [borsh])]
+struct LegacyRegistry {
     owners: LookupMap<String, String>,
     delegates: LookupMap<(String, String, String), u64>,
     attributes: LookupMap<(String, String, Vec<u8>), u64>,
@@ -10,6 +677,7 @@ pub struct NearDIDRegistry {
     nonce: LookupMap<String, u64>,
 }
 
+#[cfg(feature = "contract")]
 impl Default for NearDIDRegistry {
     fn default() -> Self {
         Self {
@@ -17,269 +685,8272 @@ impl Default for NearDIDRegistry {
             delegates: LookupMap::new(b"d"),
             attributes: LookupMap::new(b"a"),
             changed: LookupMap::new(b"c"),
-            nonce: LookupMap::new(b"n"),
+            nonce: LookupMap::new(b"m"),
+            legacy_nonce: LookupMap::new(b"n"),
+            owner_keys: LookupMap::new(b"k"),
+            relayed: LookupMap::new(b"r"),
+            thresholds: LookupMap::new(b"t"),
+            signing_keys: LookupMap::new(b"s"),
+            registry_salt: env::random_seed_array(),
+            observers: LookupMap::new(b"w"),
+            delegate_index: LookupMap::new(b"l"),
+            attribute_index: LookupMap::new(b"i"),
+            delegations: LookupMap::new(b"g"),
+            owned: LookupMap::new(b"x"),
+            attribute_values: LookupMap::new(b"v"),
+            attribute_names: LookupMap::new(b"N"),
+            delegate_counts: LookupMap::new(b"e"),
+            delegate_types: LookupMap::new(b"y"),
+            attribute_counts: LookupMap::new(b"f"),
+            history: LookupMap::new(b"h"),
+            history_bounds: LookupMap::new(b"b"),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            changed_timestamps: LookupMap::new(b"u"),
+            created: LookupMap::new(b"p"),
+            owner_history: LookupMap::new(b"q"),
+            owner_history_bounds: LookupMap::new(b"j"),
+            snapshots: LookupMap::new(b"S"),
+            snapshot_bounds: LookupMap::new(b"B"),
+            delegate_fragments: LookupMap::new(b"F"),
+            attribute_fragments: LookupMap::new(b"A"),
+            fragment_counts: LookupMap::new(b"C"),
+            deactivated: LookupMap::new(b"D"),
+            controllers: LookupMap::new(b"K"),
+            locked: LookupMap::new(b"O"),
+            scheduled_transfers: LookupMap::new(b"V"),
+            migrated: LookupMap::new(b"J"),
+            trusted_registries: LookupMap::new(b"W"),
+            authorizing_contracts: LookupMap::new(b"Z"),
+            owner_change_cooldowns: LookupMap::new(b"0"),
+            recovery_addresses: LookupMap::new(b"1"),
+            operators: LookupMap::new(b"2"),
+            temporary_owners: LookupMap::new(b"U"),
+            policies: LookupMap::new(b"Q"),
+            proposed_owners: LookupMap::new(b"P"),
+            pending_owner_changes: LookupMap::new(b"T"),
+            recovery_configs: LookupMap::new(b"R"),
+            recoveries: LookupMap::new(b"E"),
+            successors: LookupMap::new(b"H"),
+            last_activity: LookupMap::new(b"Y"),
+            stats: RegistryStats::default(),
+            identities: OrderedSet::new(b"z".to_vec()),
+        }
+    }
+}
+
+#[cfg(feature = "contract")]
+#[near]
+impl NearDIDRegistry {
+    fn domain(&self) -> payload::Domain {
+        payload::Domain { contract: env::current_account_id(), salt: self.registry_salt }
+    }
+
+    /// Checks that `actor` may write the identity's delegates and
+    /// attributes: it is the owner or the controller. Behind
+    /// `authorize_write`.
+    fn authorize(&self, identity: &str, actor: &str) -> Result<(), RegistryError> {
+        match self.authorize_owner(identity, actor) {
+            Err(RegistryError::BadActor) if self.controllers.get(identity).is_some_and(|controller| controller.as_str() == actor) => self.check_unlocked(identity),
+            result => result,
+        }
+    }
+
+    /// `authorize` for one kind of delegate or attribute write, which
+    /// approved operators whose permissions are `granted` it may make too,
+    /// see `approve_operator`.
+    fn authorize_write(&self, identity: &str, actor: &str, granted: fn(&Permissions) -> bool) -> Result<(), RegistryError> {
+        match self.authorize(identity, actor) {
+            Err(RegistryError::BadActor)
+                if self.operators.get(identity).is_some_and(|operators| operators.iter().any(|(operator, permissions)| operator.as_str() == actor && granted(permissions))) =>
+            {
+                self.check_unlocked(identity)
+            }
+            result => result,
+        }
+    }
+
+    /// Checks that `actor` is the identity's owner, for the writes the
+    /// controller may not make, behind `assert_only_owner`.
+    fn authorize_owner(&self, identity: &str, actor: &str) -> Result<(), RegistryError> {
+        self.authorize_as(identity, actor, &self.owner_of(identity))
+    }
+
+    /// `authorize_owner` for the writes that outlast a temporary owner's
+    /// grant, which only the permanent owner makes, see
+    /// `grant_temporary_ownership`.
+    fn authorize_permanent_owner(&self, identity: &str, actor: &str) -> Result<(), RegistryError> {
+        self.authorize_as(identity, actor, &self.permanent_owner(identity))
+    }
+
+    fn authorize_as(&self, identity: &str, actor: &str, owner: &str) -> Result<(), RegistryError> {
+        if !self.is_registered(identity) {
+            Err(RegistryError::UnregisteredIdentity)
+        } else if self.deactivated.contains_key(identity) {
+            Err(RegistryError::IdentityDeactivated)
+        } else if self.migrated.contains_key(identity) {
+            Err(RegistryError::IdentityMigrated)
+        } else if owner == RENOUNCED_OWNER {
+            Err(RegistryError::OwnershipRenounced)
+        } else if actor != owner {
+            Err(RegistryError::BadActor)
+        } else {
+            self.check_unlocked(identity)
+        }
+    }
+
+    /// Fails with `identity_locked` while the identity is locked, see
+    /// `lock_identity`. Checked after the actor, so that only the owner
+    /// learns of the lock and can still lift it.
+    fn check_unlocked(&self, identity: &str) -> Result<(), RegistryError> {
+        if self.locked.contains_key(identity) {
+            Err(RegistryError::IdentityLocked)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that `actor` may change the identity's owner without a
+    /// multisig, behind both `change_owner` and `can_change_owner`.
+    fn authorize_owner_change(&self, identity: &str, actor: &str) -> Result<(), RegistryError> {
+        self.authorize_permanent_owner(identity, actor)?;
+        if self.thresholds.contains_key(identity) {
+            return Err(RegistryError::MultisigRequired);
+        }
+        self.check_owner_change_cooldown(identity)
+    }
+
+    /// Fails with `owner_change_cooldown` until the identity's cooldown,
+    /// see `set_owner_change_cooldown`, has passed since its last owner
+    /// change.
+    fn check_owner_change_cooldown(&self, identity: &str) -> Result<(), RegistryError> {
+        let Some(&cooldown_secs) = self.owner_change_cooldowns.get(identity) else {
+            return Ok(());
+        };
+        let last_change = self.owner_history_bounds.get(identity).and_then(|&(_, end)| self.owner_history.get(&(identity.to_string(), end - 1)));
+        if last_change.is_some_and(|change| now_secs() < change.timestamp.saturating_add(cooldown_secs)) {
+            Err(RegistryError::OwnerChangeCooldown)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fails unless `actor` is the identity's owner, see `authorize_owner`,
+    /// and records the owner's activity for
+    /// `claim_ownership_after_inactivity`.
+    fn assert_only_owner(&mut self, identity: &str, actor: &str) {
+        self.settle_transfer(identity);
+        self.authorize_owner(identity, actor).unwrap_or_else(|error| error.panic());
+        self.last_activity.insert(identity.to_string(), now_secs());
+    }
+
+    /// `assert_only_owner` for the permanent owner, see
+    /// `authorize_permanent_owner`.
+    fn assert_permanent_owner(&mut self, identity: &str, actor: &str) {
+        self.settle_transfer(identity);
+        self.authorize_permanent_owner(identity, actor).unwrap_or_else(|error| error.panic());
+        self.last_activity.insert(identity.to_string(), now_secs());
+    }
+
+    /// `assert_only_owner` for delegate and attribute writes, which the
+    /// controller and operators `granted` them may make too, see
+    /// `authorize_write`; their writes are not the owner's activity.
+    fn assert_can_manage(&mut self, identity: &str, actor: &str, granted: fn(&Permissions) -> bool) {
+        self.settle_transfer(identity);
+        self.authorize_write(identity, actor, granted).unwrap_or_else(|error| error.panic());
+        if actor == self.owner_of(identity) {
+            self.last_activity.insert(identity.to_string(), now_secs());
+        }
+    }
+
+    /// `assert_only_owner` for owner changes, see `authorize_owner_change`.
+    fn assert_owner_change(&mut self, identity: &str, actor: &str) {
+        self.settle_transfer(identity);
+        self.authorize_owner_change(identity, actor).unwrap_or_else(|error| error.panic());
+        self.last_activity.insert(identity.to_string(), now_secs());
+    }
+
+    /// Fails with `identity_deactivated` once the identity is deactivated,
+    /// with `identity_migrated` once it was migrated, with
+    /// `ownership_renounced` once its ownership was renounced and with
+    /// `identity_locked` while it is locked.
+    fn assert_active(&self, identity: &str) {
+        ensure(!self.deactivated.contains_key(identity), RegistryError::IdentityDeactivated);
+        ensure(!self.migrated.contains_key(identity), RegistryError::IdentityMigrated);
+        ensure(self.owner_of(identity) != RENOUNCED_OWNER, RegistryError::OwnershipRenounced);
+        self.check_unlocked(identity).unwrap_or_else(|error| error.panic());
+    }
+
+    /// Returns the ed25519 key that signs on behalf of `owner`: the ed25519 key
+    /// the owner registered with `set_owner_key`, the key of an implicit
+    /// account, or the owner string itself when it is a base58-encoded key.
+    fn owner_key(&self, owner: &str) -> Option<[u8; 32]> {
+        if owner == RENOUNCED_OWNER {
+            return None;
+        }
+        match self.owner_keys.get(owner).filter(|public_key| public_key.curve_type() == CurveType::ED25519) {
+            Some(public_key) => public_key.as_bytes()[1..].try_into().ok(),
+            None => signature::implicit_account_key(owner).or_else(|| bs58::decode(owner).into_vec().ok()?.try_into().ok()),
+        }
+    }
+
+    /// Whether the recovered secp256k1 key signs on behalf of `owner`: it is
+    /// the key the owner registered, or `owner` is its Ethereum address.
+    fn owner_secp256k1_key(&self, owner: &str, key: &[u8; 64]) -> bool {
+        match self.owner_keys.get(owner).filter(|public_key| public_key.curve_type() == CurveType::SECP256K1) {
+            Some(public_key) => &public_key.as_bytes()[1..] == key,
+            None => owner.eq_ignore_ascii_case(&signature::eth_address(key)),
+        }
+    }
+
+    /// The owner key that made `signature` over `payload`. Fails with
+    /// `NoOwnerKey` when the scheme needs an ed25519 key and the owner has none.
+    fn verify_owner(&self, owner: &str, signature: &Signature, payload: &[u8]) -> Result<PublicKey, RegistryError> {
+        let ed25519_key = || self.owner_key(owner).ok_or(RegistryError::NoOwnerKey);
+        let (curve, key) = match signature {
+            Signature::Ed25519(signature) => {
+                let key = ed25519_key()?;
+                (signature::verify_ed25519(signature, payload, &key).then_some(CurveType::ED25519), key.to_vec())
+            }
+            Signature::Secp256k1(signature) => match signature::recover_secp256k1(signature, payload) {
+                Some(key) if self.owner_secp256k1_key(owner, &key) => (Some(CurveType::SECP256K1), key.to_vec()),
+                _ => (None, vec![]),
+            },
+            Signature::Nep413 { signature, nonce } => {
+                let key = ed25519_key()?;
+                let hash = signature::nep413_hash(payload, *nonce, &env::current_account_id());
+                (signature::verify_ed25519(signature, &hash, &key).then_some(CurveType::ED25519), key.to_vec())
+            }
+        };
+        curve.and_then(|curve| PublicKey::from_parts(curve, key).ok()).ok_or(RegistryError::BadSignature)
+    }
+
+    /// Verifies `signature` against the identity owner's key and, when
+    /// `signing_keys` is set, the identity's registered signing keys. Each
+    /// candidate signer is checked against `payload(self, Some(signer))`,
+    /// built at that signer's nonce (`None` is the owner). Consumes the
+    /// signer's nonce, records the relayer that submitted `operation` and
+    /// returns the owner on whose behalf the signer acts.
+    /// Payloads are accepted up to and including `valid_until_timestamp`
+    /// (unix seconds); expired ones are rejected before the nonce is read.
+    fn check_signature(
+        &mut self,
+        identity: &str,
+        operation: &str,
+        signature: &Signature,
+        valid_until_timestamp: u64,
+        signing_keys: bool,
+        payload: impl Fn(&Self, Option<String>) -> Vec<u8>,
+    ) -> String {
+        self.settle_transfer(identity);
+        self.assert_active(identity);
+        ensure(now_secs() <= valid_until_timestamp, RegistryError::SignatureExpired);
+
+        let owner = self.owner_of(identity);
+        let (signer, public_key) = match self.verify_owner(&owner, signature, &payload(self, None)) {
+            Ok(public_key) => (owner.clone(), public_key),
+            Err(error) => signing_keys
+                .then(|| {
+                    self.signing_keys.get(identity).into_iter().flatten().find_map(|key| {
+                        let signer = String::from(key);
+                        signature::verify_with_key(signature, &payload(self, Some(signer.clone())), key).then(|| (signer, key.clone()))
+                    })
+                })
+                .flatten()
+                .unwrap_or_else(|| error.panic()),
+        };
+
+        let nonce = self.get_nonce(identity.to_string(), signer.clone());
+        self.consume_nonce(identity, &signer);
+        self.record_relayed(identity, operation, &signer, vec![public_key], nonce);
+        owner
+    }
+
+    /// Stores the receipt of a signed operation and logs it as a
+    /// `did_signed_operation_executed` event naming the `public_keys` that
+    /// signed.
+    fn record_relayed(&mut self, identity: &str, operation: &str, signer: &str, public_keys: Vec<PublicKey>, nonce: u64) {
+        self.last_activity.insert(identity.to_string(), now_secs());
+        let receipt = RelayedOp {
+            operation: operation.to_string(),
+            signer: signer.to_string(),
+            relayer: env::predecessor_account_id(),
+            nonce,
+            block_height: env::block_height(),
+        };
+        DidEvent::from(SignedOperationExecuted {
+            identity: identity.to_string(),
+            operation: receipt.operation.clone(),
+            signer: receipt.signer.clone(),
+            public_keys,
+            nonce,
+            relayer: receipt.relayer.clone(),
+        })
+        .emit();
+        self.relayed.insert(identity.to_string(), receipt);
+    }
+
+    fn consume_nonce(&mut self, identity: &str, signer: &str) {
+        let n = self.get_nonce(identity.to_string(), signer.to_string());
+        if self.owner_of(identity) == signer {
+            self.legacy_nonce.remove(identity);
+        }
+        self.nonce.insert((identity.to_string(), signer.to_string()), n + 1);
+    }
+
+    /// Nonce the payload views sign with: `signer`'s, defaulting to the
+    /// identity's current owner.
+    fn payload_nonce(&self, identity: &str, signer: Option<String>) -> u64 {
+        let signer = signer.unwrap_or_else(|| self.owner_of(identity));
+        self.get_nonce(identity.to_string(), signer)
+    }
+
+    /// Registers the ed25519 or secp256k1 key used to verify signed operations
+    /// for identities owned by the caller.
+    pub fn set_owner_key(&mut self, public_key: PublicKey) {
+        ensure(matches!(public_key.curve_type(), CurveType::ED25519 | CurveType::SECP256K1), RegistryError::BadKey);
+        self.owner_keys.insert(env::predecessor_account_id().to_string(), public_key);
+    }
+
+    /// Registers a hot key that may sign relayed operations for the identity
+    /// other than owner changes. Dropped, with the other keys, when the
+    /// owner changes. Only callable by the owner.
+    pub fn set_signing_key(&mut self, identity: String, public_key: PublicKey) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+        ensure(matches!(public_key.curve_type(), CurveType::ED25519 | CurveType::SECP256K1), RegistryError::BadKey);
+
+        let mut keys = self.signing_keys.get(&identity).cloned().unwrap_or_default();
+        if !keys.contains(&public_key) {
+            ensure(keys.len() < MAX_SIGNING_KEYS, RegistryError::TooManyKeys);
+            keys.push(public_key);
+        }
+        self.signing_keys.insert(identity, keys);
+    }
+
+    /// Unregisters a signing key; its signatures stop being accepted at once.
+    /// Only callable by the owner.
+    pub fn remove_signing_key(&mut self, identity: String, public_key: PublicKey) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let mut keys = self.signing_keys.get(&identity).cloned().unwrap_or_default();
+        let len = keys.len();
+        keys.retain(|key| key != &public_key);
+        ensure(keys.len() < len, RegistryError::UnknownKey);
+        if keys.is_empty() {
+            self.signing_keys.remove(&identity);
+        } else {
+            self.signing_keys.insert(identity, keys);
+        }
+    }
+
+    pub fn get_signing_keys(&self, identity: String) -> Vec<PublicKey> {
+        let identity = normalize_did(identity);
+        self.signing_keys.get(&identity).cloned().unwrap_or_default()
+    }
+
+    /// Registers a contract to be called with `on_did_changed(identity,
+    /// change_kind, block_height)` after each change to the identity. Only
+    /// callable by the permanent owner.
+    pub fn register_observer(&mut self, identity: String, contract_id: AccountId) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let mut observers = self.observers.get(&identity).cloned().unwrap_or_default();
+        if !observers.contains(&contract_id) {
+            ensure(observers.len() < MAX_OBSERVERS, RegistryError::TooManyObservers);
+            observers.push(contract_id);
+        }
+        self.observers.insert(identity, observers);
+    }
+
+    /// Stops notifying `contract_id`. Only callable by the permanent owner.
+    pub fn unregister_observer(&mut self, identity: String, contract_id: AccountId) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let mut observers = self.observers.get(&identity).cloned().unwrap_or_default();
+        let len = observers.len();
+        observers.retain(|observer| observer != &contract_id);
+        ensure(observers.len() < len, RegistryError::UnknownObserver);
+        if observers.is_empty() {
+            self.observers.remove(&identity);
+        } else {
+            self.observers.insert(identity, observers);
+        }
+    }
+
+    pub fn get_observers(&self, identity: String) -> Vec<AccountId> {
+        let identity = normalize_did(identity);
+        self.observers.get(&identity).cloned().unwrap_or_default()
+    }
+
+    /// Calls `on_did_changed` on the identity's observers with
+    /// `OBSERVER_GAS` each. The calls are separate receipts with no callback,
+    /// so an observer failing never reverts the change.
+    fn notify_observers(&self, identity: &str, change_kind: ChangeKind) {
+        let args = serde_json::json!({ "identity": identity, "change_kind": change_kind, "block_height": env::block_height() }).to_string().into_bytes();
+        for observer in self.observers.get(identity).into_iter().flatten() {
+            Promise::new(observer.clone()).function_call("on_did_changed".to_string(), args.clone(), NearToken::from_yoctonear(0), OBSERVER_GAS);
+        }
+    }
+
+    /// Names, versions and field types of the events the registry logs,
+    /// derived from the event structs themselves.
+    pub fn get_event_schema(&self) -> serde_json::Value {
+        events::event_schema()
+    }
+
+    /// The current time as the registry sees it: the block timestamp in unix
+    /// seconds, against which expiries and `valid_until_timestamp`s are
+    /// checked.
+    pub fn get_current_time(&self) -> u64 {
+        now_secs()
+    }
+
+    /// Salt bound into every signed payload. Drawn from the block's random
+    /// seed when the registry state is created, or upgraded by `migrate`.
+    pub fn get_registry_salt(&self) -> [u8; 32] {
+        self.registry_salt
+    }
+
+    /// Upgrades the state of a registry deployed before nonces were kept per
+    /// signer, see `LegacyRegistry`. Owners, delegates, attributes and
+    /// change heights stay where they are and each identity's nonce becomes
+    /// its owner's, see `get_nonce`; everything else starts empty and the
+    /// registry salt is drawn anew, so payloads signed for the old
+    /// deployment no longer verify. Entries written before the upgrade
+    /// keep validating, and are listed, counted and resolved once indexed
+    /// with `migrate_entries`. Fails with `no_legacy_state` without a state
+    /// to upgrade. Only callable by the contract.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let legacy: LegacyRegistry = env::state_read().unwrap_or_else(|| RegistryError::NoLegacyState.panic());
+        Self {
+            owners: legacy.owners,
+            delegates: legacy.delegates,
+            attributes: legacy.attributes,
+            changed: legacy.changed,
+            legacy_nonce: legacy.nonce,
+            ..Self::default()
+        }
+    }
+
+    /// Indexes the identity's `delegates` and `attributes` written before
+    /// `migrate`, at most `MAX_MIGRATE_ENTRIES` of them, as their writes
+    /// would have: the identity is listed, its owner's `owned` and its
+    /// `created` recorded, and each entry still stored is listed, counted
+    /// and resolved in the order given. The old maps cannot be iterated, so
+    /// the entries are read off-chain, e.g. from an indexer of the old
+    /// deployment.
+    /// Entries already indexed, by an earlier call or a write since the
+    /// upgrade, and ones not stored are skipped, so an interrupted migration
+    /// is resumed by calling again and larger identities are migrated over
+    /// several calls. Nothing is logged and `changed` is kept. Run before
+    /// the identity is written again, so that its counts stay right. Only
+    /// callable by the contract.
+    #[private]
+    pub fn migrate_entries(&mut self, identity: String, delegates: Vec<(String, String)>, attributes: Vec<(String, Vec<u8>)>) {
+        let identity = normalize_did(identity);
+        ensure(delegates.len() + attributes.len() <= MAX_MIGRATE_ENTRIES, RegistryError::BatchTooLarge);
+        if !self.identities.contains(&identity) {
+            self.stats.identities_touched += 1;
+            self.identities.insert(identity.clone());
+            self.created.insert(identity.clone(), CreatedInfo { block_height: env::block_height(), timestamp: now_secs() });
+        }
+        if let Some(owner) = self.owners.get(&identity).cloned() {
+            self.owned.entry(owner.clone()).or_insert_with(|| OrderedSet::new(index_prefix(b"X", &owner))).insert(identity.clone());
+        }
+        for (delegate_type, delegate) in delegates {
+            let key = (identity.clone(), delegate_type.clone(), delegate.clone());
+            let indexed = self.delegate_index.get(&identity).is_some_and(|index| index.contains(&(delegate_type.clone(), delegate.clone())));
+            let Some(&valid_to) = self.delegates.get(&key).filter(|_| !indexed) else {
+                continue;
+            };
+            if valid_to != 0 {
+                let delegation = (identity.clone(), delegate_type.clone());
+                let delegations = self.delegations.entry(delegate.clone()).or_insert_with(|| OrderedSet::new(index_prefix(b"G", &delegate)));
+                ensure(delegations.contains(&delegation) || (delegations.len() as usize) < MAX_DELEGATIONS, RegistryError::TooManyDelegations);
+                delegations.insert(delegation);
+                let fragment = self.next_fragment(&identity);
+                self.delegate_fragments.insert(key, fragment);
+                update_count(&mut self.delegate_counts, &mut self.stats.total_delegates, &identity, false, true);
+                self.update_type_count(&identity, &delegate_type, false, true);
+            }
+            self.delegate_index.entry(identity.clone()).or_insert_with(|| OrderedSet::new(index_prefix(b"L", &identity))).insert((delegate_type, delegate));
+        }
+        for (name, value) in attributes {
+            let key = (identity.clone(), name.clone(), value.clone());
+            let indexed = self.attribute_index.get(&identity).is_some_and(|index| index.contains(&(name.clone(), value.clone())));
+            let Some(&valid_to) = self.attributes.get(&key).filter(|_| !indexed) else {
+                continue;
+            };
+            if valid_to != 0 {
+                let values_key = (identity.clone(), name.clone());
+                let mut values = self.attribute_values.get(&values_key).cloned().unwrap_or_default();
+                ensure(values.len() < MAX_ATTRIBUTE_VALUES, RegistryError::TooManyValues);
+                values.push(value.clone());
+                self.write_attribute_values(values_key, values);
+                if matches!(attribute_name::parse(&name), Some(ParsedAttributeName::PublicKey { .. })) {
+                    let fragment = self.next_fragment(&identity);
+                    self.attribute_fragments.insert(key, fragment);
+                }
+                update_count(&mut self.attribute_counts, &mut self.stats.total_attributes, &identity, false, true);
+            }
+            self.attribute_index.entry(identity.clone()).or_insert_with(|| OrderedSet::new(index_prefix(b"I", &identity))).insert((name, value));
+        }
+    }
+
+    /// Re-salts the registry, invalidating every payload signed so far. Run
+    /// when migrating or resetting state. Only callable by the contract.
+    #[private]
+    pub fn rotate_registry_salt(&mut self) {
+        self.registry_salt = env::sha256_array(&[&self.registry_salt[..], &env::random_seed_array()].concat());
+    }
+
+    /// Adds `change` to the identity's latest snapshot, see
+    /// `resolve_at_version`.
+    fn record_write(&mut self, identity: &str, change: RecordChange) {
+        let Some(&(_, end)) = self.snapshot_bounds.get(identity) else {
+            return;
+        };
+        let Some(snapshot) = self.snapshots.get_mut(&(identity.to_string(), end - 1)) else {
+            return;
+        };
+        if snapshot.writes.len() < MAX_SNAPSHOT_WRITES {
+            snapshot.writes.push((env::block_height(), change));
+        } else if snapshot.complete {
+            snapshot.writes.clear();
+            snapshot.complete = false;
+        }
+    }
+
+    /// Allocates the identity's next fragment number, see `get_fragment`.
+    fn next_fragment(&mut self, identity: &str) -> u32 {
+        let fragment = self.fragment_counts.get(identity).map_or(1, |count| count + 1);
+        self.fragment_counts.insert(identity.to_string(), fragment);
+        fragment
+    }
+
+    fn write_delegate(&mut self, identity: &str, delegate_type: String, delegate: String, valid_to: u64, previous_change: u64, memo: Option<String>) {
+        let delegation = (identity.to_string(), delegate_type.clone());
+        if valid_to == 0 {
+            self.remove_delegation(&delegate, &delegation);
+        } else {
+            let delegations = self.delegations.entry(delegate.clone()).or_insert_with(|| OrderedSet::new(index_prefix(b"G", &delegate)));
+            ensure(delegations.contains(&delegation) || (delegations.len() as usize) < MAX_DELEGATIONS, RegistryError::TooManyDelegations);
+            delegations.insert(delegation);
+        }
+        let key = (identity.to_string(), delegate_type.clone(), delegate.clone());
+        if valid_to != 0 && !self.delegate_fragments.contains_key(&key) {
+            let fragment = self.next_fragment(identity);
+            self.delegate_fragments.insert(key.clone(), fragment);
+        }
+        let previous = self.delegates.insert(key, valid_to);
+        self.record_write(identity, RecordChange::Delegate { delegate_type: delegate_type.clone(), delegate: delegate.clone(), previous });
+        update_count(&mut self.delegate_counts, &mut self.stats.total_delegates, identity, previous.is_some_and(|valid_to| valid_to != 0), valid_to != 0);
+        self.update_type_count(identity, &delegate_type, previous.is_some_and(|valid_to| valid_to != 0), valid_to != 0);
+        self.delegate_index
+            .entry(identity.to_string())
+            .or_insert_with(|| OrderedSet::new(index_prefix(b"L", identity)))
+            .insert((delegate_type.clone(), delegate.clone()));
+        #[cfg(feature = "erc1056-events")]
+        let erc1056 = events::erc1056::DidDelegateChanged::new(identity.to_string(), &delegate_type, delegate.clone(), valid_to, previous_change);
+        DidEvent::from(DelegateChanged {
+            identity: identity.to_string(),
+            delegate_type,
+            delegate,
+            valid_to,
+            changed: env::block_height(),
+            previous_change,
+            memo,
+        })
+        .emit();
+        #[cfg(feature = "erc1056-events")]
+        events::erc1056::Erc1056Event::DelegateChanged(erc1056).emit();
+    }
+
+    fn write_attribute(&mut self, identity: &str, name: String, value: Vec<u8>, valid_to: u64, previous_change: u64, memo: Option<String>) {
+        let event = AttributeChanged::new(identity.to_string(), name.clone(), &value, valid_to, env::block_height(), previous_change, memo);
+        #[cfg(feature = "erc1056-events")]
+        let erc1056 = events::erc1056::DidAttributeChanged::new(identity.to_string(), &name, &value, valid_to, previous_change);
+        let key = (identity.to_string(), name.clone());
+        let mut values = self.attribute_values.get(&key).cloned().unwrap_or_default();
+        values.retain(|stored| stored != &value);
+        if valid_to != 0 {
+            ensure(values.len() < MAX_ATTRIBUTE_VALUES, RegistryError::TooManyValues);
+            values.push(value.clone());
+        }
+        self.write_attribute_values(key, values);
+        let key = (identity.to_string(), name.clone(), value.clone());
+        if valid_to != 0 && matches!(attribute_name::parse(&name), Some(ParsedAttributeName::PublicKey { .. })) && !self.attribute_fragments.contains_key(&key) {
+            let fragment = self.next_fragment(identity);
+            self.attribute_fragments.insert(key.clone(), fragment);
+        }
+        let previous = self.attributes.insert(key, valid_to);
+        self.record_write(identity, RecordChange::Attribute { name: name.clone(), value: value.clone(), previous });
+        update_count(&mut self.attribute_counts, &mut self.stats.total_attributes, identity, previous.is_some_and(|valid_to| valid_to != 0), valid_to != 0);
+        self.attribute_index
+            .entry(identity.to_string())
+            .or_insert_with(|| OrderedSet::new(index_prefix(b"I", identity)))
+            .insert((name, value));
+        DidEvent::from(event).emit();
+        #[cfg(feature = "erc1056-events")]
+        events::erc1056::Erc1056Event::AttributeChanged(erc1056).emit();
+    }
+
+    /// Records a write to the identity at this block: `changed`, the change
+    /// history and a snapshot for the block, and on the identity's first
+    /// write `created` and the `did_identity_created` event naming `actor`.
+    /// Returns the height `changed` held before, 0 for the first write.
+    fn touch(&mut self, identity: &str, actor: &str) -> u64 {
+        let previous_change = self.changed.insert(identity.to_string(), env::block_height()).unwrap_or(0);
+        self.changed_timestamps.insert(identity.to_string(), now_secs());
+        if previous_change != env::block_height() {
+            push_history(&mut self.history, &mut self.history_bounds, self.history_limit, identity, env::block_height());
+            let snapshot = Snapshot { block_height: env::block_height(), timestamp: now_secs(), writes: Vec::new(), complete: true };
+            push_history(&mut self.snapshots, &mut self.snapshot_bounds, self.history_limit, identity, snapshot);
+        }
+        if previous_change == 0 {
+            self.stats.identities_touched += 1;
+            self.identities.insert(identity.to_string());
+            self.created.insert(identity.to_string(), CreatedInfo { block_height: env::block_height(), timestamp: now_secs() });
+            DidEvent::from(IdentityCreated { identity: identity.to_string(), actor: actor.to_string() }).emit();
+        }
+        previous_change
+    }
+
+    /// Writes the state change of `op` for `identity`, records `changed` and
+    /// logs the op's event, linked to the identity's previous change. The
+    /// identity's first write, which finds no `changed`, is announced with
+    /// `did_identity_created` naming `actor`. `memo` is carried into the op's
+    /// event only. Callers check authorization and the memo's length.
+    fn apply(&mut self, identity: &str, actor: &str, op: SignedOp, memo: Option<String>) {
+        self.assert_active(identity);
+        self.settle_transfer(identity);
+        let op = match op {
+            SignedOp::ChangeOwner { new_owner } => SignedOp::ChangeOwner { new_owner: normalize_owner(new_owner) },
+            SignedOp::AddDelegate { delegate_type, delegate, validity_secs } => SignedOp::AddDelegate { delegate_type, delegate: normalize_did(delegate), validity_secs },
+            SignedOp::RevokeDelegate { delegate_type, delegate } => SignedOp::RevokeDelegate { delegate_type, delegate: normalize_did(delegate) },
+            op => op,
+        };
+        let previous_change = self.touch(identity, actor);
+        match op {
+            SignedOp::ChangeOwner { new_owner } => {
+                let previous_owner = self.permanent_owner(identity);
+                ensure(new_owner != previous_owner, RegistryError::AlreadyOwner);
+                let revoked = if self.policies.get(identity).is_some_and(|policy| policy.revoke_delegates_on_owner_change) {
+                    let index = self.delegate_index.get(identity);
+                    ensure(index.map_or(0, |index| index.len() as u64) <= MAX_OWNER_CHANGE_REVOCATIONS, RegistryError::DelegatesNotCleared);
+                    let now = now_secs();
+                    index
+                        .into_iter()
+                        .flat_map(|index| index.iter())
+                        .filter(|(delegate_type, delegate)| self.delegates.get(&(identity.to_string(), delegate_type.clone(), delegate.clone())).is_some_and(|valid_to| *valid_to > now))
+                        .cloned()
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                let previous = self.owners.insert(identity.to_string(), new_owner.clone());
+                self.temporary_owners.remove(identity);
+                self.scheduled_transfers.remove(identity);
+                self.proposed_owners.remove(identity);
+                self.pending_owner_changes.remove(identity);
+                self.recoveries.remove(identity);
+                self.successors.remove(identity);
+                self.recovery_addresses.remove(identity);
+                self.controllers.remove(identity);
+                self.operators.remove(identity);
+                self.authorizing_contracts.remove(identity);
+                self.signing_keys.remove(identity);
+                self.thresholds.remove(identity);
+                if previous.is_some() {
+                    self.remove_owned(&previous_owner, identity);
+                }
+                self.record_write(identity, RecordChange::Owner { previous });
+                self.owned.entry(new_owner.clone()).or_insert_with(|| OrderedSet::new(index_prefix(b"X", &new_owner))).insert(identity.to_string());
+                let change = OwnerChange {
+                    previous_owner: previous_owner.clone(),
+                    new_owner: new_owner.clone(),
+                    block_height: env::block_height(),
+                    timestamp: now_secs(),
+                };
+                push_history(&mut self.owner_history, &mut self.owner_history_bounds, self.history_limit, identity, change);
+                self.stats.total_owner_changes += 1;
+                #[cfg(feature = "erc1056-events")]
+                let erc1056 = events::erc1056::DidOwnerChanged { identity: identity.to_string(), owner: new_owner.clone(), previous_change };
+                DidEvent::from(OwnerChanged {
+                    identity: identity.to_string(),
+                    previous_owner,
+                    new_owner,
+                    changed: env::block_height(),
+                    previous_change,
+                    memo,
+                })
+                .emit();
+                #[cfg(feature = "erc1056-events")]
+                events::erc1056::Erc1056Event::OwnerChanged(erc1056).emit();
+                for (delegate_type, delegate) in revoked {
+                    self.write_delegate(identity, delegate_type, delegate, 0, previous_change, None);
+                }
+            }
+            SignedOp::AddDelegate { delegate_type, delegate, validity_secs } => {
+                let valid_until = now_secs() + validity_secs;
+                self.write_delegate(identity, delegate_type, delegate, valid_until, previous_change, memo);
+            }
+            SignedOp::RevokeDelegate { delegate_type, delegate } => {
+                self.write_delegate(identity, delegate_type, delegate, 0, previous_change, memo);
+            }
+            SignedOp::SetAttribute { name, value, validity_secs } => {
+                let valid_until = now_secs() + validity_secs;
+                self.write_attribute(identity, name, value, valid_until, previous_change, memo);
+            }
+            SignedOp::RevokeAttribute { name, value } => {
+                self.write_attribute(identity, name, value, 0, previous_change, memo);
+            }
+        }
+    }
+
+    /// The identity's owner: the one recorded, else, for account and key
+    /// identifiers, the identity itself. Fails with `unregistered_identity`
+    /// for other identifiers never registered, see `register_identity`.
+    pub fn identity_owner(&self, identity: String) -> String {
+        let identity = normalize_did(identity);
+        ensure(self.is_registered(&identity), RegistryError::UnregisteredIdentity);
+        self.owner_of(&identity)
+    }
+
+    /// `identity_owner` without the registration check: an unregistered
+    /// identifier owns itself, which no caller or key can act as.
+    fn owner_of(&self, identity: &str) -> String {
+        if let Some(transfer) = self.due_transfer(identity) {
+            return transfer.new_owner.clone();
+        }
+        match self.temporary_owners.get(identity).filter(|temporary| now_secs() < temporary.expires_at) {
+            Some(temporary) => temporary.temp_owner.to_string(),
+            None => self.permanent_owner(identity),
+        }
+    }
+
+    /// The identity's owner regardless of any temporary owner, see
+    /// `grant_temporary_ownership`.
+    fn permanent_owner(&self, identity: &str) -> String {
+        match self.due_transfer(identity) {
+            Some(transfer) => transfer.new_owner.clone(),
+            None => self.owners.get(identity).cloned().unwrap_or_else(|| identity.to_string()),
+        }
+    }
+
+    /// The identity's transfer scheduled with `schedule_transfer_at`, once
+    /// its time has come, whether or not it was applied yet. Held back while
+    /// the identity is locked, so the lock stays with the owner that set it.
+    fn due_transfer(&self, identity: &str) -> Option<&PendingOwnerChange> {
+        if self.locked.contains_key(identity) {
+            return None;
+        }
+        self.scheduled_transfers.get(identity).filter(|transfer| now_secs() >= transfer.effective_at)
+    }
+
+    /// Applies the identity's due transfer, if any, as `change_owner` would,
+    /// before anything else is written to it. Until then `owner_of` already
+    /// names the new owner.
+    fn settle_transfer(&mut self, identity: &str) {
+        let Some(transfer) = self.due_transfer(identity).cloned() else {
+            return;
+        };
+        self.scheduled_transfers.remove(identity);
+        let owner = self.permanent_owner(identity);
+        self.apply(identity, &owner, SignedOp::ChangeOwner { new_owner: transfer.new_owner }, None);
+        self.notify_observers(identity, ChangeKind::Owner);
+    }
+
+    /// Whether the identity has an owner: it is an account or key
+    /// identifier, or was registered.
+    fn is_registered(&self, identity: &str) -> bool {
+        identifier_kind(identity) != IdentifierKind::Other || self.owners.contains_key(identity)
+    }
+
+    /// Records `owner` as the owner of `identity`, an identifier that is
+    /// neither an account nor a key (see `get_identifier_kind`) and so has
+    /// no implicit owner, for at most `MAX_IDENTIFIER_BYTES` bytes. Needs
+    /// `REGISTRATION_DEPOSIT` attached for the storage; any excess is
+    /// refunded. Logged as the identity's first write, an owner change to
+    /// `owner` made by the caller. Account and key identifiers fail with
+    /// `implicit_identity`, registered ones with `identity_registered`.
+    #[payable]
+    pub fn register_identity(&mut self, identity: String, owner: AccountId) {
+        let identity = normalize_did(identity);
+        ensure(identifier_kind(&identity) == IdentifierKind::Other, RegistryError::ImplicitIdentity);
+        ensure(identity.len() <= MAX_IDENTIFIER_BYTES, RegistryError::BadDid);
+        ensure(!self.owners.contains_key(&identity), RegistryError::IdentityRegistered);
+        let deposit = env::attached_deposit();
+        ensure(deposit >= REGISTRATION_DEPOSIT, RegistryError::InsufficientDeposit);
+
+        let actor = env::predecessor_account_id();
+        self.apply(&identity, actor.as_str(), SignedOp::ChangeOwner { new_owner: owner.to_string() }, None);
+        if deposit > REGISTRATION_DEPOSIT {
+            Promise::new(actor).transfer(deposit.saturating_sub(REGISTRATION_DEPOSIT));
+        }
+    }
+
+    /// How the registry treats `identity`: as a NEAR account or a key, owning
+    /// itself until its owner is changed, or as another identifier, which
+    /// needs `register_identity`.
+    pub fn get_identifier_kind(&self, identity: String) -> IdentifierKind {
+        let identity = normalize_did(identity);
+        identifier_kind(&identity)
+    }
+
+    /// `identity_owner` of each of `identities` (at most `MAX_PAGE_SIZE`), in
+    /// the same order, `None` for the unregistered ones rather than failing
+    /// the call.
+    pub fn identity_owner_many(&self, identities: Vec<String>) -> Vec<Option<String>> {
+        ensure(identities.len() as u64 <= MAX_PAGE_SIZE, RegistryError::TooManyIdentities);
+        identities
+            .into_iter()
+            .map(|identity| {
+                let identity = normalize_did(identity);
+                self.is_registered(&identity).then(|| self.owner_of(&identity))
+            })
+            .collect()
+    }
+
+    fn remove_owned(&mut self, owner: &str, identity: &str) {
+        if let Some(owned) = self.owned.get_mut(owner) {
+            owned.remove(&identity.to_string());
+            if owned.is_empty() {
+                self.owned.remove(owner);
+            }
         }
     }
-}
 
-#[near]
-impl NearDIDRegistry {
-    fn assert_only_owner(&self, identity: &String, actor: &String) {
-        let owner = self.identity_owner(identity.clone());
-        assert_eq!(actor, &owner, "bad_actor");
+    /// Up to `limit` (at most `MAX_PAGE_SIZE`) of the identities `owner` was
+    /// made owner of with a change of ownership, starting at `from_index`.
+    /// Accounts' implicit ownership of themselves is not listed.
+    pub fn identities_owned_by(&self, owner: String, from_index: u64, limit: u64) -> Vec<String> {
+        let owner = normalize_did(owner);
+        let Some(owned) = self.owned.get(&owner) else {
+            return Vec::new();
+        };
+        owned.iter().skip(from_index as usize).take(limit.min(MAX_PAGE_SIZE) as usize).cloned().collect()
+    }
+
+    /// Like `identities_owned_by`, one page after `cursor`.
+    pub fn identities_owned_by_page(&self, owner: String, cursor: Option<String>, limit: u64) -> Page<String> {
+        let owner = normalize_did(owner);
+        let (_, start) = cursor_position(cursor, 1);
+        page(self.owned.get(&owner).map_or((Vec::new(), None), |owned| owned.page(start, limit.min(MAX_PAGE_SIZE) as usize, |identity| Some(identity.clone()))))
+    }
+
+    fn change_owner_by(&mut self, identity: String, actor: String, new_owner: String, memo: Option<String>) {
+        self.assert_owner_change(&identity, &actor);
+
+        self.apply(&identity, &actor, SignedOp::ChangeOwner { new_owner }, memo);
+        self.notify_observers(&identity, ChangeKind::Owner);
+    }
+
+    pub fn change_owner(&mut self, identity: String, new_owner: String, memo: Option<String>) {
+        let identity = normalize_did(identity);
+        let new_owner = normalize_owner(new_owner);
+        assert_memo(&memo);
+        let actor = env::predecessor_account_id().to_string();
+        self.change_owner_by(identity, actor, new_owner, memo);
+    }
+
+    /// Examines up to `limit` (at most `MAX_TRANSFER_ALL`) of the identities
+    /// the caller owns, after `cursor` in `identities_owned_by` order, and
+    /// hands each over to `new_owner` as `change_owner` would. Returns the
+    /// identities moved with the cursor to resume from; call again with it
+    /// until `next_cursor` is `None`. Identities the caller could not hand
+    /// over with `change_owner`, e.g. locked ones or ones behind an owner
+    /// multisig, are skipped and stay listed, but count towards `limit`, so
+    /// every call makes progress within bounded gas.
+    pub fn transfer_all_owned(&mut self, new_owner: String, cursor: Option<String>, limit: u64) -> Page<String> {
+        let new_owner = normalize_owner(new_owner);
+        let actor = env::predecessor_account_id().to_string();
+        if new_owner == actor {
+            return page((Vec::new(), None));
+        }
+
+        let (_, start) = cursor_position(cursor, 1);
+        let (examined, next) = self.owned.get(&actor).map_or((Vec::new(), None), |owned| owned.page(start, limit.min(MAX_TRANSFER_ALL) as usize, |identity| Some(identity.clone())));
+        let mut moved = Vec::new();
+        for identity in examined {
+            if self.authorize_owner_change(&identity, &actor).is_err() {
+                continue;
+            }
+            self.last_activity.insert(identity.clone(), now_secs());
+            self.apply(&identity, &actor, SignedOp::ChangeOwner { new_owner: new_owner.clone() }, None);
+            self.notify_observers(&identity, ChangeKind::Owner);
+            moved.push(identity);
+        }
+        page((moved, next))
+    }
+
+    /// Lets `controller` add and revoke the identity's delegates and
+    /// attributes alongside the owner, e.g. a custodian managing them day to
+    /// day, replacing the previous controller. Everything else, owner
+    /// changes and the controller itself included, stays with the owner.
+    /// Dropped when the owner changes. Only callable by the owner.
+    pub fn set_controller(&mut self, identity: String, controller: AccountId) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        DidEvent::from(ControllerSet { identity: identity.clone(), controller: controller.clone() }).emit();
+        self.controllers.insert(identity, controller);
+    }
+
+    /// Removes the identity's controller. Only callable by the owner.
+    pub fn clear_controller(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        if let Some(controller) = self.controllers.remove(&identity) {
+            DidEvent::from(ControllerCleared { identity, controller }).emit();
+        }
+    }
+
+    pub fn get_controller(&self, identity: String) -> Option<AccountId> {
+        let identity = normalize_did(identity);
+        self.controllers.get(&identity).cloned()
+    }
+
+    /// Approves `operator`, e.g. a backend service refreshing a rotating
+    /// service endpoint, to make the delegate and attribute writes its
+    /// `permissions` allow, and nothing else; approving it again replaces
+    /// them. Up to `MAX_OPERATORS` per identity (else
+    /// `too_many_operators`); dropped when the owner changes and by
+    /// `clear_identity`. Only callable by the permanent owner.
+    pub fn approve_operator(&mut self, identity: String, operator: AccountId, permissions: Permissions) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let mut operators = self.operators.get(&identity).cloned().unwrap_or_default();
+        match operators.iter_mut().find(|(approved, _)| approved == &operator) {
+            Some((_, granted)) => *granted = permissions,
+            None => {
+                ensure(operators.len() < MAX_OPERATORS, RegistryError::TooManyOperators);
+                operators.push((operator.clone(), permissions));
+            }
+        }
+        DidEvent::from(OperatorApproved { identity: identity.clone(), operator, permissions }).emit();
+        self.operators.insert(identity, operators);
+    }
+
+    /// Withdraws the approval of `operator`. Only callable by the permanent
+    /// owner.
+    pub fn revoke_operator(&mut self, identity: String, operator: AccountId) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let mut operators = self.operators.get(&identity).cloned().unwrap_or_default();
+        let len = operators.len();
+        operators.retain(|(approved, _)| approved != &operator);
+        ensure(operators.len() < len, RegistryError::UnknownOperator);
+        DidEvent::from(OperatorRevoked { identity: identity.clone(), operator }).emit();
+        if operators.is_empty() {
+            self.operators.remove(&identity);
+        } else {
+            self.operators.insert(identity, operators);
+        }
+    }
+
+    /// Approved operators with their permissions, in approval order.
+    pub fn list_operators(&self, identity: String) -> Vec<(AccountId, Permissions)> {
+        let identity = normalize_did(identity);
+        self.operators.get(&identity).cloned().unwrap_or_default()
+    }
+
+    /// Locks the identity, e.g. while an incident is investigated: every
+    /// write to it, signed, relayed and owner changes included, fails with
+    /// `identity_locked` until the owner calls `unlock_identity`. Unlike
+    /// `deactivate_identity` it is undone by unlocking, and unlike a
+    /// write it leaves `changed` alone. Since the owner cannot change while
+    /// locked, the lock stays with the owner that set it. Resolution is
+    /// unaffected but for `locked` in the document metadata. Only callable
+    /// by the owner.
+    pub fn lock_identity(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_only_owner(&identity, &actor);
+
+        DidEvent::from(IdentityLocked { identity: identity.clone() }).emit();
+        self.locked.insert(identity, env::block_height());
+    }
+
+    /// Lifts the lock of `lock_identity`. Only callable by the owner.
+    pub fn unlock_identity(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.settle_transfer(&identity);
+        match self.authorize_owner(&identity, &actor) {
+            Ok(()) | Err(RegistryError::IdentityLocked) => {}
+            Err(error) => error.panic(),
+        }
+        self.last_activity.insert(identity.clone(), now_secs());
+
+        if self.locked.remove(&identity).is_some() {
+            DidEvent::from(IdentityUnlocked { identity }).emit();
+        }
+    }
+
+    pub fn is_locked(&self, identity: String) -> bool {
+        let identity = normalize_did(identity);
+        self.locked.contains_key(&identity)
+    }
+
+    /// Hands the identity over to `target_registry`, e.g. a new version of
+    /// this registry, by calling its `import_identity` with the identity's
+    /// owner and valid delegates and attributes, at most
+    /// `MAX_EXPORT_ENTRIES` of them (else `batch_too_large`). Once the
+    /// import succeeded, `on_identity_exported` marks the identity migrated:
+    /// every later write to it here fails with `identity_migrated` and
+    /// `did_identity_migrated` is logged. If the import fails, or the
+    /// identity changed hands or was written to before the callback ran, so
+    /// that the target holds a stale copy, nothing changes here. Only
+    /// callable by the permanent owner.
+    pub fn export_identity(&mut self, identity: String, target_registry: AccountId) -> Promise {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let now = now_secs();
+        let delegates: Vec<_> = self
+            .delegate_index
+            .get(&identity)
+            .into_iter()
+            .flat_map(|index| index.iter())
+            .filter_map(|(delegate_type, delegate)| {
+                let valid_to = *self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone()))?;
+                (valid_to > now).then(|| (delegate_type.clone(), delegate.clone(), valid_to))
+            })
+            .collect();
+        let attributes: Vec<_> = self
+            .attribute_index
+            .get(&identity)
+            .into_iter()
+            .flat_map(|index| index.iter())
+            .filter_map(|(name, value)| {
+                let valid_to = *self.attributes.get(&(identity.clone(), name.clone(), value.clone()))?;
+                (valid_to > now).then(|| (name.clone(), Base64VecU8::from(value.clone()), valid_to))
+            })
+            .collect();
+        ensure(delegates.len() + attributes.len() <= MAX_EXPORT_ENTRIES, RegistryError::BatchTooLarge);
+
+        let owner = self.permanent_owner(&identity);
+        let changed = *self.changed.get(&identity).unwrap_or(&0);
+        let export = IdentityExport { identity: identity.clone(), owner: owner.clone(), delegates, attributes };
+        let args = serde_json::json!({ "export": export }).to_string().into_bytes();
+        Promise::new(target_registry.clone())
+            .function_call("import_identity".to_string(), args, NearToken::from_yoctonear(0), IMPORT_GAS)
+            .then(Self::ext(env::current_account_id()).with_static_gas(EXPORT_CALLBACK_GAS).on_identity_exported(identity, target_registry, owner, changed))
+    }
+
+    /// Marks the identity migrated to `target_registry` if its import there
+    /// succeeded and it still has the `owner` and `changed` height it was
+    /// exported with, see `export_identity`, and returns whether it did.
+    #[private]
+    pub fn on_identity_exported(&mut self, identity: String, target_registry: AccountId, owner: String, changed: u64) -> bool {
+        if !near_sdk::is_promise_success() {
+            return false;
+        }
+        if self.permanent_owner(&identity) != owner || *self.changed.get(&identity).unwrap_or(&0) != changed {
+            return false;
+        }
+        self.migrated.insert(identity.clone(), target_registry.clone());
+        DidEvent::from(IdentityMigrated { identity, target_registry }).emit();
+        true
+    }
+
+    /// Recreates an identity another registry exported with
+    /// `export_identity`: its owner, delegates and attributes with their
+    /// expiries, logged as the identity's first writes, with
+    /// `did_identity_created` naming the source registry. Only accepted from
+    /// registries trusted with `set_trusted_registry`, else
+    /// `untrusted_registry`, and for identities never written here, else
+    /// `identity_registered`.
+    pub fn import_identity(&mut self, export: IdentityExport) {
+        let source = env::predecessor_account_id();
+        ensure(self.trusted_registries.contains_key(&source), RegistryError::UntrustedRegistry);
+        let identity = normalize_did(export.identity);
+        ensure(!self.changed.contains_key(&identity), RegistryError::IdentityRegistered);
+        ensure(export.delegates.len() + export.attributes.len() <= MAX_EXPORT_ENTRIES, RegistryError::BatchTooLarge);
+
+        let owner = normalize_did(export.owner);
+        if owner == identity {
+            self.touch(&identity, source.as_str());
+        } else {
+            self.apply(&identity, source.as_str(), SignedOp::ChangeOwner { new_owner: owner }, None);
+        }
+        for (delegate_type, delegate, valid_to) in export.delegates {
+            self.write_delegate(&identity, delegate_type, normalize_did(delegate), valid_to, 0, None);
+        }
+        for (name, value, valid_to) in export.attributes {
+            self.write_attribute(&identity, name, value.into(), valid_to, 0, None);
+        }
+    }
+
+    /// Registry the identity was migrated to, if any.
+    pub fn get_migration(&self, identity: String) -> Option<AccountId> {
+        let identity = normalize_did(identity);
+        self.migrated.get(&identity).cloned()
+    }
+
+    /// Trusts `registry` as a source of `import_identity` calls, or stops
+    /// trusting it. Only callable by the registry account itself.
+    #[private]
+    pub fn set_trusted_registry(&mut self, registry: AccountId, trusted: bool) {
+        if trusted {
+            self.trusted_registries.insert(registry, ());
+        } else {
+            self.trusted_registries.remove(&registry);
+        }
+    }
+
+    pub fn is_trusted_registry(&self, registry: AccountId) -> bool {
+        self.trusted_registries.contains_key(&registry)
+    }
+
+    /// Checks that `contract` authorizes requests for the identity: it
+    /// enabled them with `set_contract_authorization` and still owns it.
+    /// Owner changes also need it to hold no owner multisig and to be out
+    /// of its cooldown.
+    fn authorize_request(&self, identity: &str, contract: &AccountId, op: &SignedOp) -> Result<(), RegistryError> {
+        if self.authorizing_contracts.get(identity) != Some(contract) {
+            return Err(RegistryError::NoAuthorizingContract);
+        }
+        if matches!(op, SignedOp::ChangeOwner { .. }) {
+            if self.thresholds.contains_key(identity) {
+                return Err(RegistryError::MultisigRequired);
+            }
+            self.check_owner_change_cooldown(identity)?;
+        }
+        self.authorize_as(identity, contract.as_str(), &self.owner_of(identity))
+    }
+
+    /// Makes owner changes, but for social recovery, wait at least
+    /// `cooldown_secs` after the identity's previous one, so that a stolen
+    /// owner key cannot keep flipping ownership, e.g. to grief a recovery.
+    /// Owner changes within the cooldown fail with `owner_change_cooldown`;
+    /// ones scheduled or proposed before it ends take effect when due, since
+    /// any owner change drops them. 0 removes the cooldown. Only callable by
+    /// the permanent owner, and not within an active cooldown.
+    pub fn set_owner_change_cooldown(&mut self, identity: String, cooldown_secs: u64) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+        self.check_owner_change_cooldown(&identity).unwrap_or_else(|error| error.panic());
+
+        DidEvent::from(OwnerChangeCooldownSet { identity: identity.clone(), cooldown_secs }).emit();
+        if cooldown_secs == 0 {
+            self.owner_change_cooldowns.remove(&identity);
+        } else {
+            self.owner_change_cooldowns.insert(identity, cooldown_secs);
+        }
+    }
+
+    pub fn get_owner_change_cooldown(&self, identity: String) -> u64 {
+        let identity = normalize_did(identity);
+        self.owner_change_cooldowns.get(&identity).copied().unwrap_or(0)
+    }
+
+    /// Lets the owning contract, e.g. a DAO, authorize writes others request
+    /// with `request_authorized`, against its own policy, or stops it. Only
+    /// callable by the permanent owner, which must be the contract; dropped
+    /// by an owner change.
+    pub fn set_contract_authorization(&mut self, identity: String, enabled: bool) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id();
+        self.assert_permanent_owner(&identity, actor.as_str());
+
+        DidEvent::from(ContractAuthorizationSet { identity: identity.clone(), contract: actor.clone(), enabled }).emit();
+        if enabled {
+            self.authorizing_contracts.insert(identity, actor);
+        } else {
+            self.authorizing_contracts.remove(&identity);
+        }
+    }
+
+    /// Owner contract that authorizes the identity's requests, if any.
+    pub fn get_authorizing_contract(&self, identity: String) -> Option<AccountId> {
+        let identity = normalize_did(identity);
+        self.authorizing_contracts.get(&identity).cloned()
+    }
+
+    /// Requests `op` on behalf of the caller for an identity whose owner
+    /// contract authorizes requests, see `set_contract_authorization`. The
+    /// registry asks the contract's `is_authorized(identity, actor, action)`,
+    /// `action` being the op's name (`changeOwner`, `addDelegate`, ...), and
+    /// `on_authorized` makes the write, as the owner's, only if it returned
+    /// `true`. Fails with `no_authorizing_contract` unless enabled. The
+    /// caller attaches gas for both calls, `AUTHORIZATION_GAS` and
+    /// `AUTHORIZED_CALLBACK_GAS`.
+    pub fn request_authorized(&mut self, identity: String, op: SignedOp, memo: Option<String>) -> Promise {
+        let identity = normalize_did(identity);
+        assert_memo(&memo);
+        self.settle_transfer(&identity);
+        let contract = self.authorizing_contracts.get(&identity).cloned().unwrap_or_else(|| RegistryError::NoAuthorizingContract.panic());
+        self.authorize_request(&identity, &contract, &op).unwrap_or_else(|error| error.panic());
+
+        let actor = env::predecessor_account_id();
+        let args = serde_json::json!({ "identity": identity, "actor": actor, "action": op.operation() }).to_string().into_bytes();
+        Promise::new(contract.clone())
+            .function_call("is_authorized".to_string(), args, NearToken::from_yoctonear(0), AUTHORIZATION_GAS)
+            .then(Self::ext(env::current_account_id()).with_static_gas(AUTHORIZED_CALLBACK_GAS).on_authorized(identity, contract, actor, op, memo))
+    }
+
+    /// Makes the write of `request_authorized` if `authorizer` authorized it
+    /// and still authorizes the identity's requests, and returns whether it
+    /// did. A failed or refused authorization changes nothing.
+    #[private]
+    pub fn on_authorized(
+        &mut self,
+        identity: String,
+        authorizer: AccountId,
+        actor: AccountId,
+        op: SignedOp,
+        memo: Option<String>,
+        #[callback_result] authorized: Result<bool, PromiseError>,
+    ) -> bool {
+        if !matches!(authorized, Ok(true)) {
+            return false;
+        }
+        self.settle_transfer(&identity);
+        self.authorize_request(&identity, &authorizer, &op).unwrap_or_else(|error| error.panic());
+        self.last_activity.insert(identity.clone(), now_secs());
+
+        let change_kind = match op {
+            SignedOp::ChangeOwner { .. } => ChangeKind::Owner,
+            SignedOp::AddDelegate { .. } | SignedOp::RevokeDelegate { .. } => ChangeKind::Delegate,
+            SignedOp::SetAttribute { .. } | SignedOp::RevokeAttribute { .. } => ChangeKind::Attribute,
+        };
+        self.apply(&identity, actor.as_str(), op, memo);
+        self.notify_observers(&identity, change_kind);
+        true
+    }
+
+    /// Lends the identity to `temp_owner` for `duration_secs`, e.g. to a
+    /// contractor: until then it acts as the owner, in `identity_owner`, the
+    /// document's controller, signed operations and every write the owner
+    /// may make, while the permanent owner may not. Afterwards, or once
+    /// the permanent owner calls `revoke_temporary_ownership`, control
+    /// reverts to the permanent owner by itself. What would outlast the
+    /// grant stays with the permanent owner throughout: owner changes in
+    /// any form and cancelling them, deactivation, clearing the records,
+    /// the controller, operators, observers, the policy, signing keys and
+    /// the owner multisig. Replaces any previous grant and is dropped by an
+    /// owner change. Only callable by the permanent owner.
+    pub fn grant_temporary_ownership(&mut self, identity: String, temp_owner: AccountId, duration_secs: u64) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let temporary = TemporaryOwner { temp_owner, expires_at: now_secs() + duration_secs };
+        DidEvent::from(TemporaryOwnerGranted { identity: identity.clone(), temp_owner: temporary.temp_owner.clone(), expires_at: temporary.expires_at }).emit();
+        self.temporary_owners.insert(identity, temporary);
+    }
+
+    /// Ends the identity's temporary ownership early. Only callable by the
+    /// permanent owner.
+    pub fn revoke_temporary_ownership(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        if let Some(temporary) = self.temporary_owners.remove(&identity) {
+            DidEvent::from(TemporaryOwnerRevoked { identity, temp_owner: temporary.temp_owner, expires_at: temporary.expires_at }).emit();
+        }
+    }
+
+    /// The identity's temporary owner while its grant lasts.
+    pub fn get_temporary_owner(&self, identity: String) -> Option<TemporaryOwner> {
+        let identity = normalize_did(identity);
+        self.temporary_owners.get(&identity).filter(|temporary| now_secs() < temporary.expires_at).cloned()
+    }
+
+    /// Sets the identity's policy. With `revoke_delegates_on_owner_change`,
+    /// every owner change, whichever way it is made, revokes the identity's
+    /// valid delegates in the same transaction, logging `did_delegate_changed`
+    /// for each, so delegates of a compromised owner don't outlive it. Owner
+    /// changes then fail with `delegates_not_cleared` while the identity
+    /// holds more than `MAX_OWNER_CHANGE_REVOCATIONS` delegates, revoked and
+    /// expired ones included, until `purge_expired` or `clear_identity`
+    /// brings it under. Only callable by the permanent owner.
+    pub fn set_policy(&mut self, identity: String, revoke_delegates_on_owner_change: bool) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let policy = IdentityPolicy { revoke_delegates_on_owner_change };
+        DidEvent::from(PolicySet { identity: identity.clone(), revoke_delegates_on_owner_change }).emit();
+        if policy == IdentityPolicy::default() {
+            self.policies.remove(&identity);
+        } else {
+            self.policies.insert(identity, policy);
+        }
+    }
+
+    pub fn get_policy(&self, identity: String) -> IdentityPolicy {
+        let identity = normalize_did(identity);
+        self.policies.get(&identity).cloned().unwrap_or_default()
+    }
+
+    /// Permanently deactivates the identity. Only callable by the owner. The
+    /// identity's document resolves empty, with `deactivated` set in its
+    /// metadata, its delegates and attributes stop validating and every
+    /// later write to it, signed ones included, fails with
+    /// `identity_deactivated`. Logs `did_deactivated`.
+    pub fn deactivate_identity(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let previous_change = self.touch(&identity, &actor);
+        self.deactivated.insert(identity.clone(), env::block_height());
+        self.scheduled_transfers.remove(&identity);
+        self.record_write(&identity, RecordChange::Deactivated);
+        DidEvent::from(IdentityDeactivated { identity: identity.clone(), changed: env::block_height(), previous_change }).emit();
+        #[cfg(feature = "erc1056-events")]
+        events::erc1056::Erc1056Event::OwnerChanged(events::erc1056::DidOwnerChanged { identity: identity.clone(), owner: events::erc1056::NULL_OWNER.to_string(), previous_change }).emit();
+        self.notify_observers(&identity, ChangeKind::Deactivated);
+    }
+
+    /// Lets `threshold` of `guardians` recover the identity to a new owner
+    /// should its owner lose their keys, see `initiate_recovery`. Replaces
+    /// the previous config and drops any recovery underway; no guardians
+    /// turns recovery off. Unlike signing keys, the config survives owner
+    /// changes, so the guardians can still recover an identity a stolen key
+    /// handed over. Only callable by the owner, under the same checks as
+    /// `change_owner`.
+    pub fn set_recovery_config(&mut self, identity: String, guardians: Vec<AccountId>, threshold: u8, delay_secs: u64) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_owner_change(&identity, &actor);
+
+        if !guardians.is_empty() {
+            let mut distinct = guardians.clone();
+            distinct.sort();
+            distinct.dedup();
+            ensure(distinct.len() == guardians.len() && guardians.len() <= MAX_GUARDIANS, RegistryError::BadThreshold);
+            ensure(threshold >= 1 && threshold as usize <= guardians.len(), RegistryError::BadThreshold);
+        }
+        if self.recoveries.remove(&identity).is_some() {
+            DidEvent::from(RecoveryCancelled { identity: identity.clone(), actor }).emit();
+        }
+        DidEvent::from(RecoveryConfigured { identity: identity.clone(), guardians: guardians.clone(), threshold, delay_secs }).emit();
+        if guardians.is_empty() {
+            self.recovery_configs.remove(&identity);
+        } else {
+            self.recovery_configs.insert(identity, RecoveryConfig { guardians, threshold, delay_secs });
+        }
+    }
+
+    pub fn get_recovery_config(&self, identity: String) -> Option<RecoveryConfig> {
+        let identity = normalize_did(identity);
+        self.recovery_configs.get(&identity).cloned()
+    }
+
+    /// Starts recovering the identity to `new_owner`, approved by the
+    /// calling guardian. Once `threshold` guardians approved it and the
+    /// config's delay has passed, anyone may `finalize_recovery`; until then
+    /// the owner keeps full control and may `cancel_recovery`. Fails with
+    /// `recovery_pending` while another recovery is underway.
+    pub fn initiate_recovery(&mut self, identity: String, new_owner: String) {
+        let identity = normalize_did(identity);
+        let new_owner = normalize_owner(new_owner);
+        self.settle_transfer(&identity);
+        let guardian = env::predecessor_account_id();
+        self.assert_active(&identity);
+        let config = self.recovery_configs.get(&identity).cloned().unwrap_or_else(|| RegistryError::NotGuardian.panic());
+        ensure(config.guardians.contains(&guardian), RegistryError::NotGuardian);
+        ensure(!self.recoveries.contains_key(&identity), RegistryError::RecoveryPending);
+        ensure(new_owner != self.permanent_owner(&identity), RegistryError::AlreadyOwner);
+
+        let effective_at = now_secs() + config.delay_secs;
+        DidEvent::from(RecoveryInitiated { identity: identity.clone(), guardian: guardian.clone(), new_owner: new_owner.clone(), effective_at }).emit();
+        self.recoveries.insert(identity, PendingRecovery { new_owner, approvals: vec![guardian], effective_at });
+    }
+
+    /// Adds the calling guardian's approval to the recovery underway.
+    pub fn approve_recovery(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        self.settle_transfer(&identity);
+        let guardian = env::predecessor_account_id();
+        let config = self.recovery_configs.get(&identity).cloned().unwrap_or_else(|| RegistryError::NotGuardian.panic());
+        ensure(config.guardians.contains(&guardian), RegistryError::NotGuardian);
+        let recovery = self.recoveries.get_mut(&identity).unwrap_or_else(|| RegistryError::NoPendingRecovery.panic());
+        ensure(!recovery.approvals.contains(&guardian), RegistryError::AlreadyApproved);
+
+        recovery.approvals.push(guardian.clone());
+        DidEvent::from(RecoveryApproved { identity, guardian, approvals: recovery.approvals.len() as u8 }).emit();
+    }
+
+    /// Vetoes the recovery underway. Only callable by the owner.
+    pub fn cancel_recovery(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        ensure(self.recoveries.remove(&identity).is_some(), RegistryError::NoPendingRecovery);
+        DidEvent::from(RecoveryCancelled { identity, actor }).emit();
+    }
+
+    /// Applies the recovery underway, changing the owner as `change_owner`
+    /// would, once `threshold` guardians approved it (`threshold_not_met`
+    /// before) and its `effective_at` has passed (`owner_change_locked`
+    /// before). Callable by anyone, but not once an owner multisig was set.
+    pub fn finalize_recovery(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        self.settle_transfer(&identity);
+        let recovery = self.recoveries.get(&identity).cloned().unwrap_or_else(|| RegistryError::NoPendingRecovery.panic());
+        let threshold = self.recovery_configs.get(&identity).map_or(0, |config| config.threshold);
+        ensure(recovery.approvals.len() >= threshold as usize, RegistryError::ThresholdNotMet);
+        ensure(now_secs() >= recovery.effective_at, RegistryError::OwnerChangeLocked);
+        ensure(!self.thresholds.contains_key(&identity), RegistryError::MultisigRequired);
+
+        DidEvent::from(RecoveryFinalized { identity: identity.clone(), new_owner: recovery.new_owner.clone() }).emit();
+        let owner = self.owner_of(&identity);
+        self.apply(&identity, &owner, SignedOp::ChangeOwner { new_owner: recovery.new_owner }, None);
+        self.notify_observers(&identity, ChangeKind::Owner);
+    }
+
+    /// Names `recovery`, e.g. a cold wallet, as the one account that may
+    /// hand the identity to a new owner with `recover_ownership`, replacing
+    /// the previous recovery address; it can do nothing else. Dropped when
+    /// the owner changes. Only callable by the owner.
+    pub fn set_recovery_address(&mut self, identity: String, recovery: AccountId) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        DidEvent::from(RecoveryAddressSet { identity: identity.clone(), recovery_address: recovery.clone() }).emit();
+        self.recovery_addresses.insert(identity, recovery);
+    }
+
+    pub fn get_recovery_address(&self, identity: String) -> Option<AccountId> {
+        let identity = normalize_did(identity);
+        self.recovery_addresses.get(&identity).cloned()
+    }
+
+    /// Hands the identity to `new_owner`, as `change_owner` would, logging
+    /// `did_ownership_recovered` first. Only callable by the recovery
+    /// address, else `not_recovery_address`, which the owner change drops:
+    /// it is used up until the new owner sets one again. Like social
+    /// recovery, it ignores the owner change cooldown but not an owner
+    /// multisig.
+    pub fn recover_ownership(&mut self, identity: String, new_owner: String) {
+        let identity = normalize_did(identity);
+        let new_owner = normalize_owner(new_owner);
+        self.settle_transfer(&identity);
+        let recovery_address = env::predecessor_account_id();
+        ensure(self.recovery_addresses.get(&identity) == Some(&recovery_address), RegistryError::NotRecoveryAddress);
+        ensure(!self.thresholds.contains_key(&identity), RegistryError::MultisigRequired);
+
+        DidEvent::from(OwnershipRecovered { identity: identity.clone(), recovery_address, new_owner: new_owner.clone() }).emit();
+        let owner = self.owner_of(&identity);
+        self.apply(&identity, &owner, SignedOp::ChangeOwner { new_owner }, None);
+        self.notify_observers(&identity, ChangeKind::Owner);
+    }
+
+    /// The identity's recovery underway, if any.
+    pub fn get_pending_recovery(&self, identity: String) -> Option<PendingRecovery> {
+        let identity = normalize_did(identity);
+        self.recoveries.get(&identity).cloned()
+    }
+
+    /// Designates `successor` to take the identity over once the owner has
+    /// authorized no write for `inactivity_secs`, see
+    /// `claim_ownership_after_inactivity`. Replaces the previous successor.
+    /// Only callable by the owner, under the same checks as `change_owner`.
+    pub fn set_inactivity_successor(&mut self, identity: String, successor: AccountId, inactivity_secs: u64) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_owner_change(&identity, &actor);
+
+        self.successors.insert(identity, InactivitySuccessor { successor, inactivity_secs });
+    }
+
+    /// Removes the identity's successor. Only callable by the owner.
+    pub fn remove_inactivity_successor(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        self.successors.remove(&identity);
+    }
+
+    pub fn get_inactivity_successor(&self, identity: String) -> Option<InactivitySuccessor> {
+        let identity = normalize_did(identity);
+        self.successors.get(&identity).cloned()
+    }
+
+    /// Unix seconds of the last write the owner authorized for the identity,
+    /// directly or by signature; `None` if there was none since the registry
+    /// started tracking it.
+    pub fn get_last_activity(&self, identity: String) -> Option<u64> {
+        let identity = normalize_did(identity);
+        self.last_activity.get(&identity).copied()
+    }
+
+    /// Makes the caller, the identity's successor, its owner, as
+    /// `change_owner` would, once the owner has authorized no write for the
+    /// successor's `inactivity_secs`; before that it fails with
+    /// `owner_active`. Not once an owner multisig was set.
+    pub fn claim_ownership_after_inactivity(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        self.settle_transfer(&identity);
+        let successor = self.successors.get(&identity).cloned().filter(|successor| successor.successor == env::predecessor_account_id()).unwrap_or_else(|| RegistryError::NotSuccessor.panic());
+        let last_activity = self.last_activity.get(&identity).copied().unwrap_or(0);
+        ensure(now_secs().saturating_sub(last_activity) >= successor.inactivity_secs, RegistryError::OwnerActive);
+        ensure(!self.thresholds.contains_key(&identity), RegistryError::MultisigRequired);
+
+        let owner = self.owner_of(&identity);
+        self.apply(&identity, &owner, SignedOp::ChangeOwner { new_owner: successor.successor.to_string() }, None);
+        self.notify_observers(&identity, ChangeKind::Owner);
+    }
+
+    /// Hands the identity over to `RENOUNCED_OWNER`, which matches no
+    /// caller and no key: the document is frozen as it is, with `renounced`
+    /// set in its metadata, and every later write to the identity, signed
+    /// ones included, fails with `ownership_renounced`. Entries still expire
+    /// as set. Only callable by the owner, under the same checks as
+    /// `change_owner`, and logged as an owner change to `RENOUNCED_OWNER`.
+    pub fn renounce_ownership(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.change_owner_by(identity, actor, RENOUNCED_OWNER.to_string(), None);
+    }
+
+    /// Whether the identity was deactivated, see `deactivate_identity`.
+    pub fn is_deactivated(&self, identity: String) -> bool {
+        let identity = normalize_did(identity);
+        self.deactivated.contains_key(&identity)
+    }
+
+    /// Proposes `proposed` as the identity's next owner, replacing any earlier
+    /// proposal. Nothing changes until `proposed` calls `accept_ownership`;
+    /// until then the current owner keeps full control. Only callable by the
+    /// owner, under the same checks as `change_owner`.
+    pub fn propose_owner(&mut self, identity: String, proposed: AccountId) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_owner_change(&identity, &actor);
+
+        self.proposed_owners.insert(identity.clone(), proposed.clone());
+        DidEvent::from(OwnerProposed { identity, proposed_owner: proposed }).emit();
+    }
+
+    /// Makes the caller the identity's owner, as `change_owner` would. Only
+    /// callable by the account proposed with `propose_owner`, and not once
+    /// an owner multisig was set since.
+    pub fn accept_ownership(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        self.settle_transfer(&identity);
+        let proposed = self.proposed_owners.get(&identity).cloned().unwrap_or_else(|| RegistryError::NoOwnerProposal.panic());
+        ensure(env::predecessor_account_id() == proposed, RegistryError::NotProposedOwner);
+        ensure(!self.thresholds.contains_key(&identity), RegistryError::MultisigRequired);
+
+        let owner = self.owner_of(&identity);
+        self.apply(&identity, &owner, SignedOp::ChangeOwner { new_owner: proposed.to_string() }, None);
+        self.notify_observers(&identity, ChangeKind::Owner);
+    }
+
+    /// Withdraws the identity's pending owner proposal. Only callable by the
+    /// owner.
+    pub fn cancel_proposal(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let proposed = self.proposed_owners.remove(&identity).unwrap_or_else(|| RegistryError::NoOwnerProposal.panic());
+        DidEvent::from(OwnerProposalCancelled { identity, proposed_owner: proposed }).emit();
+    }
+
+    /// The account proposed as the identity's next owner, if any.
+    pub fn get_proposed_owner(&self, identity: String) -> Option<AccountId> {
+        let identity = normalize_did(identity);
+        self.proposed_owners.get(&identity).cloned()
+    }
+
+    /// Schedules a change of the identity's owner to `new_owner`, taking
+    /// effect once `delay_secs` have passed and `finalize_owner_change` is
+    /// called, replacing any change already scheduled. Until then the
+    /// current owner keeps full control and may `cancel_owner_change`. Only
+    /// callable by the owner, under the same checks as `change_owner`.
+    pub fn schedule_owner_change(&mut self, identity: String, new_owner: String, delay_secs: u64) {
+        let identity = normalize_did(identity);
+        let new_owner = normalize_owner(new_owner);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_owner_change(&identity, &actor);
+        ensure(new_owner != self.permanent_owner(&identity), RegistryError::AlreadyOwner);
+
+        let pending = PendingOwnerChange { new_owner, effective_at: now_secs() + delay_secs };
+        DidEvent::from(OwnerChangeScheduled { identity: identity.clone(), new_owner: pending.new_owner.clone(), effective_at: pending.effective_at }).emit();
+        self.pending_owner_changes.insert(identity, pending);
+    }
+
+    /// Drops the identity's scheduled owner change. Only callable by the
+    /// owner.
+    pub fn cancel_owner_change(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let pending = self.pending_owner_changes.remove(&identity).unwrap_or_else(|| RegistryError::NoPendingOwnerChange.panic());
+        DidEvent::from(OwnerChangeCancelled { identity, new_owner: pending.new_owner, effective_at: pending.effective_at }).emit();
+    }
+
+    /// Applies the identity's scheduled owner change, as `change_owner`
+    /// would, once its `effective_at` has passed; before that it fails with
+    /// `owner_change_locked`. Callable by anyone, but not once an owner
+    /// multisig was set since the change was scheduled.
+    pub fn finalize_owner_change(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        self.settle_transfer(&identity);
+        let pending = self.pending_owner_changes.get(&identity).cloned().unwrap_or_else(|| RegistryError::NoPendingOwnerChange.panic());
+        ensure(now_secs() >= pending.effective_at, RegistryError::OwnerChangeLocked);
+        ensure(!self.thresholds.contains_key(&identity), RegistryError::MultisigRequired);
+
+        let owner = self.owner_of(&identity);
+        self.apply(&identity, &owner, SignedOp::ChangeOwner { new_owner: pending.new_owner }, None);
+        self.notify_observers(&identity, ChangeKind::Owner);
+    }
+
+    /// The identity's scheduled owner change, if any.
+    pub fn get_pending_owner_change(&self, identity: String) -> Option<PendingOwnerChange> {
+        let identity = normalize_did(identity);
+        self.pending_owner_changes.get(&identity).cloned()
+    }
+
+    /// Hands the identity over to `new_owner` at `effective_at` (unix
+    /// seconds), e.g. a planned migration, replacing any transfer already
+    /// scheduled. Until then nothing changes and the owner may
+    /// `cancel_scheduled_transfer`; from then on `new_owner` is the owner
+    /// without any further call: `identity_owner` and every authorization
+    /// check name it, and the change is recorded and logged as `change_owner`
+    /// would, by `finalize_scheduled_transfer` or the next write to the
+    /// identity, whichever comes first. While the identity is locked the
+    /// transfer is held back, taking effect once it is unlocked. Only
+    /// callable by the owner, under the same checks as `change_owner`.
+    pub fn schedule_transfer_at(&mut self, identity: String, new_owner: String, effective_at: u64) {
+        let identity = normalize_did(identity);
+        let new_owner = normalize_owner(new_owner);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_owner_change(&identity, &actor);
+        ensure(new_owner != self.permanent_owner(&identity), RegistryError::AlreadyOwner);
+
+        let transfer = PendingOwnerChange { new_owner, effective_at };
+        DidEvent::from(TransferScheduled { identity: identity.clone(), new_owner: transfer.new_owner.clone(), effective_at: transfer.effective_at }).emit();
+        self.scheduled_transfers.insert(identity, transfer);
+    }
+
+    /// Drops the identity's scheduled transfer before its time; once due it
+    /// has taken effect and fails with `no_scheduled_transfer`. Only
+    /// callable by the owner.
+    pub fn cancel_scheduled_transfer(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        let transfer = self.get_scheduled_transfer(identity.clone()).unwrap_or_else(|| RegistryError::NoScheduledTransfer.panic());
+        self.scheduled_transfers.remove(&identity);
+        DidEvent::from(TransferCancelled { identity, new_owner: transfer.new_owner, effective_at: transfer.effective_at }).emit();
+    }
+
+    /// Records and logs the identity's due transfer, see
+    /// `schedule_transfer_at`. Fails with `owner_change_locked` before its
+    /// time and `no_scheduled_transfer` without one or once applied.
+    /// Callable by anyone.
+    pub fn finalize_scheduled_transfer(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let transfer = self.scheduled_transfers.get(&identity).unwrap_or_else(|| RegistryError::NoScheduledTransfer.panic());
+        ensure(now_secs() >= transfer.effective_at, RegistryError::OwnerChangeLocked);
+
+        self.assert_active(&identity);
+        self.settle_transfer(&identity);
+    }
+
+    /// The identity's transfer scheduled with `schedule_transfer_at` while
+    /// it has not taken effect: before its time, or while the identity is
+    /// locked.
+    pub fn get_scheduled_transfer(&self, identity: String) -> Option<PendingOwnerChange> {
+        let identity = normalize_did(identity);
+        self.scheduled_transfers.get(&identity).filter(|transfer| now_secs() < transfer.effective_at || self.locked.contains_key(&identity)).cloned()
+    }
+
+    /// Whether `change_owner` called by `actor` would pass its authorization
+    /// checks: `actor` owns the identity and no owner multisig is set.
+    pub fn can_change_owner(&self, identity: String, actor: String) -> bool {
+        let identity = normalize_did(identity);
+        let actor = normalize_did(actor);
+        self.authorize_owner_change(&identity, &actor).is_ok()
+    }
+
+    /// Which direct writes to the identity `actor` is allowed to make, by
+    /// the same checks as the writes themselves. `get_permissions` breaks
+    /// down the delegate and attribute writes.
+    pub fn can_manage(&self, identity: String, actor: String) -> ManagePermissions {
+        let identity = normalize_did(identity);
+        let actor = normalize_did(actor);
+        ManagePermissions {
+            delegates: self.authorize_write(&identity, &actor, |permissions| permissions.add_delegate && permissions.revoke_delegate).is_ok(),
+            attributes: self.authorize_write(&identity, &actor, |permissions| permissions.set_attribute && permissions.revoke_attribute).is_ok(),
+            owner: self.authorize_owner_change(&identity, &actor).is_ok(),
+        }
+    }
+
+    /// The delegate and attribute writes `actor` may make to the identity
+    /// right now: all of them for the owner and the controller, those
+    /// granted for an approved operator, none for anyone else or while the
+    /// identity does not accept writes.
+    pub fn get_permissions(&self, identity: String, actor: String) -> Permissions {
+        let identity = normalize_did(identity);
+        let actor = normalize_did(actor);
+        Permissions {
+            add_delegate: self.authorize_write(&identity, &actor, |permissions| permissions.add_delegate).is_ok(),
+            revoke_delegate: self.authorize_write(&identity, &actor, |permissions| permissions.revoke_delegate).is_ok(),
+            set_attribute: self.authorize_write(&identity, &actor, |permissions| permissions.set_attribute).is_ok(),
+            revoke_attribute: self.authorize_write(&identity, &actor, |permissions| permissions.revoke_attribute).is_ok(),
+        }
+    }
+
+    /// Relayed `change_owner`: `signature` is the owner's signature over
+    /// `get_change_owner_payload`.
+    pub fn change_owner_signed(&mut self, identity: String, signature: Signature, valid_until_timestamp: u64, new_owner: String) {
+        let identity = normalize_did(identity);
+        let new_owner = normalize_owner(new_owner);
+        let actor = self.check_signature(&identity, "changeOwner", &signature, valid_until_timestamp, false, |this, signer| {
+            this.get_change_owner_payload(identity.clone(), signer, valid_until_timestamp, new_owner.clone())
+        });
+        self.change_owner_by(identity, actor, new_owner, None);
+    }
+
+    /// Requires owner changes to be approved by `threshold` distinct keys out
+    /// of `keys` (see `change_owner_multisig`). An empty key set removes the
+    /// requirement, and so does any owner change. Only callable by the owner.
+    pub fn set_owner_threshold(&mut self, identity: String, keys: Vec<PublicKey>, threshold: u8) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+
+        if keys.is_empty() {
+            self.thresholds.remove(&identity);
+            return;
+        }
+        let mut distinct = keys.clone();
+        distinct.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        distinct.dedup();
+        ensure(distinct.len() == keys.len() && keys.len() <= MAX_THRESHOLD_KEYS, RegistryError::BadThreshold);
+        ensure(threshold >= 1 && threshold as usize <= keys.len(), RegistryError::BadThreshold);
+        self.thresholds.insert(identity, OwnerThreshold { keys, threshold });
+    }
+
+    pub fn get_owner_threshold(&self, identity: String) -> Option<OwnerThreshold> {
+        let identity = normalize_did(identity);
+        self.thresholds.get(&identity).cloned()
+    }
+
+    /// Changes the owner once at least `threshold` distinct registered keys
+    /// have signed `get_change_owner_multisig_payload`. Any account may submit
+    /// it; repeated or unregistered keys do not count. Consumes the owner's
+    /// nonce for the identity.
+    pub fn change_owner_multisig(&mut self, identity: String, valid_until_timestamp: u64, new_owner: String, signatures: Vec<(PublicKey, Signature)>) {
+        let identity = normalize_did(identity);
+        let new_owner = normalize_owner(new_owner);
+        self.settle_transfer(&identity);
+        self.assert_active(&identity);
+        ensure(now_secs() <= valid_until_timestamp, RegistryError::SignatureExpired);
+        self.check_owner_change_cooldown(&identity).unwrap_or_else(|error| error.panic());
+        let config = self.thresholds.get(&identity).cloned().unwrap_or_else(|| RegistryError::NoThreshold.panic());
+        let payload = self.get_change_owner_multisig_payload(identity.clone(), valid_until_timestamp, new_owner.clone());
+
+        let mut approvals: Vec<&PublicKey> = signatures
+            .iter()
+            .filter(|(key, signature)| config.keys.contains(key) && signature::verify_with_key(signature, &payload, key))
+            .map(|(key, _)| key)
+            .collect();
+        approvals.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        approvals.dedup();
+        ensure(approvals.len() >= config.threshold as usize, RegistryError::ThresholdNotMet);
+
+        let owner = self.owner_of(&identity);
+        let nonce = self.get_nonce(identity.clone(), owner.clone());
+        self.consume_nonce(&identity, &owner);
+        self.record_relayed(&identity, "changeOwnerMultisig", &owner, approvals.into_iter().cloned().collect(), nonce);
+
+        self.apply(&identity, &owner, SignedOp::ChangeOwner { new_owner }, None);
+        self.notify_observers(&identity, ChangeKind::Owner);
+    }
+
+    fn add_delegate_by(&mut self, identity: String, actor: String, delegate_type: String, delegate: String, validity_secs: u64, memo: Option<String>) {
+        self.assert_can_manage(&identity, &actor, |permissions| permissions.add_delegate);
+
+        self.apply(&identity, &actor, SignedOp::AddDelegate { delegate_type, delegate, validity_secs }, memo);
+        self.notify_observers(&identity, ChangeKind::Delegate);
+    }
+
+    pub fn add_delegate(&mut self, identity: String, delegate_type: String, delegate: String, validity_secs: u64, memo: Option<String>) {
+        let identity = normalize_did(identity);
+        let delegate = normalize_did(delegate);
+        assert_memo(&memo);
+        let actor = env::predecessor_account_id().to_string();
+        self.add_delegate_by(identity, actor, delegate_type, delegate, validity_secs, memo);
+    }
+
+    /// Relayed `add_delegate`: `signature` is the owner's signature over
+    /// `get_add_delegate_payload`.
+    pub fn add_delegate_signed(&mut self, identity: String, signature: Signature, valid_until_timestamp: u64, delegate_type: String, delegate: String, validity_secs: u64) {
+        let identity = normalize_did(identity);
+        let delegate = normalize_did(delegate);
+        let actor = self.check_signature(&identity, "addDelegate", &signature, valid_until_timestamp, true, |this, signer| {
+            this.get_add_delegate_payload(identity.clone(), signer, valid_until_timestamp, delegate_type.clone(), delegate.clone(), validity_secs)
+        });
+        self.add_delegate_by(identity, actor, delegate_type, delegate, validity_secs, None);
+    }
+
+    fn revoke_delegate_by(&mut self, identity: String, actor: String, delegate_type: String, delegate: String, memo: Option<String>) {
+        self.assert_can_manage(&identity, &actor, |permissions| permissions.revoke_delegate);
+
+        self.apply(&identity, &actor, SignedOp::RevokeDelegate { delegate_type, delegate }, memo);
+        self.notify_observers(&identity, ChangeKind::Delegate);
+    }
+
+    pub fn revoke_delegate(&mut self, identity: String, delegate_type: String, delegate: String, memo: Option<String>) {
+        let identity = normalize_did(identity);
+        let delegate = normalize_did(delegate);
+        assert_memo(&memo);
+        let actor = env::predecessor_account_id().to_string();
+        self.revoke_delegate_by(identity, actor, delegate_type, delegate, memo);
+    }
+
+    /// Relayed `revoke_delegate`: `signature` is the owner's signature
+    /// over `get_revoke_delegate_payload`.
+    pub fn revoke_delegate_signed(&mut self, identity: String, signature: Signature, valid_until_timestamp: u64, delegate_type: String, delegate: String) {
+        let identity = normalize_did(identity);
+        let delegate = normalize_did(delegate);
+        let actor = self.check_signature(&identity, "revokeDelegate", &signature, valid_until_timestamp, true, |this, signer| {
+            this.get_revoke_delegate_payload(identity.clone(), signer, valid_until_timestamp, delegate_type.clone(), delegate.clone())
+        });
+        self.revoke_delegate_by(identity, actor, delegate_type, delegate, None);
+    }
+
+    fn unindex_delegate(&mut self, identity: &str, pair: (String, String)) {
+        if let Some(index) = self.delegate_index.get_mut(identity) {
+            index.remove(&pair);
+            if index.is_empty() {
+                self.delegate_index.remove(identity);
+            }
+        }
+    }
+
+    /// Like `update_count`, for the identity's delegates of `delegate_type`.
+    /// A type is dropped once its last delegate is revoked or purged.
+    fn update_type_count(&mut self, identity: &str, delegate_type: &str, was_counted: bool, is_counted: bool) {
+        if was_counted == is_counted {
+            return;
+        }
+        let mut types = self.delegate_types.get(identity).cloned().unwrap_or_default();
+        match types.iter().position(|(stored, _)| stored == delegate_type) {
+            Some(position) if was_counted => {
+                types[position].1 -= 1;
+                if types[position].1 == 0 {
+                    types.remove(position);
+                }
+            }
+            Some(position) => types[position].1 += 1,
+            None => types.push((delegate_type.to_string(), 1)),
+        }
+        if types.is_empty() {
+            self.delegate_types.remove(identity);
+        } else {
+            self.delegate_types.insert(identity.to_string(), types);
+        }
+    }
+
+    /// Delegate types the identity has unrevoked delegates of, with their
+    /// number, in the order the types were first used. Counted like
+    /// `count_delegates`.
+    pub fn list_delegate_types(&self, identity: String) -> Vec<(String, u32)> {
+        let identity = normalize_did(identity);
+        self.delegate_types.get(&identity).cloned().unwrap_or_default()
+    }
+
+    fn remove_delegation(&mut self, delegate: &str, delegation: &(String, String)) {
+        if let Some(delegations) = self.delegations.get_mut(delegate) {
+            delegations.remove(delegation);
+            if delegations.is_empty() {
+                self.delegations.remove(delegate);
+            }
+        }
+    }
+
+    /// Up to `limit` (at most `MAX_PAGE_SIZE`) of the identities that
+    /// delegated to `delegate`, starting at the `from_index`th one. Revoked
+    /// and purged delegations are dropped from the index; expired ones stay
+    /// until purged and are skipped unless `include_expired` is set. A delegate
+    /// is named in at most `MAX_DELEGATIONS` unrevoked delegations, which
+    /// bounds the scan.
+    pub fn identities_for_delegate(&self, delegate: String, from_index: u64, limit: u64, include_expired: Option<bool>) -> Vec<DelegatedIdentity> {
+        let delegate = normalize_did(delegate);
+        let Some(delegations) = self.delegations.get(&delegate) else {
+            return Vec::new();
+        };
+        let now = now_secs();
+        delegations
+            .iter()
+            .map(|(identity, delegate_type)| DelegatedIdentity {
+                valid_until: self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone())).copied().unwrap_or(0),
+                identity: identity.clone(),
+                delegate_type: delegate_type.clone(),
+            })
+            .filter(|delegation| include_expired.unwrap_or(false) || delegation.valid_until > now)
+            .skip(from_index as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .collect()
+    }
+
+    /// Like `identities_for_delegate`, one page after `cursor`; `limit`
+    /// counts the delegations returned.
+    pub fn identities_for_delegate_page(&self, delegate: String, cursor: Option<String>, limit: u64, include_expired: Option<bool>) -> Page<DelegatedIdentity> {
+        let delegate = normalize_did(delegate);
+        let (_, start) = cursor_position(cursor, 1);
+        let Some(delegations) = self.delegations.get(&delegate) else {
+            return page((Vec::new(), None));
+        };
+        let now = now_secs();
+        page(delegations.page(start, limit.min(MAX_PAGE_SIZE) as usize, |(identity, delegate_type)| {
+            let valid_until = self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone())).copied().unwrap_or(0);
+            (include_expired.unwrap_or(false) || valid_until > now).then(|| DelegatedIdentity { identity: identity.clone(), delegate_type: delegate_type.clone(), valid_until })
+        }))
+    }
+
+    /// Up to `limit` (at most `MAX_PAGE_SIZE`) of the identity's delegates,
+    /// starting at `from_index`. Revoked delegates stay listed with
+    /// `valid_until` 0 until `purge_expired` removes them, so the list
+    /// matches `valid_delegate` entry for entry. Delegates are listed in the
+    /// order they were first added; a purge shifts the later ones back, which
+    /// `list_delegates_page` is not affected by.
+    pub fn list_delegates(&self, identity: String, from_index: u64, limit: u64) -> Vec<DelegateEntry> {
+        let identity = normalize_did(identity);
+        let Some(index) = self.delegate_index.get(&identity) else {
+            return Vec::new();
+        };
+        index
+            .iter()
+            .skip(from_index as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|(delegate_type, delegate)| DelegateEntry {
+                valid_until: self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone())).copied().unwrap_or(0),
+                delegate_type: delegate_type.clone(),
+                delegate: delegate.clone(),
+            })
+            .collect()
+    }
+
+    /// Like `list_delegates`, one page after `cursor`.
+    pub fn list_delegates_page(&self, identity: String, cursor: Option<String>, limit: u64) -> Page<DelegateEntry> {
+        let identity = normalize_did(identity);
+        let (_, start) = cursor_position(cursor, 1);
+        let Some(index) = self.delegate_index.get(&identity) else {
+            return page((Vec::new(), None));
+        };
+        page(index.page(start, limit.min(MAX_PAGE_SIZE) as usize, |(delegate_type, delegate)| {
+            Some(DelegateEntry {
+                valid_until: self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone())).copied().unwrap_or(0),
+                delegate_type: delegate_type.clone(),
+                delegate: delegate.clone(),
+            })
+        }))
+    }
+
+    /// `valid_delegate` of each of `queries` (at most `MAX_PAGE_SIZE`), in the
+    /// same order and all against the same block time.
+    pub fn valid_delegate_many(&self, queries: Vec<DelegateQuery>) -> Vec<bool> {
+        ensure(queries.len() as u64 <= MAX_PAGE_SIZE, RegistryError::BatchTooLarge);
+        let now = now_secs();
+        queries
+            .into_iter()
+            .map(|query| {
+                let identity = normalize_did(query.identity);
+                !self.deactivated.contains_key(&identity) && self.delegates.get(&(identity, query.delegate_type, normalize_did(query.delegate))).is_some_and(|valid_until| *valid_until > now)
+            })
+            .collect()
+    }
+
+    /// Stored expiry of the delegate in unix seconds: `None` if it was never
+    /// added (or has been purged), `Some(0)` if it was revoked.
+    pub fn get_delegate_validity(&self, identity: String, delegate_type: String, delegate: String) -> Option<u64> {
+        let identity = normalize_did(identity);
+        let delegate = normalize_did(delegate);
+        self.delegates.get(&(identity, delegate_type, delegate)).copied()
+    }
+
+    /// Fragment of the delegate's verification method in the `resolve`
+    /// document, `delegate-<n>`, allocated when it was first added and kept
+    /// for good, even once revoked or purged, so credentials issued against
+    /// `did:near:<identity>#delegate-<n>` keep pointing at the same key.
+    /// `None` if it was never added.
+    pub fn get_fragment(&self, identity: String, delegate_type: String, delegate: String) -> Option<String> {
+        let identity = normalize_did(identity);
+        let delegate = normalize_did(delegate);
+        self.delegate_fragments.get(&(identity, delegate_type, delegate)).map(|fragment| format!("delegate-{}", fragment))
+    }
+
+    pub fn valid_delegate(&self, identity: String, delegate_type: String, delegate: String) -> bool {
+        let identity = normalize_did(identity);
+        let delegate = normalize_did(delegate);
+        if self.deactivated.contains_key(&identity) {
+            return false;
+        }
+        match self.delegates.get(&(identity, delegate_type, delegate)) {
+            Some(valid_until) => *valid_until > now_secs(),
+            None => false,
+        }
+    }
+
+    fn set_attribute_by(&mut self, identity: String, actor: String, name: String, value: Vec<u8>, validity_secs: u64, memo: Option<String>) {
+        self.assert_can_manage(&identity, &actor, |permissions| permissions.set_attribute);
+
+        self.apply(&identity, &actor, SignedOp::SetAttribute { name, value, validity_secs }, memo);
+        self.notify_observers(&identity, ChangeKind::Attribute);
+    }
+
+    pub fn set_attribute(&mut self, identity: String, name: String, value: Vec<u8>, validity_secs: u64, memo: Option<String>) {
+        let identity = normalize_did(identity);
+        assert_memo(&memo);
+        let actor = env::predecessor_account_id().to_string();
+        self.set_attribute_by(identity, actor, name, value, validity_secs, memo);
+    }
+
+    /// Relayed `set_attribute`: `signature` is the owner's signature over
+    /// `get_set_attribute_payload`.
+    pub fn set_attribute_signed(&mut self, identity: String, signature: Signature, valid_until_timestamp: u64, name: String, value: Vec<u8>, validity_secs: u64) {
+        let identity = normalize_did(identity);
+        let actor = self.check_signature(&identity, "setAttribute", &signature, valid_until_timestamp, true, |this, signer| {
+            this.get_set_attribute_payload(identity.clone(), signer, valid_until_timestamp, name.clone(), value.clone(), validity_secs)
+        });
+        self.set_attribute_by(identity, actor, name, value, validity_secs, None);
+    }
+
+    fn revoke_attribute_by(&mut self, identity: String, actor: String, name: String, value: Vec<u8>, memo: Option<String>) {
+        self.assert_can_manage(&identity, &actor, |permissions| permissions.revoke_attribute);
+
+        self.apply(&identity, &actor, SignedOp::RevokeAttribute { name, value }, memo);
+        self.notify_observers(&identity, ChangeKind::Attribute);
+    }
+
+    pub fn revoke_attribute(&mut self, identity: String, name: String, value: Vec<u8>, memo: Option<String>) {
+        let identity = normalize_did(identity);
+        assert_memo(&memo);
+        let actor = env::predecessor_account_id().to_string();
+        self.revoke_attribute_by(identity, actor, name, value, memo);
+    }
+
+    /// Relayed `revoke_attribute`: `signature` is the owner's signature
+    /// over `get_revoke_attribute_payload`.
+    /// Like `revoke_attribute`, revoking a pair that was never set records it
+    /// as revoked; the nonce is consumed either way.
+    pub fn revoke_attribute_signed(&mut self, identity: String, signature: Signature, valid_until_timestamp: u64, name: String, value: Vec<u8>) {
+        let identity = normalize_did(identity);
+        let actor = self.check_signature(&identity, "revokeAttribute", &signature, valid_until_timestamp, true, |this, signer| {
+            this.get_revoke_attribute_payload(identity.clone(), signer, valid_until_timestamp, name.clone(), value.clone())
+        });
+        self.revoke_attribute_by(identity, actor, name, value, None);
+    }
+
+    /// Links the web `origin` to the identity with a
+    /// `did/svc/LinkedDomains` attribute, for DID Configuration checks;
+    /// fails with `bad_origin` unless `document::check_origin` accepts it.
+    pub fn set_linked_domain(&mut self, identity: String, origin: String, validity_secs: u64) {
+        let identity = normalize_did(identity);
+        ensure(document::check_origin(&origin).is_ok(), RegistryError::BadOrigin);
+        let actor = env::predecessor_account_id().to_string();
+        self.set_attribute_by(identity, actor, linked_domains_attribute(), origin.into_bytes(), validity_secs, None);
+    }
+
+    /// Revokes the `did/svc/LinkedDomains` attribute of `origin`.
+    pub fn remove_linked_domain(&mut self, identity: String, origin: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.revoke_attribute_by(identity, actor, linked_domains_attribute(), origin.into_bytes(), None);
+    }
+
+    /// Runs `ops` in order under one signature over `get_batch_payload`. The
+    /// signer authorizes every op, including those after a `ChangeOwner`;
+    /// signing keys may sign batches without one. The nonce is consumed once.
+    pub fn execute_signed_batch(&mut self, identity: String, signature: Signature, valid_until_timestamp: u64, ops: Vec<SignedOp>) {
+        let identity = normalize_did(identity);
+        ensure(!ops.is_empty(), RegistryError::EmptyBatch);
+        ensure(ops.len() <= MAX_BATCH_OPS, RegistryError::BatchTooLarge);
+        let changes_owner = ops.iter().any(|op| matches!(op, SignedOp::ChangeOwner { .. }));
+        ensure(!changes_owner || !self.thresholds.contains_key(&identity), RegistryError::MultisigRequired);
+
+        let actor = self.check_signature(&identity, "batch", &signature, valid_until_timestamp, !changes_owner, |this, signer| {
+            this.get_batch_payload(identity.clone(), signer, valid_until_timestamp, ops.clone())
+        });
+        ensure(!changes_owner || actor == self.permanent_owner(&identity), RegistryError::BadActor);
+        if changes_owner {
+            self.check_owner_change_cooldown(&identity).unwrap_or_else(|error| error.panic());
+        }
+
+        for op in ops {
+            self.apply(&identity, &actor, op, None);
+        }
+        self.notify_observers(&identity, ChangeKind::Batch);
+    }
+
+    /// Stores the unrevoked values of a name, listing the name in
+    /// `attribute_names` while it has any.
+    fn write_attribute_values(&mut self, key: (String, String), values: Vec<Vec<u8>>) {
+        let (identity, name) = &key;
+        if values.is_empty() {
+            if let Some(names) = self.attribute_names.get_mut(identity) {
+                names.remove(name);
+                if names.is_empty() {
+                    self.attribute_names.remove(identity);
+                }
+            }
+            self.attribute_values.remove(&key);
+        } else {
+            self.attribute_names.entry(identity.clone()).or_insert_with(|| OrderedSet::new(index_prefix(b"M", identity))).insert(name.clone());
+            self.attribute_values.insert(key, values);
+        }
+    }
+
+    /// Up to `limit` (at most `MAX_PAGE_SIZE`) of the distinct names the
+    /// identity has unrevoked attribute values under, starting at
+    /// `from_index`. A name is listed until its last value is revoked or
+    /// purged.
+    pub fn list_attribute_names(&self, identity: String, from_index: u64, limit: u64) -> Vec<String> {
+        let identity = normalize_did(identity);
+        let Some(names) = self.attribute_names.get(&identity) else {
+            return Vec::new();
+        };
+        names.iter().skip(from_index as usize).take(limit.min(MAX_PAGE_SIZE) as usize).cloned().collect()
+    }
+
+    /// Like `list_attribute_names`, one page after `cursor`.
+    pub fn list_attribute_names_page(&self, identity: String, cursor: Option<String>, limit: u64) -> Page<String> {
+        let identity = normalize_did(identity);
+        let (_, start) = cursor_position(cursor, 1);
+        page(self.attribute_names.get(&identity).map_or((Vec::new(), None), |names| names.page(start, limit.min(MAX_PAGE_SIZE) as usize, |name| Some(name.clone()))))
+    }
+
+    /// Values set under `name` for the identity with their expiry, most
+    /// recently set first. Revoked and purged values are left out; expired
+    /// ones are listed until purged, with their past expiry.
+    pub fn get_attribute_values(&self, identity: String, name: String) -> Vec<(Base64VecU8, u64)> {
+        let identity = normalize_did(identity);
+        let key = (identity, name);
+        let values = self.attribute_values.get(&key).cloned().unwrap_or_default();
+        values
+            .into_iter()
+            .rev()
+            .map(|value| {
+                let valid_until = self.attributes.get(&(key.0.clone(), key.1.clone(), value.clone())).copied().unwrap_or(0);
+                (value.into(), valid_until)
+            })
+            .collect()
+    }
+
+    /// Up to `limit` (at most `MAX_PAGE_SIZE`) of the identity's delegates,
+    /// then attributes, that are still valid but expire within `within_secs`
+    /// from now, the last second included, starting at the `from_index`th
+    /// one. Walks both enumeration indices.
+    pub fn list_expiring(&self, identity: String, within_secs: u64, from_index: u64, limit: u64) -> Vec<ExpiringEntry> {
+        let identity = normalize_did(identity);
+        let now = now_secs();
+        let expiring = |valid_until: u64| valid_until > now && valid_until <= now.saturating_add(within_secs);
+        let delegates = self.delegate_index.get(&identity).into_iter().flat_map(|index| index.iter()).filter_map(|(delegate_type, delegate)| {
+            let valid_until = *self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone()))?;
+            expiring(valid_until).then(|| ExpiringEntry::Delegate { delegate_type: delegate_type.clone(), delegate: delegate.clone(), valid_until })
+        });
+        let attributes = self.attribute_index.get(&identity).into_iter().flat_map(|index| index.iter()).filter_map(|(name, value)| {
+            let valid_until = *self.attributes.get(&(identity.clone(), name.clone(), value.clone()))?;
+            expiring(valid_until).then(|| ExpiringEntry::Attribute { name: name.clone(), value: value.clone().into(), valid_until })
+        });
+        delegates.chain(attributes).skip(from_index as usize).take(limit.min(MAX_PAGE_SIZE) as usize).collect()
+    }
+
+    /// Like `list_expiring`, one page after `cursor`, which tells whether it
+    /// resumes in the delegates or the attributes.
+    pub fn list_expiring_page(&self, identity: String, within_secs: u64, cursor: Option<String>, limit: u64) -> Page<ExpiringEntry> {
+        let identity = normalize_did(identity);
+        let (part, start) = cursor_position(cursor, 2);
+        let limit = limit.min(MAX_PAGE_SIZE) as usize;
+        let now = now_secs();
+        let expiring = |valid_until: u64| valid_until > now && valid_until <= now.saturating_add(within_secs);
+        let mut items = Vec::new();
+        if part == 0 {
+            let (delegates, next) = self.delegate_index.get(&identity).map_or((Vec::new(), None), |index| {
+                index.page(start, limit, |(delegate_type, delegate)| {
+                    let valid_until = *self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone()))?;
+                    expiring(valid_until).then(|| ExpiringEntry::Delegate { delegate_type: delegate_type.clone(), delegate: delegate.clone(), valid_until })
+                })
+            });
+            if let Some(next) = next {
+                return Page { items: delegates, next_cursor: Some(encode_cursor(0, next)) };
+            }
+            items = delegates;
+        }
+        let start = if part == 1 { start } else { 0 };
+        let (attributes, next) = self.attribute_index.get(&identity).map_or((Vec::new(), None), |index| {
+            index.page(start, limit - items.len(), |(name, value)| {
+                let valid_until = *self.attributes.get(&(identity.clone(), name.clone(), value.clone()))?;
+                expiring(valid_until).then(|| ExpiringEntry::Attribute { name: name.clone(), value: value.clone().into(), valid_until })
+            })
+        });
+        items.extend(attributes);
+        Page { items, next_cursor: next.map(|sequence| encode_cursor(1, sequence)) }
+    }
+
+    fn unindex_attribute(&mut self, identity: &str, pair: (String, Vec<u8>)) {
+        if let Some(index) = self.attribute_index.get_mut(identity) {
+            index.remove(&pair);
+            if index.is_empty() {
+                self.attribute_index.remove(identity);
+            }
+        }
+    }
+
+    /// Up to `limit` (at most `MAX_PAGE_SIZE`) of the identity's attributes,
+    /// starting at `from_index`. Listed like `list_delegates`: revoked
+    /// attributes stay with `valid_until` 0 until purged, and new attributes
+    /// are appended, so pages already read only shift when one is purged;
+    /// `list_attributes_page` does not shift.
+    pub fn list_attributes(&self, identity: String, from_index: u64, limit: u64) -> Vec<AttributeEntry> {
+        let identity = normalize_did(identity);
+        let Some(index) = self.attribute_index.get(&identity) else {
+            return Vec::new();
+        };
+        index
+            .iter()
+            .skip(from_index as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|(name, value)| AttributeEntry {
+                valid_until: self.attributes.get(&(identity.clone(), name.clone(), value.clone())).copied().unwrap_or(0),
+                name: name.clone(),
+                value: value.clone().into(),
+            })
+            .collect()
+    }
+
+    /// Like `list_attributes`, one page after `cursor`.
+    pub fn list_attributes_page(&self, identity: String, cursor: Option<String>, limit: u64) -> Page<AttributeEntry> {
+        let identity = normalize_did(identity);
+        self.find_attributes_by_prefix_page(identity, String::new(), cursor, limit)
+    }
+
+    /// Like `list_attributes`, restricted to attributes whose name starts
+    /// with `prefix`; `from_index` counts matching attributes only. Walks
+    /// the identity's attribute index and nothing else.
+    pub fn find_attributes_by_prefix(&self, identity: String, prefix: String, from_index: u64, limit: u64) -> Vec<AttributeEntry> {
+        let identity = normalize_did(identity);
+        let Some(index) = self.attribute_index.get(&identity) else {
+            return Vec::new();
+        };
+        index
+            .iter()
+            .filter(|(name, _)| name.starts_with(&prefix))
+            .skip(from_index as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|(name, value)| AttributeEntry {
+                valid_until: self.attributes.get(&(identity.clone(), name.clone(), value.clone())).copied().unwrap_or(0),
+                name: name.clone(),
+                value: value.clone().into(),
+            })
+            .collect()
+    }
+
+    /// Like `find_attributes_by_prefix`, one page after `cursor`; `limit`
+    /// counts matching attributes.
+    pub fn find_attributes_by_prefix_page(&self, identity: String, prefix: String, cursor: Option<String>, limit: u64) -> Page<AttributeEntry> {
+        let identity = normalize_did(identity);
+        let (_, start) = cursor_position(cursor, 1);
+        let Some(index) = self.attribute_index.get(&identity) else {
+            return page((Vec::new(), None));
+        };
+        page(index.page(start, limit.min(MAX_PAGE_SIZE) as usize, |(name, value)| {
+            name.starts_with(&prefix).then(|| AttributeEntry {
+                valid_until: self.attributes.get(&(identity.clone(), name.clone(), value.clone())).copied().unwrap_or(0),
+                name: name.clone(),
+                value: value.clone().into(),
+            })
+        }))
+    }
+
+    /// Stored expiry of the attribute in unix seconds, `None` if the exact
+    /// `(name, value)` pair was never set (or has been purged).
+    pub fn get_attribute_validity(&self, identity: String, name: String, value: Vec<u8>) -> Option<u64> {
+        let identity = normalize_did(identity);
+        self.attributes.get(&(identity, name, value)).copied()
+    }
+
+    pub fn valid_attribute(&self, identity: String, name: String, value: Vec<u8>) -> bool {
+        let identity = normalize_did(identity);
+        if self.deactivated.contains_key(&identity) {
+            return false;
+        }
+        match self.attributes.get(&(identity, name, value)) {
+            Some(valid_until) => *valid_until > now_secs(),
+            None => false,
+        }
+    }
+
+    /// Removes the listed delegates and attributes of `identity` whose validity
+    /// has passed, revoked ones included, freeing their storage. Entries that
+    /// are missing or still valid are skipped. Callable by anyone, also on
+    /// locked, renounced, deactivated and migrated identities: expired
+    /// entries no longer resolve, so `changed` is left as is. Logs a
+    /// `did_entry_purged` event per removed entry and returns their number.
+    pub fn purge_expired(&mut self, identity: String, delegates: Vec<(String, String)>, attributes: Vec<(String, Vec<u8>)>) -> u32 {
+        let identity = normalize_did(identity);
+        ensure(delegates.len() + attributes.len() <= MAX_PURGE_ENTRIES, RegistryError::BatchTooLarge);
+        let now = now_secs();
+        let mut purged = Vec::new();
+
+        for (delegate_type, delegate) in delegates {
+            let delegate = normalize_did(delegate);
+            if self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone())).is_some_and(|valid_to| *valid_to <= now) {
+                let valid_to = self.remove_delegate_entry(&identity, &delegate_type, &delegate);
+                purged.push(EntryPurged { identity: identity.clone(), entry: PurgedEntry::Delegate { delegate_type, delegate }, valid_to });
+            }
+        }
+        for (name, value) in attributes {
+            if self.attributes.get(&(identity.clone(), name.clone(), value.clone())).is_some_and(|valid_to| *valid_to <= now) {
+                let valid_to = self.remove_attribute_entry(&identity, &name, &value);
+                purged.push(EntryPurged { identity: identity.clone(), entry: PurgedEntry::attribute(name, &value), valid_to });
+            }
+        }
+
+        let count = purged.len() as u32;
+        for event in purged {
+            DidEvent::from(event).emit();
+        }
+        count
+    }
+
+    /// Removes a stored delegate from storage and its indices, recording the
+    /// write for `resolve_at_version`. Returns the `valid_to` it held.
+    fn remove_delegate_entry(&mut self, identity: &str, delegate_type: &str, delegate: &str) -> u64 {
+        let valid_to = self.delegates.remove(&(identity.to_string(), delegate_type.to_string(), delegate.to_string())).unwrap_or(0);
+        update_count(&mut self.delegate_counts, &mut self.stats.total_delegates, identity, valid_to != 0, false);
+        self.record_write(identity, RecordChange::Delegate { delegate_type: delegate_type.to_string(), delegate: delegate.to_string(), previous: Some(valid_to) });
+        self.update_type_count(identity, delegate_type, valid_to != 0, false);
+        self.unindex_delegate(identity, (delegate_type.to_string(), delegate.to_string()));
+        self.remove_delegation(delegate, &(identity.to_string(), delegate_type.to_string()));
+        valid_to
+    }
+
+    /// Like `remove_delegate_entry`, for a stored attribute.
+    fn remove_attribute_entry(&mut self, identity: &str, name: &str, value: &[u8]) -> u64 {
+        let valid_to = self.attributes.remove(&(identity.to_string(), name.to_string(), value.to_vec())).unwrap_or(0);
+        update_count(&mut self.attribute_counts, &mut self.stats.total_attributes, identity, valid_to != 0, false);
+        self.record_write(identity, RecordChange::Attribute { name: name.to_string(), value: value.to_vec(), previous: Some(valid_to) });
+        self.unindex_attribute(identity, (name.to_string(), value.to_vec()));
+        let key = (identity.to_string(), name.to_string());
+        let mut values = self.attribute_values.get(&key).cloned().unwrap_or_default();
+        values.retain(|stored| stored != value);
+        self.write_attribute_values(key, values);
+        valid_to
+    }
+
+    /// Removes up to `limit` (at most `MAX_CLEAR_ENTRIES`) of the identity's
+    /// stored delegates, then attributes, revoked ones included, releasing
+    /// their storage, and returns how many are left; call again until it
+    /// returns 0. Each call that removes anything counts as one change of
+    /// the identity and logs a single `did_identity_cleared` with the number
+    /// of delegates and attributes it removed. Approved operators are
+    /// dropped too; the owner and other settings are kept. Only callable by
+    /// the permanent owner, see `grant_temporary_ownership`.
+    pub fn clear_identity(&mut self, identity: String, limit: u64) -> u64 {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_permanent_owner(&identity, &actor);
+        let limit = limit.min(MAX_CLEAR_ENTRIES) as usize;
+        self.operators.remove(&identity);
+
+        let delegates: Vec<(String, String)> = self.delegate_index.get(&identity).into_iter().flat_map(|index| index.iter().take(limit).cloned()).collect();
+        let attributes: Vec<(String, Vec<u8>)> = self.attribute_index.get(&identity).into_iter().flat_map(|index| index.iter().take(limit - delegates.len()).cloned()).collect();
+        if !delegates.is_empty() || !attributes.is_empty() {
+            let previous_change = self.touch(&identity, &actor);
+            for (delegate_type, delegate) in &delegates {
+                self.remove_delegate_entry(&identity, delegate_type, delegate);
+            }
+            for (name, value) in &attributes {
+                self.remove_attribute_entry(&identity, name, value);
+            }
+            DidEvent::from(IdentityCleared {
+                identity: identity.clone(),
+                delegates: delegates.len() as u32,
+                attributes: attributes.len() as u32,
+                changed: env::block_height(),
+                previous_change,
+            })
+            .emit();
+            self.notify_observers(&identity, ChangeKind::Cleared);
+        }
+        self.delegate_index.get(&identity).map_or(0, |index| index.len() as u64) + self.attribute_index.get(&identity).map_or(0, |index| index.len() as u64)
+    }
+
+    /// Nonce of `signer` for `identity`. Nonces recorded before they were
+    /// keyed by signer still count for the identity's current owner until
+    /// the owner next signs.
+    pub fn get_nonce(&self, identity: String, signer: String) -> u64 {
+        let identity = normalize_did(identity);
+        let signer = normalize_did(signer);
+        match self.nonce.get(&(identity.clone(), signer.clone())) {
+            Some(n) => *n,
+            None if self.owner_of(&identity) == signer => *self.legacy_nonce.get(&identity).unwrap_or(&0),
+            None => 0,
+        }
+    }
+
+    /// Bytes `change_owner_signed` verifies for these arguments at `signer`'s
+    /// current nonce (the identity owner's when `None`).
+    pub fn get_change_owner_payload(&self, identity: String, signer: Option<String>, valid_until_timestamp: u64, new_owner: String) -> Vec<u8> {
+        let identity = normalize_did(identity);
+        let new_owner = normalize_did(new_owner);
+        payload::change_owner(&self.domain(), self.payload_nonce(&identity, signer), valid_until_timestamp, &identity, &new_owner)
+    }
+
+    /// Bytes each key signs for `change_owner_multisig`, at the identity
+    /// owner's current nonce.
+    pub fn get_change_owner_multisig_payload(&self, identity: String, valid_until_timestamp: u64, new_owner: String) -> Vec<u8> {
+        let identity = normalize_did(identity);
+        let new_owner = normalize_did(new_owner);
+        payload::change_owner_multisig(&self.domain(), self.payload_nonce(&identity, None), valid_until_timestamp, &identity, &new_owner)
+    }
+
+    pub fn get_add_delegate_payload(&self, identity: String, signer: Option<String>, valid_until_timestamp: u64, delegate_type: String, delegate: String, validity_secs: u64) -> Vec<u8> {
+        let identity = normalize_did(identity);
+        let delegate = normalize_did(delegate);
+        payload::add_delegate(&self.domain(), self.payload_nonce(&identity, signer), valid_until_timestamp, &identity, &delegate_type, &delegate, validity_secs)
+    }
+
+    pub fn get_revoke_delegate_payload(&self, identity: String, signer: Option<String>, valid_until_timestamp: u64, delegate_type: String, delegate: String) -> Vec<u8> {
+        let identity = normalize_did(identity);
+        let delegate = normalize_did(delegate);
+        payload::revoke_delegate(&self.domain(), self.payload_nonce(&identity, signer), valid_until_timestamp, &identity, &delegate_type, &delegate)
+    }
+
+    pub fn get_set_attribute_payload(&self, identity: String, signer: Option<String>, valid_until_timestamp: u64, name: String, value: Vec<u8>, validity_secs: u64) -> Vec<u8> {
+        let identity = normalize_did(identity);
+        payload::set_attribute(&self.domain(), self.payload_nonce(&identity, signer), valid_until_timestamp, &identity, &name, &value, validity_secs)
+    }
+
+    pub fn get_revoke_attribute_payload(&self, identity: String, signer: Option<String>, valid_until_timestamp: u64, name: String, value: Vec<u8>) -> Vec<u8> {
+        let identity = normalize_did(identity);
+        payload::revoke_attribute(&self.domain(), self.payload_nonce(&identity, signer), valid_until_timestamp, &identity, &name, &value)
+    }
+
+    pub fn get_batch_payload(&self, identity: String, signer: Option<String>, valid_until_timestamp: u64, ops: Vec<SignedOp>) -> Vec<u8> {
+        let identity = normalize_did(identity);
+        payload::batch(&self.domain(), self.payload_nonce(&identity, signer), valid_until_timestamp, &identity, &ops)
+    }
+
+    /// Advances the owner's nonce, invalidating payloads the owner signed but
+    /// no relayer has submitted yet, and logs the cancelled nonce so relayers
+    /// can drop them. Only callable by the owner.
+    pub fn cancel_nonce(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        let actor = env::predecessor_account_id().to_string();
+        self.assert_only_owner(&identity, &actor);
+
+        let nonce = self.get_nonce(identity.clone(), actor.clone());
+        self.consume_nonce(&identity, &actor);
+        DidEvent::from(NonceCancelled { identity, signer: actor, nonce }).emit();
+    }
+
+    /// Same as `cancel_nonce`.
+    pub fn increment_nonce(&mut self, identity: String) {
+        let identity = normalize_did(identity);
+        self.cancel_nonce(identity);
+    }
+
+    /// Whether `signature` is a valid ed25519 signature over `message` by
+    /// `public_key` (`ed25519:<base58>` or bare base58) and that key signs
+    /// for the identity: it is the owner's key or one of the identity's
+    /// signing keys, see `set_signing_key`. Always false for a deactivated
+    /// identity. Never panics on malformed input.
+    pub fn verify_owner_signature(&self, identity: String, message: Vec<u8>, signature: Vec<u8>, public_key: String) -> bool {
+        let identity = normalize_did(identity);
+        if self.deactivated.contains_key(&identity) {
+            return false;
+        }
+        let encoded = public_key.strip_prefix("ed25519:").unwrap_or(&public_key);
+        let Some(key) = bs58::decode(encoded).into_vec().ok().and_then(|key| <[u8; 32]>::try_from(key).ok()) else {
+            return false;
+        };
+        let owner = self.owner_of(&identity);
+        let signing_key = || PublicKey::from_parts(CurveType::ED25519, key.to_vec()).is_ok_and(|key| self.signing_keys.get(&identity).is_some_and(|keys| keys.contains(&key)));
+        (self.owner_key(&owner) == Some(key) || signing_key()) && signature::verify_ed25519(&signature, &message, &key)
+    }
+
+    /// Receipt of the last signed operation executed for the identity.
+    pub fn get_last_relayed_op(&self, identity: String) -> Option<RelayedOp> {
+        let identity = normalize_did(identity);
+        self.relayed.get(&identity).cloned()
+    }
+
+    pub fn get_changed(&self, identity: String) -> u64 {
+        let identity = normalize_did(identity);
+        *self.changed.get(&identity).unwrap_or(&0)
+    }
+
+    /// Number of the identity's delegates that were not revoked, expired ones
+    /// included until purged. Re-adding a delegate does not count it twice.
+    pub fn count_delegates(&self, identity: String) -> u64 {
+        let identity = normalize_did(identity);
+        self.delegate_counts.get(&identity).copied().unwrap_or(0)
+    }
+
+    /// Number of the identity's `(name, value)` attributes that were not
+    /// revoked, counted like `count_delegates`.
+    pub fn count_attributes(&self, identity: String) -> u64 {
+        let identity = normalize_did(identity);
+        self.attribute_counts.get(&identity).copied().unwrap_or(0)
+    }
+
+    /// Block height and timestamp of the identity's first write, `None` if it
+    /// was never written or only before creation was recorded. For
+    /// identities written before `migrate`, those of their first
+    /// `migrate_entries`.
+    pub fn get_created(&self, identity: String) -> Option<CreatedInfo> {
+        let identity = normalize_did(identity);
+        self.created.get(&identity).cloned()
+    }
+
+    /// Block height and timestamp of the identity's last change, `None` if it
+    /// never changed. `get_changed` keeps returning the height alone.
+    pub fn get_changed_info(&self, identity: String) -> Option<ChangedInfo> {
+        let identity = normalize_did(identity);
+        let block_height = *self.changed.get(&identity)?;
+        Some(ChangedInfo { block_height, timestamp: self.changed_timestamps.get(&identity).copied().unwrap_or(0) })
+    }
+
+    /// Up to `limit` (at most `MAX_PAGE_SIZE`) of the block heights the
+    /// identity changed at, oldest first, starting at `from_index`. Only the
+    /// last `get_history_limit` heights are kept; earlier ones can be
+    /// recovered from the event log by following `previous_change`.
+    pub fn get_change_history(&self, identity: String, from_index: u64, limit: u64) -> Vec<u64> {
+        let identity = normalize_did(identity);
+        history_page(&self.history, &self.history_bounds, self.history_limit, identity, from_index, limit)
+    }
+
+    /// Like `get_change_history`, one page after `cursor`.
+    pub fn get_change_history_page(&self, identity: String, cursor: Option<String>, limit: u64) -> Page<u64> {
+        let identity = normalize_did(identity);
+        history_cursor_page(&self.history, &self.history_bounds, self.history_limit, identity, cursor, limit)
+    }
+
+    /// Up to `limit` (at most `MAX_PAGE_SIZE`) of the identity's owner
+    /// changes, oldest first, starting at `from_index`. Only the last
+    /// `get_history_limit` changes are kept; earlier ones are in the
+    /// `did_owner_changed` events.
+    pub fn get_owner_history(&self, identity: String, from_index: u64, limit: u64) -> Vec<OwnerChange> {
+        let identity = normalize_did(identity);
+        history_page(&self.owner_history, &self.owner_history_bounds, self.history_limit, identity, from_index, limit)
+    }
+
+    /// Like `get_owner_history`, one page after `cursor`.
+    pub fn get_owner_history_page(&self, identity: String, cursor: Option<String>, limit: u64) -> Page<OwnerChange> {
+        let identity = normalize_did(identity);
+        history_cursor_page(&self.owner_history, &self.owner_history_bounds, self.history_limit, identity, cursor, limit)
+    }
+
+    pub fn get_history_limit(&self) -> u64 {
+        self.history_limit
+    }
+
+    /// Sets how many change heights, and owner changes, are kept per identity. Only callable by
+    /// the contract.
+    #[private]
+    pub fn set_history_limit(&mut self, history_limit: u64) {
+        ensure(history_limit > 0, RegistryError::BadHistoryLimit);
+        self.history_limit = history_limit;
+    }
+
+    /// Owner, nonce and change heights of the identity with one page of its
+    /// delegates and attributes, each starting at `from_index` and holding up
+    /// to `limit` (at most `MAX_PAGE_SIZE`) entries. Fetch further pages with
+    /// `next_index` until it is `None`.
+    pub fn get_identity_state(&self, identity: String, from_index: u64, limit: u64) -> IdentityState {
+        let identity = normalize_did(identity);
+        let limit = limit.min(MAX_PAGE_SIZE);
+        let delegates = self.list_delegates(identity.clone(), from_index, limit);
+        let attributes = self.list_attributes(identity.clone(), from_index, limit);
+        let next = from_index.saturating_add(limit);
+        let more = |len: Option<u32>| len.is_some_and(|len| u64::from(len) > next);
+        let next_index = (more(self.delegate_index.get(&identity).map(|index| index.len())) || more(self.attribute_index.get(&identity).map(|index| index.len()))).then_some(next);
+        let owner = self.owner_of(&identity);
+        IdentityState {
+            nonce: self.get_nonce(identity.clone(), owner.clone()),
+            changed: self.get_changed(identity.clone()),
+            created: self.get_created(identity.clone()),
+            identity,
+            owner,
+            delegates,
+            attributes,
+            next_index,
+        }
+    }
+
+    /// Counters over the whole registry: identities written, unrevoked
+    /// delegates and attributes, and owner changes made.
+    pub fn get_registry_stats(&self) -> RegistryStats {
+        self.stats.clone()
+    }
+
+    /// Whether the identity is left without any delegate, attribute or owner
+    /// other than itself.
+    fn is_cleared(&self, identity: &str) -> bool {
+        !self.delegate_index.contains_key(identity) && !self.attribute_index.contains_key(identity) && self.owners.get(identity).is_none_or(|owner| owner == identity)
+    }
+
+    /// Identities written at least once, in the order of their first write,
+    /// positions `from_index` up to `from_index + limit` (`limit` at most
+    /// `MAX_PAGE_SIZE`). With `exclude_cleared`, identities left without any
+    /// delegate, attribute or owner other than themselves are dropped from
+    /// the page, which may then be shorter; walk positions up to
+    /// `get_registry_stats().identities_touched` to see them all.
+    pub fn list_identities(&self, from_index: u64, limit: u64, exclude_cleared: Option<bool>) -> Vec<String> {
+        self.identities
+            .iter()
+            .skip(from_index as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .filter(|identity| !exclude_cleared.unwrap_or(false) || !self.is_cleared(identity))
+            .cloned()
+            .collect()
+    }
+
+    /// Like `list_identities`, one page after `cursor`. `limit` counts the
+    /// identities examined, so with `exclude_cleared` a page may be shorter
+    /// while `next_cursor` is set.
+    pub fn list_identities_page(&self, cursor: Option<String>, limit: u64, exclude_cleared: Option<bool>) -> Page<String> {
+        let (_, start) = cursor_position(cursor, 1);
+        let (identities, next) = self.identities.page(start, limit.min(MAX_PAGE_SIZE) as usize, |identity| Some(identity.clone()));
+        page((identities.into_iter().filter(|identity| !exclude_cleared.unwrap_or(false) || !self.is_cleared(identity)).collect(), next))
+    }
+
+    /// The delegate types `resolve` places in verification relationships,
+    /// with those relationships. Delegates of other types are only listed in
+    /// `verificationMethod`.
+    pub fn get_delegate_type_relationships(&self) -> Vec<(String, Vec<Relationship>)> {
+        document::DELEGATE_TYPES.iter().map(|(_, name, relationships)| (name.to_string(), relationships.to_vec())).collect()
+    }
+
+    /// The identity's W3C DID document, built as described in the `document`
+    /// module from its owner and the delegates and attributes still valid at
+    /// the block time. Delegates are listed by their ed25519 key as found for
+    /// owners (see `set_owner_key`); delegates without one are left out.
+    /// Walks the identity's whole indices. `options.key_format` selects how
+    /// keys are shown, see the `document::key_format` module.
+    pub fn resolve(&self, identity: String, options: Option<ResolveOptions>) -> DidDocument {
+        let identity = normalize_did(identity);
+        document::build(&self.identity_records(&identity), &options.unwrap_or_default()).0
+    }
+
+    /// The verification method or service a DID URL such as
+    /// `did:near:alice.near#delegate-2` names in the `resolve` document,
+    /// `None` if there is none by that fragment. A DID URL without a fragment
+    /// dereferences to the whole document.
+    pub fn dereference(&self, did_url: String) -> Option<serde_json::Value> {
+        let (did, fragment) = match did_url.split_once('#') {
+            Some((did, fragment)) => (did.to_string(), Some(fragment)),
+            None => (did_url.clone(), None),
+        };
+        let document = self.resolve(did, None);
+        let Some(fragment) = fragment else {
+            return serde_json::to_value(document).ok();
+        };
+        let id = format!("{}#{}", document.id, fragment);
+        match document.verification_method.into_iter().find(|method| method.id == id) {
+            Some(method) => serde_json::to_value(method).ok(),
+            None => document.service.into_iter().find(|service| service.id == id).and_then(|service| serde_json::to_value(service).ok()),
+        }
+    }
+
+    /// Up to `limit` (at most `MAX_PAGE_SIZE`) entries of one list of the
+    /// `resolve` document, starting at `from_index`, for documents too large
+    /// to resolve in one view call. Only the records up to the end of the
+    /// page are read. Compare `resolve_summary().versionId` before and after
+    /// the pages to know they were read from the same version.
+    pub fn resolve_paginated(&self, identity: String, section: DocSection, from_index: u64, limit: u64) -> SectionEntries {
+        let identity = normalize_did(identity);
+        let limit = limit.min(MAX_PAGE_SIZE) as usize;
+        let end = (from_index as usize).saturating_add(limit);
+        self.build_document(&identity, Some((section, end))).section(section, from_index as usize, limit)
+    }
+
+    /// Number of entries in each list of the `resolve` document, with the
+    /// version they were counted at. Reads all of the identity's records.
+    pub fn resolve_summary(&self, identity: String) -> DocumentSummary {
+        let identity = normalize_did(identity);
+        let document = self.build_document(&identity, None);
+        DocumentSummary {
+            version_id: self.changed.get(&identity).map(|changed| changed.to_string()),
+            verification_method: document.section_len(DocSection::VerificationMethod) as u32,
+            service: document.section_len(DocSection::Service) as u32,
+            authentication: document.section_len(DocSection::Authentication) as u32,
+            assertion_method: document.section_len(DocSection::AssertionMethod) as u32,
+            key_agreement: document.section_len(DocSection::KeyAgreement) as u32,
+        }
+    }
+
+    /// Builds the identity's document with keys in the default format,
+    /// stopping once `until` has its section filled to the given length.
+    /// Reads the same records as `identity_records`, in canonical order; the
+    /// validity and keys of entries past the end are not read. The document
+    /// of a deactivated identity is empty.
+    fn build_document(&self, identity: &str, until: Option<(DocSection, usize)>) -> DocumentBuilder {
+        let identity = identity.to_string();
+        if self.deactivated.contains_key(&identity) {
+            return DocumentBuilder::new(&identity, &identity);
+        }
+        let now = now_secs();
+        let owner = self.owner_of(&identity);
+        let mut document = DocumentBuilder::new(&identity, &owner);
+        let filled = |document: &DocumentBuilder| until.is_some_and(|(section, len)| document.section_len(section) >= len);
+        if let Some(key) = self.owner_key(&owner) {
+            document.owner(&key);
+        }
+        let (delegates, attributes) = self.sorted_entries(&identity);
+        for (delegate_type, delegate) in delegates {
+            if filled(&document) {
+                return document;
+            }
+            let entry = (identity.clone(), delegate_type, delegate);
+            if self.delegates.get(&entry).is_some_and(|valid_until| *valid_until > now) {
+                if let Some(key) = self.owner_key(&entry.2) {
+                    document.delegate(&entry.1, &key, self.delegate_fragments.get(&entry).copied());
+                }
+            }
+        }
+        for (name, value) in attributes {
+            if filled(&document) {
+                return document;
+            }
+            let entry = (identity.clone(), name, value);
+            if self.attributes.get(&entry).is_some_and(|valid_until| *valid_until > now) {
+                document.attribute(&entry.1, &entry.2, self.attribute_fragments.get(&entry).copied());
+            }
+        }
+        document
+    }
+
+    /// `resolve` with the document's metadata: `created` and `updated` from
+    /// the identity's first and last write, `versionId` the block height of
+    /// the last one. A deactivated identity resolves to an empty document
+    /// with `deactivated` set. Valid attributes left out of the document as
+    /// malformed are named in `malformed_attributes`, keys left out for not
+    /// fitting `options.key_format` in `warnings`.
+    pub fn resolve_with_metadata(&self, identity: String, options: Option<ResolveOptions>) -> ResolutionResult {
+        let identity = normalize_did(identity);
+        document::resolve(&self.identity_records(&identity), &options.unwrap_or_default())
+    }
+
+    /// The `did_document_metadata` of `resolve_with_metadata`, read from the
+    /// identity's write bookkeeping without building the document, for
+    /// caches deciding whether to resolve again. `malformed_attributes` and
+    /// `warnings`, which only building finds, are left empty.
+    pub fn get_document_metadata(&self, identity: String) -> DocumentMetadata {
+        let identity = normalize_did(identity);
+        DocumentMetadata::of_records(&self.written_records(&identity))
+    }
+
+    /// `resolve_with_metadata` of each of `identities` (at most
+    /// `MAX_RESOLVE_MANY`), in the same order. An identifier that does not
+    /// resolve gets a result without document, its
+    /// `did_resolution_metadata.error` telling why, rather than failing the
+    /// call. A deactivated identity gets no document either, but its
+    /// metadata, with `error` `deactivated`.
+    pub fn resolve_many(&self, identities: Vec<String>) -> Vec<ResolutionResult> {
+        ensure(identities.len() <= MAX_RESOLVE_MANY, RegistryError::TooManyIdentities);
+        identities
+            .into_iter()
+            .map(|identity| match document::parse_did(&identity, current_network()) {
+                Ok(identity) if self.deactivated.contains_key(identity) => ResolutionResult {
+                    did_document: None,
+                    did_resolution_metadata: ResolutionMetadata { error: Some(ResolutionError::Deactivated) },
+                    ..self.resolve_with_metadata(identity.to_string(), None)
+                },
+                Ok(identity) => self.resolve_with_metadata(identity.to_string(), None),
+                Err(RegistryError::UnsupportedDidMethod) => ResolutionResult::failed(ResolutionError::MethodNotSupported),
+                Err(_) => ResolutionResult::failed(ResolutionError::InvalidDid),
+            })
+            .collect()
+    }
+
+    /// `resolve_with_metadata` as of the end of block `version_id`, one of
+    /// the identity's change heights (see `get_change_history`), with the
+    /// validity of entries taken at that block's time. Past records are
+    /// rebuilt by undoing the writes made since, which are kept for the last
+    /// `get_history_limit()` change heights, at most `MAX_SNAPSHOT_WRITES` per
+    /// height; older versions, and versions followed by a height with more
+    /// writes, fail with `VersionPruned`, heights the identity did not change
+    /// at with `VersionNotFound`. Owner and delegate keys registered with
+    /// `set_owner_key` are not versioned: the current ones are shown.
+    pub fn resolve_at_version(&self, identity: String, version_id: u64) -> ResolutionResult {
+        let identity = normalize_did(identity);
+        let Some(&(start, end)) = self.snapshot_bounds.get(&identity) else {
+            return ResolutionResult::failed(ResolutionError::VersionNotFound);
+        };
+        let first = start.max(end.saturating_sub(self.history_limit));
+        let mut undone = UndoneWrites { owner: self.owners.get(&identity).cloned(), deactivated: self.deactivated.contains_key(&identity), ..Default::default() };
+        for sequence in (first..end).rev() {
+            let snapshot = &self.snapshots[&(identity.clone(), sequence)];
+            if snapshot.block_height < version_id {
+                return ResolutionResult::failed(ResolutionError::VersionNotFound);
+            }
+            if !snapshot.complete {
+                return ResolutionResult::failed(ResolutionError::VersionPruned);
+            }
+            for (_, change) in snapshot.writes.iter().rev().filter(|(block_height, _)| *block_height > version_id) {
+                match change.clone() {
+                    RecordChange::Owner { previous } => undone.owner = previous,
+                    RecordChange::Delegate { delegate_type, delegate, previous } => {
+                        undone.delegates.insert((delegate_type, delegate), previous);
+                    }
+                    RecordChange::Attribute { name, value, previous } => {
+                        undone.attributes.insert((name, value), previous);
+                    }
+                    RecordChange::Deactivated => undone.deactivated = false,
+                }
+            }
+            if snapshot.block_height == version_id {
+                return document::resolve(&self.records_at(&identity, undone, snapshot), &ResolveOptions::default());
+            }
+        }
+        ResolutionResult::failed(if first > 0 { ResolutionError::VersionPruned } else { ResolutionError::VersionNotFound })
+    }
+
+    /// The verification methods and services that changed from the document
+    /// at `from_version` to the one at `to_version`, both rebuilt as
+    /// `resolve_at_version` does, by fragment; see `document::diff`. When
+    /// either version cannot be rebuilt the diff is empty and `error` says
+    /// why, as `resolve_at_version` would.
+    pub fn diff_versions(&self, identity: String, from_version: u64, to_version: u64) -> DocumentDiff {
+        let identity = normalize_did(identity);
+        let [from, to] = [from_version, to_version].map(|version| self.resolve_at_version(identity.clone(), version));
+        match (from.did_document, to.did_document) {
+            (Some(from), Some(to)) => document::diff(&from, &to),
+            _ => DocumentDiff::failed(from.did_resolution_metadata.error.or(to.did_resolution_metadata.error).unwrap_or(ResolutionError::VersionNotFound)),
+        }
+    }
+
+    /// `identity_records` as of `snapshot`, the current records with
+    /// `undone` taking precedence, valid at the snapshot's time.
+    fn records_at(&self, identity: &str, undone: UndoneWrites, snapshot: &Snapshot) -> IdentityRecords {
+        let identity = identity.to_string();
+        let (mut delegates, mut attributes) = self.sorted_entries(&identity);
+        delegates.retain(|entry| !undone.delegates.contains_key(entry));
+        attributes.retain(|entry| !undone.attributes.contains_key(entry));
+        let valid = |valid_until: &Option<u64>| valid_until.is_some_and(|valid_until| valid_until > snapshot.timestamp);
+        let delegates = delegates
+            .into_iter()
+            .map(|(delegate_type, delegate)| {
+                let valid_until = self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone())).copied();
+                ((delegate_type, delegate), valid_until)
+            })
+            .chain(undone.delegates)
+            .filter(|(_, valid_until)| valid(valid_until))
+            .filter_map(|((delegate_type, delegate), _)| self.delegate_record(&identity, delegate_type, delegate))
+            .collect();
+        let attributes = attributes
+            .into_iter()
+            .map(|(name, value)| {
+                let valid_until = self.attributes.get(&(identity.clone(), name.clone(), value.clone())).copied();
+                ((name, value), valid_until)
+            })
+            .chain(undone.attributes)
+            .filter(|(_, valid_until)| valid(valid_until))
+            .map(|((name, value), _)| self.attribute_record(&identity, name, value))
+            .collect();
+        let owner = undone.owner.unwrap_or_else(|| identity.clone());
+        IdentityRecords {
+            owner_key: self.owner_key(&owner),
+            created: self.created.get(&identity).map(|created| created.timestamp),
+            updated: Some(snapshot.timestamp),
+            version_id: Some(snapshot.block_height),
+            deactivated: undone.deactivated,
+            controller: self.controllers.get(&identity).map(AccountId::to_string),
+            locked: self.locked.contains_key(&identity),
+            identity,
+            owner,
+            delegates,
+            attributes,
+        }
+    }
+
+    /// The identity's owner, delegates and attributes valid at the block
+    /// time, and the times of its writes, as `document::resolve` takes them.
+    /// Delegates without an ed25519 key are left out.
+    fn identity_records(&self, identity: &str) -> IdentityRecords {
+        let (delegates, attributes) = self.valid_entries(identity);
+        let delegates = delegates.into_iter().filter_map(|(delegate_type, delegate)| self.delegate_record(identity, delegate_type, delegate)).collect();
+        let attributes = attributes.into_iter().map(|(name, value)| self.attribute_record(identity, name, value)).collect();
+        let records = self.written_records(identity);
+        IdentityRecords { owner_key: self.owner_key(&records.owner), delegates, attributes, ..records }
+    }
+
+    /// `identity_records` without the entries and owner key: what the
+    /// document metadata is made of.
+    fn written_records(&self, identity: &str) -> IdentityRecords {
+        IdentityRecords {
+            identity: identity.to_string(),
+            owner: self.owner_of(identity),
+            created: self.created.get(identity).map(|created| created.timestamp),
+            updated: self.changed_timestamps.get(identity).copied(),
+            version_id: self.changed.get(identity).copied(),
+            deactivated: self.deactivated.contains_key(identity),
+            controller: self.controllers.get(identity).map(AccountId::to_string),
+            locked: self.locked.contains_key(identity),
+            ..Default::default()
+        }
+    }
+
+    /// A delegate as `IdentityRecords` lists it, with its ed25519 key and
+    /// fragment; `None` if it has no key.
+    fn delegate_record(&self, identity: &str, delegate_type: String, delegate: String) -> Option<(String, String, [u8; 32], Option<u32>)> {
+        let key = self.owner_key(&delegate)?;
+        let fragment = self.delegate_fragments.get(&(identity.to_string(), delegate_type.clone(), delegate.clone())).copied();
+        Some((delegate_type, delegate, key, fragment))
+    }
+
+    /// An attribute as `IdentityRecords` lists it, with its fragment.
+    fn attribute_record(&self, identity: &str, name: String, value: Vec<u8>) -> (String, Vec<u8>, Option<u32>) {
+        let fragment = self.attribute_fragments.get(&(identity.to_string(), name.clone(), value.clone())).copied();
+        (name, value, fragment)
+    }
+
+    /// Fingerprint of the identity's effective records, base58 encoded: the
+    /// sha256 of the borsh serialization of
+    ///
+    /// `("did-near-document", 1u8, identity, owner, delegates, attributes)`
+    ///
+    /// where `delegates` are the `(delegate_type, delegate)` and `attributes`
+    /// the `(name, value)` pairs still valid at the block time, each sorted
+    /// ascending. Expiries are left out, so renewing an entry does not change
+    /// the hash while it stays valid. Walks the identity's whole indices.
+    pub fn document_hash(&self, identity: String) -> String {
+        let identity = normalize_did(identity);
+        let (delegates, attributes) = self.valid_entries(&identity);
+        let owner = self.owner_of(&identity);
+        let document = near_sdk::borsh::to_vec(&("did-near-document", 1u8, identity, owner, delegates, attributes)).unwrap();
+        bs58::encode(env::sha256_array(&document)).into_string()
+    }
+
+    /// The identity's `(delegate_type, delegate)` and `(name, value)` index
+    /// entries, valid or not, each sorted ascending: the canonical order of
+    /// documents and `document_hash`. Walks the identity's whole indices.
+    fn sorted_entries(&self, identity: &str) -> DocumentEntries {
+        let mut delegates: Vec<(String, String)> = self.delegate_index.get(identity).into_iter().flat_map(|index| index.iter()).cloned().collect();
+        delegates.sort();
+        let mut attributes: Vec<(String, Vec<u8>)> = self.attribute_index.get(identity).into_iter().flat_map(|index| index.iter()).cloned().collect();
+        attributes.sort();
+        (delegates, attributes)
+    }
+
+    /// `sorted_entries` still valid at the block time.
+    fn valid_entries(&self, identity: &str) -> DocumentEntries {
+        let now = now_secs();
+        let (mut delegates, mut attributes) = self.sorted_entries(identity);
+        let identity = identity.to_string();
+        delegates.retain(|(delegate_type, delegate)| self.delegates.get(&(identity.clone(), delegate_type.clone(), delegate.clone())).is_some_and(|valid_until| *valid_until > now));
+        attributes.retain(|(name, value)| self.attributes.get(&(identity.clone(), name.clone(), value.clone())).is_some_and(|valid_until| *valid_until > now));
+        (delegates, attributes)
+    }
+
+    /// Whether the identity has any record in the registry. Every write goes
+    /// through `apply`, which sets `changed`, so this is a handful of key
+    /// lookups and never walks the identity's entries.
+    pub fn identity_has_records(&self, identity: String) -> bool {
+        let identity = normalize_did(identity);
+        self.changed.contains_key(&identity)
+            || self.owners.contains_key(&identity)
+            || self.delegate_index.contains_key(&identity)
+            || self.attribute_index.contains_key(&identity)
+    }
+}
+
+#[cfg(all(test, feature = "contract"))]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use near_sdk::{test_utils::{accounts, get_logs, VMContextBuilder}, testing_env};
+    use payload::Domain;
+
+    fn set_context(predecessor: AccountId) {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .predecessor_account_id(predecessor)
+            .block_height(40)
+            .block_timestamp(1_000_000)
+            // .attached_deposit(0)
+            // .account_balance(0)
+            .is_view(false);
+
+        testing_env!(builder.build());
+    }
+
+    fn set_context_at(predecessor: AccountId, timestamp_secs: u64) {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .predecessor_account_id(predecessor)
+            .block_height(40)
+            .block_timestamp(timestamp_secs * 1_000_000_000);
+
+        testing_env!(builder.build());
+    }
+
+    const UUID: &str = "123E4567-E89B-12D3-A456-426614174000";
+
+    fn set_context_with_deposit(predecessor: AccountId, deposit: NearToken) {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(predecessor).block_height(40).attached_deposit(deposit).build());
+    }
+
+    /// Writes the cached collections out in a fresh context, so a long test
+    /// does not leave them all to the gas of its last call.
+    fn flushed(contract: NearDIDRegistry) -> NearDIDRegistry {
+        testing_env!(VMContextBuilder::new().build());
+        env::state_write(&contract);
+        drop(contract);
+        env::state_read().unwrap()
+    }
+
+    const DEADLINE: u64 = 3600;
+
+    /// Logs of the last call without the optional ERC-1056 events.
+    fn registry_logs() -> Vec<String> {
+        get_logs().into_iter().filter(|log| !log.contains(r#""standard":"erc1056""#)).collect()
+    }
+
+    /// Domain of a registry created in the current test context.
+    fn domain() -> Domain {
+        Domain { contract: env::current_account_id(), salt: env::random_seed_array() }
+    }
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn key_identity(key: &SigningKey) -> String {
+        bs58::encode(key.verifying_key().as_bytes()).into_string()
+    }
+
+    fn sign(key: &SigningKey, payload: &[u8]) -> Signature {
+        Signature::Ed25519(key.sign(payload).to_bytes().to_vec())
+    }
+
+    /// Mirrors a wallet's NEP-413 `signMessage` independently of `signature::nep413_hash`.
+    fn sign_nep413(key: &SigningKey, payload: &[u8], nonce: [u8; 32]) -> Signature {
+        use near_sdk::base64::{engine::general_purpose::STANDARD, Engine};
+
+        #[derive(near_sdk::borsh::BorshSerialize)]
+        #[borsh(crate = "near_sdk::borsh")]
+        struct Payload {
+            message: String,
+            nonce: [u8; 32],
+            recipient: String,
+            callback_url: Option<String>,
+        }
+
+        let envelope = Payload {
+            message: STANDARD.encode(payload),
+            nonce,
+            recipient: env::current_account_id().to_string(),
+            callback_url: None,
+        };
+        let mut bytes = 2_147_484_061u32.to_le_bytes().to_vec();
+        bytes.extend(near_sdk::borsh::to_vec(&envelope).unwrap());
+        let signature = key.sign(&env::sha256(&bytes)).to_bytes().to_vec();
+        Signature::Nep413 { signature, nonce }
+    }
+
+    fn secp256k1_key(seed: u8) -> secp256k1::SecretKey {
+        secp256k1::SecretKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    fn secp256k1_public_key(key: &secp256k1::SecretKey) -> [u8; 64] {
+        let public_key = key.public_key(&secp256k1::Secp256k1::new()).serialize_uncompressed();
+        public_key[1..].try_into().unwrap()
+    }
+
+    fn sign_secp256k1(key: &secp256k1::SecretKey, payload: &[u8]) -> Signature {
+        let message = secp256k1::Message::from_slice(&env::keccak256_array(payload)).unwrap();
+        let (recovery_id, compact) = secp256k1::Secp256k1::new().sign_ecdsa_recoverable(&message, key).serialize_compact();
+        let mut signature = compact.to_vec();
+        signature.push(recovery_id.to_i32() as u8 + 27);
+        Signature::Secp256k1(signature)
+    }
+
+    #[test]
+    fn identity_owner() {
+        let owner = accounts(1);
+        let contract = NearDIDRegistry::default();
+        assert_eq!(contract.identity_owner(owner.clone().to_string()), owner);
+    }
+
+    #[test]
+    fn change_owner_success() {
+        let owner = accounts(1);
+        let new_owner = accounts(2);
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+
+        assert_eq!(contract.identity_owner(owner.clone().to_string().to_string()), owner);
+
+        contract.change_owner(owner.clone().to_string(), new_owner.clone().to_string(), None);
+
+        assert_eq!(contract.identity_owner(owner.clone().to_string()), new_owner);
+        // assert_eq!(contract.get_changed(owner), 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn change_owner_fails() {
+        let owner = accounts(1);
+        let attacker = accounts(3);
+        let new_owner = accounts(2);
+
+        set_context(attacker.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        contract.change_owner(owner.to_string(), new_owner.to_string(), None);
+    }
+
+    #[test]
+    fn change_owner_rejects_malformed_owners() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+
+        for new_owner in [String::new(), "ALICE.NEAR".to_string(), "alice..near".to_string(), "a".repeat(3000)] {
+            let error = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.change_owner(owner.to_string(), new_owner.clone(), None))).unwrap_err();
+            assert!(error.downcast_ref::<String>().is_some_and(|message| message.contains("invalid_owner")), "{new_owner:?}");
+        }
+        assert_eq!(contract.identity_owner(owner.to_string()), owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid_owner")]
+    fn change_owner_rejects_overlong_owner() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.change_owner(owner.to_string(), format!("{}.near", "a".repeat(MAX_OWNER_BYTES)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "already_owner")]
+    fn change_owner_rejects_current_owner() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.change_owner(owner.to_string(), owner.to_string(), None);
+    }
+
+    #[test]
+    fn change_owner_accepts_identifier_forms() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+
+        let key_owner = key_identity(&signing_key(2));
+        contract.change_owner(owner.to_string(), format!("did:near:mainnet:{key_owner}"), None);
+        assert_eq!(contract.identity_owner(owner.to_string()), key_owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid_owner")]
+    fn change_owner_signed_rejects_malformed_owner() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = "Not An Account".to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        contract.change_owner_signed(identity, sign(&owner_key, &payload), DEADLINE, new_owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "already_owner")]
+    fn change_owner_signed_rejects_current_owner() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &identity);
+        contract.change_owner_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, identity);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid_owner")]
+    fn schedule_transfer_rejects_malformed_owner() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.schedule_transfer_at(owner.to_string(), String::new(), 3_600);
+    }
+
+    #[test]
+    #[should_panic(expected = "already_owner")]
+    fn schedule_transfer_rejects_current_owner() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.schedule_transfer_at(owner.to_string(), owner.to_string(), 3_600);
+    }
+
+    #[test]
+    #[should_panic(expected = "already_owner")]
+    fn schedule_owner_change_rejects_current_owner() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.schedule_owner_change(owner.to_string(), owner.to_string(), 3_600);
+    }
+
+    #[test]
+    fn propose_and_accept_ownership() {
+        let owner = accounts(1);
+        let proposed = accounts(2);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.propose_owner(owner.to_string(), proposed.clone());
+        assert_eq!(contract.get_proposed_owner(owner.to_string()), Some(proposed.clone()));
+
+        // The owner keeps full control until the proposal is accepted.
+        assert_eq!(contract.identity_owner(owner.to_string()), owner.to_string());
+        assert_eq!(contract.get_changed(owner.to_string()), 0);
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(3).to_string(), 3600, None);
+
+        set_context(proposed.clone());
+        contract.accept_ownership(format!("did:near:{}", owner));
+        assert_eq!(contract.identity_owner(owner.to_string()), proposed.to_string());
+        assert_eq!(contract.get_proposed_owner(owner.to_string()), None);
+        let Some(EventLog { event: DidEvent::OwnerChanged(event), .. }) = EventLog::from_log(&registry_logs()[0]) else { panic!("not an owner event") };
+        assert_eq!((event.previous_owner, event.new_owner), (owner.to_string(), proposed.to_string()));
+
+        contract.revoke_delegate(owner.to_string(), "veriKey".to_string(), accounts(3).to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "not_proposed_owner")]
+    fn accept_ownership_by_other_account() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.propose_owner(owner.to_string(), accounts(2));
+
+        set_context(accounts(3));
+        contract.accept_ownership(owner.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "no_owner_proposal")]
+    fn cancelled_proposal_cannot_be_accepted() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.propose_owner(owner.to_string(), accounts(2));
+        contract.cancel_proposal(owner.to_string());
+        assert_eq!(contract.get_proposed_owner(owner.to_string()), None);
+
+        set_context(accounts(2));
+        contract.accept_ownership(owner.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn propose_owner_only_by_owner() {
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+        contract.propose_owner(accounts(1).to_string(), accounts(3));
+    }
+
+    #[test]
+    fn owner_change_drops_proposal() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.propose_owner(owner.to_string(), accounts(2));
+        contract.change_owner(owner.to_string(), accounts(3).to_string(), None);
+        assert_eq!(contract.get_proposed_owner(owner.to_string()), None);
+    }
+
+    #[test]
+    fn scheduled_owner_change_finalizes_after_delay() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.schedule_owner_change(owner.clone(), accounts(2).to_string(), 3600);
+        assert_eq!(contract.get_pending_owner_change(owner.clone()), Some(PendingOwnerChange { new_owner: accounts(2).to_string(), effective_at: 4_600 }));
+
+        set_context_at(accounts(3), 4_600);
+        assert_eq!(contract.identity_owner(owner.clone()), owner);
+        contract.finalize_owner_change(owner.clone());
+        assert_eq!(contract.identity_owner(owner.clone()), accounts(2).to_string());
+        assert_eq!(contract.get_pending_owner_change(owner.clone()), None);
+        assert_eq!(contract.get_owner_history(owner, 0, 10)[0].new_owner, accounts(2).to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "owner_change_locked")]
+    fn scheduled_owner_change_locked_until_delay() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.schedule_owner_change(owner.clone(), accounts(2).to_string(), 3600);
+
+        set_context_at(accounts(3), 4_599);
+        contract.finalize_owner_change(owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "no_pending_owner_change")]
+    fn cancelled_owner_change_cannot_finalize() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.schedule_owner_change(owner.clone(), accounts(2).to_string(), 3600);
+        set_context_at(accounts(1), 2_000);
+        contract.cancel_owner_change(owner.clone());
+        assert_eq!(contract.identity_owner(owner.clone()), owner);
+
+        set_context_at(accounts(3), 5_000);
+        contract.finalize_owner_change(owner);
+    }
+
+    #[test]
+    fn renounced_identity_is_frozen() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(2)), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        let before = contract.resolve(owner.clone(), None);
+        contract.renounce_ownership(owner.clone());
+
+        assert_eq!(contract.identity_owner(owner.clone()), RENOUNCED_OWNER);
+        let Some(EventLog { event: DidEvent::OwnerChanged(event), .. }) = EventLog::from_log(registry_logs().last().unwrap()) else { panic!("not an owner event") };
+        assert_eq!(event.new_owner, RENOUNCED_OWNER);
+        assert!(contract.valid_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(2))));
+        assert!(!contract.can_manage(owner.clone(), owner.clone()).delegates);
+        assert!(!contract.can_manage(owner.clone(), RENOUNCED_OWNER.to_string()).attributes);
+
+        let result = contract.resolve_with_metadata(owner.clone(), None);
+        assert!(result.did_document_metadata.renounced);
+        assert_eq!(contract.get_document_metadata(owner.clone()), result.did_document_metadata);
+        let document = result.did_document.unwrap();
+        assert_eq!(document.controller, Some(format!("did:near:{}", RENOUNCED_OWNER)));
+        assert_eq!(DidDocument { controller: None, ..document }, before);
+    }
+
+    #[test]
+    #[should_panic(expected = "ownership_renounced")]
+    fn renounced_identity_locks_out_previous_owner() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.renounce_ownership(owner.clone());
+        contract.set_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ownership_renounced")]
+    fn renounced_identity_rejects_signed_ops() {
+        let owner = accounts(1);
+        let hot_key = signing_key(2);
+        let signer = String::from(&ed25519_public_key(&hot_key));
+        let delegate = accounts(2).to_string();
+        let delegate_type = "veriKey".to_string();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+        let payload = contract.get_add_delegate_payload(owner.to_string(), Some(signer), DEADLINE, delegate_type.clone(), delegate.clone(), 3600);
+        contract.renounce_ownership(owner.to_string());
+
+        set_context(accounts(3));
+        contract.add_delegate_signed(owner.to_string(), sign(&hot_key, &payload), DEADLINE, delegate_type, delegate, 3600);
+    }
+
+    #[test]
+    fn recovery_by_two_of_three_guardians() {
+        let owner = accounts(0).to_string();
+        let guardians = vec![accounts(1), accounts(2), accounts(3)];
+        set_context_at(accounts(0), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_config(owner.clone(), guardians.clone(), 2, 3600);
+        assert_eq!(contract.get_recovery_config(owner.clone()), Some(RecoveryConfig { guardians, threshold: 2, delay_secs: 3600 }));
+
+        set_context_at(accounts(1), 2_000);
+        contract.initiate_recovery(owner.clone(), accounts(4).to_string());
+        let Some(EventLog { event: DidEvent::RecoveryInitiated(event), .. }) = EventLog::from_log(&registry_logs()[0]) else { panic!("not a recovery event") };
+        assert_eq!(event, RecoveryInitiated { identity: owner.clone(), guardian: accounts(1), new_owner: accounts(4).to_string(), effective_at: 5_600 });
+        set_context_at(accounts(3), 3_000);
+        contract.approve_recovery(owner.clone());
+        let Some(EventLog { event: DidEvent::RecoveryApproved(event), .. }) = EventLog::from_log(&registry_logs()[0]) else { panic!("not a recovery event") };
+        assert_eq!(event.approvals, 2);
+        assert_eq!(contract.identity_owner(owner.clone()), owner);
+
+        set_context_at(accounts(5), 5_600);
+        contract.finalize_recovery(owner.clone());
+        assert_eq!(contract.identity_owner(owner.clone()), accounts(4).to_string());
+        assert_eq!(contract.get_pending_recovery(owner.clone()), None);
+        let logs: Vec<_> = registry_logs().iter().filter_map(|log| EventLog::from_log(log)).map(|log| log.event).collect();
+        assert!(matches!(logs.as_slice(), [DidEvent::RecoveryFinalized(_), DidEvent::IdentityCreated(_), DidEvent::OwnerChanged(_)]));
+    }
+
+    #[test]
+    #[should_panic(expected = "no_pending_recovery")]
+    fn vetoed_recovery_cannot_finalize() {
+        let owner = accounts(0).to_string();
+        set_context_at(accounts(0), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_config(owner.clone(), vec![accounts(1), accounts(2), accounts(3)], 2, 3600);
+        set_context_at(accounts(1), 2_000);
+        contract.initiate_recovery(owner.clone(), accounts(4).to_string());
+        set_context_at(accounts(2), 2_000);
+        contract.approve_recovery(owner.clone());
+
+        set_context_at(accounts(0), 3_000);
+        contract.cancel_recovery(owner.clone());
+        let Some(EventLog { event: DidEvent::RecoveryCancelled(event), .. }) = EventLog::from_log(&registry_logs()[0]) else { panic!("not a recovery event") };
+        assert_eq!(event, RecoveryCancelled { identity: owner.clone(), actor: owner.clone() });
+
+        set_context_at(accounts(5), 6_000);
+        contract.finalize_recovery(owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "threshold_not_met")]
+    fn recovery_needs_threshold_approvals() {
+        let owner = accounts(0).to_string();
+        set_context_at(accounts(0), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_config(owner.clone(), vec![accounts(1), accounts(2), accounts(3)], 2, 0);
+        set_context_at(accounts(1), 2_000);
+        contract.initiate_recovery(owner.clone(), accounts(4).to_string());
+        contract.finalize_recovery(owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "owner_change_locked")]
+    fn recovery_waits_for_delay() {
+        let owner = accounts(0).to_string();
+        set_context_at(accounts(0), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_config(owner.clone(), vec![accounts(1)], 1, 3600);
+        set_context_at(accounts(1), 2_000);
+        contract.initiate_recovery(owner.clone(), accounts(4).to_string());
+        set_context_at(accounts(1), 5_599);
+        contract.finalize_recovery(owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "not_guardian")]
+    fn recovery_only_by_guardians() {
+        let owner = accounts(0).to_string();
+        set_context(accounts(0));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_config(owner.clone(), vec![accounts(1), accounts(2)], 2, 0);
+        set_context(accounts(3));
+        contract.initiate_recovery(owner, accounts(3).to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "already_approved")]
+    fn recovery_approvals_are_distinct() {
+        let owner = accounts(0).to_string();
+        set_context(accounts(0));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_config(owner.clone(), vec![accounts(1), accounts(2)], 2, 0);
+        set_context(accounts(1));
+        contract.initiate_recovery(owner.clone(), accounts(4).to_string());
+        contract.approve_recovery(owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_threshold")]
+    fn recovery_config_checks_threshold() {
+        let owner = accounts(0).to_string();
+        set_context(accounts(0));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_config(owner, vec![accounts(1), accounts(2)], 3, 0);
+    }
+
+    #[test]
+    fn successor_claims_after_inactivity() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_inactivity_successor(owner.clone(), accounts(2), 86_400);
+        assert_eq!(contract.get_inactivity_successor(owner.clone()), Some(InactivitySuccessor { successor: accounts(2), inactivity_secs: 86_400 }));
+        set_context_at(accounts(1), 50_000);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        assert_eq!(contract.get_last_activity(owner.clone()), Some(50_000));
+
+        set_context_at(accounts(2), 136_400);
+        contract.claim_ownership_after_inactivity(owner.clone());
+        assert_eq!(contract.identity_owner(owner.clone()), accounts(2).to_string());
+        assert_eq!(contract.get_inactivity_successor(owner), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "owner_active")]
+    fn recent_activity_blocks_successor_claim() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_inactivity_successor(owner.clone(), accounts(2), 86_400);
+        set_context_at(accounts(1), 80_000);
+        contract.register_observer(owner.clone(), accounts(4));
+
+        set_context_at(accounts(2), 87_400);
+        contract.claim_ownership_after_inactivity(owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "not_successor")]
+    fn removed_successor_cannot_claim() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_inactivity_successor(owner.clone(), accounts(2), 86_400);
+        contract.remove_inactivity_successor(owner.clone());
+
+        set_context_at(accounts(2), 200_000);
+        contract.claim_ownership_after_inactivity(owner);
+    }
+
+    #[test]
+    fn identifier_kinds() {
+        set_context(accounts(1));
+        let contract = NearDIDRegistry::default();
+        for (identity, kind) in [
+            ("alice.near", IdentifierKind::Account),
+            ("did:near:mainnet:bob", IdentifierKind::Account),
+            (&"a".repeat(64), IdentifierKind::Account),
+            (&key_identity(&signing_key(1)), IdentifierKind::Key),
+            ("0xB9C5714089478a327F09197987f16f9E5d936E8a", IdentifierKind::Key),
+            (UUID, IdentifierKind::Other),
+            ("sha256:9F86D081884C7D659A2FEAA0C55AD015", IdentifierKind::Other),
+        ] {
+            assert_eq!(contract.get_identifier_kind(identity.to_string()), kind, "{}", identity);
+        }
+    }
+
+    #[test]
+    fn registered_identifier_has_chosen_owner() {
+        set_context_with_deposit(accounts(3), REGISTRATION_DEPOSIT);
+        let mut contract = NearDIDRegistry::default();
+        contract.register_identity(format!("did:near:{}", UUID), accounts(1));
+        assert_eq!(contract.identity_owner(UUID.to_string()), accounts(1).to_string());
+        assert_eq!(contract.identities_owned_by(accounts(1).to_string(), 0, 10), vec![UUID.to_string()]);
+        assert_eq!(contract.get_created(UUID.to_string()).unwrap().block_height, 40);
+
+        set_context(accounts(1));
+        contract.add_delegate(UUID.to_string(), "sigAuth".to_string(), accounts(2).to_string(), 3600, None);
+        assert!(contract.valid_delegate(UUID.to_string(), "sigAuth".to_string(), accounts(2).to_string()));
+        assert_eq!(contract.resolve(UUID.to_string(), None).controller, Some("did:near:bob".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "unregistered_identity")]
+    fn unregistered_identifier_has_no_owner() {
+        set_context(accounts(1));
+        let contract = NearDIDRegistry::default();
+        assert_eq!(contract.identity_owner(accounts(2).to_string()), accounts(2).to_string());
+        contract.identity_owner(UUID.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "unregistered_identity")]
+    fn unregistered_identifier_rejects_writes() {
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_attribute(UUID.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "implicit_identity")]
+    fn account_identities_are_not_registered() {
+        set_context_with_deposit(accounts(3), REGISTRATION_DEPOSIT);
+        let mut contract = NearDIDRegistry::default();
+        contract.register_identity(accounts(2).to_string(), accounts(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_registered")]
+    fn identifiers_register_once() {
+        set_context_with_deposit(accounts(3), REGISTRATION_DEPOSIT);
+        let mut contract = NearDIDRegistry::default();
+        contract.register_identity(UUID.to_string(), accounts(1));
+        contract.register_identity(UUID.to_string(), accounts(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "insufficient_deposit")]
+    fn registration_needs_deposit() {
+        set_context_with_deposit(accounts(3), REGISTRATION_DEPOSIT.saturating_sub(NearToken::from_yoctonear(1)));
+        let mut contract = NearDIDRegistry::default();
+        contract.register_identity(UUID.to_string(), accounts(1));
+    }
+
+    #[test]
+    fn controller_manages_entries() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_controller(owner.clone(), accounts(2));
+        assert_eq!(contract.get_controller(owner.clone()), Some(accounts(2)));
+
+        set_context(accounts(2));
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), accounts(3).to_string(), 3600, None);
+        assert!(contract.valid_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec()));
+        assert_eq!(contract.can_manage(owner.clone(), accounts(2).to_string()), ManagePermissions { delegates: true, attributes: true, owner: false });
+
+        let metadata = contract.resolve_with_metadata(owner.clone(), None).did_document_metadata;
+        assert_eq!(metadata.controller, Some(accounts(2).to_string()));
+        assert_eq!(contract.get_document_metadata(owner.clone()), metadata);
+        assert_eq!(contract.resolve(owner, None).controller, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn controller_cannot_change_owner() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_controller(owner.clone(), accounts(2));
+
+        set_context(accounts(2));
+        contract.change_owner(owner, accounts(2).to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn controller_cannot_replace_itself() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_controller(owner.clone(), accounts(2));
+
+        set_context(accounts(2));
+        contract.set_controller(owner, accounts(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn cleared_controller_loses_access() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_controller(owner.clone(), accounts(2));
+        contract.clear_controller(owner.clone());
+        assert_eq!(contract.get_controller(owner.clone()), None);
+        assert_eq!(contract.resolve_with_metadata(owner.clone(), None).did_document_metadata.controller, None);
+
+        set_context(accounts(2));
+        contract.revoke_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), None);
+    }
+
+    #[test]
+    fn add_delegate_success() {
+        let identity = accounts(1);
+        let owner = identity.clone();
+        let delegate = accounts(2);
+        let delegate_type = "veriKey".to_string();
+        let validity_secs = 3600;
+
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string(), validity_secs, None);
+
+        let valid = contract.valid_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string());
+        assert!(valid, "El delegado debería ser válido");
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn add_delegate_fails() {
+        let identity = accounts(1);
+        let attacker = accounts(3);
+        let delegate = accounts(2);
+        let delegate_type = "veriKey".to_string();
+
+        set_context(attacker.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(identity.to_string(), delegate_type, delegate.to_string(), 1000, None);
+    }
+
+    #[test]
+    fn revoke_delegate_success() {
+        let identity = accounts(1);
+        let owner = identity.clone();
+        let delegate = accounts(2);
+        let delegate_type = "veriKey".to_string();
+        let validity_secs = 3600;
+
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+
+        contract.add_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string(), validity_secs, None);
+        assert!(contract.valid_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string()));
+
+        contract.revoke_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string(), None);
+        assert!(!contract.valid_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn revoke_delegate_fails() {
+        let identity = accounts(1);
+        let attacker = accounts(3);
+        let delegate = accounts(2);
+        let delegate_type = "veriKey".to_string();
+
+        set_context(attacker.clone());
+
+        let mut contract = NearDIDRegistry::default();
+
+        contract.revoke_delegate(identity.to_string(), delegate_type, delegate.to_string(), None);
+    }
+
+    #[test]
+    fn set_attribute_success() {
+        let identity = accounts(1);
+        let owner = identity.clone();
+        let name = "did/pub/Ed25519/veriKey/base64".to_string();
+        let value = b"base64EncodedKeyHere".to_vec();
+        let validity_secs = 3600;
+
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(identity.clone().to_string(), name.clone(), value.clone(), validity_secs, None);
+
+        let stored_valid_until = contract
+            .attributes
+            .get(&(identity.clone().to_string(), name.clone(), value.clone()))
+            .unwrap();
+        assert_eq!(stored_valid_until, &validity_secs);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn test_set_attribute_fails_if_not_owner() {
+        let identity = accounts(1);
+        let attacker = accounts(3);
+        let name = "did/pub/Ed25519/veriKey/base64".to_string();
+        let value = b"maliciousKey".to_vec();
+
+        set_context(attacker.clone());
+
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(identity.to_string(), name, value, 1000, None);
+    }
+
+    #[test]
+    fn test_revoke_attribute_success() {
+        let identity = accounts(1);
+        let owner = identity.clone();
+        let name = "did/service/endpoint".to_string();
+        let value = b"https://example.com".to_vec();
+        let validity_secs = 3600;
+
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(identity.clone().to_string(), name.clone(), value.clone(), validity_secs, None);
+        assert!(contract.valid_attribute(identity.clone().to_string(), name.clone(), value.clone()));
+
+        contract.revoke_attribute(identity.clone().to_string(), name.clone(), value.clone(), None);
+        assert!(!contract.valid_attribute(identity.clone().to_string(), name.clone(), value.clone()));
+
+        let stored = contract
+            .attributes
+            .get(&(identity.clone().to_string(), name.clone(), value.clone()))
+            .unwrap();
+        assert_eq!(stored, &0, "El atributo debe estar revocado (valor 0)");
+    }
+
+    #[test]
+    fn change_owner_signed_by_relayer() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        contract.change_owner_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, new_owner.clone());
+
+        assert_eq!(contract.identity_owner(identity.clone()), new_owner);
+        assert_eq!(contract.get_nonce(identity.clone(), key_identity(&owner_key)), 1);
+        assert_eq!(contract.get_changed(identity), 40);
+    }
+
+    #[test]
+    fn change_owner_signed_with_registered_key() {
+        let owner = accounts(1);
+        let owner_key = signing_key(1);
+        let new_owner = accounts(2).to_string();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        let public_key = PublicKey::from_parts(CurveType::ED25519, owner_key.verifying_key().to_bytes().to_vec()).unwrap();
+        contract.set_owner_key(public_key);
+
+        set_context(accounts(3));
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, owner.as_str(), &new_owner);
+        contract.change_owner_signed(owner.to_string(), sign(&owner_key, &payload), DEADLINE, new_owner.clone());
+
+        assert_eq!(contract.identity_owner(owner.to_string()), new_owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn change_owner_signed_wrong_signer() {
+        let owner_key = signing_key(1);
+        let attacker_key = signing_key(2);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(3).to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        contract.change_owner_signed(identity, sign(&attacker_key, &payload), DEADLINE, new_owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn change_owner_signed_replay() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = key_identity(&signing_key(2));
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        let signature = sign(&owner_key, &payload);
+        contract.change_owner_signed(identity.clone(), signature.clone(), DEADLINE, new_owner.clone());
+        contract.change_owner_signed(identity, signature, DEADLINE, new_owner);
+    }
+
+    #[test]
+    fn add_delegate_signed_by_relayer() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let delegate = accounts(2).to_string();
+        let delegate_type = "veriKey".to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::add_delegate(&domain(), 0, DEADLINE, &identity, &delegate_type, &delegate, 3600);
+        contract.add_delegate_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, delegate_type.clone(), delegate.clone(), 3600);
+
+        assert!(contract.valid_delegate(identity.clone(), delegate_type, delegate));
+        assert_eq!(contract.get_nonce(identity.clone(), key_identity(&owner_key)), 1);
+        assert_eq!(contract.get_changed(identity), 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn add_delegate_signed_stale_nonce() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let delegate = accounts(2).to_string();
+        let delegate_type = "veriKey".to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::add_delegate(&domain(), 0, DEADLINE, &identity, &delegate_type, &delegate, 3600);
+        let other = payload::revoke_delegate(&domain(), 0, DEADLINE, &identity, &delegate_type, &delegate);
+        contract.revoke_delegate_signed(identity.clone(), sign(&owner_key, &other), DEADLINE, delegate_type.clone(), delegate.clone());
+        contract.add_delegate_signed(identity, sign(&owner_key, &payload), DEADLINE, delegate_type, delegate, 3600);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn add_delegate_signed_altered_validity() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let delegate = accounts(2).to_string();
+        let delegate_type = "veriKey".to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::add_delegate(&domain(), 0, DEADLINE, &identity, &delegate_type, &delegate, 3600);
+        contract.add_delegate_signed(identity, sign(&owner_key, &payload), DEADLINE, delegate_type, delegate, 3600 * 24);
+    }
+
+    #[test]
+    fn revoke_delegate_signed_by_relayer() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let delegate = accounts(2).to_string();
+        let delegate_type = "veriKey".to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::add_delegate(&domain(), 0, DEADLINE, &identity, &delegate_type, &delegate, 3600);
+        contract.add_delegate_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, delegate_type.clone(), delegate.clone(), 3600);
+        assert!(contract.valid_delegate(identity.clone(), delegate_type.clone(), delegate.clone()));
+
+        let payload = payload::revoke_delegate(&domain(), 1, DEADLINE, &identity, &delegate_type, &delegate);
+        contract.revoke_delegate_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, delegate_type.clone(), delegate.clone());
+
+        assert!(!contract.valid_delegate(identity.clone(), delegate_type, delegate));
+        assert_eq!(contract.get_nonce(identity.clone(), key_identity(&owner_key)), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn revoke_delegate_signed_other_delegate() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let delegate_type = "veriKey".to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::revoke_delegate(&domain(), 0, DEADLINE, &identity, &delegate_type, accounts(2).as_str());
+        contract.revoke_delegate_signed(identity, sign(&owner_key, &payload), DEADLINE, delegate_type, accounts(4).to_string());
+    }
+
+    #[test]
+    fn set_attribute_signed_by_relayer() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let name = "did/svc/LinkedDomains".to_string();
+        let value = b"https://example.com".to_vec();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::set_attribute(&domain(), 0, DEADLINE, &identity, &name, &value, 3600);
+        contract.set_attribute_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, name.clone(), value.clone(), 3600);
+
+        assert!(contract.valid_attribute(identity.clone(), name, value));
+        assert_eq!(contract.get_nonce(identity.clone(), key_identity(&owner_key)), 1);
+        assert_eq!(contract.get_changed(identity), 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn set_attribute_signed_same_nonce_twice() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let name = "did/svc/LinkedDomains".to_string();
+        let first = b"https://example.com".to_vec();
+        let second = b"https://example.org".to_vec();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let first_payload = payload::set_attribute(&domain(), 0, DEADLINE, &identity, &name, &first, 3600);
+        let second_payload = payload::set_attribute(&domain(), 0, DEADLINE, &identity, &name, &second, 3600);
+        contract.set_attribute_signed(identity.clone(), sign(&owner_key, &first_payload), DEADLINE, name.clone(), first, 3600);
+        contract.set_attribute_signed(identity, sign(&owner_key, &second_payload), DEADLINE, name, second, 3600);
+    }
+
+    #[test]
+    fn revoke_attribute_signed_by_relayer() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let name = "did/svc/LinkedDomains".to_string();
+        let value = b"https://example.com".to_vec();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::set_attribute(&domain(), 0, DEADLINE, &identity, &name, &value, 3600);
+        contract.set_attribute_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, name.clone(), value.clone(), 3600);
+
+        let payload = payload::revoke_attribute(&domain(), 1, DEADLINE, &identity, &name, &value);
+        contract.revoke_attribute_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, name.clone(), value.clone());
+
+        assert!(!contract.valid_attribute(identity.clone(), name, value));
+        assert_eq!(contract.get_nonce(identity.clone(), key_identity(&owner_key)), 2);
+    }
+
+    #[test]
+    fn revoke_attribute_signed_never_set() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let name = "did/svc/LinkedDomains".to_string();
+        let value = b"https://example.com".to_vec();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::revoke_attribute(&domain(), 0, DEADLINE, &identity, &name, &value);
+        contract.revoke_attribute_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, name.clone(), value.clone());
+
+        assert_eq!(contract.attributes.get(&(identity.clone(), name, value)), Some(&0));
+        assert_eq!(contract.get_nonce(identity.clone(), key_identity(&owner_key)), 1);
+        assert_eq!(contract.get_changed(identity), 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn revoke_attribute_signed_other_value() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let name = "did/svc/LinkedDomains".to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::revoke_attribute(&domain(), 0, DEADLINE, &identity, &name, b"https://example.com");
+        contract.revoke_attribute_signed(identity, sign(&owner_key, &payload), DEADLINE, name, b"https://example.org".to_vec());
+    }
+
+    #[test]
+    fn change_owner_signed_secp256k1_eth_address() {
+        let owner_key = secp256k1_key(1);
+        // EIP-55 checksummed address of `owner_key`; matched case-insensitively.
+        let identity = "0x1a642f0E3c3aF545E7AcBD38b07251B3990914F1".to_string();
+        let new_owner = accounts(2).to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        contract.change_owner_signed(identity.clone(), sign_secp256k1(&owner_key, &payload), DEADLINE, new_owner.clone());
+
+        assert_eq!(contract.identity_owner(identity.clone()), new_owner);
+        assert_eq!(contract.get_nonce(identity.clone(), identity.clone()), 1);
+    }
+
+    #[test]
+    fn change_owner_signed_secp256k1_registered_key() {
+        let owner = accounts(1);
+        let owner_key = secp256k1_key(1);
+        let new_owner = accounts(2).to_string();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        let public_key = PublicKey::from_parts(CurveType::SECP256K1, secp256k1_public_key(&owner_key).to_vec()).unwrap();
+        contract.set_owner_key(public_key);
+
+        set_context(accounts(3));
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, owner.as_str(), &new_owner);
+        contract.change_owner_signed(owner.to_string(), sign_secp256k1(&owner_key, &payload), DEADLINE, new_owner.clone());
+
+        assert_eq!(contract.identity_owner(owner.to_string()), new_owner);
+        assert_eq!(contract.get_nonce(owner.to_string(), owner.to_string()), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn change_owner_signed_secp256k1_wrong_signer() {
+        let identity = signature::eth_address(&secp256k1_public_key(&secp256k1_key(1)));
+        let attacker_key = secp256k1_key(2);
+        let new_owner = accounts(3).to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        contract.change_owner_signed(identity, sign_secp256k1(&attacker_key, &payload), DEADLINE, new_owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn change_owner_signed_without_domain_separator() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let legacy = near_sdk::borsh::to_vec(&(env::current_account_id().as_str(), 0u64, identity.as_str(), "changeOwner", new_owner.as_str())).unwrap();
+        contract.change_owner_signed(identity, sign(&owner_key, &legacy), DEADLINE, new_owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn change_owner_signed_other_registry() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&Domain { contract: "registry.testnet".parse().unwrap(), ..domain() }, 0, DEADLINE, &identity, &new_owner);
+        contract.change_owner_signed(identity, sign(&owner_key, &payload), DEADLINE, new_owner);
+    }
+
+    #[test]
+    fn signature_test_vectors() {
+        set_context(accounts(3));
+
+        let payload = payload::change_owner(&Domain { contract: "registry.near".parse().unwrap(), salt: [0; 32] }, 0, DEADLINE, "alice.near", "bob.near");
+        assert_eq!(bs58::encode(&payload).into_string(), "Fa8vd1uUM3YYWgjC6qkURCNN7KTBiidqh62DT9sNYtwkBW64f6enQXir56wcQjtREDzxc1C7xSgZVdBNEh3ovH39crVDZf2imhp2KRhC3AzEppKUdMaaTV7uetVgMdwn2dqVLgJo6ErtXMhqqixMcFb9djamY175A9");
+
+        let Signature::Ed25519(ed25519) = sign(&signing_key(1), &payload) else { unreachable!() };
+        assert_eq!(bs58::encode(ed25519).into_string(), "5DQMe4574myq5FrcP8pUwi1pxYRF893Qo7L83Y9YthBTRneKmBcJvQXSng2XmF5jLygjLPANSvyKBpevuDzdXVM4");
+
+        let secp256k1_key = secp256k1_key(1);
+        assert_eq!(signature::eth_address(&secp256k1_public_key(&secp256k1_key)), "0x1a642f0e3c3af545e7acbd38b07251b3990914f1");
+        let Signature::Secp256k1(secp256k1) = sign_secp256k1(&secp256k1_key, &payload) else { unreachable!() };
+        assert_eq!(bs58::encode(secp256k1).into_string(), "63qpTUG8KemzzhmeDr8wnT7j12MtVTfNyWehiYp4fdYgiKJFkW1A3iy6MLomiMXDo25TJp4f3kR4BRkDyzK1QqRSb");
+    }
+
+    #[test]
+    fn change_owner_signed_nep413() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        contract.change_owner_signed(identity.clone(), sign_nep413(&owner_key, &payload, [7; 32]), DEADLINE, new_owner.clone());
+
+        assert_eq!(contract.identity_owner(identity.clone()), new_owner);
+        assert_eq!(contract.get_nonce(identity.clone(), key_identity(&owner_key)), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn change_owner_signed_nep413_raw_payload() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        let Signature::Ed25519(signature) = sign(&owner_key, &payload) else { unreachable!() };
+        contract.change_owner_signed(identity, Signature::Nep413 { signature, nonce: [7; 32] }, DEADLINE, new_owner);
+    }
+
+    #[test]
+    fn nep413_test_vector() {
+        set_context(accounts(3));
+
+        let payload = payload::change_owner(&Domain { contract: "registry.near".parse().unwrap(), salt: [0; 32] }, 0, DEADLINE, "alice.near", "bob.near");
+        let hash = signature::nep413_hash(&payload, [7; 32], &"registry.near".parse().unwrap());
+        assert_eq!(bs58::encode(hash).into_string(), "BmRBBg2pNFj2EGgvNmDzPYmxjGcDSuqoQDx5vpfVwP5Q");
+
+        let Signature::Nep413 { signature, .. } = sign_nep413(&signing_key(1), &payload, [7; 32]) else { unreachable!() };
+        assert_eq!(bs58::encode(signature).into_string(), "2FTP11TiyBdxZFPQzCnmj3to2yjejFejG12JfVQ8JBBnvamz2sWpTxnVd4a4LzuhusLsfjEtpu6nuFfYvwBqvauE");
+    }
+
+    #[test]
+    fn signed_operations_from_payload_views() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let delegate = accounts(2).to_string();
+        let delegate_type = "veriKey".to_string();
+        let name = "did/svc/LinkedDomains".to_string();
+        let value = b"https://example.com".to_vec();
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+
+        let payload = contract.get_add_delegate_payload(identity.clone(), None, DEADLINE, delegate_type.clone(), delegate.clone(), 3600);
+        contract.add_delegate_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, delegate_type.clone(), delegate.clone(), 3600);
+        assert!(contract.valid_delegate(identity.clone(), delegate_type.clone(), delegate.clone()));
+
+        let payload = contract.get_revoke_delegate_payload(identity.clone(), None, DEADLINE, delegate_type.clone(), delegate.clone());
+        contract.revoke_delegate_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, delegate_type.clone(), delegate.clone());
+        assert!(!contract.valid_delegate(identity.clone(), delegate_type, delegate));
+
+        let payload = contract.get_set_attribute_payload(identity.clone(), None, DEADLINE, name.clone(), value.clone(), 3600);
+        contract.set_attribute_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, name.clone(), value.clone(), 3600);
+        assert!(contract.valid_attribute(identity.clone(), name.clone(), value.clone()));
+
+        let payload = contract.get_revoke_attribute_payload(identity.clone(), None, DEADLINE, name.clone(), value.clone());
+        contract.revoke_attribute_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, name.clone(), value.clone());
+        assert!(!contract.valid_attribute(identity.clone(), name, value));
+
+        let new_owner = accounts(4).to_string();
+        let payload = contract.get_change_owner_payload(identity.clone(), None, DEADLINE, new_owner.clone());
+        contract.change_owner_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, new_owner.clone());
+        assert_eq!(contract.identity_owner(identity.clone()), new_owner);
+        assert_eq!(contract.get_nonce(identity.clone(), key_identity(&owner_key)), 5);
+    }
+
+    #[test]
+    fn execute_signed_batch_onboarding() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(1).to_string();
+        let name = "did/svc/LinkedDomains".to_string();
+        let value = b"https://example.com".to_vec();
+        let ops = vec![
+            SignedOp::ChangeOwner { new_owner: new_owner.clone() },
+            SignedOp::AddDelegate { delegate_type: "veriKey".to_string(), delegate: accounts(2).to_string(), validity_secs: 3600 },
+            SignedOp::AddDelegate { delegate_type: "sigAuth".to_string(), delegate: accounts(3).to_string(), validity_secs: 3600 },
+            SignedOp::SetAttribute { name: name.clone(), value: value.clone(), validity_secs: 3600 },
+        ];
+
+        set_context(accounts(4));
+        let mut contract = NearDIDRegistry::default();
+
+        let payload = contract.get_batch_payload(identity.clone(), None, DEADLINE, ops.clone());
+        contract.execute_signed_batch(identity.clone(), sign(&owner_key, &payload), DEADLINE, ops);
+
+        assert_eq!(contract.identity_owner(identity.clone()), new_owner);
+        assert!(contract.valid_delegate(identity.clone(), "veriKey".to_string(), accounts(2).to_string()));
+        assert!(contract.valid_delegate(identity.clone(), "sigAuth".to_string(), accounts(3).to_string()));
+        assert!(contract.valid_attribute(identity.clone(), name, value));
+        assert_eq!(contract.get_nonce(identity.clone(), key_identity(&owner_key)), 1);
+        assert_eq!(contract.get_changed(identity), 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn execute_signed_batch_extra_op() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let mut ops = vec![SignedOp::AddDelegate { delegate_type: "veriKey".to_string(), delegate: accounts(2).to_string(), validity_secs: 3600 }];
+
+        set_context(accounts(4));
+        let mut contract = NearDIDRegistry::default();
+
+        let payload = contract.get_batch_payload(identity.clone(), None, DEADLINE, ops.clone());
+        ops.push(SignedOp::ChangeOwner { new_owner: accounts(4).to_string() });
+        contract.execute_signed_batch(identity, sign(&owner_key, &payload), DEADLINE, ops);
+    }
+
+    #[test]
+    #[should_panic(expected = "empty_batch")]
+    fn execute_signed_batch_empty() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+
+        set_context(accounts(4));
+        let mut contract = NearDIDRegistry::default();
+
+        let payload = contract.get_batch_payload(identity.clone(), None, DEADLINE, vec![]);
+        contract.execute_signed_batch(identity, sign(&owner_key, &payload), DEADLINE, vec![]);
+    }
+
+    #[test]
+    fn change_owner_signed_expires_at_block_time() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context_at(accounts(3), 1_700_000_000);
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = contract.get_change_owner_payload(identity.clone(), None, 1_700_000_000, new_owner.clone());
+        contract.change_owner_signed(identity.clone(), sign(&owner_key, &payload), 1_700_000_000, new_owner.clone());
+
+        assert_eq!(contract.identity_owner(identity), new_owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "signature_expired")]
+    fn change_owner_signed_one_second_late() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context_at(accounts(3), 1_700_000_001);
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = contract.get_change_owner_payload(identity.clone(), None, 1_700_000_000, new_owner.clone());
+        contract.change_owner_signed(identity, sign(&owner_key, &payload), 1_700_000_000, new_owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn change_owner_signed_extended_deadline() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context_at(accounts(3), 1_700_000_001);
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = contract.get_change_owner_payload(identity.clone(), None, 1_700_000_000, new_owner.clone());
+        contract.change_owner_signed(identity, sign(&owner_key, &payload), 1_700_000_060, new_owner);
+    }
+
+    #[test]
+    fn nonces_progress_per_signer() {
+        let first_key = signing_key(1);
+        let second_key = signing_key(2);
+        let identity = key_identity(&first_key);
+        let first = key_identity(&first_key);
+        let second = key_identity(&second_key);
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+
+        let payload = contract.get_add_delegate_payload(identity.clone(), None, DEADLINE, "veriKey".to_string(), accounts(2).to_string(), 3600);
+        contract.add_delegate_signed(identity.clone(), sign(&first_key, &payload), DEADLINE, "veriKey".to_string(), accounts(2).to_string(), 3600);
+        let payload = contract.get_change_owner_payload(identity.clone(), None, DEADLINE, second.clone());
+        contract.change_owner_signed(identity.clone(), sign(&first_key, &payload), DEADLINE, second.clone());
+        assert_eq!(contract.get_nonce(identity.clone(), first.clone()), 2);
+        assert_eq!(contract.get_nonce(identity.clone(), second.clone()), 0);
+
+        let payload = contract.get_revoke_delegate_payload(identity.clone(), Some(second.clone()), DEADLINE, "veriKey".to_string(), accounts(2).to_string());
+        contract.revoke_delegate_signed(identity.clone(), sign(&second_key, &payload), DEADLINE, "veriKey".to_string(), accounts(2).to_string());
+        assert_eq!(contract.get_nonce(identity.clone(), first), 2);
+        assert_eq!(contract.get_nonce(identity, second), 1);
+    }
+
+    #[test]
+    fn legacy_nonce_migrates_to_owner() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+        contract.legacy_nonce.insert(identity.clone(), 7);
+
+        assert_eq!(contract.get_nonce(identity.clone(), identity.clone()), 7);
+        assert_eq!(contract.get_nonce(identity.clone(), accounts(3).to_string()), 0);
+
+        let payload = contract.get_change_owner_payload(identity.clone(), None, DEADLINE, accounts(2).to_string());
+        contract.change_owner_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, accounts(2).to_string());
+
+        assert_eq!(contract.get_nonce(identity.clone(), identity.clone()), 8);
+        assert!(contract.legacy_nonce.get(&identity).is_none());
+    }
+
+    #[test]
+    fn migrate_reads_legacy_state() {
+        let owner_key = signing_key(1);
+        let key_owned = key_identity(&owner_key);
+        let identity = accounts(1).to_string();
+
+        set_context(accounts(1));
+        let mut legacy = LegacyRegistry {
+            owners: LookupMap::new(b"o"),
+            delegates: LookupMap::new(b"d"),
+            attributes: LookupMap::new(b"a"),
+            changed: LookupMap::new(b"c"),
+            nonce: LookupMap::new(b"n"),
+        };
+        legacy.owners.insert(identity.clone(), accounts(2).to_string());
+        legacy.delegates.insert((identity.clone(), "veriKey".to_string(), accounts(3).to_string()), 3600);
+        legacy.attributes.insert((identity.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec()), 3600);
+        legacy.changed.insert(identity.clone(), 12);
+        legacy.nonce.insert(key_owned.clone(), 7);
+        env::state_write(&legacy);
+        drop(legacy);
+
+        let contract = flushed(NearDIDRegistry::migrate());
+        assert_eq!(contract.identity_owner(identity.clone()), accounts(2).to_string());
+        assert!(contract.valid_delegate(identity.clone(), "veriKey".to_string(), accounts(3).to_string()));
+        assert!(contract.valid_attribute(identity.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec()));
+        assert_eq!(contract.get_changed(identity), 12);
+        assert_eq!(contract.get_nonce(key_owned.clone(), key_owned), 7);
+        assert_eq!(contract.get_registry_salt(), env::random_seed_array());
+        assert_eq!(contract.get_history_limit(), DEFAULT_HISTORY_LIMIT);
+    }
+
+    #[test]
+    fn migrate_entries_resolves_as_before() {
+        let identity = accounts(1).to_string();
+        let key = ed25519_public_key(&signing_key(1)).as_bytes()[1..].to_vec();
+        let expected = {
+            set_context(accounts(1));
+            let mut contract = NearDIDRegistry::default();
+            contract.change_owner(identity.clone(), accounts(2).to_string(), None);
+            set_context(accounts(2));
+            contract.add_delegate(identity.clone(), "veriKey".to_string(), accounts(3).to_string(), 3600, None);
+            contract.set_attribute(identity.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+            contract.set_attribute(identity.clone(), "did/pub/Ed25519/veriKey/base64".to_string(), key.clone(), 3600, None);
+            contract.resolve(identity.clone(), None)
+        };
+        near_sdk::mock::with_mocked_blockchain(|blockchain| blockchain.take_storage());
+
+        set_context(accounts(1));
+        let mut legacy = LegacyRegistry {
+            owners: LookupMap::new(b"o"),
+            delegates: LookupMap::new(b"d"),
+            attributes: LookupMap::new(b"a"),
+            changed: LookupMap::new(b"c"),
+            nonce: LookupMap::new(b"n"),
+        };
+        legacy.owners.insert(identity.clone(), accounts(2).to_string());
+        legacy.delegates.insert((identity.clone(), "veriKey".to_string(), accounts(3).to_string()), 3600);
+        legacy.delegates.insert((identity.clone(), "sigAuth".to_string(), accounts(4).to_string()), 0);
+        legacy.attributes.insert((identity.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec()), 3600);
+        legacy.attributes.insert((identity.clone(), "did/pub/Ed25519/veriKey/base64".to_string(), key.clone()), 3600);
+        legacy.changed.insert(identity.clone(), 12);
+        env::state_write(&legacy);
+        drop(legacy);
+
+        let mut contract = flushed(NearDIDRegistry::migrate());
+        let delegates = vec![("veriKey".to_string(), accounts(3).to_string()), ("sigAuth".to_string(), accounts(4).to_string()), ("veriKey".to_string(), accounts(5).to_string())];
+        let attributes = vec![("did/svc/Hub".to_string(), b"https://hub.example".to_vec()), ("did/pub/Ed25519/veriKey/base64".to_string(), key)];
+        contract.migrate_entries(identity.clone(), delegates[..1].to_vec(), Vec::new());
+        contract.migrate_entries(identity.clone(), delegates.clone(), attributes.clone());
+        contract.migrate_entries(identity.clone(), delegates, attributes);
+
+        assert_eq!(contract.resolve(identity.clone(), None), expected);
+        assert_eq!((contract.count_delegates(identity.clone()), contract.count_attributes(identity.clone())), (1, 2));
+        assert_eq!(contract.list_delegates(identity.clone(), 0, 10).len(), 2);
+        assert_eq!(contract.list_identities(0, 10, None), vec![identity.clone()]);
+        assert_eq!(contract.identities_owned_by(accounts(2).to_string(), 0, 10), vec![identity.clone()]);
+        assert_eq!(contract.get_registry_stats().identities_touched, 1);
+        assert!(contract.get_created(identity.clone()).is_some());
+        assert_eq!(contract.get_changed(identity), 12);
+    }
+
+    #[test]
+    #[should_panic(expected = "no_legacy_state")]
+    fn migrate_without_state() {
+        set_context(accounts(1));
+        NearDIDRegistry::migrate();
+    }
+
+    #[test]
+    fn increment_nonce_by_owner() {
+        let owner = accounts(1);
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.increment_nonce(owner.to_string());
+
+        assert_eq!(contract.get_nonce(owner.to_string(), owner.to_string()), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn increment_nonce_by_third_party() {
+        let owner = accounts(1);
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+        contract.increment_nonce(owner.to_string());
+    }
+
+    #[test]
+    fn signed_operation_records_relayer() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+        assert_eq!(contract.get_last_relayed_op(identity.clone()), None);
+
+        let payload = contract.get_add_delegate_payload(identity.clone(), None, DEADLINE, "veriKey".to_string(), accounts(2).to_string(), 3600);
+        contract.add_delegate_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, "veriKey".to_string(), accounts(2).to_string(), 3600);
+
+        let receipt = RelayedOp {
+            operation: "addDelegate".to_string(),
+            signer: identity.clone(),
+            relayer: accounts(3),
+            nonce: 0,
+            block_height: 40,
+        };
+        assert_eq!(contract.get_last_relayed_op(identity.clone()), Some(receipt.clone()));
+        assert_eq!(
+            EventLog::from_log(&registry_logs()[0]).unwrap().event,
+            DidEvent::from(SignedOperationExecuted {
+                identity,
+                operation: receipt.operation,
+                signer: receipt.signer,
+                public_keys: vec![ed25519_public_key(&owner_key)],
+                nonce: 0,
+                relayer: receipt.relayer,
+            })
+        );
+    }
+
+    fn ed25519_public_key(key: &SigningKey) -> PublicKey {
+        PublicKey::from_parts(CurveType::ED25519, key.verifying_key().to_bytes().to_vec()).unwrap()
+    }
+
+    fn multisig_contract(owner: &AccountId) -> NearDIDRegistry {
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        let keys = (1..=3).map(|seed| ed25519_public_key(&signing_key(seed))).collect();
+        contract.set_owner_threshold(owner.to_string(), keys, 2);
+        set_context(accounts(3));
+        contract
+    }
+
+    #[test]
+    fn change_owner_multisig_two_of_three() {
+        let owner = accounts(1);
+        let new_owner = accounts(2).to_string();
+        let mut contract = multisig_contract(&owner);
+
+        let payload = contract.get_change_owner_multisig_payload(owner.to_string(), DEADLINE, new_owner.clone());
+        let signatures = [1, 3].iter().map(|&seed| (ed25519_public_key(&signing_key(seed)), sign(&signing_key(seed), &payload))).collect();
+        contract.change_owner_multisig(owner.to_string(), DEADLINE, new_owner.clone(), signatures);
+
+        assert_eq!(contract.identity_owner(owner.to_string()), new_owner);
+        assert_eq!(contract.get_nonce(owner.to_string(), owner.to_string()), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "threshold_not_met")]
+    fn change_owner_multisig_one_of_three() {
+        let owner = accounts(1);
+        let new_owner = accounts(2).to_string();
+        let mut contract = multisig_contract(&owner);
+
+        let payload = contract.get_change_owner_multisig_payload(owner.to_string(), DEADLINE, new_owner.clone());
+        let signatures = vec![(ed25519_public_key(&signing_key(2)), sign(&signing_key(2), &payload))];
+        contract.change_owner_multisig(owner.to_string(), DEADLINE, new_owner, signatures);
+    }
+
+    #[test]
+    #[should_panic(expected = "threshold_not_met")]
+    fn change_owner_multisig_duplicate_key() {
+        let owner = accounts(1);
+        let new_owner = accounts(2).to_string();
+        let mut contract = multisig_contract(&owner);
+
+        let payload = contract.get_change_owner_multisig_payload(owner.to_string(), DEADLINE, new_owner.clone());
+        let approval = (ed25519_public_key(&signing_key(2)), sign(&signing_key(2), &payload));
+        contract.change_owner_multisig(owner.to_string(), DEADLINE, new_owner, vec![approval.clone(), approval]);
+    }
+
+    #[test]
+    #[should_panic(expected = "multisig_required")]
+    fn change_owner_with_threshold_requires_multisig() {
+        let owner = accounts(1);
+        let mut contract = multisig_contract(&owner);
+
+        set_context(owner.clone());
+        contract.change_owner(owner.to_string(), accounts(2).to_string(), None);
+    }
+
+    #[test]
+    fn verify_owner_signature_checks_key_and_owner() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let challenge = b"login challenge 42".to_vec();
+        let signature = owner_key.sign(&challenge).to_bytes().to_vec();
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+
+        assert!(contract.verify_owner_signature(identity.clone(), challenge.clone(), signature.clone(), identity.clone()));
+        assert!(contract.verify_owner_signature(identity.clone(), challenge.clone(), signature.clone(), format!("ed25519:{}", identity)));
+        assert!(!contract.verify_owner_signature(identity.clone(), b"other".to_vec(), signature.clone(), identity.clone()));
+
+        let other_key = signing_key(2);
+        let other_signature = other_key.sign(&challenge).to_bytes().to_vec();
+        assert!(!contract.verify_owner_signature(identity.clone(), challenge.clone(), other_signature.clone(), key_identity(&other_key)));
+
+        let payload = contract.get_change_owner_payload(identity.clone(), None, DEADLINE, key_identity(&other_key));
+        contract.change_owner_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, key_identity(&other_key));
+        assert!(!contract.verify_owner_signature(identity.clone(), challenge.clone(), signature, identity.clone()));
+        assert!(contract.verify_owner_signature(identity, challenge, other_signature, key_identity(&other_key)));
+    }
+
+    #[test]
+    fn verify_owner_signature_accepts_signing_keys() {
+        let owner = accounts(1);
+        let hot_key = signing_key(2);
+        let challenge = b"login challenge 42".to_vec();
+        let signature = hot_key.sign(&challenge).to_bytes().to_vec();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        assert!(!contract.verify_owner_signature(owner.to_string(), challenge.clone(), signature.clone(), key_identity(&hot_key)));
+
+        contract.set_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+        assert!(contract.verify_owner_signature(owner.to_string(), challenge.clone(), signature.clone(), String::from(&ed25519_public_key(&hot_key))));
+        assert!(!contract.verify_owner_signature(owner.to_string(), b"other".to_vec(), signature.clone(), key_identity(&hot_key)));
+
+        contract.remove_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+        assert!(!contract.verify_owner_signature(owner.to_string(), challenge, signature, key_identity(&hot_key)));
+    }
+
+    #[test]
+    fn verify_owner_signature_malformed_input() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let challenge = b"login challenge 42".to_vec();
+        let signature = owner_key.sign(&challenge).to_bytes().to_vec();
+
+        set_context(accounts(3));
+        let contract = NearDIDRegistry::default();
+
+        assert!(!contract.verify_owner_signature(identity.clone(), challenge.clone(), signature[..63].to_vec(), identity.clone()));
+        assert!(!contract.verify_owner_signature(identity.clone(), challenge.clone(), vec![], identity.clone()));
+        assert!(!contract.verify_owner_signature(identity.clone(), challenge, signature, "not-a-key".to_string()));
+    }
+
+    fn implicit_account(key: &SigningKey) -> String {
+        key.verifying_key().as_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn set_attribute_signed_implicit_account() {
+        let owner_key = signing_key(1);
+        let identity = implicit_account(&owner_key);
+        let name = "did/svc/LinkedDomains".to_string();
+        let value = b"https://example.com".to_vec();
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+
+        let payload = contract.get_set_attribute_payload(identity.clone(), None, DEADLINE, name.clone(), value.clone(), 3600);
+        contract.set_attribute_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, name.clone(), value.clone(), 3600);
+
+        assert!(contract.valid_attribute(identity.clone(), name, value));
+        assert_eq!(contract.get_nonce(identity.clone(), identity), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "no_owner_key")]
+    fn set_attribute_signed_implicit_account_not_hex() {
+        let owner_key = signing_key(1);
+        let identity = implicit_account(&owner_key).replacen(|c: char| c.is_ascii_digit(), "g", 1);
+        let name = "did/svc/LinkedDomains".to_string();
+        let value = b"https://example.com".to_vec();
+        assert_eq!(identity.len(), 64);
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+
+        let payload = contract.get_set_attribute_payload(identity.clone(), None, DEADLINE, name.clone(), value.clone(), 3600);
+        contract.set_attribute_signed(identity, sign(&owner_key, &payload), DEADLINE, name, value, 3600);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn cancel_nonce_invalidates_signed_payload() {
+        let owner = accounts(1);
+        let owner_key = signing_key(1);
+        let new_owner = accounts(2).to_string();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_owner_key(ed25519_public_key(&owner_key));
+        let payload = contract.get_change_owner_payload(owner.to_string(), None, DEADLINE, new_owner.clone());
+        contract.cancel_nonce(owner.to_string());
+
+        assert_eq!(registry_logs(), vec![DidEvent::from(NonceCancelled { identity: owner.to_string(), signer: owner.to_string(), nonce: 0 }).to_event_string()]);
+
+        set_context(accounts(3));
+        contract.change_owner_signed(owner.to_string(), sign(&owner_key, &payload), DEADLINE, new_owner);
+    }
+
+    #[test]
+    fn add_delegate_signed_with_signing_key() {
+        let owner = accounts(1);
+        let hot_key = signing_key(2);
+        let signer = String::from(&ed25519_public_key(&hot_key));
+        let delegate = accounts(2).to_string();
+        let delegate_type = "veriKey".to_string();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+        assert_eq!(contract.get_signing_keys(owner.to_string()), vec![ed25519_public_key(&hot_key)]);
+
+        set_context(accounts(3));
+        let payload = contract.get_add_delegate_payload(owner.to_string(), Some(signer.clone()), DEADLINE, delegate_type.clone(), delegate.clone(), 3600);
+        contract.add_delegate_signed(owner.to_string(), sign(&hot_key, &payload), DEADLINE, delegate_type.clone(), delegate.clone(), 3600);
+
+        assert!(contract.valid_delegate(owner.to_string(), delegate_type, delegate));
+        assert_eq!(contract.get_nonce(owner.to_string(), signer), 1);
+        assert_eq!(contract.get_nonce(owner.to_string(), owner.to_string()), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn change_owner_signed_with_signing_key() {
+        let owner = accounts(1);
+        let hot_key = signing_key(2);
+        let new_owner = accounts(2).to_string();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_owner_key(ed25519_public_key(&signing_key(1)));
+        contract.set_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+
+        set_context(accounts(3));
+        let signer = String::from(&ed25519_public_key(&hot_key));
+        let payload = contract.get_change_owner_payload(owner.to_string(), Some(signer), DEADLINE, new_owner.clone());
+        contract.change_owner_signed(owner.to_string(), sign(&hot_key, &payload), DEADLINE, new_owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "no_owner_key")]
+    fn removed_signing_key_rejected() {
+        let owner = accounts(1);
+        let hot_key = signing_key(2);
+        let name = "did/svc/MessagingService".to_string();
+        let value = b"https://example.com".to_vec();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+        let signer = String::from(&ed25519_public_key(&hot_key));
+        let payload = contract.get_set_attribute_payload(owner.to_string(), Some(signer), DEADLINE, name.clone(), value.clone(), 3600);
+        contract.remove_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+        assert!(contract.get_signing_keys(owner.to_string()).is_empty());
+
+        set_context(accounts(3));
+        contract.set_attribute_signed(owner.to_string(), sign(&hot_key, &payload), DEADLINE, name, value, 3600);
+    }
+
+    #[test]
+    #[should_panic(expected = "no_owner_key")]
+    fn owner_change_drops_signing_keys() {
+        let owner = accounts(1);
+        let hot_key = signing_key(2);
+        let name = "did/svc/MessagingService".to_string();
+        let value = b"https://example.com".to_vec();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+        contract.change_owner(owner.to_string(), accounts(2).to_string(), None);
+        assert!(contract.get_signing_keys(owner.to_string()).is_empty());
+
+        let signer = String::from(&ed25519_public_key(&hot_key));
+        let payload = contract.get_set_attribute_payload(owner.to_string(), Some(signer), DEADLINE, name.clone(), value.clone(), 3600);
+        set_context(accounts(3));
+        contract.set_attribute_signed(owner.to_string(), sign(&hot_key, &payload), DEADLINE, name, value, 3600);
+    }
+
+    #[test]
+    fn multisig_owner_change_drops_threshold() {
+        let owner = accounts(1);
+        let key = signing_key(3);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_owner_threshold(owner.to_string(), vec![ed25519_public_key(&key)], 1);
+
+        let payload = contract.get_change_owner_multisig_payload(owner.to_string(), DEADLINE, accounts(2).to_string());
+        contract.change_owner_multisig(owner.to_string(), DEADLINE, accounts(2).to_string(), vec![(ed25519_public_key(&key), sign(&key, &payload))]);
+        assert_eq!(contract.get_owner_threshold(owner.to_string()), None);
+
+        set_context(accounts(2));
+        contract.change_owner(owner.to_string(), accounts(4).to_string(), None);
+        assert_eq!(contract.identity_owner(owner.to_string()), accounts(4).to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn set_signing_key_by_third_party() {
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_signing_key(accounts(1).to_string(), ed25519_public_key(&signing_key(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_signature")]
+    fn payload_rejected_after_salt_rotation() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+        let salt = contract.get_registry_salt();
+        let payload = contract.get_change_owner_payload(identity.clone(), None, DEADLINE, new_owner.clone());
+
+        let mut builder = VMContextBuilder::new();
+        builder.predecessor_account_id(env::current_account_id()).block_height(40).random_seed([9; 32]);
+        testing_env!(builder.build());
+        contract.rotate_registry_salt();
+        assert_ne!(contract.get_registry_salt(), salt);
+
+        set_context(accounts(3));
+        contract.change_owner_signed(identity, sign(&owner_key, &payload), DEADLINE, new_owner);
+    }
+
+    #[test]
+    fn change_owner_emits_event() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        contract.change_owner(owner.to_string(), accounts(2).to_string(), None);
+
+        assert_eq!(
+            registry_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"did-near","version":"1.0.0","event":"did_identity_created","data":{"identity":"bob","actor":"bob"}}"#,
+                r#"EVENT_JSON:{"standard":"did-near","version":"1.0.0","event":"did_owner_changed","data":{"identity":"bob","previous_owner":"bob","new_owner":"charlie","changed":40,"previous_change":0}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn change_owner_signed_emits_event() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context(accounts(3));
+
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        contract.change_owner_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, new_owner.clone());
+
+        let event = events::OwnerChanged { identity: identity.clone(), previous_owner: identity, new_owner, changed: 40, previous_change: 0, memo: None };
+        assert_eq!(
+            registry_logs().last().unwrap(),
+            &format!(r#"EVENT_JSON:{{"standard":"did-near","version":"1.0.0","event":"did_owner_changed","data":{}}}"#, serde_json::to_string(&event).unwrap())
+        );
+    }
+
+    #[test]
+    fn add_and_revoke_delegate_emit_events() {
+        let owner = accounts(1);
+        set_context_at(owner.clone(), 1_000);
+
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        assert_eq!(
+            registry_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"did-near","version":"1.0.0","event":"did_identity_created","data":{"identity":"bob","actor":"bob"}}"#,
+                r#"EVENT_JSON:{"standard":"did-near","version":"1.0.0","event":"did_delegate_changed","data":{"identity":"bob","delegate_type":"veriKey","delegate":"charlie","valid_to":4600,"changed":40,"previous_change":0}}"#,
+            ]
+        );
+
+        set_context_at(owner.clone(), 2_000);
+        contract.revoke_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), None);
+        assert_eq!(
+            registry_logs(),
+            vec![r#"EVENT_JSON:{"standard":"did-near","version":"1.0.0","event":"did_delegate_changed","data":{"identity":"bob","delegate_type":"veriKey","delegate":"charlie","valid_to":0,"changed":40,"previous_change":40}}"#]
+        );
+    }
+
+    #[test]
+    fn add_delegate_by_third_party_emits_nothing() {
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.add_delegate(accounts(1).to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        }));
+
+        assert!(result.is_err());
+        assert!(registry_logs().is_empty());
+    }
+
+    #[test]
+    fn preflight_views_match_mutating_calls() {
+        let identity = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context(accounts(1));
+        contract.change_owner(identity.clone(), accounts(2).to_string(), None);
+
+        let succeeds = |contract: &mut NearDIDRegistry, actor: AccountId, call: &dyn Fn(&mut NearDIDRegistry)| {
+            set_context(actor);
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| call(contract))).is_ok()
+        };
+        let add_delegate = |contract: &mut NearDIDRegistry| contract.add_delegate(accounts(1).to_string(), "veriKey".to_string(), accounts(4).to_string(), 3600, None);
+        let set_attribute = |contract: &mut NearDIDRegistry| contract.set_attribute(accounts(1).to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        let change_owner = |contract: &mut NearDIDRegistry| contract.schedule_owner_change(accounts(1).to_string(), accounts(4).to_string(), 3600);
+
+        for threshold in [false, true] {
+            if threshold {
+                set_context(accounts(2));
+                contract.set_owner_threshold(identity.clone(), vec![ed25519_public_key(&signing_key(1))], 1);
+            }
+            for actor in [accounts(1), accounts(2), accounts(3)] {
+                let permissions = contract.can_manage(identity.clone(), actor.to_string());
+                assert_eq!(permissions.owner, contract.can_change_owner(identity.clone(), actor.to_string()));
+                assert_eq!(permissions.delegates, succeeds(&mut contract, actor.clone(), &add_delegate));
+                assert_eq!(permissions.attributes, succeeds(&mut contract, actor.clone(), &set_attribute));
+                assert_eq!(permissions.owner, succeeds(&mut contract, actor.clone(), &change_owner));
+            }
+        }
+        assert_eq!(contract.can_manage(identity.clone(), accounts(2).to_string()), ManagePermissions { delegates: true, attributes: true, owner: false });
+        assert!(!contract.can_change_owner(identity, accounts(1).to_string()));
+        assert!(contract.can_change_owner(accounts(3).to_string(), accounts(3).to_string()));
+    }
+
+    #[test]
+    fn set_and_revoke_attribute_emit_events() {
+        use near_sdk::base64::{engine::general_purpose::STANDARD, Engine};
+
+        let owner = accounts(1);
+        let value = vec![0, 159, 146, 150, 255];
+        set_context_at(owner.clone(), 1_000);
+
+        let mut contract = NearDIDRegistry::default();
+        contract.set_attribute(owner.to_string(), "did/pub/Ed25519/veriKey/base64".to_string(), value.clone(), 3600, None);
+        contract.revoke_attribute(owner.to_string(), "did/pub/Ed25519/veriKey/base64".to_string(), value.clone(), None);
+
+        let logs = registry_logs();
+        assert_eq!(
+            logs[1],
+            r#"EVENT_JSON:{"standard":"did-near","version":"1.0.0","event":"did_attribute_changed","data":{"identity":"bob","name":"did/pub/Ed25519/veriKey/base64","value":"AJ+Slv8=","truncated":false,"valid_to":4600,"changed":40,"previous_change":0}}"#
+        );
+        let event: serde_json::Value = serde_json::from_str(logs[2].strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+        assert_eq!(event["data"]["valid_to"], 0);
+        assert_eq!(STANDARD.decode(event["data"]["value"].as_str().unwrap()).unwrap(), value);
+    }
+
+    #[test]
+    fn set_attribute_event_truncates_large_value() {
+        use near_sdk::base64::{engine::general_purpose::STANDARD, Engine};
+
+        let owner = accounts(1);
+        let value: Vec<u8> = (0..1_500).map(|i| i as u8).collect();
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        contract.set_attribute(owner.to_string(), "did/svc/Blob".to_string(), value.clone(), 3600, None);
+
+        let event: serde_json::Value = serde_json::from_str(registry_logs()[1].strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+        assert_eq!(event["data"]["truncated"], true);
+        assert_eq!(STANDARD.decode(event["data"]["value"].as_str().unwrap()).unwrap(), value[..events::MAX_EVENT_VALUE_BYTES]);
+        assert!(contract.valid_attribute(owner.to_string(), "did/svc/Blob".to_string(), value));
+    }
+
+    #[test]
+    fn change_events_link_previous_change() {
+        let owner = accounts(1);
+        let mut contract = NearDIDRegistry::default();
+        let mut previous_changes = vec![];
+
+        for block_height in [10, 25, 70] {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(owner.clone()).block_height(block_height).build());
+            match block_height {
+                10 => contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None),
+                25 => contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None),
+                _ => contract.change_owner(owner.to_string(), accounts(2).to_string(), None),
+            }
+
+            let event: serde_json::Value = serde_json::from_str(registry_logs().last().unwrap().strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+            assert_eq!(event["data"]["changed"], block_height);
+            previous_changes.push(event["data"]["previous_change"].as_u64().unwrap());
+        }
+
+        assert_eq!(previous_changes, vec![0, 10, 25]);
+        assert_eq!(contract.get_changed(owner.to_string()), 70);
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let events = [
+            DidEvent::from(OwnerChanged {
+                identity: "alice.near".to_string(),
+                previous_owner: "alice.near".to_string(),
+                new_owner: "bob.near".to_string(),
+                changed: 12,
+                previous_change: 0,
+                memo: Some("key rotation".to_string()),
+            }),
+            DidEvent::from(DelegateChanged {
+                identity: "alice.near".to_string(),
+                delegate_type: "sigAuth".to_string(),
+                delegate: "carol.near".to_string(),
+                valid_to: 4600,
+                changed: 14,
+                previous_change: 12,
+                memo: None,
+            }),
+            DidEvent::from(AttributeChanged::new("alice.near".to_string(), "did/svc/Hub".to_string(), b"https://hub.example", 0, 15, 14, None)),
+        ];
+
+        for event in events {
+            let log = event.to_event_string();
+            let parsed = EventLog::from_log(&log).unwrap();
+            assert_eq!((parsed.standard.as_str(), parsed.version.as_str()), (events::STANDARD, events::VERSION));
+            assert_eq!(parsed.event, event);
+            assert_eq!(serde_json::from_str::<DidEvent>(&serde_json::to_string(&event).unwrap()).unwrap(), event);
+        }
+
+        assert_eq!(EventLog::from_log("{\"identity\":\"alice.near\"}"), None);
+        assert_eq!(EventLog::from_log(r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_mint","data":[]}"#), None);
+    }
+
+    #[test]
+    fn emitted_logs_parse_as_events() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+
+        let Some(EventLog { event: DidEvent::DelegateChanged(event), .. }) = EventLog::from_log(&registry_logs()[1]) else { panic!("not a delegate event") };
+        assert_eq!(event.delegate, accounts(2).to_string());
+        assert_eq!(event.valid_to, 3600);
+    }
+
+    fn signed_operation_event(logs: &[String]) -> SignedOperationExecuted {
+        logs.iter()
+            .find_map(|log| match EventLog::from_log(log)?.event {
+                DidEvent::SignedOperationExecuted(event) => Some(event),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn signed_operation_emits_executed_event() {
+        let owner = accounts(1);
+        let owner_key = signing_key(1);
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_owner_key(ed25519_public_key(&owner_key));
+        contract.cancel_nonce(owner.to_string());
+
+        set_context(accounts(3));
+        let nonce = contract.get_nonce(owner.to_string(), owner.to_string());
+        let payload = contract.get_revoke_delegate_payload(owner.to_string(), None, DEADLINE, "veriKey".to_string(), accounts(2).to_string());
+        contract.revoke_delegate_signed(owner.to_string(), sign(&owner_key, &payload), DEADLINE, "veriKey".to_string(), accounts(2).to_string());
+
+        let logs = registry_logs();
+        assert_eq!(
+            signed_operation_event(&logs),
+            SignedOperationExecuted {
+                identity: owner.to_string(),
+                operation: "revokeDelegate".to_string(),
+                signer: owner.to_string(),
+                public_keys: vec![ed25519_public_key(&owner_key)],
+                nonce,
+                relayer: accounts(3),
+            }
+        );
+        assert_eq!(nonce, 1);
+        assert!(matches!(EventLog::from_log(logs.last().unwrap()).unwrap().event, DidEvent::DelegateChanged(_)));
+    }
+
+    #[test]
+    fn signed_operation_event_names_signing_key() {
+        let owner = accounts(1);
+        let hot_key = signing_key(2);
+        let signer = String::from(&ed25519_public_key(&hot_key));
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+
+        set_context(accounts(3));
+        let nonce = contract.get_nonce(owner.to_string(), signer.clone());
+        let payload = contract.get_add_delegate_payload(owner.to_string(), Some(signer.clone()), DEADLINE, "veriKey".to_string(), accounts(2).to_string(), 3600);
+        contract.add_delegate_signed(owner.to_string(), sign(&hot_key, &payload), DEADLINE, "veriKey".to_string(), accounts(2).to_string(), 3600);
+
+        let event = signed_operation_event(&registry_logs());
+        assert_eq!(event.signer, signer);
+        assert_eq!(event.public_keys, vec![ed25519_public_key(&hot_key)]);
+        assert_eq!(event.nonce, nonce);
+    }
+
+    #[test]
+    fn purge_expired_removes_only_expired_entries() {
+        let owner = accounts(1);
+        let value = b"https://hub.example".to_vec();
+        let mut contract = NearDIDRegistry::default();
+
+        set_context_at(owner.clone(), 1_000);
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 100, None);
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(3).to_string(), 10_000, None);
+        contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), value.clone(), 100, None);
+
+        set_context_at(accounts(4), 2_000);
+        let purged = contract.purge_expired(
+            owner.to_string(),
+            vec![("veriKey".to_string(), accounts(2).to_string()), ("veriKey".to_string(), accounts(3).to_string()), ("sigAuth".to_string(), accounts(2).to_string())],
+            vec![("did/svc/Hub".to_string(), value.clone())],
+        );
+
+        assert_eq!(purged, 2);
+        assert_eq!(
+            registry_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"did-near","version":"1.0.0","event":"did_entry_purged","data":{"identity":"bob","kind":"delegate","delegate_type":"veriKey","delegate":"charlie","valid_to":1100}}"#,
+                r#"EVENT_JSON:{"standard":"did-near","version":"1.0.0","event":"did_entry_purged","data":{"identity":"bob","kind":"attribute","name":"did/svc/Hub","value":"aHR0cHM6Ly9odWIuZXhhbXBsZQ==","truncated":false,"valid_to":1100}}"#,
+            ]
+        );
+        assert!(!contract.delegates.contains_key(&(owner.to_string(), "veriKey".to_string(), accounts(2).to_string())));
+        assert!(contract.valid_delegate(owner.to_string(), "veriKey".to_string(), accounts(3).to_string()));
+        assert_eq!(contract.get_changed(owner.to_string()), 40);
+
+        let event = EventLog::from_log(&registry_logs()[1]).unwrap().event;
+        assert_eq!(
+            event,
+            DidEvent::from(EntryPurged { identity: owner.to_string(), entry: PurgedEntry::attribute("did/svc/Hub".to_string(), &value), valid_to: 1100 })
+        );
+    }
+
+    #[test]
+    fn purge_expired_leaves_changed_untouched() {
+        let owner = accounts(1);
+        let mut contract = NearDIDRegistry::default();
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner.clone()).block_height(10).build());
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 0, None);
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(3)).block_height(20).build());
+        assert_eq!(contract.purge_expired(owner.to_string(), vec![("veriKey".to_string(), accounts(2).to_string())], vec![]), 1);
+        assert_eq!(contract.get_changed(owner.to_string()), 10);
+        assert_eq!(contract.purge_expired(owner.to_string(), vec![("veriKey".to_string(), accounts(2).to_string())], vec![]), 0);
+    }
+
+    #[test]
+    fn purge_expired_on_inactive_identities() {
+        let mut contract = NearDIDRegistry::default();
+        let (locked, renounced, deactivated) = (accounts(1), accounts(2), accounts(3));
+        for owner in [&locked, &renounced, &deactivated] {
+            set_context(owner.clone());
+            contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(4).to_string(), 0, None);
+        }
+        set_context(locked.clone());
+        contract.lock_identity(locked.to_string());
+        set_context(renounced.clone());
+        contract.renounce_ownership(renounced.to_string());
+        set_context(deactivated.clone());
+        contract.deactivate_identity(deactivated.to_string());
+
+        set_context(accounts(5));
+        for owner in [locked, renounced, deactivated] {
+            assert_eq!(contract.purge_expired(owner.to_string(), vec![("veriKey".to_string(), accounts(4).to_string())], vec![]), 1);
+            assert_eq!(contract.get_delegate_validity(owner.to_string(), "veriKey".to_string(), accounts(4).to_string()), None);
+        }
+    }
+
+    #[test]
+    fn clear_identity_resumes_until_empty() {
+        let owner = accounts(1);
+        let mut contract = NearDIDRegistry::default();
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner.clone()).block_height(10).build());
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.to_string(), "sigAuth".to_string(), accounts(3).to_string(), 3600, None);
+        contract.revoke_delegate(owner.to_string(), "sigAuth".to_string(), accounts(3).to_string(), None);
+        contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.to_string(), "did/svc/Mail".to_string(), b"mailto:bob@example.com".to_vec(), 3600, None);
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner.clone()).block_height(20).build());
+        assert_eq!(contract.clear_identity(owner.to_string(), 3), 1);
+        let event = EventLog::from_log(registry_logs().last().unwrap()).unwrap().event;
+        assert_eq!(event, DidEvent::from(IdentityCleared { identity: owner.to_string(), delegates: 2, attributes: 1, changed: 20, previous_change: 10 }));
+        assert_eq!(contract.count_delegates(owner.to_string()), 0);
+        assert_eq!(contract.count_attributes(owner.to_string()), 1);
+        assert!(contract.identities_for_delegate(accounts(2).to_string(), 0, 10, Some(true)).is_empty());
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner.clone()).block_height(30).build());
+        assert_eq!(contract.clear_identity(owner.to_string(), 10), 0);
+        assert_eq!(registry_logs().len(), 1);
+        assert!(contract.list_delegates(owner.to_string(), 0, 10).is_empty());
+        assert!(contract.list_attributes(owner.to_string(), 0, 10).is_empty());
+        assert_eq!(contract.count_attributes(owner.to_string()), 0);
+        assert_eq!(contract.get_registry_stats().total_attributes, 0);
+        assert!(contract.resolve(owner.to_string(), None).service.is_empty());
+        assert_eq!(contract.get_changed(owner.to_string()), 30);
+        assert_eq!(contract.resolve_at_version(owner.to_string(), 10).did_document.unwrap().service.len(), 2);
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(owner.clone()).block_height(40).build());
+        assert_eq!(contract.clear_identity(owner.to_string(), 10), 0);
+        assert!(registry_logs().is_empty());
+        assert_eq!(contract.get_changed(owner.to_string()), 30);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn clear_identity_only_owner() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+
+        set_context(accounts(2));
+        contract.clear_identity(owner.to_string(), 10);
+    }
+
+    #[test]
+    fn owner_change_revokes_delegates_under_policy() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), accounts(3).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(4).to_string(), 3600, None);
+        contract.revoke_delegate(owner.clone(), "veriKey".to_string(), accounts(4).to_string(), None);
+        contract.set_policy(owner.clone(), true);
+        assert_eq!(contract.get_policy(owner.clone()), IdentityPolicy { revoke_delegates_on_owner_change: true });
+        contract.propose_owner(owner.clone(), accounts(5));
+
+        set_context(accounts(5));
+        contract.accept_ownership(owner.clone());
+        assert!(!contract.valid_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string()));
+        assert!(!contract.valid_delegate(owner.clone(), "sigAuth".to_string(), accounts(3).to_string()));
+        let revoked: Vec<_> = registry_logs()
+            .iter()
+            .filter_map(|log| match EventLog::from_log(log)?.event {
+                DidEvent::DelegateChanged(event) => Some((event.delegate_type, event.delegate, event.valid_to)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(revoked, vec![("veriKey".to_string(), accounts(2).to_string(), 0), ("sigAuth".to_string(), accounts(3).to_string(), 0)]);
+        assert_eq!(contract.get_policy(owner), IdentityPolicy { revoke_delegates_on_owner_change: true });
+    }
+
+    #[test]
+    fn owner_change_keeps_delegates_without_policy() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.set_policy(owner.clone(), true);
+        contract.set_policy(owner.clone(), false);
+        assert_eq!(contract.get_policy(owner.clone()), IdentityPolicy::default());
+
+        contract.change_owner(owner.clone(), accounts(3).to_string(), None);
+        assert!(contract.valid_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string()));
+        assert!(matches!(EventLog::from_log(registry_logs().last().unwrap()).unwrap().event, DidEvent::OwnerChanged(_)));
+    }
+
+    #[test]
+    #[should_panic(expected = "delegates_not_cleared")]
+    fn owner_change_over_revocation_bound_fails() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        for i in 0..=MAX_OWNER_CHANGE_REVOCATIONS {
+            contract.add_delegate(owner.clone(), "veriKey".to_string(), format!("delegate{i}.near"), 3600, None);
+        }
+        contract.set_policy(owner.clone(), true);
+
+        contract.change_owner(owner, accounts(2).to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_locked")]
+    fn locked_identity_rejects_set_attribute() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.lock_identity(owner.clone());
+
+        contract.set_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+    }
+
+    #[test]
+    fn unlocked_identity_accepts_writes() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(10).build());
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(20).build());
+        contract.lock_identity(owner.clone());
+        assert!(contract.is_locked(owner.clone()));
+        assert!(contract.resolve_with_metadata(owner.clone(), None).did_document_metadata.locked);
+        assert!(contract.get_document_metadata(owner.clone()).locked);
+        assert_eq!(contract.get_changed(owner.clone()), 10);
+        assert_eq!(contract.can_manage(owner.clone(), owner.clone()), ManagePermissions { delegates: false, attributes: false, owner: false });
+
+        contract.unlock_identity(owner.clone());
+        assert!(!contract.is_locked(owner.clone()));
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        assert!(contract.valid_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec()));
+        assert!(!contract.get_document_metadata(owner).locked);
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_locked")]
+    fn locked_identity_rejects_owner_change() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.lock_identity(owner.clone());
+
+        contract.change_owner(owner, accounts(2).to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_locked")]
+    fn locked_identity_rejects_controller() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_controller(owner.clone(), accounts(2));
+        contract.lock_identity(owner.clone());
+
+        set_context(accounts(2));
+        contract.add_delegate(owner, "veriKey".to_string(), accounts(3).to_string(), 3600, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn unlock_identity_only_owner() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.lock_identity(owner.clone());
+
+        set_context(accounts(2));
+        contract.unlock_identity(owner);
+    }
+
+    #[test]
+    fn temporary_owner_acts_until_expiry() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.grant_temporary_ownership(owner.clone(), accounts(2), 100);
+        assert_eq!(contract.get_temporary_owner(owner.clone()), Some(TemporaryOwner { temp_owner: accounts(2), expires_at: 1_100 }));
+
+        set_context_at(accounts(2), 1_099);
+        assert_eq!(contract.identity_owner(owner.clone()), accounts(2).to_string());
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        assert_eq!(contract.resolve(owner.clone(), None).controller, Some(format!("did:near:{}", accounts(2))));
+        assert!(!contract.can_change_owner(owner.clone(), accounts(2).to_string()));
+        assert_eq!(contract.can_manage(owner.clone(), owner.clone()), ManagePermissions { delegates: false, attributes: false, owner: true });
+
+        set_context_at(accounts(1), 1_100);
+        assert_eq!(contract.identity_owner(owner.clone()), owner);
+        assert_eq!(contract.get_temporary_owner(owner.clone()), None);
+        contract.revoke_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), None);
+        assert_eq!(contract.can_manage(owner.clone(), accounts(2).to_string()), ManagePermissions { delegates: false, attributes: false, owner: false });
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn temporary_owner_rejected_at_expiry() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.grant_temporary_ownership(owner.clone(), accounts(2), 100);
+
+        set_context_at(accounts(2), 1_100);
+        contract.set_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn temporary_owner_rejected_after_revocation() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.grant_temporary_ownership(owner.clone(), accounts(2), 100);
+
+        set_context_at(accounts(1), 1_050);
+        contract.revoke_temporary_ownership(owner.clone());
+        assert_eq!(contract.identity_owner(owner.clone()), owner);
+        assert_eq!(contract.get_temporary_owner(owner.clone()), None);
+
+        set_context_at(accounts(2), 1_050);
+        contract.add_delegate(owner, "veriKey".to_string(), accounts(3).to_string(), 3600, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn temporary_owner_cannot_change_owner() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.grant_temporary_ownership(owner.clone(), accounts(2), 100);
+
+        set_context_at(accounts(2), 1_050);
+        contract.change_owner(owner, accounts(2).to_string(), None);
+    }
+
+    #[test]
+    fn temporary_owner_cannot_change_lasting_settings() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.register_observer(owner.clone(), accounts(4));
+        contract.set_policy(owner.clone(), true);
+        contract.grant_temporary_ownership(owner.clone(), accounts(2), 100);
+
+        set_context_at(accounts(2), 1_050);
+        let writes: [fn(&mut NearDIDRegistry, String); 3] = [
+            |contract, owner| contract.register_observer(owner, accounts(3)),
+            |contract, owner| contract.unregister_observer(owner, accounts(4)),
+            |contract, owner| contract.set_policy(owner, false),
+        ];
+        for call in writes {
+            let error = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| call(&mut contract, owner.clone()))).unwrap_err();
+            assert!(error.downcast_ref::<String>().is_some_and(|message| message.contains("bad_actor")));
+        }
+
+        set_context_at(accounts(1), 1_050);
+        contract.register_observer(owner.clone(), accounts(3));
+        assert_eq!(contract.get_observers(owner.clone()), vec![accounts(4), accounts(3)]);
+        assert_eq!(contract.get_policy(owner), IdentityPolicy { revoke_delegates_on_owner_change: true });
+    }
+
+    #[test]
+    fn owner_change_drops_temporary_owner() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.grant_temporary_ownership(owner.clone(), accounts(2), 100);
+        contract.change_owner(owner.clone(), accounts(3).to_string(), None);
+
+        let Some(EventLog { event: DidEvent::OwnerChanged(event), .. }) = EventLog::from_log(registry_logs().last().unwrap()) else { panic!("not an owner change") };
+        assert_eq!(event.previous_owner, owner.clone());
+        assert_eq!(contract.get_temporary_owner(owner.clone()), None);
+        assert_eq!(contract.identity_owner(owner), accounts(3).to_string());
+    }
+
+    #[test]
+    fn scheduled_transfer_takes_effect_lazily() {
+        let owner = accounts(1).to_string();
+        let new_owner = accounts(2).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.schedule_transfer_at(owner.clone(), new_owner.clone(), 2_000);
+        assert_eq!(contract.get_scheduled_transfer(owner.clone()), Some(PendingOwnerChange { new_owner: new_owner.clone(), effective_at: 2_000 }));
+
+        set_context_at(accounts(1), 1_999);
+        assert_eq!(contract.identity_owner(owner.clone()), owner);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        assert_eq!(contract.identity_owner(owner.clone()), owner);
+
+        set_context_at(accounts(2), 2_000);
+        assert_eq!(contract.identity_owner(owner.clone()), new_owner);
+        assert_eq!(contract.get_scheduled_transfer(owner.clone()), None);
+        assert!(contract.can_change_owner(owner.clone(), new_owner.clone()));
+        assert!(!contract.can_change_owner(owner.clone(), owner.clone()));
+        assert_eq!(contract.resolve(owner.clone(), None).controller, Some(format!("did:near:{new_owner}")));
+
+        contract.revoke_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), None);
+        let events: Vec<_> = registry_logs().iter().filter_map(|log| Some(EventLog::from_log(log)?.event)).collect();
+        let DidEvent::OwnerChanged(change) = &events[0] else { panic!("not an owner change") };
+        assert_eq!((change.previous_owner.as_str(), change.new_owner.as_str()), (owner.as_str(), new_owner.as_str()));
+        assert!(matches!(events[1], DidEvent::AttributeChanged(_)));
+        assert_eq!(contract.identity_owner(owner), new_owner);
+    }
+
+    #[test]
+    fn locked_identity_holds_due_transfer_back() {
+        let owner = accounts(1).to_string();
+        let new_owner = accounts(2).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.schedule_transfer_at(owner.clone(), new_owner.clone(), 2_000);
+        contract.lock_identity(owner.clone());
+
+        set_context_at(accounts(2), 2_500);
+        assert_eq!(contract.identity_owner(owner.clone()), owner);
+        assert_eq!(contract.get_scheduled_transfer(owner.clone()), Some(PendingOwnerChange { new_owner: new_owner.clone(), effective_at: 2_000 }));
+        let unlocked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.unlock_identity(owner.clone())));
+        assert!(unlocked.is_err());
+        assert!(contract.is_locked(owner.clone()));
+
+        set_context_at(accounts(1), 2_500);
+        contract.unlock_identity(owner.clone());
+        assert_eq!(contract.identity_owner(owner.clone()), new_owner);
+        assert_eq!(contract.get_scheduled_transfer(owner.clone()), None);
+
+        set_context_at(accounts(2), 2_500);
+        contract.finalize_scheduled_transfer(owner.clone());
+        assert_eq!(contract.get_owner_history(owner, 0, 10).len(), 1);
+    }
+
+    #[test]
+    fn finalize_scheduled_transfer_records_change() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.schedule_transfer_at(owner.clone(), accounts(2).to_string(), 2_000);
+
+        set_context_at(accounts(3), 2_500);
+        contract.finalize_scheduled_transfer(owner.clone());
+        let Some(EventLog { event: DidEvent::OwnerChanged(change), .. }) = EventLog::from_log(registry_logs().last().unwrap()) else { panic!("not an owner change") };
+        assert_eq!(change.new_owner, accounts(2).to_string());
+        assert_eq!(contract.identity_owner(owner.clone()), accounts(2).to_string());
+        assert_eq!(contract.get_owner_history(owner, 0, 10).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "owner_change_locked")]
+    fn finalize_scheduled_transfer_before_time() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.schedule_transfer_at(owner.clone(), accounts(2).to_string(), 2_000);
+
+        set_context_at(accounts(3), 1_999);
+        contract.finalize_scheduled_transfer(owner);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn previous_owner_rejected_once_transfer_due() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.schedule_transfer_at(owner.clone(), accounts(2).to_string(), 2_000);
+
+        set_context_at(accounts(1), 2_000);
+        contract.add_delegate(owner, "veriKey".to_string(), accounts(3).to_string(), 3600, None);
+    }
+
+    #[test]
+    fn cancelled_transfer_never_takes_effect() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.schedule_transfer_at(owner.clone(), accounts(2).to_string(), 2_000);
+
+        set_context_at(accounts(1), 1_500);
+        contract.cancel_scheduled_transfer(owner.clone());
+        assert_eq!(contract.get_scheduled_transfer(owner.clone()), None);
+
+        set_context_at(accounts(1), 2_000);
+        assert_eq!(contract.identity_owner(owner.clone()), owner);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), 3600, None);
+        assert!(contract.valid_delegate(owner, "veriKey".to_string(), accounts(3).to_string()));
+    }
+
+    #[test]
+    fn transfer_all_owned_moves_in_pages() {
+        let custodian = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        for n in 2..=4 {
+            set_context(accounts(n));
+            contract.change_owner(accounts(n).to_string(), custodian.clone(), None);
+        }
+        set_context(accounts(5));
+        contract.change_owner(accounts(5).to_string(), accounts(4).to_string(), None);
+
+        set_context(accounts(1));
+        let first = contract.transfer_all_owned(accounts(0).to_string(), None, 2);
+        assert_eq!(first.items, vec![accounts(2).to_string(), accounts(3).to_string()]);
+        let moved: Vec<_> = registry_logs()
+            .iter()
+            .filter_map(|log| match EventLog::from_log(log)?.event {
+                DidEvent::OwnerChanged(event) => Some(event.identity),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(moved, first.items);
+        assert_eq!(contract.identities_owned_by(custodian.clone(), 0, 10), vec![accounts(4).to_string()]);
+
+        set_context(accounts(1));
+        let second = contract.transfer_all_owned(accounts(0).to_string(), first.next_cursor, 10);
+        assert_eq!((second.items, second.next_cursor), (vec![accounts(4).to_string()], None));
+        assert!(contract.transfer_all_owned(accounts(0).to_string(), None, 10).items.is_empty());
+        assert!(contract.identities_owned_by(custodian.clone(), 0, 10).is_empty());
+        assert_eq!(contract.identities_owned_by(accounts(0).to_string(), 0, 10), vec![accounts(2).to_string(), accounts(3).to_string(), accounts(4).to_string()]);
+        assert_eq!(contract.identity_owner(accounts(3).to_string()), accounts(0).to_string());
+        assert_eq!(contract.identity_owner(accounts(5).to_string()), accounts(4).to_string());
+        assert_eq!(contract.get_owner_history(accounts(2).to_string(), 0, 10).len(), 2);
+    }
+
+    #[test]
+    fn transfer_all_owned_skips_locked_identities() {
+        let custodian = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        for n in 2..=3 {
+            set_context(accounts(n));
+            contract.change_owner(accounts(n).to_string(), custodian.clone(), None);
+        }
+
+        set_context(accounts(1));
+        contract.lock_identity(accounts(2).to_string());
+        let moved = contract.transfer_all_owned(accounts(0).to_string(), None, 10);
+        assert_eq!((moved.items, moved.next_cursor), (vec![accounts(3).to_string()], None));
+        assert!(contract.transfer_all_owned(accounts(0).to_string(), None, 10).items.is_empty());
+        assert_eq!(contract.identities_owned_by(custodian, 0, 10), vec![accounts(2).to_string()]);
+        assert_eq!(contract.identity_owner(accounts(3).to_string()), accounts(0).to_string());
+    }
+
+    #[test]
+    fn transfer_all_owned_counts_skipped_identities() {
+        let custodian = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        for n in 2..=4 {
+            set_context(accounts(n));
+            contract.change_owner(accounts(n).to_string(), custodian.clone(), None);
+        }
+
+        set_context(accounts(1));
+        contract.lock_identity(accounts(2).to_string());
+        contract.lock_identity(accounts(3).to_string());
+        let first = contract.transfer_all_owned(accounts(0).to_string(), None, 2);
+        assert!(first.items.is_empty());
+        assert!(first.next_cursor.is_some());
+        let second = contract.transfer_all_owned(accounts(0).to_string(), first.next_cursor, 2);
+        assert_eq!((second.items, second.next_cursor), (vec![accounts(4).to_string()], None));
+        assert_eq!(contract.identities_owned_by(custodian, 0, 10), vec![accounts(2).to_string(), accounts(3).to_string()]);
+    }
+
+    fn set_callback_context(result: near_sdk::PromiseResult) {
+        let registry = VMContextBuilder::new().build().current_account_id;
+        testing_env!(
+            VMContextBuilder::new().predecessor_account_id(registry).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    #[test]
+    fn export_identity_calls_import() {
+        use near_sdk::mock::MockAction;
+        use near_sdk::test_utils::get_created_receipts;
+
+        let owner = accounts(1).to_string();
+        let target: AccountId = "v2.near".parse().unwrap();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), accounts(3).to_string(), 3600, None);
+        contract.revoke_delegate(owner.clone(), "sigAuth".to_string(), accounts(3).to_string(), None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 100, None);
+        contract.change_owner(owner.clone(), accounts(4).to_string(), None);
+
+        set_context_at(accounts(4), 1_000);
+        let _ = contract.export_identity(owner.clone(), target.clone());
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, target);
+        let MockAction::FunctionCallWeight { method_name, args, prepaid_gas, .. } = &receipts[0].actions[0] else { panic!("not a function call") };
+        assert_eq!(method_name, b"import_identity");
+        assert_eq!(*prepaid_gas, IMPORT_GAS);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(args).unwrap(),
+            serde_json::json!({ "export": {
+                "identity": "bob",
+                "owner": "eugene",
+                "delegates": [["veriKey", "charlie", 4_600]],
+                "attributes": [["did/svc/Hub", "aHR0cHM6Ly9odWIuZXhhbXBsZQ==", 1_100]],
+            } })
+        );
+        let MockAction::FunctionCallWeight { method_name, .. } = &receipts[1].actions[0] else { panic!("not a function call") };
+        assert_eq!(method_name, b"on_identity_exported");
+        assert_eq!(contract.get_migration(owner), None);
+    }
+
+    #[test]
+    fn successful_import_marks_identity_migrated() {
+        let owner = accounts(1).to_string();
+        let target: AccountId = "v2.near".parse().unwrap();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+
+        let changed = contract.get_changed(owner.clone());
+        set_callback_context(near_sdk::PromiseResult::Successful(vec![]));
+        assert!(contract.on_identity_exported(owner.clone(), target.clone(), owner.clone(), changed));
+        assert_eq!(contract.get_migration(owner.clone()), Some(target.clone()));
+        assert_eq!(EventLog::from_log(registry_logs().last().unwrap()).unwrap().event, DidEvent::from(IdentityMigrated { identity: owner.clone(), target_registry: target }));
+        assert_eq!(contract.can_manage(owner.clone(), owner), ManagePermissions { delegates: false, attributes: false, owner: false });
+    }
+
+    #[test]
+    fn stale_export_leaves_identity_writable() {
+        let owner = accounts(1).to_string();
+        let target: AccountId = "v2.near".parse().unwrap();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        let changed = contract.get_changed(owner.clone());
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(41).build());
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        set_callback_context(near_sdk::PromiseResult::Successful(vec![]));
+        assert!(!contract.on_identity_exported(owner.clone(), target.clone(), owner.clone(), changed));
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(42).build());
+        contract.change_owner(owner.clone(), accounts(3).to_string(), None);
+        set_callback_context(near_sdk::PromiseResult::Successful(vec![]));
+        assert!(!contract.on_identity_exported(owner.clone(), target, owner.clone(), contract.get_changed(owner.clone())));
+        assert_eq!(contract.get_migration(owner.clone()), None);
+
+        set_context(accounts(3));
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(4).to_string(), 3600, None);
+        assert!(contract.valid_delegate(owner, "veriKey".to_string(), accounts(4).to_string()));
+    }
+
+    #[test]
+    fn failed_import_leaves_identity_writable() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+
+        set_callback_context(near_sdk::PromiseResult::Failed);
+        assert!(!contract.on_identity_exported(owner.clone(), "v2.near".parse().unwrap(), owner.clone(), 0));
+        assert_eq!(contract.get_migration(owner.clone()), None);
+
+        set_context(accounts(1));
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        assert!(contract.valid_delegate(owner, "veriKey".to_string(), accounts(2).to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_migrated")]
+    fn migrated_identity_rejects_writes() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_callback_context(near_sdk::PromiseResult::Successful(vec![]));
+        contract.on_identity_exported(owner.clone(), "v2.near".parse().unwrap(), owner.clone(), 0);
+
+        set_context(accounts(1));
+        contract.set_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+    }
+
+    #[test]
+    fn import_identity_from_trusted_registry() {
+        let source: AccountId = "v1.near".parse().unwrap();
+        let mut contract = NearDIDRegistry::default();
+        set_callback_context(near_sdk::PromiseResult::Successful(vec![]));
+        contract.set_trusted_registry(source.clone(), true);
+        assert!(contract.is_trusted_registry(source.clone()));
+
+        set_context_at(source.clone(), 1_000);
+        contract.import_identity(IdentityExport {
+            identity: "bob".to_string(),
+            owner: "danny".to_string(),
+            delegates: vec![("veriKey".to_string(), "charlie".to_string(), 4_600)],
+            attributes: vec![("did/svc/Hub".to_string(), b"https://hub.example".to_vec().into(), 1_100)],
+        });
+        assert_eq!(contract.identity_owner("bob".to_string()), "danny");
+        assert!(contract.valid_delegate("bob".to_string(), "veriKey".to_string(), "charlie".to_string()));
+        assert!(contract.valid_attribute("bob".to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec()));
+        assert_eq!(contract.identities_owned_by("danny".to_string(), 0, 10), vec!["bob".to_string()]);
+        assert_eq!(EventLog::from_log(&registry_logs()[0]).unwrap().event, DidEvent::from(IdentityCreated { identity: "bob".to_string(), actor: source.to_string() }));
+    }
+
+    #[test]
+    #[should_panic(expected = "untrusted_registry")]
+    fn import_identity_from_untrusted_registry() {
+        let mut contract = NearDIDRegistry::default();
+        set_context(accounts(1));
+        contract.import_identity(IdentityExport { identity: "bob".to_string(), owner: "bob".to_string(), delegates: vec![], attributes: vec![] });
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_registered")]
+    fn import_identity_over_existing_records() {
+        let source: AccountId = "v1.near".parse().unwrap();
+        let mut contract = NearDIDRegistry::default();
+        set_callback_context(near_sdk::PromiseResult::Successful(vec![]));
+        contract.set_trusted_registry(source.clone(), true);
+        set_context(accounts(1));
+        contract.add_delegate(accounts(1).to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+
+        set_context(source);
+        contract.import_identity(IdentityExport { identity: accounts(1).to_string(), owner: accounts(1).to_string(), delegates: vec![], attributes: vec![] });
+    }
+
+    #[test]
+    fn identity_created_on_first_write_only() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        contract.change_owner(owner.to_string(), accounts(2).to_string(), None);
+
+        let created: Vec<_> = registry_logs()
+            .iter()
+            .filter_map(|log| match EventLog::from_log(log)?.event {
+                DidEvent::IdentityCreated(event) => Some(event),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(created, vec![IdentityCreated { identity: owner.to_string(), actor: owner.to_string() }]);
+        assert_eq!(EventLog::from_log(&registry_logs()[0]).unwrap().event, DidEvent::from(created[0].clone()));
+    }
+
+    #[test]
+    fn identity_created_by_change_owner() {
+        let owner_key = signing_key(1);
+        let identity = key_identity(&owner_key);
+        let new_owner = accounts(2).to_string();
+
+        set_context(accounts(3));
+        let mut contract = NearDIDRegistry::default();
+        let payload = payload::change_owner(&domain(), 0, DEADLINE, &identity, &new_owner);
+        contract.change_owner_signed(identity.clone(), sign(&owner_key, &payload), DEADLINE, new_owner.clone());
+
+        let events: Vec<_> = registry_logs().iter().filter_map(|log| EventLog::from_log(log)).map(|log| log.event).collect();
+        assert!(matches!(&events[..], [DidEvent::SignedOperationExecuted(_), DidEvent::IdentityCreated(created), DidEvent::OwnerChanged(_)]
+            if created == &IdentityCreated { identity: identity.clone(), actor: identity.clone() }));
+
+        set_context(accounts(2));
+        contract.change_owner(identity, accounts(3).to_string(), None);
+        assert_eq!(registry_logs().len(), 1);
+    }
+
+    #[test]
+    fn observers_notified_after_change() {
+        use near_sdk::mock::MockAction;
+        use near_sdk::test_utils::get_created_receipts;
+
+        let owner = accounts(1);
+        let observer: AccountId = "gate.near".parse().unwrap();
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        contract.register_observer(owner.to_string(), observer.clone());
+        contract.register_observer(owner.to_string(), observer.clone());
+        assert_eq!(contract.get_observers(owner.to_string()), vec![observer.clone()]);
+        assert!(get_created_receipts().is_empty());
+
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, observer);
+        let MockAction::FunctionCallWeight { method_name, args, prepaid_gas, .. } = &receipts[0].actions[0] else { panic!("not a function call") };
+        assert_eq!(method_name, b"on_did_changed");
+        assert_eq!(*prepaid_gas, OBSERVER_GAS);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(args).unwrap(),
+            serde_json::json!({ "identity": "bob", "change_kind": "delegate", "block_height": 40 })
+        );
+    }
+
+    #[test]
+    fn unregistered_observer_not_notified() {
+        use near_sdk::test_utils::get_created_receipts;
+
+        let owner = accounts(1);
+        let observer: AccountId = "gate.near".parse().unwrap();
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        contract.register_observer(owner.to_string(), observer.clone());
+        contract.unregister_observer(owner.to_string(), observer);
+        contract.change_owner(owner.to_string(), accounts(2).to_string(), None);
+
+        assert!(contract.get_observers(owner.to_string()).is_empty());
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "too_many_observers")]
+    fn register_observer_over_cap() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+
+        let mut contract = NearDIDRegistry::default();
+        for i in 0..=MAX_OBSERVERS {
+            contract.register_observer(owner.to_string(), format!("observer{i}.near").parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn get_delegate_validity_cases() {
+        let owner = accounts(1);
+        set_context_at(owner.clone(), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(3).to_string(), 3600, None);
+        contract.revoke_delegate(owner.to_string(), "veriKey".to_string(), accounts(3).to_string(), None);
+
+        assert_eq!(contract.get_delegate_validity(owner.to_string(), "veriKey".to_string(), accounts(2).to_string()), Some(4_600));
+        assert_eq!(contract.get_delegate_validity(owner.to_string(), "veriKey".to_string(), accounts(3).to_string()), Some(0));
+        assert_eq!(contract.get_delegate_validity(owner.to_string(), "veriKey".to_string(), accounts(4).to_string()), None);
+        assert!(!contract.valid_delegate(owner.to_string(), "veriKey".to_string(), accounts(3).to_string()));
+        assert!(!contract.valid_delegate(owner.to_string(), "veriKey".to_string(), accounts(4).to_string()));
+    }
+
+    #[test]
+    fn get_attribute_validity_cases() {
+        let owner = accounts(1);
+        let value = b"https://hub.example".to_vec();
+        set_context_at(owner.clone(), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), value.clone(), 100, None);
+        assert_eq!(contract.get_attribute_validity(owner.to_string(), "did/svc/Hub".to_string(), value.clone()), Some(1_100));
+
+        set_context_at(owner.clone(), 2_000);
+        assert_eq!(contract.get_attribute_validity(owner.to_string(), "did/svc/Hub".to_string(), value.clone()), Some(1_100));
+        assert!(!contract.valid_attribute(owner.to_string(), "did/svc/Hub".to_string(), value.clone()));
+        assert_eq!(contract.get_attribute_validity(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example.org".to_vec()), None);
+    }
+
+    #[test]
+    fn identity_has_records_after_first_write() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+
+        assert!(!contract.identity_has_records(owner.to_string()));
+        contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        assert!(contract.identity_has_records(owner.to_string()));
+        assert!(!contract.identity_has_records(accounts(2).to_string()));
+
+        contract.change_owner(owner.to_string(), accounts(2).to_string(), None);
+        assert!(contract.identity_has_records(owner.to_string()));
+        assert!(!contract.identity_has_records(accounts(2).to_string()));
+    }
+
+    #[test]
+    fn valid_delegate_many_mixed() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), 100, None);
+
+        set_context_at(accounts(4), 2_000);
+        let query = |delegate_type: &str, delegate: AccountId| DelegateQuery { identity: owner.clone(), delegate_type: delegate_type.to_string(), delegate: delegate.to_string() };
+        assert_eq!(
+            contract.valid_delegate_many(vec![query("veriKey", accounts(3)), query("veriKey", accounts(2)), query("sigAuth", accounts(2)), query("veriKey", accounts(2))]),
+            vec![false, true, false, true]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "batch_too_large")]
+    fn valid_delegate_many_bounded() {
+        set_context(accounts(1));
+        let contract = NearDIDRegistry::default();
+        let query = DelegateQuery { identity: accounts(1).to_string(), delegate_type: "veriKey".to_string(), delegate: accounts(2).to_string() };
+        contract.valid_delegate_many(vec![query; MAX_PAGE_SIZE as usize + 1]);
+    }
+
+    #[test]
+    fn delegate_types_follow_revocations() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), 7200, None);
+        assert_eq!(contract.list_delegate_types(owner.clone()), vec![("veriKey".to_string(), 2), ("sigAuth".to_string(), 1)]);
+
+        contract.revoke_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), None);
+        contract.revoke_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), None);
+        contract.revoke_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), None);
+        assert_eq!(contract.list_delegate_types(owner.clone()), vec![("sigAuth".to_string(), 1)]);
+
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        assert_eq!(contract.list_delegate_types(owner.clone()), vec![("sigAuth".to_string(), 1), ("veriKey".to_string(), 1)]);
+
+        contract.revoke_delegate(owner.clone(), "sigAuth".to_string(), accounts(2).to_string(), None);
+        contract.revoke_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), None);
+        assert_eq!(contract.list_delegate_types(owner.clone()), vec![]);
+        assert!(!contract.delegate_types.contains_key(&owner));
+    }
+
+    #[test]
+    fn registry_stats_after_scripted_operations() {
+        let value = b"https://hub.example".to_vec();
+        let mut contract = NearDIDRegistry::default();
+
+        set_context_at(accounts(1), 1_000);
+        contract.add_delegate(accounts(1).to_string(), "veriKey".to_string(), accounts(2).to_string(), 100, None);
+        contract.add_delegate(accounts(1).to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(accounts(1).to_string(), "sigAuth".to_string(), accounts(2).to_string(), 100, None);
+        contract.set_attribute(accounts(1).to_string(), "did/svc/Hub".to_string(), value.clone(), 100, None);
+        contract.revoke_attribute(accounts(1).to_string(), "did/svc/Hub".to_string(), value.clone(), None);
+        contract.revoke_attribute(accounts(1).to_string(), "did/svc/Hub".to_string(), value.clone(), None);
+        contract.change_owner(accounts(1).to_string(), accounts(3).to_string(), None);
+        set_context_at(accounts(2), 1_000);
+        contract.set_attribute(accounts(2).to_string(), "did/svc/Hub".to_string(), value.clone(), 100, None);
+        contract.revoke_delegate(accounts(2).to_string(), "veriKey".to_string(), accounts(4).to_string(), None);
+        set_context_at(accounts(3), 1_000);
+        contract.change_owner(accounts(1).to_string(), accounts(4).to_string(), None);
+
+        assert_eq!(
+            contract.get_registry_stats(),
+            RegistryStats { identities_touched: 2, total_delegates: 2, total_attributes: 1, total_owner_changes: 2 }
+        );
+
+        set_context_at(accounts(4), 2_000);
+        contract.purge_expired(accounts(1).to_string(), vec![("sigAuth".to_string(), accounts(2).to_string())], vec![("did/svc/Hub".to_string(), value.clone())]);
+        contract.purge_expired(accounts(2).to_string(), vec![], vec![("did/svc/Hub".to_string(), value)]);
+        assert_eq!(
+            contract.get_registry_stats(),
+            RegistryStats { identities_touched: 2, total_delegates: 1, total_attributes: 0, total_owner_changes: 2 }
+        );
+    }
+
+    #[test]
+    fn list_identities_pages() {
+        let mut contract = NearDIDRegistry::default();
+
+        let identities: Vec<String> = (0..MAX_PAGE_SIZE + 10).map(|i| format!("identity{}.near", i)).collect();
+        for identity in &identities {
+            set_context_at(identity.parse().unwrap(), 1_000);
+            contract.add_delegate(identity.clone(), "veriKey".to_string(), accounts(2).to_string(), 100, None);
+            contract.add_delegate(identity.clone(), "sigAuth".to_string(), accounts(2).to_string(), 100, None);
+        }
+        assert_eq!(contract.get_registry_stats().identities_touched, MAX_PAGE_SIZE + 10);
+
+        let first = contract.list_identities(0, u64::MAX, None);
+        assert_eq!(first.len() as u64, MAX_PAGE_SIZE);
+        let second = contract.list_identities(MAX_PAGE_SIZE, MAX_PAGE_SIZE, None);
+        assert_eq!([first, second].concat(), identities);
+
+        set_context_at(accounts(4), 2_000);
+        contract.purge_expired(identities[1].clone(), vec![("veriKey".to_string(), accounts(2).to_string()), ("sigAuth".to_string(), accounts(2).to_string())], vec![]);
+        assert_eq!(contract.list_identities(0, 3, None), identities[..3]);
+        assert_eq!(contract.list_identities(0, 3, Some(true)), vec![identities[0].clone(), identities[2].clone()]);
+    }
+
+    #[test]
+    fn resolve_builds_did_document() {
+        let owner = accounts(1).to_string();
+        let (owner_key, auth_key, verifier_key) = (signing_key(1), signing_key(2), signing_key(3));
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_owner_key(ed25519_public_key(&owner_key));
+
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&auth_key), 3600, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), implicit_account(&verifier_key), 3600, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), key_identity(&signing_key(4)), 100, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(5)), 3600, None);
+        contract.revoke_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(5)), None);
+        contract.set_attribute(owner.clone(), "did/pub/Secp256k1/veriKey/hex".to_string(), vec![0x02, 0xab, 0xcd], 3600, None);
+        contract.set_attribute(owner.clone(), "did/pub/X25519/enc/base64".to_string(), vec![0xde, 0xad, 0xbe, 0xef], 3600, None);
+        contract.set_attribute(owner.clone(), "did/pub/Ed25519/veriKey/pem".to_string(), vec![0x01], 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/MessagingService".to_string(), b"https://msg.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://old-hub.example".to_vec(), 100, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), "email".to_string(), b"bob@example.com".to_vec(), 3600, None);
+
+        // Fragments 3 to 5 went to the delegates without a key, expired or
+        // revoked, and stay theirs.
+        set_context_at(accounts(1), 2_000);
+        let did = "did:near:bob";
+        assert_eq!(
+            serde_json::to_value(contract.resolve(owner.clone(), None)).unwrap(),
+            serde_json::json!({
+                "@context": ["https://www.w3.org/ns/did/v1"],
+                "id": did,
+                "verificationMethod": [
+                    { "id": "did:near:bob#owner", "type": "Ed25519VerificationKey2018", "controller": did, "publicKeyBase58": key_identity(&owner_key) },
+                    { "id": "did:near:bob#delegate-1", "type": "Ed25519VerificationKey2018", "controller": did, "publicKeyBase58": key_identity(&auth_key) },
+                    { "id": "did:near:bob#delegate-2", "type": "Ed25519VerificationKey2018", "controller": did, "publicKeyBase58": key_identity(&verifier_key) },
+                    { "id": "did:near:bob#delegate-6", "type": "EcdsaSecp256k1VerificationKey2019", "controller": did, "publicKeyHex": "02abcd" },
+                    { "id": "did:near:bob#delegate-7", "type": "X25519KeyAgreementKey2019", "controller": did, "publicKeyBase64": "3q2+7w==" },
+                ],
+                "authentication": ["did:near:bob#owner", "did:near:bob#delegate-1"],
+                "assertionMethod": ["did:near:bob#owner", "did:near:bob#delegate-1", "did:near:bob#delegate-2", "did:near:bob#delegate-6"],
+                "keyAgreement": ["did:near:bob#delegate-7"],
+                "service": [
+                    { "id": "did:near:bob#service-1", "type": "Hub", "serviceEndpoint": "https://hub.example" },
+                    { "id": "did:near:bob#service-2", "type": "MessagingService", "serviceEndpoint": "https://msg.example" },
+                ],
+            })
+        );
+
+        contract.change_owner(owner.clone(), accounts(2).to_string(), None);
+        contract.purge_expired(owner.clone(), vec![], vec![("did/svc/Hub".to_string(), b"https://old-hub.example".to_vec())]);
+        let document = contract.resolve(owner.clone(), None);
+        assert_eq!(document.controller, Some("did:near:charlie".to_string()));
+        assert_eq!(document.authentication, vec!["did:near:bob#delegate-1"]);
+        assert_eq!(
+            serde_json::to_value(contract.resolve(accounts(3).to_string(), None)).unwrap(),
+            serde_json::json!({ "@context": ["https://www.w3.org/ns/did/v1"], "id": "did:near:danny", "verificationMethod": [], "authentication": [], "assertionMethod": [] })
+        );
+    }
+
+    #[test]
+    fn resolve_derives_implicit_account_key() {
+        let key = signing_key(1);
+        let implicit = implicit_account(&key);
+        set_context_at(accounts(1), 1_000);
+        let contract = NearDIDRegistry::default();
+
+        let document = contract.resolve(implicit.clone(), None);
+        let did = format!("did:near:{implicit}");
+        assert_eq!(
+            serde_json::to_value(&document.verification_method).unwrap(),
+            serde_json::json!([{ "id": format!("{did}#owner"), "type": "Ed25519VerificationKey2018", "controller": did, "publicKeyBase58": key_identity(&key) }])
+        );
+        assert_eq!(document.authentication, vec![format!("{did}#owner")]);
+        assert_eq!(document.assertion_method, vec![format!("{did}#owner")]);
+
+        for identity in ["alice.near".to_string(), "g".repeat(64), implicit.to_uppercase()] {
+            let document = contract.resolve(identity, None);
+            assert!(document.verification_method.is_empty());
+            assert!(document.authentication.is_empty() && document.assertion_method.is_empty());
+        }
+    }
+
+    #[test]
+    fn resolve_names_only_direct_controller() {
+        let (bob, charlie, danny) = (accounts(1).to_string(), accounts(2).to_string(), accounts(3).to_string());
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        assert!(serde_json::to_value(contract.resolve(bob.clone(), None)).unwrap().get("controller").is_none());
+
+        contract.change_owner(bob.clone(), charlie.clone(), None);
+        set_context_at(accounts(2), 1_000);
+        contract.change_owner(charlie.clone(), danny.clone(), None);
+        assert_eq!(contract.resolve(bob.clone(), None).controller, Some("did:near:charlie".to_string()));
+        assert_eq!(contract.resolve(charlie.clone(), None).controller, Some("did:near:danny".to_string()));
+        assert_eq!(contract.resolve(danny, None).controller, None);
+
+        contract.change_owner(bob.clone(), bob.clone(), None);
+        assert!(serde_json::to_value(contract.resolve(bob, None)).unwrap().get("controller").is_none());
+    }
+
+    #[test]
+    fn resolve_is_canonical() {
+        let owner = accounts(1).to_string();
+        let delegates = [("veriKey", 2), ("sigAuth", 3), ("veriKey", 4), ("enc", 5)];
+        let attributes = [("did/svc/Hub", "https://hub.example"), ("did/pub/Secp256k1/veriKey/hex", "02ab"), ("did/svc/Hub", "https://alt.example"), ("did/svc/Agent", "https://agent.example")];
+        let build = |reversed: bool| {
+            set_context_at(accounts(1), 1_000);
+            let mut contract = NearDIDRegistry::default();
+            let write = |contract: &mut NearDIDRegistry, i: usize, validity_secs: u64| {
+                let (delegate_type, seed) = delegates[i];
+                contract.add_delegate(owner.clone(), delegate_type.to_string(), key_identity(&signing_key(seed)), validity_secs, None);
+                let (name, value) = attributes[i];
+                contract.set_attribute(owner.clone(), name.to_string(), value.as_bytes().to_vec(), validity_secs, None);
+            };
+            if reversed {
+                // Keys keep the fragments of their first addition, so give
+                // them the same ones before writing in reverse.
+                (0..4).for_each(|i| write(&mut contract, i, 0));
+                (0..4).rev().for_each(|i| write(&mut contract, i, 3600));
+                contract.add_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(6)), 3600, None);
+                contract.revoke_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(6)), None);
+            } else {
+                (0..4).for_each(|i| write(&mut contract, i, 3600));
+            }
+            contract
+        };
+        let (forward, reversed) = (build(false), build(true));
+
+        let json = serde_json::to_string(&forward.resolve(owner.clone(), None)).unwrap();
+        assert_eq!(serde_json::to_string(&reversed.resolve(owner.clone(), None)).unwrap(), json);
+        assert_eq!(forward.document_hash(owner.clone()), reversed.document_hash(owner.clone()));
+        // `Value` objects sort their keys, so this holds only when the
+        // document's are sorted already.
+        assert_eq!(serde_json::to_string(&serde_json::from_str::<serde_json::Value>(&json).unwrap()).unwrap(), json);
+        let metadata = serde_json::to_string(&forward.resolve_many(vec![owner.clone()])).unwrap();
+        assert_eq!(serde_json::to_string(&serde_json::from_str::<serde_json::Value>(&metadata).unwrap()).unwrap(), metadata);
+
+        let document = forward.resolve(owner, None);
+        let services: Vec<&str> = document.service.iter().map(|service| service.service_endpoint.uri()).collect();
+        assert_eq!(services, ["https://agent.example", "https://alt.example", "https://hub.example"]);
+        let ids: Vec<String> = [5, 2, 1, 4, 3].iter().map(|i| format!("did:near:bob#delegate-{}", i)).collect();
+        assert_eq!(document.verification_method.iter().map(|method| method.id.clone()).collect::<Vec<_>>(), ids);
+        assert_eq!(document.key_agreement, vec![ids[0].clone()]);
+        assert_eq!(document.authentication, vec![ids[1].clone()]);
+        assert_eq!(document.assertion_method, ids[1..].to_vec());
+    }
+
+    #[test]
+    fn resolve_appends_configured_contexts() {
+        let owner = accounts(1).to_string();
+        let context = "did/cfg/context".to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        assert_eq!(contract.resolve(owner.clone(), None).context, vec![document::DID_CONTEXT]);
+
+        let suite = "https://w3id.org/security/suites/ed25519-2020/v1";
+        contract.set_attribute(owner.clone(), context.clone(), format!(r#"["{}", "{}"]"#, suite, document::DID_CONTEXT).into_bytes(), 3600, None);
+        let resolved = contract.resolve_with_metadata(owner.clone(), None);
+        assert_eq!(resolved.did_document.unwrap().context, vec![document::DID_CONTEXT, suite]);
+        assert!(resolved.did_document_metadata.warnings.is_empty());
+
+        let too_many = serde_json::to_vec(&(0..document::MAX_CONTEXTS).map(|i| format!("https://example.com/{}", i)).collect::<Vec<_>>()).unwrap();
+        for value in [b"[\"https://a.example\"".to_vec(), br#"{"@vocab": "https://a.example"}"#.to_vec(), br#"["not a uri"]"#.to_vec(), too_many] {
+            contract.set_attribute(owner.clone(), context.clone(), value, 3600, None);
+        }
+        let resolved = contract.resolve_with_metadata(owner.clone(), None);
+        assert_eq!(resolved.did_document.unwrap().context, vec![document::DID_CONTEXT, suite]);
+        assert_eq!(
+            resolved.did_document_metadata.warnings,
+            vec![
+                "did/cfg/context: not a JSON array of strings",
+                "did/cfg/context: more than 8 contexts",
+                "did/cfg/context: \"not a uri\" is not a URI",
+                "did/cfg/context: not a JSON array of strings",
+            ]
+        );
+
+        contract.set_attribute(owner.clone(), context, vec![b' '; document::MAX_CONTEXT_BYTES + 1], 3600, None);
+        assert!(contract.resolve_with_metadata(owner, None).did_document_metadata.warnings.contains(&"did/cfg/context: value longer than 1024 bytes".to_string()));
+    }
+
+    #[test]
+    fn resolve_builds_didcomm_endpoints() {
+        let owner = accounts(1).to_string();
+        let name = "did/svc/DIDCommMessaging".to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        let full = br#"{"uri": "https://mediator.example/didcomm", "accept": ["didcomm/v2"], "routingKeys": ["did:near:mediator.near#key-1"]}"#;
+        contract.set_attribute(owner.clone(), name.clone(), full.to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), name.clone(), b"https://agent.example/didcomm".to_vec(), 3600, None);
+        let document = serde_json::to_value(contract.resolve(owner.clone(), None)).unwrap();
+        assert_eq!(
+            document["service"],
+            serde_json::json!([
+                {"id": "did:near:bob#service-1", "serviceEndpoint": {"uri": "https://agent.example/didcomm"}, "type": "DIDCommMessaging"},
+                {
+                    "id": "did:near:bob#service-2",
+                    "serviceEndpoint": {"accept": ["didcomm/v2"], "routingKeys": ["did:near:mediator.near#key-1"], "uri": "https://mediator.example/didcomm"},
+                    "type": "DIDCommMessaging",
+                },
+            ])
+        );
+
+        for value in [br#"{"uri": "https://a.example", "routingKeys": "did:near:m.near#key-1"}"#.to_vec(), br#"{"uri": "mediator"}"#.to_vec(), b"not a uri".to_vec()] {
+            contract.set_attribute(owner.clone(), name.clone(), value, 3600, None);
+        }
+        contract.set_attribute(owner.clone(), "did/svc/LinkedDomains".to_string(), b"https://example.com".to_vec(), 3600, None);
+        let resolved = contract.resolve_with_metadata(owner, None);
+        let services = resolved.did_document.unwrap().service;
+        assert_eq!(services.iter().map(|service| service.id.as_str()).collect::<Vec<_>>(), ["did:near:bob#service-1", "did:near:bob#service-4", "did:near:bob#service-6"]);
+        assert_eq!(services[2].service_endpoint, ServiceEndpoint::LinkedDomains(LinkedDomainsEndpoint { origins: vec!["https://example.com".to_string()] }));
+        assert_eq!(
+            resolved.did_document_metadata.warnings,
+            [
+                "did:near:bob#service-2: \"not a uri\" is not a URI",
+                "did:near:bob#service-3: not a DIDComm endpoint object",
+                "did:near:bob#service-5: \"mediator\" is not a URI",
+            ]
+        );
+    }
+
+    #[test]
+    fn linked_domains_resolve_as_origins() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_linked_domain(owner.clone(), "https://example.com".to_string(), 3600);
+        contract.set_linked_domain(format!("did:near:{}", owner), "https://id.example.com:8443".to_string(), 3600);
+        contract.set_attribute(owner.clone(), linked_domains_attribute(), b"http://example.com".to_vec(), 3600, None);
+        assert!(contract.valid_attribute(owner.clone(), "did/svc/LinkedDomains".to_string(), b"https://example.com".to_vec()));
+
+        let resolved = contract.resolve_with_metadata(owner.clone(), None);
+        assert_eq!(
+            serde_json::to_value(resolved.did_document.unwrap().service).unwrap(),
+            serde_json::json!([
+                {"id": "did:near:bob#service-2", "serviceEndpoint": {"origins": ["https://example.com"]}, "type": "LinkedDomains"},
+                {"id": "did:near:bob#service-3", "serviceEndpoint": {"origins": ["https://id.example.com:8443"]}, "type": "LinkedDomains"},
+            ])
+        );
+        assert_eq!(resolved.did_document_metadata.warnings, ["did:near:bob#service-1: origin not https"]);
+
+        contract.remove_linked_domain(owner.clone(), "https://example.com".to_string());
+        let services = contract.resolve(owner, None).service;
+        assert_eq!(services.iter().map(|service| service.service_endpoint.uri()).collect::<Vec<_>>(), ["https://id.example.com:8443"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_origin")]
+    fn linked_domain_rejects_http() {
+        set_context(accounts(1));
+        NearDIDRegistry::default().set_linked_domain(accounts(1).to_string(), "http://example.com".to_string(), 3600);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_origin")]
+    fn linked_domain_rejects_paths() {
+        set_context(accounts(1));
+        NearDIDRegistry::default().set_linked_domain(accounts(1).to_string(), "https://example.com/did".to_string(), 3600);
+    }
+
+    #[test]
+    fn resolve_places_delegates_by_type() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        for (seed, delegate_type) in [(1, "veriKey"), (2, "sigAuth"), (3, "enc"), (4, "custom")] {
+            contract.add_delegate(owner.clone(), delegate_type.to_string(), key_identity(&signing_key(seed)), 3600, None);
+        }
+        let document = contract.resolve(owner.clone(), None);
+        // Numbered in the order added, listed by type.
+        let ids: Vec<String> = (1..=4).map(|i| format!("did:near:bob#delegate-{}", i)).collect();
+        assert_eq!(document.verification_method.iter().map(|method| method.id.clone()).collect::<Vec<_>>(), ids.iter().rev().cloned().collect::<Vec<_>>());
+        assert_eq!(document.assertion_method, vec![ids[1].clone(), ids[0].clone()]);
+        assert_eq!(document.authentication, vec![ids[1].clone()]);
+        assert_eq!(document.key_agreement, vec![ids[2].clone()]);
+
+        assert_eq!(DelegateType::parse("custom"), DelegateType::Unknown);
+        assert_eq!(DelegateType::parse("enc").relationships(), [Relationship::KeyAgreement]);
+        assert_eq!(
+            serde_json::to_value(contract.get_delegate_type_relationships()).unwrap(),
+            serde_json::json!([["veriKey", ["assertionMethod"]], ["sigAuth", ["authentication", "assertionMethod"]], ["enc", ["keyAgreement"]]])
+        );
+    }
+
+    #[test]
+    fn compact_resolution_agrees_with_full() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        for (seed, delegate_type) in [(1, "veriKey"), (2, "sigAuth"), (3, "enc"), (4, "custom")] {
+            contract.add_delegate(owner.clone(), delegate_type.to_string(), key_identity(&signing_key(seed)), 3600, None);
+        }
+        contract.set_attribute(owner.clone(), "did/svc/LinkedDomains".to_string(), b"https://example.com".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/HubService".to_string(), b"https://hub.example.com".to_vec(), 3600, None);
+
+        let full = contract.resolve(owner.clone(), None);
+        let options = ResolveOptions { compact: true, service_type: Some("LinkedDomains".to_string()), ..Default::default() };
+        let compact = contract.resolve(owner.clone(), Some(options));
+        let embedded = |entry: &RelationshipEntry| {
+            RelationshipEntry::Embedded(Box::new(full.verification_method.iter().find(|method| method.id == entry.id()).unwrap().clone()))
+        };
+        let ids: Vec<String> = (1..=4).map(|i| format!("did:near:bob#delegate-{}", i)).collect();
+
+        assert!(compact.verification_method.is_empty() && compact.key_agreement.is_empty());
+        assert_eq!(full.authentication, vec![ids[1].clone()]);
+        assert_eq!(full.assertion_method, vec![ids[1].clone(), ids[0].clone()]);
+        assert_eq!(compact.authentication, vec![embedded(&full.authentication[0])]);
+        assert_eq!(compact.assertion_method, vec![full.assertion_method[0].clone(), embedded(&full.assertion_method[1])]);
+        assert_eq!(compact.service, full.service.iter().filter(|service| service.service_type == "LinkedDomains").cloned().collect::<Vec<_>>());
+        assert_eq!((&compact.id, &compact.context, &compact.controller), (&full.id, &full.context, &full.controller));
+
+        let json = serde_json::to_string(&compact).unwrap();
+        assert_eq!(json, serde_json::to_value(&compact).unwrap().to_string());
+        assert_eq!(serde_json::from_str::<DidDocument>(&json).unwrap(), compact);
+    }
+
+    #[test]
+    fn did_spellings_share_records() {
+        let identity = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.add_delegate("did:near:bob".to_string(), "sigAuth".to_string(), format!("did:near:{}", key_identity(&signing_key(2))), 3600, None);
+        contract.set_attribute("did:near:mainnet:bob".to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        contract.change_owner(identity.clone(), "did:near:charlie".to_string(), None);
+
+        assert!(contract.valid_delegate(identity.clone(), "sigAuth".to_string(), key_identity(&signing_key(2))));
+        assert!(contract.valid_attribute("did:near:bob".to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec()));
+        assert_eq!(contract.identity_owner("did:near:mainnet:bob".to_string()), accounts(2).to_string());
+        assert_eq!(contract.identities_owned_by("did:near:charlie".to_string(), 0, 10), vec![identity.clone()]);
+        assert_eq!(contract.resolve("did:near:bob".to_string(), None), contract.resolve(identity.clone(), None));
+        assert_eq!(contract.resolve("did:near:bob".to_string(), None).id, "did:near:bob");
+        assert_eq!(contract.list_identities(0, 10, None), vec![identity]);
+
+        for (id, parsed) in [
+            ("bob.near", Ok("bob.near")),
+            ("did:near:bob.near", Ok("bob.near")),
+            ("did:near:testnet:bob.testnet", Ok("bob.testnet")),
+            ("did:near:mainnet:bob.near", Err(RegistryError::BadDid)),
+            ("did:near:betanet:bob.testnet", Err(RegistryError::BadDid)),
+            ("did:ethr:0xb9c5714089478a327f09197987f16f9e5d936e8a", Err(RegistryError::UnsupportedDidMethod)),
+            ("did:near:", Err(RegistryError::BadDid)),
+            ("did:near:testnet:", Err(RegistryError::BadDid)),
+            ("did:near::bob.near", Err(RegistryError::BadDid)),
+            ("did:near:a:b:bob.near", Err(RegistryError::BadDid)),
+        ] {
+            assert_eq!(document::parse_did(id, "testnet"), parsed, "{}", id);
+        }
+    }
+
+    #[test]
+    fn did_of_other_network_rejected() {
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        let error = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.set_attribute("did:near:testnet:bob".to_string(), "did/svc/Hub".to_string(), vec![], 3600, None))).unwrap_err();
+        assert!(error.downcast_ref::<String>().is_some_and(|message| message.contains("bad_did")));
+        assert_eq!(contract.resolve_many(vec!["did:near:testnet:bob".to_string()])[0].did_resolution_metadata.error, Some(ResolutionError::InvalidDid));
+
+        testing_env!(VMContextBuilder::new().current_account_id("registry.testnet".parse().unwrap()).predecessor_account_id(accounts(1)).build());
+        contract.set_attribute("did:near:testnet:bob".to_string(), "did/svc/Hub".to_string(), vec![], 3600, None);
+        assert!(contract.valid_attribute("bob".to_string(), "did/svc/Hub".to_string(), vec![]));
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported_did_method")]
+    fn did_of_other_method_rejected() {
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_attribute("did:ethr:0xb9c5714089478a327f09197987f16f9e5d936e8a".to_string(), "did/svc/Hub".to_string(), vec![], 3600, None);
+    }
+
+    #[test]
+    fn attribute_names_parse() {
+        use document::attribute_name::{self, KeyAlgorithm::*, KeyEncoding::*, ParsedAttributeName};
+
+        let key = |algorithm, purpose, encoding| Some(ParsedAttributeName::PublicKey { algorithm, purpose, encoding });
+        let service = |service_type: &str| Some(ParsedAttributeName::Service { service_type: service_type.to_string() });
+        for (name, parsed) in [
+            ("did/pub/Ed25519/veriKey/base64", key(Ed25519, DelegateType::VeriKey, Base64)),
+            ("did/pub/Secp256k1/sigAuth/hex", key(Secp256k1, DelegateType::SigAuth, Hex)),
+            ("did/pub/X25519/enc/base58", key(X25519, DelegateType::Enc, Base58)),
+            ("did/pub/Secp256k1/veriKey", key(Secp256k1, DelegateType::VeriKey, Hex)),
+            ("did/svc/DIDCommMessaging", service("DIDCommMessaging")),
+            ("did/svc/LinkedDomains/v2", service("LinkedDomains/v2")),
+            ("email", Some(ParsedAttributeName::Other)),
+            ("did/svc", Some(ParsedAttributeName::Other)),
+            ("did/pubkey/Ed25519", Some(ParsedAttributeName::Other)),
+            ("did/svc/", None),
+            ("did/pub/", None),
+            ("did/pub/Ed25519", None),
+            ("did/pub/RSA/veriKey/hex", None),
+            ("did/pub/Ed25519/custom/hex", None),
+            ("did/pub/Ed25519/veriKey/pem", None),
+            ("did/pub/ed25519/veriKey/hex", None),
+            ("did/pub/Ed25519/veriKey/hex/extra", None),
+            ("did/cfg/context", Some(ParsedAttributeName::Context)),
+            ("did/cfg/contexts", None),
+            ("did/cfg", Some(ParsedAttributeName::Other)),
+        ] {
+            assert_eq!(attribute_name::parse(name), parsed, "{}", name);
+        }
+        assert_eq!(Base58.encode(&[0, 1, 2]), "15T");
+        assert_eq!(Hex.encode(&[0x0a, 0xff]), "0aff");
+        assert_eq!(Base64.encode(&[0xde, 0xad]), "3q0=");
+    }
+
+    #[test]
+    fn resolve_formats_keys() {
+        use document::key_format::KeyFormat;
+
+        // RFC 8032 test 1, with its JWK from RFC 8037 appendix A.2.
+        let hex = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+        let key: Vec<u8> = (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect();
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_attribute(owner.clone(), "did/pub/Ed25519/veriKey/hex".to_string(), key.clone(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/pub/Secp256k1/veriKey/hex".to_string(), vec![0x02, 0xab, 0xcd], 3600, None);
+        contract.set_attribute(owner.clone(), "did/pub/X25519/enc/base64".to_string(), vec![7; 32], 3600, None);
+
+        let options = |key_format| Some(ResolveOptions { key_format, ..Default::default() });
+        let multibase = contract.resolve_with_metadata(owner.clone(), options(KeyFormat::Multibase));
+        let multikey = multibase.did_document.unwrap();
+        assert_eq!(
+            serde_json::to_value(&multikey.verification_method[0]).unwrap(),
+            serde_json::json!({ "id": "did:near:bob#delegate-1", "type": "Multikey", "controller": "did:near:bob", "publicKeyMultibase": "z6MktwupdmLXVVqTzCw4i46r4uGyosGXRnR3XjN4Zq7oMMsw" })
+        );
+        assert_eq!(multikey.verification_method.len(), 2);
+        assert_eq!(multikey.verification_method[1].id, "did:near:bob#delegate-3");
+        assert!(multikey.verification_method[1].public_key_multibase.as_ref().unwrap().starts_with("z6LS"));
+        assert_eq!(multikey.assertion_method, vec!["did:near:bob#delegate-1"]);
+        assert_eq!(multibase.did_document_metadata.warnings, vec!["did:near:bob#delegate-2: invalid Secp256k1 key length 3"]);
+
+        let jwk = contract.resolve(owner.clone(), options(KeyFormat::Jwk));
+        assert_eq!(
+            serde_json::to_value(&jwk.verification_method[0]).unwrap(),
+            serde_json::json!({
+                "id": "did:near:bob#delegate-1",
+                "type": "JsonWebKey2020",
+                "controller": "did:near:bob",
+                "publicKeyJwk": { "kty": "OKP", "crv": "Ed25519", "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo" },
+            })
+        );
+        assert_eq!(contract.resolve(owner.clone(), options(KeyFormat::Default)), contract.resolve(owner.clone(), None));
+        assert_eq!(contract.resolve(owner, None).verification_method[1].public_key_hex, Some("02abcd".to_string()));
+
+        let secp = secp256k1_key(1).public_key(&secp256k1::Secp256k1::new());
+        let compressed = document::key_format::multibase(document::attribute_name::KeyAlgorithm::Secp256k1, &secp.serialize()).unwrap();
+        assert!(compressed.starts_with("zQ3s"));
+        assert_eq!(document::key_format::multibase(document::attribute_name::KeyAlgorithm::Secp256k1, &secp.serialize_uncompressed()), Ok(compressed));
+        let jwk = document::key_format::jwk(document::attribute_name::KeyAlgorithm::Secp256k1, &secp.serialize_uncompressed()).unwrap();
+        assert_eq!((jwk.kty.as_str(), jwk.crv.as_str(), jwk.y.is_some()), ("EC", "secp256k1", true));
+    }
+
+    #[test]
+    fn resolve_reports_malformed_attributes() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(owner.clone(), "did/pub/Ed25519/veriKey/pem".to_string(), vec![0x01], 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/pub/RSA/veriKey".to_string(), vec![0x02], 100, None);
+        contract.set_attribute(owner.clone(), "did/pub/Ed25519/veriKey/base58".to_string(), vec![0, 1, 2], 3600, None);
+
+        set_context_at(accounts(1), 2_000);
+        let resolved = contract.resolve_with_metadata(owner.clone(), None);
+        let methods = resolved.did_document.unwrap().verification_method;
+        assert_eq!(methods.len(), 1);
+        assert_eq!(methods[0].public_key_base58, Some("15T".to_string()));
+        assert_eq!(resolved.did_document_metadata.malformed_attributes, vec!["did/pub/Ed25519/veriKey/pem", "did/svc/"]);
+        assert_eq!(
+            serde_json::to_value(&resolved.did_document_metadata).unwrap()["malformedAttributes"],
+            serde_json::json!(["did/pub/Ed25519/veriKey/pem", "did/svc/"])
+        );
+    }
+
+    #[test]
+    fn dereference_finds_fragments() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), key_identity(&signing_key(2)), 3600, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(3)), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+
+        assert_eq!(
+            contract.dereference("did:near:bob#delegate-1".to_string()),
+            Some(serde_json::json!({ "id": "did:near:bob#delegate-1", "type": "Ed25519VerificationKey2018", "controller": "did:near:bob", "publicKeyBase58": key_identity(&signing_key(2)) }))
+        );
+        assert_eq!(
+            contract.dereference("did:near:mainnet:bob#service-1".to_string()),
+            Some(serde_json::json!({ "id": "did:near:bob#service-1", "type": "Hub", "serviceEndpoint": "https://hub.example" }))
+        );
+        assert_eq!(contract.dereference("did:near:bob#delegate-3".to_string()), None);
+        assert_eq!(contract.dereference("did:near:bob#".to_string()), None);
+        assert_eq!(contract.dereference("did:near:bob".to_string()), serde_json::to_value(contract.resolve(owner, None)).ok());
+    }
+
+    #[test]
+    fn fragments_survive_revocation() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        let delegate = |seed| key_identity(&signing_key(seed));
+        for seed in 1..=3 {
+            contract.add_delegate(owner.clone(), "sigAuth".to_string(), delegate(seed), 3600, None);
+        }
+        contract.set_attribute(owner.clone(), "did/pub/Secp256k1/veriKey/hex".to_string(), vec![0x02, 0xab], 3600, None);
+        let method_ids = |contract: &NearDIDRegistry| contract.resolve(owner.clone(), None).verification_method.into_iter().map(|method| method.id).collect::<Vec<_>>();
+        let key_of = |contract: &NearDIDRegistry, seed| contract.dereference(format!("did:near:bob#{}", contract.get_fragment(owner.clone(), "sigAuth".to_string(), delegate(seed)).unwrap()));
+        let (second, third) = (key_of(&contract, 2), key_of(&contract, 3));
+        assert!(second.is_some() && third.is_some());
+
+        contract.revoke_delegate(owner.clone(), "sigAuth".to_string(), delegate(1), None);
+        assert_eq!(contract.get_fragment(owner.clone(), "sigAuth".to_string(), delegate(1)), Some("delegate-1".to_string()));
+        assert_eq!(contract.get_fragment(format!("did:near:{}", owner), "sigAuth".to_string(), delegate(3)), Some("delegate-3".to_string()));
+        assert_eq!((key_of(&contract, 2), key_of(&contract, 3)), (second, third));
+        assert_eq!(key_of(&contract, 1), None);
+        let ids = method_ids(&contract);
+        assert!(ids.contains(&"did:near:bob#delegate-4".to_string()) && !ids.contains(&"did:near:bob#delegate-1".to_string()));
+
+        // New keys take the next number, readded ones their old one.
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), delegate(5), 3600, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), delegate(1), 3600, None);
+        assert_eq!(contract.get_fragment(owner.clone(), "sigAuth".to_string(), delegate(5)), Some("delegate-5".to_string()));
+        assert_eq!(contract.get_fragment(owner.clone(), "sigAuth".to_string(), delegate(1)), Some("delegate-1".to_string()));
+        assert_eq!(contract.get_fragment(owner.clone(), "veriKey".to_string(), delegate(1)), None);
+        assert_eq!(method_ids(&contract).len(), 5);
+    }
+
+    /// Assembles the `resolve` document of the identity from
+    /// `resolve_paginated` pages of `MAX_PAGE_SIZE` entries.
+    fn resolve_in_pages(contract: &NearDIDRegistry, identity: &str) -> DidDocument {
+        let mut document = DocumentBuilder::new(identity, &contract.identity_owner(identity.to_string())).build();
+        for section in [DocSection::VerificationMethod, DocSection::Service, DocSection::Authentication, DocSection::AssertionMethod, DocSection::KeyAgreement] {
+            let mut from_index = 0;
+            loop {
+                let page = contract.resolve_paginated(identity.to_string(), section, from_index, MAX_PAGE_SIZE);
+                let len = match page {
+                    SectionEntries::Methods(methods) => {
+                        let len = methods.len();
+                        document.verification_method.extend(methods);
+                        len
+                    }
+                    SectionEntries::Services(services) => {
+                        let len = services.len();
+                        document.service.extend(services);
+                        len
+                    }
+                    SectionEntries::References(references) => {
+                        let len = references.len();
+                        let references = references.into_iter().map(RelationshipEntry::Reference);
+                        match section {
+                            DocSection::Authentication => document.authentication.extend(references),
+                            DocSection::AssertionMethod => document.assertion_method.extend(references),
+                            _ => document.key_agreement.extend(references),
+                        }
+                        len
+                    }
+                };
+                from_index += len as u64;
+                if (len as u64) < MAX_PAGE_SIZE {
+                    break;
+                }
+            }
+        }
+        document
+    }
+
+    #[test]
+    fn resolve_paginated_assembles_document() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_owner_key(ed25519_public_key(&signing_key(1)));
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(2)), 3600, None);
+        contract.add_delegate(owner.clone(), "enc".to_string(), key_identity(&signing_key(3)), 3600, None);
+        contract.set_attribute(owner.clone(), "did/pub/Secp256k1/veriKey/hex".to_string(), vec![0x02, 0xab], 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        assert_eq!(resolve_in_pages(&contract, &owner), contract.resolve(owner.clone(), None));
+        assert_eq!(
+            contract.resolve_paginated(owner.clone(), DocSection::AssertionMethod, 1, 2),
+            SectionEntries::References(vec!["did:near:bob#delegate-1".to_string(), "did:near:bob#delegate-3".to_string()])
+        );
+        assert_eq!(serde_json::to_value(contract.resolve_paginated(owner.clone(), DocSection::KeyAgreement, 0, 10)).unwrap(), serde_json::json!(["did:near:bob#delegate-2"]));
+
+        let large = accounts(2).to_string();
+        for i in 0..150 {
+            set_context_at(accounts(2), 1_000);
+            contract.add_delegate(large.clone(), ["sigAuth", "veriKey", "enc"][i % 3].to_string(), format!("{:064x}", i + 1), 3600, None);
+            contract.set_attribute(large.clone(), format!("did/svc/Service{}", i), format!("https://service{}.example", i).into_bytes(), 3600, None);
+            if i % 50 == 49 {
+                contract = flushed(contract);
+            }
+        }
+        set_context_at(accounts(2), 1_000);
+        let summary = contract.resolve_summary(large.clone());
+        assert_eq!(
+            summary,
+            DocumentSummary { version_id: Some("40".to_string()), verification_method: 150, service: 150, authentication: 50, assertion_method: 100, key_agreement: 50 }
+        );
+        let assembled = resolve_in_pages(&contract, &large);
+        assert_eq!(assembled.verification_method.len(), 150);
+        assert_eq!(assembled.service.len(), 150);
+        assert_eq!(assembled.service[149].service_endpoint.uri(), "https://service99.example");
+        assert_eq!(assembled, contract.resolve(large.clone(), None));
+        assert_eq!(contract.resolve_summary(large), summary);
+    }
+
+    #[test]
+    fn resolve_with_metadata_tracks_writes() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context(accounts(1));
+        assert_eq!(
+            serde_json::to_value(contract.resolve_with_metadata(owner.clone(), None).did_document_metadata).unwrap(),
+            serde_json::json!({ "deactivated": false })
+        );
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(25).block_timestamp(1_700_000_000_500_000_000).build());
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(2)), 3600, None);
+        let created = contract.resolve_with_metadata(owner.clone(), None);
+        assert_eq!(created.did_document, Some(contract.resolve(owner.clone(), None)));
+        assert_eq!(
+            serde_json::to_value(&created).unwrap()["did_document_metadata"],
+            serde_json::json!({ "created": "2023-11-14T22:13:20Z", "updated": "2023-11-14T22:13:20Z", "versionId": "25", "deactivated": false })
+        );
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(90).block_timestamp(1_709_251_199_000_000_000).build());
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        let updated = contract.resolve_with_metadata(owner.clone(), None);
+        assert_eq!(
+            updated.did_document_metadata,
+            DocumentMetadata { controller: None, created: Some("2023-11-14T22:13:20Z".to_string()), updated: Some("2024-02-29T23:59:59Z".to_string()), version_id: Some("90".to_string()), deactivated: false, locked: false, malformed_attributes: vec![], renounced: false, warnings: vec![] }
+        );
+        assert_eq!(updated.did_document.unwrap().service.len(), 1);
+        assert_eq!(contract.get_document_metadata(format!("did:near:{}", owner)), updated.did_document_metadata);
+    }
+
+    #[test]
+    fn document_metadata_matches_full_resolution() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context(accounts(1));
+        assert_eq!(contract.get_document_metadata(owner.clone()), contract.resolve_with_metadata(owner.clone(), None).did_document_metadata);
+
+        for (block_height, timestamp_secs) in [(25, 1_700_000_000), (90, 1_709_251_199)] {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(block_height).block_timestamp(timestamp_secs * 1_000_000_000).build());
+            contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), format!("https://hub{}.example", block_height).into_bytes(), 3600, None);
+            let metadata = contract.get_document_metadata(owner.clone());
+            assert_eq!(metadata, contract.resolve_with_metadata(owner.clone(), None).did_document_metadata);
+            assert_eq!(metadata.version_id, Some(block_height.to_string()));
+        }
+
+        // Only building the document finds malformed attributes.
+        contract.set_attribute(owner.clone(), "did/pub/Ed25519/veriKey/pem".to_string(), vec![1], 3600, None);
+        let full = contract.resolve_with_metadata(owner.clone(), None).did_document_metadata;
+        assert_eq!(full.malformed_attributes, ["did/pub/Ed25519/veriKey/pem"]);
+        assert_eq!(contract.get_document_metadata(owner), DocumentMetadata { malformed_attributes: vec![], ..full });
+    }
+
+    #[test]
+    fn resolve_many_reports_failures_per_identity() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+
+        let results = contract.resolve_many(vec![
+            "did:near:bob".to_string(),
+            accounts(3).to_string(),
+            "did:near:".to_string(),
+            "did:ethr:0xb9c5714089478a327f09197987f16f9e5d936e8a".to_string(),
+        ]);
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0], contract.resolve_with_metadata(owner, None));
+        assert_eq!(results[0].did_document.as_ref().unwrap().service.len(), 1);
+        assert_eq!(results[1].did_resolution_metadata.error, None);
+        assert_eq!(results[1].did_document.as_ref().unwrap().id, "did:near:danny");
+        assert_eq!(results[1].did_document_metadata.version_id, None);
+        assert_eq!(results[2], ResolutionResult::failed(ResolutionError::InvalidDid));
+        assert_eq!(
+            serde_json::to_value(&results[3]).unwrap(),
+            serde_json::json!({ "did_resolution_metadata": { "error": "methodNotSupported" }, "did_document": null, "did_document_metadata": { "deactivated": false } })
+        );
+        assert_eq!(contract.resolve_many(vec![]), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "too_many_identities")]
+    fn resolve_many_bounded() {
+        set_context(accounts(1));
+        let contract = NearDIDRegistry::default();
+        contract.resolve_many(vec![accounts(2).to_string(); MAX_RESOLVE_MANY + 1]);
+    }
+
+    #[test]
+    fn resolve_at_version_rebuilds_middle_version() {
+        let owner = accounts(1).to_string();
+        let at = |block_height: u64, timestamp_secs: u64| {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(block_height).block_timestamp(timestamp_secs * 1_000_000_000).build());
+        };
+        let hub = |version: &str| format!("https://hub{}.example", version).into_bytes();
+        at(10, 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(2)), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), hub("1"), 3600, None);
+        at(20, 2_000);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), key_identity(&signing_key(3)), 100, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), hub("2"), 3600, None);
+        contract.revoke_attribute(owner.clone(), "did/svc/Hub".to_string(), hub("1"), None);
+        let version_20 = contract.resolve_with_metadata(owner.clone(), None);
+        at(30, 3_000);
+        contract.revoke_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(2)), None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), hub("3"), 3600, None);
+        contract.change_owner(owner.clone(), accounts(2).to_string(), None);
+        let version_30 = contract.resolve_with_metadata(owner.clone(), None);
+        at(40, 4_000);
+        assert_eq!(contract.purge_expired(owner.clone(), vec![("veriKey".to_string(), key_identity(&signing_key(3)))], vec![]), 1);
+
+        assert_eq!(contract.get_change_history(owner.clone(), 0, 10), vec![10, 20, 30]);
+        let middle = contract.resolve_at_version(owner.clone(), 20);
+        assert_eq!(middle, version_20);
+        assert_eq!(middle.did_document.as_ref().unwrap().verification_method.len(), 2);
+        assert_eq!(middle.did_document.unwrap().service[0].service_endpoint.uri(), "https://hub2.example");
+        assert_eq!(contract.resolve_at_version(owner.clone(), 30), version_30);
+        let first = contract.resolve_at_version(owner.clone(), 10);
+        assert_eq!(first.did_document_metadata.version_id, Some("10".to_string()));
+        assert_eq!(first.did_document.unwrap().service[0].service_endpoint.uri(), "https://hub1.example");
+
+        for version_id in [0, 15, 35, 40] {
+            assert_eq!(contract.resolve_at_version(owner.clone(), version_id), ResolutionResult::failed(ResolutionError::VersionNotFound), "{}", version_id);
+        }
+        assert_eq!(contract.resolve_at_version(accounts(3).to_string(), 10), ResolutionResult::failed(ResolutionError::VersionNotFound));
+
+        contract.set_history_limit(2);
+        assert_eq!(contract.resolve_at_version(owner.clone(), 10), ResolutionResult::failed(ResolutionError::VersionPruned));
+        assert_eq!(contract.resolve_at_version(owner.clone(), 20), version_20);
+    }
+
+    #[test]
+    fn diff_versions_lists_changed_entries() {
+        let owner = accounts(1).to_string();
+        let at = |block_height: u64| {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(block_height).block_timestamp(block_height * 1_000_000_000_000).build());
+        };
+        set_context(accounts(2));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_owner_key(ed25519_public_key(&signing_key(9)));
+        at(10);
+        contract.set_owner_key(ed25519_public_key(&signing_key(1)));
+        contract.set_attribute(owner.clone(), "did/pub/Secp256k1/veriKey/hex".to_string(), vec![0x02, 0xab], 36_000, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 36_000, None);
+        at(20);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), key_identity(&signing_key(2)), 36_000, None);
+        at(30);
+        contract.revoke_attribute(owner.clone(), "did/pub/Secp256k1/veriKey/hex".to_string(), vec![0x02, 0xab], None);
+        at(40);
+        contract.change_owner(owner.clone(), accounts(2).to_string(), None);
+
+        let method = |version: u64, fragment: &str| {
+            let document = contract.resolve_at_version(owner.clone(), version).did_document.unwrap();
+            let method = document.verification_method.into_iter().find(|method| method.id.ends_with(&format!("#{}", fragment))).unwrap();
+            Some(DocumentEntry::Method(method))
+        };
+        let added = EntryDiff { fragment: "delegate-2".to_string(), from: None, to: method(20, "delegate-2") };
+        let removed = EntryDiff { fragment: "delegate-1".to_string(), from: method(20, "delegate-1"), to: None };
+        let owner_changed = EntryDiff { fragment: "owner".to_string(), from: method(30, "owner"), to: method(40, "owner") };
+        assert_ne!(owner_changed.from, owner_changed.to);
+
+        assert_eq!(contract.diff_versions(owner.clone(), 10, 20), DocumentDiff { added: vec![added.clone()], ..Default::default() });
+        assert_eq!(contract.diff_versions(owner.clone(), 20, 30), DocumentDiff { removed: vec![removed.clone()], ..Default::default() });
+        assert_eq!(contract.diff_versions(owner.clone(), 30, 40), DocumentDiff { modified: vec![owner_changed.clone()], ..Default::default() });
+        assert_eq!(
+            contract.diff_versions(format!("did:near:{}", owner), 10, 40),
+            DocumentDiff { added: vec![added], modified: vec![owner_changed], removed: vec![removed], error: None }
+        );
+        assert_eq!(contract.diff_versions(owner.clone(), 40, 40), DocumentDiff::default());
+        let json = serde_json::to_value(contract.diff_versions(owner.clone(), 20, 10)).unwrap();
+        assert_eq!(json["removed"], serde_json::json!([{ "fragment": "delegate-2", "from": serde_json::to_value(method(20, "delegate-2")).unwrap() }]));
+
+        assert_eq!(contract.diff_versions(owner.clone(), 20, 25), DocumentDiff::failed(ResolutionError::VersionNotFound));
+        contract.set_history_limit(2);
+        assert_eq!(contract.diff_versions(owner.clone(), 10, 40), DocumentDiff::failed(ResolutionError::VersionPruned));
+        assert_eq!(contract.diff_versions(owner, 40, 10), DocumentDiff::failed(ResolutionError::VersionPruned));
+    }
+
+    #[test]
+    fn deactivated_identity_resolves_empty() {
+        let owner = accounts(1).to_string();
+        let delegate = key_identity(&signing_key(2));
+        let at = |block_height: u64| {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(block_height).block_timestamp(block_height * 1_000_000_000).build());
+        };
+        at(10);
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), delegate.clone(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        let active = contract.resolve_with_metadata(owner.clone(), None);
+        at(20);
+        contract.deactivate_identity(format!("did:near:{}", owner));
+
+        assert!(contract.is_deactivated(owner.clone()));
+        assert_eq!(contract.get_changed(owner.clone()), 20);
+        let Some(EventLog { event: DidEvent::IdentityDeactivated(event), .. }) = EventLog::from_log(&registry_logs()[0]) else { panic!("not a deactivation event") };
+        assert_eq!(event, IdentityDeactivated { identity: owner.clone(), changed: 20, previous_change: 10 });
+
+        assert!(!contract.valid_delegate(owner.clone(), "sigAuth".to_string(), delegate.clone()));
+        assert_eq!(contract.valid_delegate_many(vec![DelegateQuery { identity: owner.clone(), delegate_type: "sigAuth".to_string(), delegate }]), vec![false]);
+        assert!(!contract.valid_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec()));
+
+        let result = contract.resolve_with_metadata(owner.clone(), None);
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            serde_json::json!({
+                "did_resolution_metadata": {},
+                "did_document": { "@context": [document::DID_CONTEXT], "id": "did:near:bob", "verificationMethod": [], "authentication": [], "assertionMethod": [] },
+                "did_document_metadata": { "created": "1970-01-01T00:00:10Z", "deactivated": true, "updated": "1970-01-01T00:00:20Z", "versionId": "20" },
+            })
+        );
+        assert_eq!(contract.get_document_metadata(owner.clone()), result.did_document_metadata);
+        assert_eq!(contract.resolve_summary(owner.clone()).verification_method, 0);
+        let many = contract.resolve_many(vec![owner.clone()]);
+        assert_eq!(many[0].did_document, None);
+        assert_eq!(many[0].did_resolution_metadata.error, Some(ResolutionError::Deactivated));
+        assert!(many[0].did_document_metadata.deactivated);
+
+        assert_eq!(contract.resolve_at_version(owner.clone(), 10), active);
+        assert_eq!(contract.resolve_at_version(owner, 20), result);
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_deactivated")]
+    fn deactivated_identity_rejects_writes() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.deactivate_identity(owner.clone());
+        contract.set_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_deactivated")]
+    fn deactivated_identity_rejects_owner_change() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.deactivate_identity(owner.clone());
+        contract.change_owner(owner, accounts(2).to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_deactivated")]
+    fn deactivated_identity_rejects_signed_ops() {
+        let owner = accounts(1);
+        let hot_key = signing_key(2);
+        let signer = String::from(&ed25519_public_key(&hot_key));
+        let delegate = accounts(2).to_string();
+        let delegate_type = "veriKey".to_string();
+
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_signing_key(owner.to_string(), ed25519_public_key(&hot_key));
+        let payload = contract.get_add_delegate_payload(owner.to_string(), Some(signer), DEADLINE, delegate_type.clone(), delegate.clone(), 3600);
+        contract.deactivate_identity(owner.to_string());
+
+        set_context(accounts(3));
+        contract.add_delegate_signed(owner.to_string(), sign(&hot_key, &payload), DEADLINE, delegate_type, delegate, 3600);
+    }
+
+    #[test]
+    #[should_panic(expected = "identity_deactivated")]
+    fn deactivation_is_permanent() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.deactivate_identity(owner.clone());
+        contract.deactivate_identity(owner);
+    }
+
+    #[test]
+    fn resolve_at_version_needs_every_later_write() {
+        let owner = accounts(1).to_string();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(10).build());
+        let mut contract = NearDIDRegistry::default();
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        for block_height in [20, 30] {
+            let writes = if block_height == 20 { MAX_SNAPSHOT_WRITES + 1 } else { 1 };
+            for i in 0..writes {
+                testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(block_height).build());
+                contract.set_attribute(owner.clone(), format!("note{}", i % 32), format!("{}-{}", block_height, i).into_bytes(), 3600, None);
+            }
+        }
+        assert_eq!(contract.resolve_at_version(owner.clone(), 10), ResolutionResult::failed(ResolutionError::VersionPruned));
+        assert_eq!(contract.resolve_at_version(owner.clone(), 20), ResolutionResult::failed(ResolutionError::VersionPruned));
+        assert_eq!(contract.resolve_at_version(owner.clone(), 30), contract.resolve_with_metadata(owner, None));
+    }
+
+    #[test]
+    fn rfc3339_formats_utc() {
+        assert_eq!(document::rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(document::rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(document::rfc3339(978_307_199), "2000-12-31T23:59:59Z");
+        assert_eq!(document::rfc3339(4_107_542_400), "2100-03-01T00:00:00Z");
+    }
+
+    #[test]
+    fn document_hash_tracks_effective_records() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        let empty = contract.document_hash(owner.clone());
+
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        let hash = contract.document_hash(owner.clone());
+        assert_ne!(hash, empty);
+        assert_eq!(contract.document_hash(owner.clone()), hash);
+
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 7200, None);
+        contract.set_attribute(owner.clone(), "did/svc/Old".to_string(), b"https://old.example".to_vec(), 10, None);
+        set_context_at(accounts(1), 2_000);
+        assert_eq!(contract.document_hash(owner.clone()), hash);
+
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example.org".to_vec(), 3600, None);
+        let changed = contract.document_hash(owner.clone());
+        assert_ne!(changed, hash);
+        contract.revoke_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example.org".to_vec(), None);
+        assert_eq!(contract.document_hash(owner.clone()), hash);
+
+        set_context_at(accounts(2), 2_000);
+        contract.set_attribute(accounts(2).to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        contract.add_delegate(accounts(2).to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        assert_ne!(contract.document_hash(accounts(2).to_string()), hash);
+        assert_eq!(bs58::decode(&hash).into_vec().unwrap().len(), 32);
+
+        set_context_at(accounts(1), 10_000);
+        let delegates = vec![("veriKey".to_string(), accounts(2).to_string())];
+        let attributes = vec![("did/svc/Hub".to_string(), b"https://hub.example".to_vec()), ("did/svc/Old".to_string(), b"https://old.example".to_vec())];
+        contract.purge_expired(owner.clone(), delegates, attributes);
+        assert_eq!(contract.document_hash(owner.clone()), empty);
+
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 100, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 100, None);
+        assert_eq!(contract.document_hash(owner), hash);
+    }
+
+    #[test]
+    fn current_time_matches_expiry_checks() {
+        let owner = accounts(1).to_string();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_timestamp(1_700_000_000_999_999_999).build());
+        let mut contract = NearDIDRegistry::default();
+        assert_eq!(contract.get_current_time(), 1_700_000_000);
+
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 1, None);
+        assert_eq!(contract.get_delegate_validity(owner.clone(), "veriKey".to_string(), accounts(2).to_string()), Some(contract.get_current_time() + 1));
+        assert!(contract.valid_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string()));
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_timestamp(1_700_000_001_000_000_000).build());
+        assert_eq!(contract.get_current_time(), 1_700_000_001);
+        assert!(!contract.valid_delegate(owner, "veriKey".to_string(), accounts(2).to_string()));
+    }
+
+    #[test]
+    fn list_delegates_pages() {
+        let owner = accounts(1);
+        set_context_at(owner.clone(), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        for i in 0..25 {
+            contract.add_delegate(owner.to_string(), "veriKey".to_string(), format!("delegate{}.near", i), 3600, None);
+        }
+        contract.revoke_delegate(owner.to_string(), "veriKey".to_string(), "delegate3.near".to_string(), None);
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), "delegate3.near".to_string(), 60, None);
+        contract.revoke_delegate(owner.to_string(), "veriKey".to_string(), "delegate4.near".to_string(), None);
+
+        let mut listed = Vec::new();
+        let mut from_index = 0;
+        loop {
+            let page = contract.list_delegates(owner.to_string(), from_index, 10);
+            assert!(page.len() <= 10);
+            from_index += page.len() as u64;
+            listed.extend(page.clone());
+            if page.len() < 10 {
+                break;
+            }
+        }
+        assert_eq!(listed.len(), 25);
+        assert_eq!(listed.iter().map(|entry| entry.delegate.clone()).collect::<Vec<_>>(), (0..25).map(|i| format!("delegate{}.near", i)).collect::<Vec<_>>());
+        assert_eq!(listed[0], DelegateEntry { delegate_type: "veriKey".to_string(), delegate: "delegate0.near".to_string(), valid_until: 4_600 });
+        assert_eq!(listed[3].valid_until, 1_060);
+        assert_eq!(listed[4].valid_until, 0);
+
+        assert_eq!(contract.list_delegates(owner.to_string(), 25, 10), vec![]);
+        assert_eq!(contract.list_delegates(accounts(2).to_string(), 0, 10), vec![]);
+    }
+
+    #[test]
+    fn list_delegates_after_purge() {
+        let owner = accounts(1);
+        set_context_at(owner.clone(), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        for i in 0..MAX_PAGE_SIZE + 5 {
+            set_context_at(owner.clone(), 1_000);
+            contract.add_delegate(owner.to_string(), "veriKey".to_string(), format!("delegate{}.near", i), 3600, None);
+        }
+        assert_eq!(contract.list_delegates(owner.to_string(), 0, u64::MAX).len() as u64, MAX_PAGE_SIZE);
+
+        contract.revoke_delegate(owner.to_string(), "veriKey".to_string(), "delegate0.near".to_string(), None);
+        contract.purge_expired(owner.to_string(), vec![("veriKey".to_string(), "delegate0.near".to_string())], vec![]);
+
+        let listed = contract.list_delegates(owner.to_string(), 0, MAX_PAGE_SIZE);
+        assert_eq!(listed[0].delegate, "delegate1.near");
+        assert_eq!(listed[MAX_PAGE_SIZE as usize - 1].delegate, format!("delegate{}.near", MAX_PAGE_SIZE));
+        assert_eq!(contract.list_delegates(owner.to_string(), MAX_PAGE_SIZE, MAX_PAGE_SIZE).len(), 4);
+        assert!(listed.iter().all(|entry| entry.delegate != "delegate0.near"));
+    }
+
+    #[test]
+    fn list_delegates_page_survives_purge_between_pages() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        let delegates: Vec<String> = (0..6).map(|i| format!("delegate{}.near", i)).collect();
+        for delegate in &delegates {
+            contract.add_delegate(owner.clone(), "veriKey".to_string(), delegate.clone(), 3600, None);
+        }
+        let first = contract.list_delegates_page(owner.clone(), None, 3);
+        assert_eq!(first.items.iter().map(|entry| entry.delegate.clone()).collect::<Vec<_>>(), delegates[..3]);
+
+        for delegate in [&delegates[0], &delegates[3]] {
+            contract.revoke_delegate(owner.clone(), "veriKey".to_string(), delegate.clone(), None);
+            contract.purge_expired(owner.clone(), vec![("veriKey".to_string(), delegate.clone())], vec![]);
+        }
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), "delegate6.near".to_string(), 3600, None);
+
+        let second = contract.list_delegates_page(owner.clone(), first.next_cursor.clone(), 3);
+        assert_eq!(second.items.iter().map(|entry| entry.delegate.clone()).collect::<Vec<_>>(), vec!["delegate4.near", "delegate5.near", "delegate6.near"]);
+        assert_eq!(second.next_cursor, None);
+        // The positional page after the purge skips delegate4.
+        assert_eq!(contract.list_delegates(owner.clone(), 3, 3)[0].delegate, "delegate5.near");
+
+        assert_eq!(contract.list_delegates_page(owner.clone(), first.next_cursor, 0), Page { items: vec![], next_cursor: Some(encode_cursor(0, 4)) });
+        assert_eq!(contract.list_delegates_page(accounts(2).to_string(), None, 10), Page { items: vec![], next_cursor: None });
+    }
+
+    #[test]
+    fn list_expiring_page_crosses_into_attributes() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 100, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), 200, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 300, None);
+        contract.set_attribute(owner.clone(), "did/svc/Inbox".to_string(), b"https://inbox.example".to_vec(), 400, None);
+
+        let mut pages = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = contract.list_expiring_page(owner.clone(), 1_000, cursor, 2);
+            pages.push(page.items);
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages.concat(), contract.list_expiring(owner.clone(), 1_000, 0, 10));
+
+        let attributes = contract.list_expiring_page(owner.clone(), 1_000, Some(encode_cursor(1, 1)), 10);
+        assert_eq!(attributes.items, contract.list_expiring(owner, 1_000, 3, 10));
+    }
+
+    #[test]
+    fn history_pages_resume_at_sequence() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry { history_limit: 3, ..Default::default() };
+
+        for block_height in 1..=3 {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(block_height).build());
+            contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        }
+        let first = contract.get_change_history_page(owner.clone(), None, 2);
+        assert_eq!(first.items, vec![1, 2]);
+
+        for block_height in 4..=5 {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(block_height).build());
+            contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        }
+        let second = contract.get_change_history_page(owner.clone(), first.next_cursor, 2);
+        assert_eq!(second.items, vec![3, 4]);
+        assert_eq!(contract.get_change_history_page(owner.clone(), second.next_cursor, 2), Page { items: vec![5], next_cursor: None });
+        assert_eq!(contract.get_owner_history_page(owner, None, 2), Page { items: vec![], next_cursor: None });
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_cursor")]
+    fn malformed_cursor_rejected() {
+        set_context(accounts(1));
+        let contract = NearDIDRegistry::default();
+        contract.list_delegates_page(accounts(1).to_string(), Some("0OIl".to_string()), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_cursor")]
+    fn cursor_of_other_version_rejected() {
+        set_context(accounts(1));
+        let contract = NearDIDRegistry::default();
+        let cursor = bs58::encode([&[index::CURSOR_VERSION + 1, 0][..], &5u64.to_le_bytes()].concat()).into_string();
+        contract.list_attributes_page(accounts(1).to_string(), Some(cursor), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_cursor")]
+    fn cursor_part_out_of_range_rejected() {
+        set_context(accounts(1));
+        let contract = NearDIDRegistry::default();
+        contract.list_delegates_page(accounts(1).to_string(), Some(encode_cursor(1, 0)), 10);
+    }
+
+    #[test]
+    fn identities_for_delegate_tracks_add_and_revoke() {
+        let employee = accounts(4).to_string();
+        let mut contract = NearDIDRegistry::default();
+
+        for (identity, validity_secs) in [(accounts(1), 3600), (accounts(2), 100), (accounts(3), 3600)] {
+            set_context_at(identity.clone(), 1_000);
+            contract.add_delegate(identity.to_string(), "veriKey".to_string(), employee.clone(), validity_secs, None);
+        }
+        set_context_at(accounts(1), 1_000);
+        contract.add_delegate(accounts(1).to_string(), "sigAuth".to_string(), employee.clone(), 3600, None);
+        contract.revoke_delegate(accounts(1).to_string(), "veriKey".to_string(), employee.clone(), None);
+
+        set_context_at(accounts(1), 2_000);
+        let delegation = |identity: AccountId, delegate_type: &str, valid_until| DelegatedIdentity { identity: identity.to_string(), delegate_type: delegate_type.to_string(), valid_until };
+        assert_eq!(
+            contract.identities_for_delegate(employee.clone(), 0, 10, None),
+            vec![delegation(accounts(3), "veriKey", 4_600), delegation(accounts(1), "sigAuth", 4_600)]
+        );
+        assert_eq!(
+            contract.identities_for_delegate(employee.clone(), 0, 10, Some(true)),
+            vec![delegation(accounts(2), "veriKey", 1_100), delegation(accounts(3), "veriKey", 4_600), delegation(accounts(1), "sigAuth", 4_600)]
+        );
+        assert_eq!(contract.identities_for_delegate(employee.clone(), 1, 1, Some(true)), vec![delegation(accounts(3), "veriKey", 4_600)]);
+
+        contract.purge_expired(accounts(2).to_string(), vec![("veriKey".to_string(), employee.clone())], vec![]);
+        contract.revoke_delegate(accounts(1).to_string(), "sigAuth".to_string(), employee.clone(), None);
+        set_context_at(accounts(3), 2_000);
+        contract.revoke_delegate(accounts(3).to_string(), "veriKey".to_string(), employee.clone(), None);
+        assert_eq!(contract.identities_for_delegate(employee.clone(), 0, 10, Some(true)), vec![]);
+        assert!(!contract.delegations.contains_key(&employee));
+    }
+
+    #[test]
+    #[should_panic(expected = "too_many_delegations")]
+    fn identities_for_delegate_bounded() {
+        let mut contract = NearDIDRegistry::default();
+
+        for i in 0..=MAX_DELEGATIONS {
+            let identity: AccountId = format!("identity{}.near", i).parse().unwrap();
+            set_context(identity.clone());
+            contract.add_delegate(identity.to_string(), "veriKey".to_string(), accounts(4).to_string(), 3600, None);
+            if i == MAX_DELEGATIONS - 1 {
+                contract.add_delegate(identity.to_string(), "veriKey".to_string(), accounts(4).to_string(), 7200, None);
+            }
+            if i % 64 == 63 {
+                contract = flushed(contract);
+            }
+        }
+    }
+
+    #[test]
+    fn identities_owned_by_follows_transfers() {
+        let identity = accounts(1).to_string();
+        let custodian = "custodian.near".to_string();
+        let mut contract = NearDIDRegistry::default();
+
+        set_context(accounts(1));
+        contract.set_attribute(identity.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        assert_eq!(contract.identities_owned_by(identity.clone(), 0, 10), Vec::<String>::new());
+
+        contract.change_owner(identity.clone(), accounts(2).to_string(), None);
+        set_context(accounts(3));
+        contract.change_owner(accounts(3).to_string(), custodian.clone(), None);
+        assert_eq!(contract.identities_owned_by(accounts(2).to_string(), 0, 10), vec![identity.clone()]);
+
+        set_context(accounts(2));
+        contract.change_owner(identity.clone(), custodian.clone(), None);
+        assert_eq!(contract.identities_owned_by(accounts(2).to_string(), 0, 10), Vec::<String>::new());
+        assert_eq!(contract.identities_owned_by(custodian.clone(), 0, 10), vec![accounts(3).to_string(), identity.clone()]);
+        assert_eq!(contract.identities_owned_by(custodian.clone(), 1, 10), vec![identity.clone()]);
+
+        set_context(custodian.parse().unwrap());
+        contract.change_owner(identity.clone(), identity.clone(), None);
+        assert_eq!(contract.identities_owned_by(custodian.clone(), 0, 10), vec![accounts(3).to_string()]);
+        assert_eq!(contract.identities_owned_by(identity.clone(), 0, 10), vec![identity.clone()]);
+        assert!(!contract.owned.contains_key(&accounts(2).to_string()));
+    }
+
+    #[test]
+    fn get_attribute_values_most_recent_first() {
+        let owner = accounts(1).to_string();
+        let name = "did/svc/Hub".to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(owner.clone(), name.clone(), b"https://a.example".to_vec(), 100, None);
+        contract.set_attribute(owner.clone(), name.clone(), b"https://b.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), name.clone(), b"https://c.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Other".to_string(), b"https://d.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), name.clone(), b"https://a.example".to_vec(), 7200, None);
+        contract.revoke_attribute(owner.clone(), name.clone(), b"https://b.example".to_vec(), None);
+
+        assert_eq!(
+            contract.get_attribute_values(owner.clone(), name.clone()),
+            vec![(b"https://a.example".to_vec().into(), 8_200), (b"https://c.example".to_vec().into(), 4_600)]
+        );
+        assert_eq!(contract.get_attribute_values(owner.clone(), "did/svc/Missing".to_string()), vec![]);
+
+        contract.revoke_attribute(owner.clone(), name.clone(), b"https://a.example".to_vec(), None);
+        contract.revoke_attribute(owner.clone(), name.clone(), b"https://c.example".to_vec(), None);
+        assert_eq!(contract.get_attribute_values(owner.clone(), name.clone()), vec![]);
+        assert!(!contract.attribute_values.contains_key(&(owner, name)));
+    }
+
+    #[test]
+    #[should_panic(expected = "too_many_values")]
+    fn get_attribute_values_bounded() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+
+        for i in 0..MAX_ATTRIBUTE_VALUES {
+            contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), format!("https://hub{}.example", i).into_bytes(), 3600, None);
+        }
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub0.example".to_vec(), 7200, None);
+        contract.set_attribute(owner, "did/svc/Hub".to_string(), b"https://one-more.example".to_vec(), 3600, None);
+    }
+
+    #[test]
+    fn counts_follow_reset_revoke_and_readd() {
+        let owner = accounts(1).to_string();
+        let value = b"https://hub.example".to_vec();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 100, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), accounts(2).to_string(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), value.clone(), 100, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), value.clone(), 3600, None);
+        assert_eq!((contract.count_delegates(owner.clone()), contract.count_attributes(owner.clone())), (2, 1));
+
+        contract.revoke_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), None);
+        contract.revoke_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), None);
+        contract.revoke_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), None);
+        contract.revoke_attribute(owner.clone(), "did/svc/Hub".to_string(), value.clone(), None);
+        assert_eq!((contract.count_delegates(owner.clone()), contract.count_attributes(owner.clone())), (1, 0));
+
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 100, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), value.clone(), 100, None);
+        assert_eq!((contract.count_delegates(owner.clone()), contract.count_attributes(owner.clone())), (2, 1));
+
+        set_context_at(accounts(4), 2_000);
+        assert_eq!(contract.count_delegates(owner.clone()), 2);
+        contract.purge_expired(owner.clone(), vec![("veriKey".to_string(), accounts(2).to_string())], vec![("did/svc/Hub".to_string(), value)]);
+        assert_eq!((contract.count_delegates(owner.clone()), contract.count_attributes(owner.clone())), (1, 0));
+    }
+
+    #[test]
+    fn list_expiring_window() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 100, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), 3000, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(4).to_string(), 3001, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), accounts(2).to_string(), 1000, None);
+        contract.revoke_delegate(owner.clone(), "sigAuth".to_string(), accounts(2).to_string(), None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 2000, None);
+
+        set_context_at(accounts(1), 1_500);
+        let expiring = contract.list_expiring(owner.clone(), 2_500, 0, 10);
+        assert_eq!(
+            expiring,
+            vec![
+                ExpiringEntry::Delegate { delegate_type: "veriKey".to_string(), delegate: accounts(3).to_string(), valid_until: 4_000 },
+                ExpiringEntry::Attribute { name: "did/svc/Hub".to_string(), value: b"https://hub.example".to_vec().into(), valid_until: 3_000 },
+            ]
+        );
+        assert_eq!(contract.list_expiring(owner.clone(), 2_500, 1, 10), expiring[1..]);
+        assert_eq!(contract.list_expiring(owner.clone(), 2_499, 0, 10), expiring[1..]);
+        assert_eq!(
+            serde_json::to_value(&expiring[0]).unwrap(),
+            serde_json::json!({ "kind": "delegate", "delegate_type": "veriKey", "delegate": "danny", "valid_until": 4000 })
+        );
+        assert_eq!(contract.list_expiring(accounts(2).to_string(), u64::MAX, 0, 10), vec![]);
     }
 
-    pub fn identity_owner(&self, identity: String) -> String {
-        self.owners.get(&identity).unwrap_or(&identity).clone()
+    #[test]
+    fn change_history_in_order() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+
+        for block_height in [10, 25, 25, 70] {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(block_height).build());
+            contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), format!("https://hub{}.example", block_height).into_bytes(), 3600, None);
+        }
+
+        let history = contract.get_change_history(owner.clone(), 0, 10);
+        assert_eq!(history, vec![10, 25, 70]);
+        assert_eq!(*history.last().unwrap(), contract.get_changed(owner.clone()));
+        assert_eq!(contract.get_change_history(owner.clone(), 1, 1), vec![25]);
+        assert_eq!(contract.get_change_history(owner.clone(), 3, 10), Vec::<u64>::new());
+        assert_eq!(contract.get_change_history(accounts(2).to_string(), 0, 10), Vec::<u64>::new());
     }
 
-    pub fn change_owner(&mut self, identity: String, new_owner: String) {
-        let actor = env::predecessor_account_id().to_string();
-        self.assert_only_owner(&identity, &actor);
+    #[test]
+    fn change_history_bounded() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry { history_limit: 3, ..Default::default() };
+
+        for block_height in 1..=5 {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(block_height).build());
+            contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        }
+        assert_eq!(contract.get_change_history(owner.clone(), 0, 10), vec![3, 4, 5]);
+        assert!(!contract.history.contains_key(&(owner.clone(), 1)));
 
-        self.owners.insert(identity.clone(), new_owner);
-        self.changed.insert(identity, env::block_height());
+        testing_env!(VMContextBuilder::new().predecessor_account_id(env::current_account_id()).block_height(6).build());
+        contract.set_history_limit(1);
+        assert_eq!(contract.get_change_history(owner.clone(), 0, 10), vec![5]);
+        assert_eq!(contract.get_history_limit(), 1);
     }
 
-    pub fn add_delegate(&mut self, identity: String, delegate_type: String, delegate: String, validity_secs: u64) {
-        let actor = env::predecessor_account_id().to_string();
-        self.assert_only_owner(&identity, &actor);
+    #[test]
+    fn changed_info_records_block_timestamp() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        assert_eq!(contract.get_changed_info(owner.clone()), None);
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(25).block_timestamp(1_700_000_000_500_000_000).build());
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
 
-        let valid_until = env::block_timestamp_ms() / 1000 + validity_secs;
-        self.delegates.insert((identity.clone(), delegate_type.clone(), delegate.clone()), valid_until);
-        self.changed.insert(identity, env::block_height());
+        assert_eq!(contract.get_changed_info(owner.clone()), Some(ChangedInfo { block_height: 25, timestamp: 1_700_000_000 }));
+        assert_eq!(contract.get_changed_info(owner.clone()).unwrap().timestamp, env::block_timestamp() / 1_000_000_000);
+        assert_eq!(contract.get_changed(owner), 25);
     }
 
-    pub fn revoke_delegate(&mut self, identity: String, delegate_type: String, delegate: String) {
-        let actor = env::predecessor_account_id().to_string();
-        self.assert_only_owner(&identity, &actor);
+    #[test]
+    fn created_recorded_on_first_write_only() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        assert_eq!(contract.get_created(owner.clone()), None);
 
-        self.delegates.insert((identity.clone(), delegate_type.clone(), delegate.clone()), 0);
-        self.changed.insert(identity, env::block_height());
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(10).block_timestamp(1_000_000_000_000).build());
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(70).block_timestamp(2_000_000_000_000).build());
+        contract.change_owner(owner.clone(), accounts(2).to_string(), None);
+
+        assert_eq!(contract.get_created(owner.clone()), Some(CreatedInfo { block_height: 10, timestamp: 1_000 }));
+        assert_eq!(contract.get_changed_info(owner).unwrap().block_height, 70);
+        assert_eq!(contract.get_created(accounts(2).to_string()), None);
     }
 
-    pub fn valid_delegate(&self, identity: String, delegate_type: String, delegate: String) -> bool {
-        match self.delegates.get(&(identity, delegate_type, delegate)) {
-            Some(valid_until) => *valid_until > env::block_timestamp_ms() / 1000,
-            None => false,
+    #[test]
+    fn owner_history_of_three_transfers() {
+        let identity = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+
+        for (block_height, owner, new_owner) in [(10, accounts(1), accounts(2)), (25, accounts(2), accounts(3)), (70, accounts(3), accounts(4))] {
+            testing_env!(VMContextBuilder::new().predecessor_account_id(owner).block_height(block_height).block_timestamp(block_height * 1_000_000_000).build());
+            contract.change_owner(identity.clone(), new_owner.to_string(), None);
         }
+
+        let change = |previous_owner: AccountId, new_owner: AccountId, block_height| OwnerChange {
+            previous_owner: previous_owner.to_string(),
+            new_owner: new_owner.to_string(),
+            block_height,
+            timestamp: block_height,
+        };
+        assert_eq!(
+            contract.get_owner_history(identity.clone(), 0, 10),
+            vec![change(accounts(1), accounts(2), 10), change(accounts(2), accounts(3), 25), change(accounts(3), accounts(4), 70)]
+        );
+        assert_eq!(contract.get_owner_history(identity.clone(), 2, 10), vec![change(accounts(3), accounts(4), 70)]);
+        assert_eq!(contract.get_owner_history(accounts(2).to_string(), 0, 10), vec![]);
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(env::current_account_id()).block_height(80).build());
+        contract.set_history_limit(2);
+        assert_eq!(contract.get_owner_history(identity, 0, 10).len(), 2);
     }
 
-    pub fn set_attribute(&mut self, identity: String, name: String, value: Vec<u8>, validity_secs: u64) {
-        let actor = env::predecessor_account_id().to_string();
-        self.assert_only_owner(&identity, &actor);
+    #[test]
+    fn identity_state_dump() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(10).block_timestamp(1_000_000_000_000).build());
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+        contract.add_delegate(owner.clone(), "veriKey".to_string(), accounts(3).to_string(), 100, None);
+        contract.add_delegate(owner.clone(), "sigAuth".to_string(), accounts(2).to_string(), 3600, None);
+        contract.revoke_delegate(owner.clone(), "sigAuth".to_string(), accounts(2).to_string(), None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 100, None);
+        contract.increment_nonce(owner.clone());
 
-        let valid_until = env::block_timestamp_ms() / 1000 + validity_secs;
-        self.attributes.insert((identity.clone(), name.clone(), value.clone()), valid_until);
-        self.changed.insert(identity, env::block_height());
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).block_height(25).block_timestamp(2_000_000_000_000).build());
+        let state = contract.get_identity_state(owner.clone(), 0, 10);
+        assert_eq!(
+            state,
+            IdentityState {
+                identity: owner.clone(),
+                owner: owner.clone(),
+                delegates: vec![
+                    DelegateEntry { delegate_type: "veriKey".to_string(), delegate: accounts(2).to_string(), valid_until: 4_600 },
+                    DelegateEntry { delegate_type: "veriKey".to_string(), delegate: accounts(3).to_string(), valid_until: 1_100 },
+                    DelegateEntry { delegate_type: "sigAuth".to_string(), delegate: accounts(2).to_string(), valid_until: 0 },
+                ],
+                attributes: vec![AttributeEntry { name: "did/svc/Hub".to_string(), value: b"https://hub.example".to_vec().into(), valid_until: 1_100 }],
+                changed: 10,
+                created: Some(CreatedInfo { block_height: 10, timestamp: 1_000 }),
+                nonce: 1,
+                next_index: None,
+            }
+        );
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<IdentityState>(&json).unwrap(), state);
+
+        let first = contract.get_identity_state(owner.clone(), 0, 2);
+        assert_eq!((first.delegates.len(), first.attributes.len(), first.next_index), (2, 1, Some(2)));
+        let second = contract.get_identity_state(owner.clone(), 2, 2);
+        assert_eq!((second.delegates, second.attributes, second.next_index), (state.delegates[2..].to_vec(), vec![], None));
     }
 
-    pub fn revoke_attribute(&mut self, identity: String, name: String, value: Vec<u8>) {
-        let actor = env::predecessor_account_id().to_string();
-        self.assert_only_owner(&identity, &actor);
+    #[test]
+    fn identity_owner_many_in_order() {
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.change_owner(accounts(1).to_string(), accounts(4).to_string(), None);
 
-        self.attributes.insert((identity.clone(), name.clone(), value.clone()), 0);
-        self.changed.insert(identity, env::block_height());
+        assert_eq!(
+            contract.identity_owner_many(vec![accounts(2).to_string(), accounts(1).to_string(), accounts(2).to_string()]),
+            vec![Some(accounts(2).to_string()), Some(accounts(4).to_string()), Some(accounts(2).to_string())]
+        );
+        assert_eq!(contract.identity_owner_many(vec![]), Vec::<Option<String>>::new());
     }
 
-    pub fn valid_attribute(&self, identity: String, name: String, value: Vec<u8>) -> bool {
-        match self.attributes.get(&(identity, name, value)) {
-            Some(valid_until) => *valid_until > env::block_timestamp_ms() / 1000,
-            None => false,
-        }
+    #[test]
+    fn identity_owner_many_skips_unregistered() {
+        set_context_with_deposit(accounts(1), REGISTRATION_DEPOSIT);
+        let mut contract = NearDIDRegistry::default();
+        contract.register_identity(UUID.to_string(), accounts(3));
+
+        assert_eq!(
+            contract.identity_owner_many(vec![accounts(2).to_string(), "223E4567-E89B-12D3-A456-426614174000".to_string(), format!("did:near:{UUID}")]),
+            vec![Some(accounts(2).to_string()), None, Some(accounts(3).to_string())]
+        );
     }
 
-    pub fn get_nonce(&self, identity: String) -> u64 {
-        *self.nonce.get(&identity).unwrap_or(&0)
+    #[test]
+    #[should_panic(expected = "too_many_identities")]
+    fn identity_owner_many_bounded() {
+        set_context(accounts(1));
+        let contract = NearDIDRegistry::default();
+        contract.identity_owner_many(vec![accounts(2).to_string(); MAX_PAGE_SIZE as usize + 1]);
     }
 
-    pub fn increment_nonce(&mut self, identity: String) {
-        let n = self.nonce.get(&identity).unwrap_or(&0);
-        self.nonce.insert(identity, n + 1);
+    #[test]
+    fn attribute_names_counted_per_value() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://a.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://b.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/pub/Ed25519/veriKey/base64".to_string(), b"key".to_vec(), 3600, None);
+        assert_eq!(contract.list_attribute_names(owner.clone(), 0, 10), vec!["did/svc/Hub".to_string(), "did/pub/Ed25519/veriKey/base64".to_string()]);
+        assert_eq!(contract.list_attribute_names(owner.clone(), 1, 10), vec!["did/pub/Ed25519/veriKey/base64".to_string()]);
+
+        contract.revoke_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://a.example".to_vec(), None);
+        assert_eq!(contract.list_attribute_names(owner.clone(), 0, 10), vec!["did/svc/Hub".to_string(), "did/pub/Ed25519/veriKey/base64".to_string()]);
+
+        contract.revoke_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://b.example".to_vec(), None);
+        assert_eq!(contract.list_attribute_names(owner.clone(), 0, 10), vec!["did/pub/Ed25519/veriKey/base64".to_string()]);
+
+        contract.revoke_attribute(owner.clone(), "did/pub/Ed25519/veriKey/base64".to_string(), b"key".to_vec(), None);
+        assert_eq!(contract.list_attribute_names(owner.clone(), 0, 10), Vec::<String>::new());
+        assert!(!contract.attribute_names.contains_key(&owner));
     }
 
-    pub fn get_changed(&self, identity: String) -> u64 {
-        *self.changed.get(&identity).unwrap_or(&0)
+    #[test]
+    fn find_attributes_by_prefix_matches_names() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/pub/Ed25519/veriKey/base64".to_string(), b"key".to_vec(), 3600, None);
+        contract.set_attribute(owner.clone(), "did/svc/LinkedDomains".to_string(), b"https://example.com".to_vec(), 3600, None);
+
+        let entry = |name: &str, value: &[u8]| AttributeEntry { name: name.to_string(), value: value.to_vec().into(), valid_until: 4_600 };
+        assert_eq!(
+            contract.find_attributes_by_prefix(owner.clone(), "did/svc/".to_string(), 0, 10),
+            vec![entry("did/svc/Hub", b"https://hub.example"), entry("did/svc/LinkedDomains", b"https://example.com")]
+        );
+        assert_eq!(contract.find_attributes_by_prefix(owner.clone(), "did/svc/".to_string(), 1, 1), vec![entry("did/svc/LinkedDomains", b"https://example.com")]);
+        assert_eq!(contract.find_attributes_by_prefix(owner.clone(), String::new(), 0, 10), contract.list_attributes(owner.clone(), 0, 10));
+        assert_eq!(contract.find_attributes_by_prefix(owner.clone(), String::new(), 0, 2).len(), 2);
+        assert_eq!(contract.find_attributes_by_prefix(owner.clone(), "did/enc/".to_string(), 0, 10), vec![]);
+        assert_eq!(contract.find_attributes_by_prefix(accounts(2).to_string(), String::new(), 0, 10), vec![]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::{test_utils::{accounts, VMContextBuilder}, testing_env};
+    #[test]
+    fn list_attributes_pages() {
+        let owner = accounts(1);
+        let mut contract = NearDIDRegistry::default();
 
-    fn set_context(predecessor: AccountId) {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .predecessor_account_id(predecessor)
-            .block_height(40)
-            .block_timestamp(1_000_000)
-            // .attached_deposit(0)
-            // .account_balance(0)
-            .is_view(false);
+        let count = MAX_PAGE_SIZE + 20;
+        for i in 0..count {
+            set_context_at(owner.clone(), 1_000);
+            contract.set_attribute(owner.to_string(), format!("did/svc/Hub{}", i), format!("https://hub{}.example", i).into_bytes(), 3600, None);
+        }
+        contract.revoke_attribute(owner.to_string(), "did/svc/Hub7".to_string(), b"https://hub7.example".to_vec(), None);
+        assert_eq!(contract.list_attributes(owner.to_string(), 0, u64::MAX).len() as u64, MAX_PAGE_SIZE);
 
-        testing_env!(builder.build());
+        let first = contract.list_attributes(owner.to_string(), 0, 50);
+        contract.set_attribute(owner.to_string(), "did/svc/Late".to_string(), b"https://late.example".to_vec(), 3600, None);
+        let mut listed = first;
+        loop {
+            let page = contract.list_attributes(owner.to_string(), listed.len() as u64, 50);
+            let last = page.len() < 50;
+            listed.extend(page);
+            if last {
+                break;
+            }
+        }
+
+        assert_eq!(listed.len() as u64, count + 1);
+        assert_eq!(listed[0], AttributeEntry { name: "did/svc/Hub0".to_string(), value: b"https://hub0.example".to_vec().into(), valid_until: 4_600 });
+        assert_eq!(listed[7].valid_until, 0);
+        assert_eq!(listed.last().unwrap().name, "did/svc/Late");
+        assert!(listed.iter().enumerate().take(count as usize).all(|(i, entry)| entry.name == format!("did/svc/Hub{}", i)));
+        assert_eq!(
+            serde_json::to_value(&listed[0]).unwrap(),
+            serde_json::json!({ "name": "did/svc/Hub0", "value": "aHR0cHM6Ly9odWIwLmV4YW1wbGU=", "valid_until": 4600 })
+        );
     }
 
     #[test]
-    fn identity_owner() {
+    fn list_attributes_after_purge() {
         let owner = accounts(1);
-        let contract = NearDIDRegistry::default();
-        assert_eq!(contract.identity_owner(owner.clone().to_string()), owner);
+        set_context_at(owner.clone(), 1_000);
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 100, None);
+        contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example.org".to_vec(), 3600, None);
+
+        set_context_at(accounts(4), 2_000);
+        contract.purge_expired(owner.to_string(), vec![], vec![("did/svc/Hub".to_string(), b"https://hub.example".to_vec())]);
+        assert_eq!(
+            contract.list_attributes(owner.to_string(), 0, 10),
+            vec![AttributeEntry { name: "did/svc/Hub".to_string(), value: b"https://hub.example.org".to_vec().into(), valid_until: 4_600 }]
+        );
+
+        set_context_at(accounts(4), 5_000);
+        contract.purge_expired(owner.to_string(), vec![], vec![("did/svc/Hub".to_string(), b"https://hub.example.org".to_vec())]);
+        assert_eq!(contract.list_attributes(owner.to_string(), 0, 10), vec![]);
+        assert!(!contract.attribute_index.contains_key(&owner.to_string()));
     }
 
     #[test]
-    fn change_owner_success() {
+    fn memo_carried_into_events() {
         let owner = accounts(1);
-        let new_owner = accounts(2);
         set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, Some("laptop key".to_string()));
+        contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+        contract.change_owner(owner.to_string(), accounts(2).to_string(), Some("custody handover".to_string()));
+
+        let events: Vec<_> = registry_logs().iter().filter_map(|log| EventLog::from_log(log)).map(|log| log.event).collect();
+        assert!(matches!(&events[1], DidEvent::DelegateChanged(event) if event.memo.as_deref() == Some("laptop key")));
+        assert!(matches!(&events[2], DidEvent::AttributeChanged(event) if event.memo.is_none()));
+        assert!(matches!(&events[3], DidEvent::OwnerChanged(event) if event.memo.as_deref() == Some("custody handover")));
+        assert!(!registry_logs()[2].contains("memo"));
+    }
 
+    #[test]
+    fn setting_changes_logged_as_events() {
+        let owner = accounts(1);
+        set_context(owner.clone());
         let mut contract = NearDIDRegistry::default();
 
-        assert_eq!(contract.identity_owner(owner.clone().to_string().to_string()), owner);
+        contract.set_controller(owner.to_string(), accounts(2));
+        contract.lock_identity(owner.to_string());
+        contract.unlock_identity(owner.to_string());
+        contract.schedule_transfer_at(owner.to_string(), accounts(3).to_string(), 100);
+        contract.cancel_scheduled_transfer(owner.to_string());
 
-        contract.change_owner(owner.clone().to_string(), new_owner.clone().to_string());
+        let events: Vec<_> = registry_logs().iter().filter_map(|log| EventLog::from_log(log)).map(|log| log.event).collect();
+        let identity = || owner.to_string();
+        assert_eq!(
+            events,
+            vec![
+                DidEvent::from(ControllerSet { identity: identity(), controller: accounts(2) }),
+                DidEvent::from(IdentityLocked { identity: identity() }),
+                DidEvent::from(IdentityUnlocked { identity: identity() }),
+                DidEvent::from(TransferScheduled { identity: identity(), new_owner: accounts(3).to_string(), effective_at: 100 }),
+                DidEvent::from(TransferCancelled { identity: identity(), new_owner: accounts(3).to_string(), effective_at: 100 }),
+            ]
+        );
+    }
 
-        assert_eq!(contract.identity_owner(owner.clone().to_string()), new_owner);
-        // assert_eq!(contract.get_changed(owner), 40);
+    #[test]
+    #[should_panic(expected = "memo_too_long")]
+    fn oversized_memo_rejected() {
+        let owner = accounts(1);
+        set_context(owner.clone());
+        let mut contract = NearDIDRegistry::default();
+
+        contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, Some("m".repeat(MAX_MEMO_BYTES)));
+        contract.revoke_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), Some("m".repeat(MAX_MEMO_BYTES + 1)));
     }
 
     #[test]
-    #[should_panic(expected = "bad_actor")]
-    fn change_owner_fails() {
+    fn event_schema_snapshot() {
+        set_context(accounts(1));
+        let contract = NearDIDRegistry::default();
+
+        assert_eq!(
+            contract.get_event_schema(),
+            serde_json::json!({
+                "standard": "did-near",
+                "version": "1.0.0",
+                "schema_version": 8,
+                "events": [
+                    { "event": "did_identity_created", "fields": {
+                        "actor": { "type": "string", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_owner_changed", "fields": {
+                        "changed": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "memo": { "type": "string", "optional": true },
+                        "new_owner": { "type": "string", "optional": false },
+                        "previous_change": { "type": "integer", "optional": false },
+                        "previous_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_delegate_changed", "fields": {
+                        "changed": { "type": "integer", "optional": false },
+                        "delegate": { "type": "string", "optional": false },
+                        "delegate_type": { "type": "string", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "memo": { "type": "string", "optional": true },
+                        "previous_change": { "type": "integer", "optional": false },
+                        "valid_to": { "type": "integer", "optional": false },
+                    } },
+                    { "event": "did_attribute_changed", "fields": {
+                        "changed": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "memo": { "type": "string", "optional": true },
+                        "name": { "type": "string", "optional": false },
+                        "previous_change": { "type": "integer", "optional": false },
+                        "truncated": { "type": "boolean", "optional": false },
+                        "valid_to": { "type": "integer", "optional": false },
+                        "value": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_signed_operation_executed", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "nonce": { "type": "integer", "optional": false },
+                        "operation": { "type": "string", "optional": false },
+                        "public_keys": { "type": "array<string>", "optional": false },
+                        "relayer": { "type": "string", "optional": false },
+                        "signer": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_entry_purged", "fields": {
+                        "delegate": { "type": "string", "optional": true },
+                        "delegate_type": { "type": "string", "optional": true },
+                        "identity": { "type": "string", "optional": false },
+                        "kind": { "type": "string", "optional": false },
+                        "name": { "type": "string", "optional": true },
+                        "truncated": { "type": "boolean", "optional": true },
+                        "valid_to": { "type": "integer", "optional": false },
+                        "value": { "type": "string", "optional": true },
+                    } },
+                    { "event": "did_deactivated", "fields": {
+                        "changed": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "previous_change": { "type": "integer", "optional": false },
+                    } },
+                    { "event": "did_identity_cleared", "fields": {
+                        "attributes": { "type": "integer", "optional": false },
+                        "changed": { "type": "integer", "optional": false },
+                        "delegates": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "previous_change": { "type": "integer", "optional": false },
+                    } },
+                    { "event": "did_identity_migrated", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "target_registry": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_recovery_configured", "fields": {
+                        "delay_secs": { "type": "integer", "optional": false },
+                        "guardians": { "type": "array<string>", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "threshold": { "type": "integer", "optional": false },
+                    } },
+                    { "event": "did_recovery_initiated", "fields": {
+                        "effective_at": { "type": "integer", "optional": false },
+                        "guardian": { "type": "string", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "new_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_recovery_approved", "fields": {
+                        "approvals": { "type": "integer", "optional": false },
+                        "guardian": { "type": "string", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_recovery_cancelled", "fields": {
+                        "actor": { "type": "string", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_recovery_finalized", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "new_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_ownership_recovered", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "new_owner": { "type": "string", "optional": false },
+                        "recovery_address": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_controller_set", "fields": {
+                        "controller": { "type": "string", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_controller_cleared", "fields": {
+                        "controller": { "type": "string", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_operator_approved", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "operator": { "type": "string", "optional": false },
+                        "permissions": { "type": "object", "optional": false },
+                    } },
+                    { "event": "did_operator_revoked", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "operator": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_locked", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_unlocked", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_owner_change_cooldown_set", "fields": {
+                        "cooldown_secs": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_contract_authorization_set", "fields": {
+                        "contract": { "type": "string", "optional": false },
+                        "enabled": { "type": "boolean", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_temporary_owner_granted", "fields": {
+                        "expires_at": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "temp_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_temporary_owner_revoked", "fields": {
+                        "expires_at": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "temp_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_policy_set", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "revoke_delegates_on_owner_change": { "type": "boolean", "optional": false },
+                    } },
+                    { "event": "did_recovery_address_set", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "recovery_address": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_owner_proposed", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "proposed_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_owner_proposal_cancelled", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "proposed_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_owner_change_scheduled", "fields": {
+                        "effective_at": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "new_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_owner_change_cancelled", "fields": {
+                        "effective_at": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "new_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_transfer_scheduled", "fields": {
+                        "effective_at": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "new_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_transfer_cancelled", "fields": {
+                        "effective_at": { "type": "integer", "optional": false },
+                        "identity": { "type": "string", "optional": false },
+                        "new_owner": { "type": "string", "optional": false },
+                    } },
+                    { "event": "did_nonce_cancelled", "fields": {
+                        "identity": { "type": "string", "optional": false },
+                        "nonce": { "type": "integer", "optional": false },
+                        "signer": { "type": "string", "optional": false },
+                    } },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn erc1056_event_shapes() {
+        use events::erc1056::{bytes32, DidAttributeChanged, DidDelegateChanged, DidOwnerChanged, Erc1056Event};
+
+        set_context(accounts(1));
+        assert_eq!(bytes32("veriKey"), "0x766572694b657900000000000000000000000000000000000000000000000000");
+        assert_eq!(bytes32(&"a".repeat(33)), format!("0x{}", env::keccak256_array("a".repeat(33).as_bytes()).iter().map(|b| format!("{:02x}", b)).collect::<String>()));
+
+        let owner = Erc1056Event::OwnerChanged(DidOwnerChanged { identity: "alice.near".to_string(), owner: "bob.near".to_string(), previous_change: 12 });
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(owner.to_event_string().strip_prefix("EVENT_JSON:").unwrap()).unwrap(),
+            serde_json::json!({
+                "standard": "erc1056",
+                "version": "1.0.0",
+                "event": "DIDOwnerChanged",
+                "data": { "identity": "alice.near", "owner": "bob.near", "previousChange": 12 },
+            })
+        );
+
+        let delegate = Erc1056Event::DelegateChanged(DidDelegateChanged::new("alice.near".to_string(), "sigAuth", "carol.near".to_string(), 4600, 14));
+        assert_eq!(
+            serde_json::to_value(&delegate).unwrap()["data"],
+            serde_json::json!({
+                "identity": "alice.near",
+                "delegateType": "0x7369674175746800000000000000000000000000000000000000000000000000",
+                "delegate": "carol.near",
+                "validTo": 4600,
+                "previousChange": 14,
+            })
+        );
+
+        let attribute = Erc1056Event::AttributeChanged(DidAttributeChanged::new("alice.near".to_string(), "did/svc/Hub", &[0xde, 0xad], 0, 15));
+        assert_eq!(
+            serde_json::to_value(&attribute).unwrap()["data"],
+            serde_json::json!({
+                "identity": "alice.near",
+                "name": "0x6469642f7376632f487562000000000000000000000000000000000000000000",
+                "value": "0xdead",
+                "truncated": false,
+                "validTo": 0,
+                "previousChange": 15,
+            })
+        );
+    }
+
+    #[cfg(feature = "erc1056-events")]
+    #[test]
+    fn erc1056_events_logged_alongside() {
         let owner = accounts(1);
-        let attacker = accounts(3);
-        let new_owner = accounts(2);
+        set_context(owner.clone());
 
-        set_context(attacker.clone());
+        let mut contract = NearDIDRegistry::default();
+        contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+
+        let logs = get_logs();
+        assert!(matches!(EventLog::from_log(&logs[1]).unwrap().event, DidEvent::DelegateChanged(_)));
+        assert_eq!(
+            logs[2],
+            r#"EVENT_JSON:{"data":{"delegate":"charlie","delegateType":"0x766572694b657900000000000000000000000000000000000000000000000000","identity":"bob","previousChange":0,"validTo":3600},"event":"DIDDelegateChanged","standard":"erc1056","version":"1.0.0"}"#
+        );
+    }
+
+    #[test]
+    fn request_authorized_asks_owner_contract() {
+        use near_sdk::mock::MockAction;
+        use near_sdk::test_utils::get_created_receipts;
 
+        let dao = accounts(1);
         let mut contract = NearDIDRegistry::default();
-        contract.change_owner(owner.to_string(), new_owner.to_string());
+        set_context(dao.clone());
+        contract.set_contract_authorization(dao.to_string(), true);
+        assert_eq!(contract.get_authorizing_contract(dao.to_string()), Some(dao.clone()));
+
+        set_context(accounts(2));
+        let op = SignedOp::AddDelegate { delegate_type: "veriKey".to_string(), delegate: accounts(3).to_string(), validity_secs: 3600 };
+        let _ = contract.request_authorized(dao.to_string(), op, None);
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, dao);
+        let MockAction::FunctionCallWeight { method_name, args, prepaid_gas, .. } = &receipts[0].actions[0] else { panic!("not a function call") };
+        assert_eq!(method_name, b"is_authorized");
+        assert_eq!(*prepaid_gas, AUTHORIZATION_GAS);
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(args).unwrap(), serde_json::json!({ "identity": "bob", "actor": "charlie", "action": "addDelegate" }));
+        let MockAction::FunctionCallWeight { method_name, prepaid_gas, .. } = &receipts[1].actions[0] else { panic!("not a function call") };
+        assert_eq!(method_name, b"on_authorized");
+        assert_eq!(*prepaid_gas, AUTHORIZED_CALLBACK_GAS);
+        assert!(!contract.valid_delegate(dao.to_string(), "veriKey".to_string(), accounts(3).to_string()));
     }
 
     #[test]
-    fn add_delegate_success() {
-        let identity = accounts(1);
-        let owner = identity.clone();
-        let delegate = accounts(2);
-        let delegate_type = "veriKey".to_string();
-        let validity_secs = 3600;
+    fn authorized_request_is_applied() {
+        let dao = accounts(1);
+        let mut contract = NearDIDRegistry::default();
+        set_context(dao.clone());
+        contract.set_contract_authorization(dao.to_string(), true);
 
-        set_context(owner.clone());
+        set_callback_context(near_sdk::PromiseResult::Successful(b"true".to_vec()));
+        let op = SignedOp::AddDelegate { delegate_type: "veriKey".to_string(), delegate: accounts(3).to_string(), validity_secs: 3600 };
+        assert!(contract.on_authorized(dao.to_string(), dao.clone(), accounts(2), op, Some("proposal 7".to_string()), Ok(true)));
+        assert!(contract.valid_delegate(dao.to_string(), "veriKey".to_string(), accounts(3).to_string()));
+        let DidEvent::DelegateChanged(event) = EventLog::from_log(registry_logs().last().unwrap()).unwrap().event else { panic!("not a delegate change") };
+        assert_eq!(event.memo, Some("proposal 7".to_string()));
+
+        let op = SignedOp::ChangeOwner { new_owner: accounts(4).to_string() };
+        assert!(contract.on_authorized(dao.to_string(), dao.clone(), accounts(2), op, None, Ok(true)));
+        assert_eq!(contract.identity_owner(dao.to_string()), accounts(4));
+        assert_eq!(contract.get_authorizing_contract(dao.to_string()), None);
+    }
 
+    #[test]
+    fn refused_request_changes_nothing() {
+        let dao = accounts(1);
         let mut contract = NearDIDRegistry::default();
-        contract.add_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string(), validity_secs);
+        set_context(dao.clone());
+        contract.set_contract_authorization(dao.to_string(), true);
 
-        let valid = contract.valid_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string());
-        assert!(valid, "El delegado debería ser válido");
+        set_callback_context(near_sdk::PromiseResult::Failed);
+        let op = SignedOp::SetAttribute { name: "did/svc/Hub".to_string(), value: b"https://hub.example".to_vec(), validity_secs: 3600 };
+        assert!(!contract.on_authorized(dao.to_string(), dao.clone(), accounts(2), op.clone(), None, Ok(false)));
+        assert!(!contract.on_authorized(dao.to_string(), dao.clone(), accounts(2), op, None, Err(PromiseError::Failed)));
+        assert!(!contract.valid_attribute(dao.to_string(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec()));
+        assert_eq!(contract.get_changed(dao.to_string()), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "no_authorizing_contract")]
+    fn request_authorized_without_contract_authorization() {
+        let mut contract = NearDIDRegistry::default();
+        set_context(accounts(2));
+        let op = SignedOp::RevokeDelegate { delegate_type: "veriKey".to_string(), delegate: accounts(3).to_string() };
+        let _ = contract.request_authorized(accounts(1).to_string(), op, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "no_authorizing_contract")]
+    fn authorization_outlived_by_owner_change() {
+        let dao = accounts(1);
+        let mut contract = NearDIDRegistry::default();
+        set_context(dao.clone());
+        contract.set_contract_authorization(dao.to_string(), true);
+        contract.change_owner(dao.to_string(), accounts(4).to_string(), None);
+
+        set_callback_context(near_sdk::PromiseResult::Successful(b"true".to_vec()));
+        let op = SignedOp::AddDelegate { delegate_type: "veriKey".to_string(), delegate: accounts(3).to_string(), validity_secs: 3600 };
+        contract.on_authorized(dao.to_string(), dao, accounts(2), op, None, Ok(true));
     }
 
     #[test]
     #[should_panic(expected = "bad_actor")]
-    fn add_delegate_fails() {
-        let identity = accounts(1);
-        let attacker = accounts(3);
-        let delegate = accounts(2);
-        let delegate_type = "veriKey".to_string();
+    fn contract_authorization_only_by_owner() {
+        let mut contract = NearDIDRegistry::default();
+        set_context(accounts(2));
+        contract.set_contract_authorization(accounts(1).to_string(), true);
+    }
 
-        set_context(attacker.clone());
+    #[test]
+    #[should_panic(expected = "owner_change_cooldown")]
+    fn owner_change_cooldown_rejects_rapid_transfer() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_owner_change_cooldown(owner.clone(), 3600);
+        assert_eq!(contract.get_owner_change_cooldown(owner.clone()), 3600);
+        contract.change_owner(owner.clone(), accounts(2).to_string(), None);
+
+        set_context_at(accounts(2), 4_599);
+        assert!(!contract.can_change_owner(owner.clone(), accounts(2).to_string()));
+        contract.change_owner(owner, accounts(3).to_string(), None);
+    }
 
+    #[test]
+    fn owner_change_after_cooldown() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
         let mut contract = NearDIDRegistry::default();
-        contract.add_delegate(identity.to_string(), delegate_type, delegate.to_string(), 1000);
+        contract.set_owner_change_cooldown(owner.clone(), 3600);
+        contract.change_owner(owner.clone(), accounts(2).to_string(), None);
+
+        set_context_at(accounts(2), 4_600);
+        contract.change_owner(owner.clone(), accounts(3).to_string(), None);
+        assert_eq!(contract.identity_owner(owner), accounts(3).to_string());
     }
 
     #[test]
-    fn revoke_delegate_success() {
-        let identity = accounts(1);
-        let owner = identity.clone();
-        let delegate = accounts(2);
-        let delegate_type = "veriKey".to_string();
-        let validity_secs = 3600;
+    fn recovery_bypasses_owner_change_cooldown() {
+        let owner = accounts(0).to_string();
+        set_context_at(accounts(0), 1_000);
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_config(owner.clone(), vec![accounts(1)], 1, 0);
+        contract.set_owner_change_cooldown(owner.clone(), 86_400);
+        contract.change_owner(owner.clone(), accounts(2).to_string(), None);
 
-        set_context(owner.clone());
+        set_context_at(accounts(1), 1_100);
+        contract.initiate_recovery(owner.clone(), accounts(4).to_string());
+        contract.finalize_recovery(owner.clone());
+        assert_eq!(contract.identity_owner(owner), accounts(4).to_string());
+    }
 
+    #[test]
+    #[should_panic(expected = "owner_change_cooldown")]
+    fn owner_change_cooldown_fixed_within_cooldown() {
+        let owner = accounts(1).to_string();
+        set_context_at(accounts(1), 1_000);
         let mut contract = NearDIDRegistry::default();
+        contract.set_owner_change_cooldown(owner.clone(), 3600);
+        contract.change_owner(owner.clone(), accounts(2).to_string(), None);
 
-        contract.add_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string(), validity_secs);
-        assert!(contract.valid_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string()));
+        set_context_at(accounts(2), 2_000);
+        contract.set_owner_change_cooldown(owner, 0);
+    }
 
-        contract.revoke_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string());
-        assert!(!contract.valid_delegate(identity.clone().to_string(), delegate_type.clone(), delegate.clone().to_string()));
+    #[test]
+    fn recovery_address_recovers_ownership() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_address(owner.clone(), accounts(2));
+        assert_eq!(contract.get_recovery_address(owner.clone()), Some(accounts(2)));
+
+        set_context(accounts(2));
+        contract.recover_ownership(owner.clone(), accounts(3).to_string());
+        assert_eq!(contract.identity_owner(owner.clone()), accounts(3).to_string());
+        assert_eq!(contract.get_changed(owner.clone()), 40);
+        assert_eq!(contract.get_recovery_address(owner.clone()), None);
+        let logs: Vec<_> = registry_logs().iter().filter_map(|log| EventLog::from_log(log)).map(|log| log.event).collect();
+        assert_eq!(logs[0], DidEvent::from(OwnershipRecovered { identity: owner, recovery_address: accounts(2), new_owner: accounts(3).to_string() }));
+        assert!(matches!(logs.as_slice(), [DidEvent::OwnershipRecovered(_), DidEvent::IdentityCreated(_), DidEvent::OwnerChanged(_)]));
     }
 
     #[test]
     #[should_panic(expected = "bad_actor")]
-    fn revoke_delegate_fails() {
-        let identity = accounts(1);
-        let attacker = accounts(3);
-        let delegate = accounts(2);
-        let delegate_type = "veriKey".to_string();
+    fn recovery_address_cannot_set_attributes() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_address(owner.clone(), accounts(2));
 
-        set_context(attacker.clone());
+        set_context(accounts(2));
+        contract.set_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+    }
 
+    #[test]
+    #[should_panic(expected = "not_recovery_address")]
+    fn recovery_address_is_single_use() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
         let mut contract = NearDIDRegistry::default();
+        contract.set_recovery_address(owner.clone(), accounts(2));
 
-        contract.revoke_delegate(identity.to_string(), delegate_type, delegate.to_string());
+        set_context(accounts(2));
+        contract.recover_ownership(owner.clone(), accounts(3).to_string());
+        contract.recover_ownership(owner, accounts(4).to_string());
     }
 
     #[test]
-    fn set_attribute_success() {
-        let identity = accounts(1);
-        let owner = identity.clone();
-        let name = "did/pub/Ed25519/veriKey/base64".to_string();
-        let value = b"base64EncodedKeyHere".to_vec();
-        let validity_secs = 3600;
+    fn operator_sets_attributes() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        let attributes = Permissions { set_attribute: true, revoke_attribute: true, ..Permissions::default() };
+        contract.approve_operator(owner.clone(), accounts(2), attributes);
+        contract.approve_operator(owner.clone(), accounts(3), Permissions::ALL);
+        contract.approve_operator(owner.clone(), accounts(3), attributes);
+        assert_eq!(contract.list_operators(owner.clone()), vec![(accounts(2), attributes), (accounts(3), attributes)]);
+        assert_eq!(contract.can_manage(owner.clone(), accounts(2).to_string()), ManagePermissions { delegates: false, attributes: true, owner: false });
 
-        set_context(owner.clone());
+        set_context(accounts(2));
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example/1".to_vec(), 604_800, None);
+        contract.revoke_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example/1".to_vec(), None);
+        contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example/2".to_vec(), 604_800, None);
+        assert!(!contract.valid_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example/1".to_vec()));
+        assert!(contract.valid_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example/2".to_vec()));
+    }
+
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn operator_cannot_add_delegate() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.approve_operator(owner.clone(), accounts(2), Permissions { set_attribute: true, revoke_attribute: true, ..Permissions::default() });
+
+        set_context(accounts(2));
+        contract.add_delegate(owner, "veriKey".to_string(), accounts(2).to_string(), 3600, None);
+    }
 
+    #[test]
+    #[should_panic(expected = "bad_actor")]
+    fn revoked_operator_cannot_set_attributes() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
         let mut contract = NearDIDRegistry::default();
+        contract.approve_operator(owner.clone(), accounts(2), Permissions { set_attribute: true, revoke_attribute: true, ..Permissions::default() });
+        contract.revoke_operator(owner.clone(), accounts(2));
+        assert!(contract.list_operators(owner.clone()).is_empty());
 
-        contract.set_attribute(identity.clone().to_string(), name.clone(), value.clone(), validity_secs);
+        set_context(accounts(2));
+        contract.set_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None);
+    }
 
-        let stored_valid_until = contract
-            .attributes
-            .get(&(identity.clone().to_string(), name.clone(), value.clone()))
-            .unwrap();
-        assert_eq!(stored_valid_until, &validity_secs);
+    #[test]
+    fn clear_identity_drops_operators() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.approve_operator(owner.clone(), accounts(2), Permissions { set_attribute: true, revoke_attribute: true, ..Permissions::default() });
+        contract.clear_identity(owner.clone(), MAX_CLEAR_ENTRIES);
+        assert!(contract.list_operators(owner.clone()).is_empty());
+        assert!(!contract.can_manage(owner, accounts(2).to_string()).attributes);
     }
 
     #[test]
     #[should_panic(expected = "bad_actor")]
-    fn test_set_attribute_fails_if_not_owner() {
-        let identity = accounts(1);
-        let attacker = accounts(3);
-        let name = "did/pub/Ed25519/veriKey/base64".to_string();
-        let value = b"maliciousKey".to_vec();
+    fn temporary_owner_cannot_clear_identity() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.approve_operator(owner.clone(), accounts(3), Permissions::ALL);
+        contract.grant_temporary_ownership(owner.clone(), accounts(2), 100);
 
-        set_context(attacker.clone());
+        set_context_at(accounts(2), 1_050);
+        contract.clear_identity(owner, MAX_CLEAR_ENTRIES);
+    }
 
+    #[test]
+    fn operator_permissions_grant_each_write_alone() {
+        let writes: [fn(&mut NearDIDRegistry, String); 4] = [
+            |contract, owner| contract.add_delegate(owner, "veriKey".to_string(), accounts(4).to_string(), 3600, None),
+            |contract, owner| contract.revoke_delegate(owner, "veriKey".to_string(), accounts(3).to_string(), None),
+            |contract, owner| contract.set_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example/2".to_vec(), 3600, None),
+            |contract, owner| contract.revoke_attribute(owner, "did/svc/Hub".to_string(), b"https://hub.example/1".to_vec(), None),
+        ];
+        let grants = [
+            Permissions { add_delegate: true, ..Permissions::default() },
+            Permissions { revoke_delegate: true, ..Permissions::default() },
+            Permissions { set_attribute: true, ..Permissions::default() },
+            Permissions { revoke_attribute: true, ..Permissions::default() },
+        ];
         let mut contract = NearDIDRegistry::default();
+        for (granted, permissions) in grants.into_iter().enumerate() {
+            let owner: AccountId = format!("owner{granted}.near").parse().unwrap();
+            set_context(owner.clone());
+            contract.add_delegate(owner.to_string(), "veriKey".to_string(), accounts(3).to_string(), 3600, None);
+            contract.set_attribute(owner.to_string(), "did/svc/Hub".to_string(), b"https://hub.example/1".to_vec(), 3600, None);
+            contract.approve_operator(owner.to_string(), accounts(2), permissions);
+            assert_eq!(contract.get_permissions(owner.to_string(), accounts(2).to_string()), permissions);
 
-        contract.set_attribute(identity.to_string(), name, value, 1000);
+            set_context(accounts(2));
+            for (write, call) in writes.iter().enumerate() {
+                let allowed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| call(&mut contract, owner.to_string()))).is_ok();
+                assert_eq!(allowed, write == granted, "{permissions:?} allowed write {write}");
+            }
+        }
     }
 
     #[test]
-    fn test_revoke_attribute_success() {
-        let identity = accounts(1);
-        let owner = identity.clone();
-        let name = "did/service/endpoint".to_string();
-        let value = b"https://example.com".to_vec();
-        let validity_secs = 3600;
+    #[should_panic(expected = "bad_actor")]
+    fn temporary_owner_cannot_approve_operator() {
+        let owner = accounts(1).to_string();
+        let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.grant_temporary_ownership(owner.clone(), accounts(2), 100);
 
-        set_context(owner.clone());
+        set_context_at(accounts(2), 1_050);
+        contract.approve_operator(owner, accounts(3), Permissions::ALL);
+    }
 
+    #[test]
+    fn operator_revoked_during_grant_stays_revoked() {
+        let owner = accounts(1).to_string();
         let mut contract = NearDIDRegistry::default();
+        set_context_at(accounts(1), 1_000);
+        contract.approve_operator(owner.clone(), accounts(3), Permissions::ALL);
+        contract.grant_temporary_ownership(owner.clone(), accounts(2), 100);
 
-        contract.set_attribute(identity.clone().to_string(), name.clone(), value.clone(), validity_secs);
-        assert!(contract.valid_attribute(identity.clone().to_string(), name.clone(), value.clone()));
+        set_context_at(accounts(2), 1_050);
+        let revoked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.revoke_operator(owner.clone(), accounts(3))));
+        assert!(revoked.is_err());
+        set_context_at(accounts(1), 1_050);
+        contract.revoke_operator(owner.clone(), accounts(3));
 
-        contract.revoke_attribute(identity.clone().to_string(), name.clone(), value.clone());
-        assert!(!contract.valid_attribute(identity.clone().to_string(), name.clone(), value.clone()));
+        set_context_at(accounts(3), 1_100);
+        assert_eq!(contract.get_permissions(owner.clone(), accounts(3).to_string()), Permissions::default());
+        let written = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.set_attribute(owner.clone(), "did/svc/Hub".to_string(), b"https://hub.example".to_vec(), 3600, None)));
+        assert!(written.is_err());
+    }
 
-        let stored = contract
-            .attributes
-            .get(&(identity.clone().to_string(), name.clone(), value.clone()))
-            .unwrap();
-        assert_eq!(stored, &0, "El atributo debe estar revocado (valor 0)");
+    #[test]
+    fn get_permissions_of_owner_controller_and_others() {
+        let owner = accounts(1).to_string();
+        set_context(accounts(1));
+        let mut contract = NearDIDRegistry::default();
+        contract.set_controller(owner.clone(), accounts(2));
+        contract.approve_operator(owner.clone(), accounts(3), Permissions { add_delegate: true, set_attribute: true, ..Permissions::default() });
+
+        assert_eq!(contract.get_permissions(owner.clone(), owner.clone()), Permissions::ALL);
+        assert_eq!(contract.get_permissions(owner.clone(), accounts(2).to_string()), Permissions::ALL);
+        assert_eq!(contract.get_permissions(owner.clone(), accounts(4).to_string()), Permissions::default());
+        assert_eq!(contract.can_manage(owner.clone(), accounts(3).to_string()), ManagePermissions { delegates: false, attributes: false, owner: false });
+
+        contract.lock_identity(owner.clone());
+        assert_eq!(contract.get_permissions(owner.clone(), accounts(3).to_string()), Permissions::default());
+        assert_eq!(contract.get_permissions(owner.clone(), owner), Permissions::default());
     }
 }
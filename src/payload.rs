@@ -0,0 +1,95 @@
+//! Canonical byte payloads for signed (relayed) operations.
+//!
+//! Every payload is a domain separator followed by the operation:
+//!
+//! ```text
+//! "did-near"  string   u32 LE length + UTF-8 bytes
+//! VERSION     u8       payload format version, currently 2
+//! contract    string   registry account id (env::current_account_id)
+//! salt        [u8; 32] registry salt (get_registry_salt)
+//! nonce       u64 LE   identity's current nonce
+//! valid_until u64 LE   last unix second the payload may be submitted in
+//! identity    string
+//! operation   string   "changeOwner", "addDelegate", ...
+//! params      ...      operation parameters, in method argument order
+//! ```
+//!
+//! All fields are Borsh encoded. Borsh strings and byte vectors are `u32`
+//! little-endian length prefixed, so the encoding is unambiguous. The
+//! separator binds a signature to one registry deployment and payload format;
+//! the salt changes when the registry state is reset, so payloads signed
+//! against an earlier state cannot be replayed once nonces restart at zero.
+//! Wallets sign the returned bytes as-is with the owner's ed25519 key.
+
+use near_sdk::{borsh, near, AccountId};
+
+pub const DOMAIN: &str = "did-near";
+pub const VERSION: u8 = 2;
+
+/// One operation of a signed batch. Fields mirror the arguments of the
+/// corresponding method.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub enum SignedOp {
+    ChangeOwner { new_owner: String },
+    AddDelegate { delegate_type: String, delegate: String, validity_secs: u64 },
+    RevokeDelegate { delegate_type: String, delegate: String },
+    SetAttribute { name: String, value: Vec<u8>, validity_secs: u64 },
+    RevokeAttribute { name: String, value: Vec<u8> },
+}
+
+impl SignedOp {
+    /// Name of the operation, as in its standalone payload.
+    pub fn operation(&self) -> &'static str {
+        match self {
+            Self::ChangeOwner { .. } => "changeOwner",
+            Self::AddDelegate { .. } => "addDelegate",
+            Self::RevokeDelegate { .. } => "revokeDelegate",
+            Self::SetAttribute { .. } => "setAttribute",
+            Self::RevokeAttribute { .. } => "revokeAttribute",
+        }
+    }
+}
+
+/// Registry state a payload is bound to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Domain {
+    pub contract: AccountId,
+    pub salt: [u8; 32],
+}
+
+fn encode(domain: &Domain, operation: impl borsh::BorshSerialize) -> Vec<u8> {
+    let mut payload = borsh::to_vec(&(DOMAIN, VERSION, domain.contract.as_str(), domain.salt)).unwrap();
+    payload.extend(borsh::to_vec(&operation).unwrap());
+    payload
+}
+
+pub fn change_owner(domain: &Domain, nonce: u64, valid_until: u64, identity: &str, new_owner: &str) -> Vec<u8> {
+    encode(domain, (nonce, valid_until, identity, "changeOwner", new_owner))
+}
+
+pub fn change_owner_multisig(domain: &Domain, nonce: u64, valid_until: u64, identity: &str, new_owner: &str) -> Vec<u8> {
+    encode(domain, (nonce, valid_until, identity, "changeOwnerMultisig", new_owner))
+}
+
+pub fn add_delegate(domain: &Domain, nonce: u64, valid_until: u64, identity: &str, delegate_type: &str, delegate: &str, validity_secs: u64) -> Vec<u8> {
+    encode(domain, (nonce, valid_until, identity, "addDelegate", delegate_type, delegate, validity_secs))
+}
+
+pub fn revoke_delegate(domain: &Domain, nonce: u64, valid_until: u64, identity: &str, delegate_type: &str, delegate: &str) -> Vec<u8> {
+    encode(domain, (nonce, valid_until, identity, "revokeDelegate", delegate_type, delegate))
+}
+
+pub fn set_attribute(domain: &Domain, nonce: u64, valid_until: u64, identity: &str, name: &str, value: &[u8], validity_secs: u64) -> Vec<u8> {
+    encode(domain, (nonce, valid_until, identity, "setAttribute", name, value, validity_secs))
+}
+
+pub fn revoke_attribute(domain: &Domain, nonce: u64, valid_until: u64, identity: &str, name: &str, value: &[u8]) -> Vec<u8> {
+    encode(domain, (nonce, valid_until, identity, "revokeAttribute", name, value))
+}
+
+/// A batch covers the Borsh encoding of the whole op vector (`u32` length
+/// followed by each op's variant index and fields).
+pub fn batch(domain: &Domain, nonce: u64, valid_until: u64, identity: &str, ops: &[SignedOp]) -> Vec<u8> {
+    encode(domain, (nonce, valid_until, identity, "batch", ops))
+}
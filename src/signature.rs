@@ -0,0 +1,105 @@
+//! Signatures accepted by the signed (relayed) operations.
+//!
+//! * `Ed25519` signs the payload bytes as-is. The 64-byte signature is checked
+//!   against the owner's registered ed25519 key, or against the owner string
+//!   itself when it is a base58-encoded public key.
+//! * `Secp256k1` signs `keccak256(payload)`. The 65-byte signature is
+//!   `r || s || v`, where `v` is the recovery id (`0`/`1`, or `27`/`28` as
+//!   produced by Ethereum wallets). The recovered 64-byte public key must match
+//!   the owner's registered secp256k1 key, or the owner string must be the
+//!   Ethereum address derived from it (`0x` + last 20 bytes of
+//!   `keccak256(public_key)`, compared case-insensitively).
+//! * `Nep413` is an ed25519 signature produced by a wallet's NEP-413
+//!   `signMessage`, checked against the same key as `Ed25519`. The message is
+//!   the standard base64 encoding of the payload bytes, the recipient is the
+//!   registry account and `callback_url` is `None`; `nonce` is the wallet's
+//!   32-byte NEP-413 nonce (replay protection comes from the payload nonce).
+
+use near_sdk::{base64::{engine::general_purpose::STANDARD, Engine}, borsh, env, near, AccountId, CurveType, PublicKey};
+
+/// `2^31 + 413`, the NEP-413 prefix tag that keeps signed messages from ever
+/// being valid transactions.
+const NEP413_TAG: u32 = (1 << 31) + 413;
+
+#[near(serializers = [json])]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Signature {
+    Ed25519(Vec<u8>),
+    Secp256k1(Vec<u8>),
+    Nep413 { signature: Vec<u8>, nonce: [u8; 32] },
+}
+
+#[near(serializers = [borsh])]
+struct Nep413Payload {
+    message: String,
+    nonce: [u8; 32],
+    recipient: String,
+    callback_url: Option<String>,
+}
+
+pub fn verify_ed25519(signature: &[u8], payload: &[u8], public_key: &[u8; 32]) -> bool {
+    match signature.try_into() {
+        Ok(signature) => env::ed25519_verify(signature, payload, public_key),
+        Err(_) => false,
+    }
+}
+
+/// Recovers the uncompressed (64-byte, no prefix) secp256k1 public key that
+/// produced `signature` over `keccak256(payload)`.
+pub fn recover_secp256k1(signature: &[u8], payload: &[u8]) -> Option<[u8; 64]> {
+    if signature.len() != 65 {
+        return None;
+    }
+    let v = match signature[64] {
+        v @ (27 | 28) => v - 27,
+        v => v,
+    };
+    env::ecrecover(&env::keccak256_array(payload), &signature[..64], v, true)
+}
+
+/// Ethereum address (`0x`-prefixed lowercase hex) of a 64-byte secp256k1 key.
+pub fn eth_address(public_key: &[u8; 64]) -> String {
+    let hash = env::keccak256_array(public_key);
+    let hex: String = hash[12..].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", hex)
+}
+
+/// The sha256 digest a NEP-413 wallet signs for `payload`.
+pub fn nep413_hash(payload: &[u8], nonce: [u8; 32], recipient: &AccountId) -> [u8; 32] {
+    let envelope = Nep413Payload {
+        message: STANDARD.encode(payload),
+        nonce,
+        recipient: recipient.to_string(),
+        callback_url: None,
+    };
+    let mut bytes = borsh::to_vec(&NEP413_TAG).unwrap();
+    bytes.extend(borsh::to_vec(&envelope).unwrap());
+    env::sha256_array(&bytes)
+}
+
+/// Whether `signature` over `payload` was produced by `public_key`, for keys
+/// named explicitly by the caller (multisig approvals).
+pub fn verify_with_key(signature: &Signature, payload: &[u8], public_key: &PublicKey) -> bool {
+    let key = &public_key.as_bytes()[1..];
+    match (signature, public_key.curve_type()) {
+        (Signature::Ed25519(signature), CurveType::ED25519) => verify_ed25519(signature, payload, key.try_into().unwrap()),
+        (Signature::Nep413 { signature, nonce }, CurveType::ED25519) => {
+            verify_ed25519(signature, &nep413_hash(payload, *nonce, &env::current_account_id()), key.try_into().unwrap())
+        }
+        (Signature::Secp256k1(signature), CurveType::SECP256K1) => recover_secp256k1(signature, payload).is_some_and(|recovered| recovered[..] == *key),
+        _ => false,
+    }
+}
+
+/// Ed25519 key of a NEAR implicit account: 64 lowercase hex characters.
+pub fn implicit_account_key(account: &str) -> Option<[u8; 32]> {
+    if account.len() != 64 || !account.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&account[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(key)
+}
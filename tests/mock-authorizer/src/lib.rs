@@ -0,0 +1,25 @@
+//! Owns an identity like a DAO would: authorizes the registry requests of
+//! its members; panics on each check when deployed with `fail`.
+
+use near_sdk::{near, AccountId};
+
+#[near(contract_state)]
+#[derive(Default)]
+pub struct MockAuthorizer {
+    fail: bool,
+    members: Vec<AccountId>,
+}
+
+#[near]
+impl MockAuthorizer {
+    #[init]
+    pub fn new(members: Vec<AccountId>, fail: bool) -> Self {
+        Self { fail, members }
+    }
+
+    #[allow(unused_variables)]
+    pub fn is_authorized(&self, identity: String, actor: AccountId, action: String) -> bool {
+        assert!(!self.fail, "authorizer failure");
+        self.members.contains(&actor)
+    }
+}
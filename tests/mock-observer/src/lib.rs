@@ -0,0 +1,28 @@
+//! Records the `on_did_changed` notifications it receives; panics on each
+//! one when deployed with `fail`.
+
+use near_sdk::near;
+
+#[near(contract_state)]
+#[derive(Default)]
+pub struct MockObserver {
+    fail: bool,
+    notifications: Vec<(String, String, u64)>,
+}
+
+#[near]
+impl MockObserver {
+    #[init]
+    pub fn new(fail: bool) -> Self {
+        Self { fail, notifications: vec![] }
+    }
+
+    pub fn on_did_changed(&mut self, identity: String, change_kind: String, block_height: u64) {
+        assert!(!self.fail, "observer failure");
+        self.notifications.push((identity, change_kind, block_height));
+    }
+
+    pub fn get_notifications(&self) -> Vec<(String, String, u64)> {
+        self.notifications.clone()
+    }
+}
@@ -2,12 +2,13 @@ use serde_json::json;
 
 #[tokio::test]
 async fn test_contract_is_operational() -> Result<(), Box<dyn std::error::Error>> {
-    let contract_wasm = near_workspaces::compile_project("./").await?;
+    let _contract_wasm = near_workspaces::compile_project("./").await?;
 
-    // test_basics_on(&contract_wasm).await?;
+    // test_basics_on(&_contract_wasm).await?;
     Ok(())
 }
 
+#[allow(dead_code)]
 async fn test_basics_on(contract_wasm: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
     let sandbox = near_workspaces::sandbox().await?;
     let contract = sandbox.dev_deploy(contract_wasm).await?;
@@ -0,0 +1,81 @@
+use near_workspaces::types::Gas;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_owner_contract_authorizes_requests() -> Result<(), Box<dyn std::error::Error>> {
+    let registry_wasm = near_workspaces::compile_project("./").await?;
+    let authorizer_wasm = near_workspaces::compile_project("./tests/mock-authorizer").await?;
+
+    let sandbox = near_workspaces::sandbox().await?;
+    let registry = sandbox.dev_deploy(&registry_wasm).await?;
+    let dao = sandbox.dev_deploy(&authorizer_wasm).await?;
+    let member = sandbox.dev_create_account().await?;
+    let outsider = sandbox.dev_create_account().await?;
+    dao.call("new").args_json(json!({ "members": [member.id()], "fail": false })).transact().await?.into_result()?;
+
+    let identity = dao.id().to_string();
+    dao.as_account()
+        .call(registry.id(), "set_contract_authorization")
+        .args_json(json!({ "identity": identity, "enabled": true }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let op = |delegate: &str| json!({ "AddDelegate": { "delegate_type": "veriKey", "delegate": delegate, "validity_secs": 3600 } });
+    let valid_delegate = |delegate: &'static str| {
+        registry.view("valid_delegate").args_json(json!({ "identity": identity, "delegate_type": "veriKey", "delegate": delegate }))
+    };
+
+    let outcome = member.call(registry.id(), "request_authorized").args_json(json!({ "identity": identity, "op": op("carol.near") })).max_gas().transact().await?;
+    assert!(outcome.is_success());
+    assert!(outcome.json::<bool>()?);
+    assert!(valid_delegate("carol.near").await?.json::<bool>()?);
+
+    let outcome = outsider.call(registry.id(), "request_authorized").args_json(json!({ "identity": identity, "op": op("dave.near") })).max_gas().transact().await?;
+    assert!(outcome.is_success());
+    assert!(!outcome.json::<bool>()?);
+    assert!(!valid_delegate("dave.near").await?.json::<bool>()?);
+
+    let outcome = member
+        .call(registry.id(), "request_authorized")
+        .args_json(json!({ "identity": identity, "op": op("dave.near") }))
+        .gas(Gas::from_tgas(30))
+        .transact()
+        .await?;
+    assert!(outcome.is_failure());
+    assert!(!valid_delegate("dave.near").await?.json::<bool>()?);
+
+    let outcome = member.call(registry.id(), "add_delegate").args_json(json!({ "identity": identity, "delegate_type": "veriKey", "delegate": "dave.near", "validity_secs": 3600 })).transact().await?;
+    assert!(outcome.is_failure());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failing_owner_contract_changes_nothing() -> Result<(), Box<dyn std::error::Error>> {
+    let registry_wasm = near_workspaces::compile_project("./").await?;
+    let authorizer_wasm = near_workspaces::compile_project("./tests/mock-authorizer").await?;
+
+    let sandbox = near_workspaces::sandbox().await?;
+    let registry = sandbox.dev_deploy(&registry_wasm).await?;
+    let dao = sandbox.dev_deploy(&authorizer_wasm).await?;
+    let member = sandbox.dev_create_account().await?;
+    dao.call("new").args_json(json!({ "members": [member.id()], "fail": true })).transact().await?.into_result()?;
+
+    let identity = dao.id().to_string();
+    dao.as_account()
+        .call(registry.id(), "set_contract_authorization")
+        .args_json(json!({ "identity": identity, "enabled": true }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let op = json!({ "ChangeOwner": { "new_owner": member.id() } });
+    let outcome = member.call(registry.id(), "request_authorized").args_json(json!({ "identity": identity, "op": op })).max_gas().transact().await?;
+    assert!(outcome.is_success());
+    assert!(outcome.receipt_failures().iter().any(|failure| failure.executor_id == *dao.id()));
+    assert!(!outcome.json::<bool>()?);
+
+    let owner = registry.view("identity_owner").args_json(json!({ "identity": identity })).await?.json::<String>()?;
+    assert_eq!(owner, identity);
+    Ok(())
+}
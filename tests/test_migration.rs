@@ -0,0 +1,86 @@
+use serde_json::json;
+
+#[tokio::test]
+async fn test_identity_migrates_between_registries() -> Result<(), Box<dyn std::error::Error>> {
+    let registry_wasm = near_workspaces::compile_project("./").await?;
+
+    let sandbox = near_workspaces::sandbox().await?;
+    let source = sandbox.dev_deploy(&registry_wasm).await?;
+    let target = sandbox.dev_deploy(&registry_wasm).await?;
+    target.call("set_trusted_registry").args_json(json!({ "registry": source.id(), "trusted": true })).transact().await?.into_result()?;
+
+    let owner = sandbox.dev_create_account().await?;
+    let identity = owner.id().to_string();
+    owner
+        .call(source.id(), "add_delegate")
+        .args_json(json!({ "identity": identity, "delegate_type": "veriKey", "delegate": "carol.near", "validity_secs": 3600 }))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(source.id(), "set_attribute")
+        .args_json(json!({ "identity": identity, "name": "did/svc/Hub", "value": b"https://hub.example", "validity_secs": 3600 }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let outcome = owner.call(source.id(), "export_identity").args_json(json!({ "identity": identity, "target_registry": target.id() })).max_gas().transact().await?;
+    assert!(outcome.is_success());
+    assert!(outcome.json::<bool>()?);
+
+    let valid = target
+        .view("valid_delegate")
+        .args_json(json!({ "identity": identity, "delegate_type": "veriKey", "delegate": "carol.near" }))
+        .await?
+        .json::<bool>()?;
+    assert!(valid);
+    let valid = target
+        .view("valid_attribute")
+        .args_json(json!({ "identity": identity, "name": "did/svc/Hub", "value": b"https://hub.example" }))
+        .await?
+        .json::<bool>()?;
+    assert!(valid);
+
+    let migration = source.view("get_migration").args_json(json!({ "identity": identity })).await?.json::<Option<String>>()?;
+    assert_eq!(migration, Some(target.id().to_string()));
+    let outcome = owner
+        .call(source.id(), "add_delegate")
+        .args_json(json!({ "identity": identity, "delegate_type": "veriKey", "delegate": "dave.near", "validity_secs": 3600 }))
+        .transact()
+        .await?;
+    assert!(outcome.is_failure());
+
+    owner
+        .call(target.id(), "add_delegate")
+        .args_json(json!({ "identity": identity, "delegate_type": "veriKey", "delegate": "dave.near", "validity_secs": 3600 }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_import_keeps_identity() -> Result<(), Box<dyn std::error::Error>> {
+    let registry_wasm = near_workspaces::compile_project("./").await?;
+
+    let sandbox = near_workspaces::sandbox().await?;
+    let source = sandbox.dev_deploy(&registry_wasm).await?;
+    let untrusting = sandbox.dev_deploy(&registry_wasm).await?;
+
+    let owner = sandbox.dev_create_account().await?;
+    let identity = owner.id().to_string();
+    let outcome = owner.call(source.id(), "export_identity").args_json(json!({ "identity": identity, "target_registry": untrusting.id() })).max_gas().transact().await?;
+    assert!(outcome.is_success());
+    assert!(outcome.receipt_failures().iter().any(|failure| failure.executor_id == *untrusting.id()));
+    assert!(!outcome.json::<bool>()?);
+
+    let migration = source.view("get_migration").args_json(json!({ "identity": identity })).await?.json::<Option<String>>()?;
+    assert_eq!(migration, None);
+    owner
+        .call(source.id(), "add_delegate")
+        .args_json(json!({ "identity": identity, "delegate_type": "veriKey", "delegate": "carol.near", "validity_secs": 3600 }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use serde_json::json;
+
+#[tokio::test]
+async fn test_observers_are_notified() -> Result<(), Box<dyn std::error::Error>> {
+    let registry_wasm = near_workspaces::compile_project("./").await?;
+    let observer_wasm = near_workspaces::compile_project("./tests/mock-observer").await?;
+
+    let sandbox = near_workspaces::sandbox().await?;
+    let registry = sandbox.dev_deploy(&registry_wasm).await?;
+    let observer = sandbox.dev_deploy(&observer_wasm).await?;
+    let failing = sandbox.dev_deploy(&observer_wasm).await?;
+    observer.call("new").args_json(json!({ "fail": false })).transact().await?.into_result()?;
+    failing.call("new").args_json(json!({ "fail": true })).transact().await?.into_result()?;
+
+    let owner = sandbox.dev_create_account().await?;
+    let identity = owner.id().to_string();
+    for contract_id in [observer.id(), failing.id()] {
+        owner
+            .call(registry.id(), "register_observer")
+            .args_json(json!({ "identity": identity, "contract_id": contract_id }))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    let outcome = owner
+        .call(registry.id(), "add_delegate")
+        .args_json(json!({ "identity": identity, "delegate_type": "veriKey", "delegate": "carol.near", "validity_secs": 3600 }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    assert!(outcome.receipt_failures().iter().any(|failure| failure.executor_id == *failing.id()));
+
+    let valid = registry
+        .view("valid_delegate")
+        .args_json(json!({ "identity": identity, "delegate_type": "veriKey", "delegate": "carol.near" }))
+        .await?
+        .json::<bool>()?;
+    assert!(valid);
+
+    let changed = registry.view("get_changed").args_json(json!({ "identity": identity })).await?.json::<u64>()?;
+    let notifications = observer.view("get_notifications").args_json(json!({})).await?.json::<Vec<(String, String, u64)>>()?;
+    assert_eq!(notifications, vec![(identity, "delegate".to_string(), changed)]);
+
+    Ok(())
+}